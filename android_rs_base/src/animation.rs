@@ -0,0 +1,442 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use cgmath::{Matrix4, Point3, Quaternion, Vector2, Vector3, InnerSpace, VectorSpace, EuclideanSpace};
+use matrices::{Transform, TransformQ};
+
+/// A curve mapping how far through a tween it is (from 0 to 1) to how far between its values it should be
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineInOut,
+    /// Overshoots the end slightly before settling on it
+    BackOut,
+    /// Springs past the end a few times before settling on it
+    ElasticOut,
+    /// Bounces off the end like a dropped ball
+    BounceOut,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+impl Easing {
+    /// Eases `t`, which is clamped to between 0 and 1. Every curve gives 0 at 0 and 1 at 1
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => t * (2.0 - t),
+            Easing::QuadInOut => if t < 0.5 { 2.0 * t * t } else { 1.0 - 2.0 * (1.0 - t) * (1.0 - t) },
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => if t < 0.5 { 4.0 * t * t * t } else { 1.0 - 4.0 * (1.0 - t).powi(3) },
+            Easing::SineInOut => 0.5 - 0.5 * (t * std::f32::consts::PI).cos(),
+            Easing::BackOut => {
+                const OVERSHOOT: f32 = 1.701_58;
+                let t = t - 1.0;
+                1.0 + t * t * ((OVERSHOOT + 1.0) * t + OVERSHOOT)
+            },
+            Easing::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * std::f32::consts::PI * 2.0 / 3.0).sin() + 1.0
+                }
+            },
+            Easing::BounceOut => {
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+                if t < 1.0 / D {
+                    N * t * t
+                } else if t < 2.0 / D {
+                    let t = t - 1.5 / D;
+                    N * t * t + 0.75
+                } else if t < 2.5 / D {
+                    let t = t - 2.25 / D;
+                    N * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D;
+                    N * t * t + 0.984_375
+                }
+            },
+        }
+    }
+}
+
+/// A value which can be tweened between two others
+pub trait Tweenable: Copy {
+    /// Gets the value `t` of the way from `self` to `other`. `t` can go slightly past 0 and 1 with some easings
+    fn tween(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Tweenable for f64 {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t as f64
+    }
+}
+
+// Positions, scales and colours (tweened in the space they're given in, usually gamma encoded sRGB)
+macro_rules! tween_array {
+    ($($n:expr),*) => {$(
+        impl Tweenable for [f32; $n] {
+            fn tween(&self, other: &Self, t: f32) -> Self {
+                let mut out = *self;
+                for (out, other) in out.iter_mut().zip(other) {
+                    *out += (other - *out) * t;
+                }
+                out
+            }
+        }
+    )*};
+}
+
+tween_array!(2, 3, 4);
+
+impl Tweenable for Vector2<f32> {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+impl Tweenable for Vector3<f32> {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+impl Tweenable for Point3<f32> {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        Point3::from_vec(self.to_vec().lerp(other.to_vec(), t))
+    }
+}
+
+/// Rotations, taking the shorter way around
+impl Tweenable for Quaternion<f32> {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        let other = if self.dot(*other) < 0.0 { -*other } else { *other };
+        self.normalize().slerp(other.normalize(), t)
+    }
+}
+
+impl Tweenable for TransformQ {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        self.slerp(other, t)
+    }
+}
+
+/// Decomposed into a `TransformQ` so rotations stay rotations part way through,
+/// falling back to interpolating each matrix when a part can't be decomposed
+impl Tweenable for Transform<Matrix4<f32>> {
+    fn tween(&self, other: &Self, t: f32) -> Self {
+        match (TransformQ::from_transform(self), TransformQ::from_transform(other)) {
+            (Some(a), Some(b)) => a.slerp(&b, t).to_transform(),
+            _ => Transform {
+                scale: self.scale + (other.scale - self.scale) * t,
+                rotate: self.rotate + (other.rotate - self.rotate) * t,
+                translate: self.translate + (other.translate - self.translate) * t,
+            },
+        }
+    }
+}
+
+/// A value shared between whatever draws with it and the `Timeline` animating it, such as an object's `Transform`
+#[derive(Debug, Default)]
+pub struct Animated<T: Copy>(Rc<Cell<T>>);
+
+impl<T: Copy> Clone for Animated<T> {
+    fn clone(&self) -> Self {
+        Animated(self.0.clone())
+    }
+}
+
+impl<T: Copy> Animated<T> {
+    pub fn new(value: T) -> Self {
+        Animated(Rc::new(Cell::new(value)))
+    }
+
+    pub fn get(&self) -> T {
+        self.0.get()
+    }
+
+    pub fn set(&self, value: T) {
+        self.0.set(value)
+    }
+}
+
+/// Something a `Timeline` plays, such as a `Tween` with a target, or a `Sequence` of them
+pub trait Track {
+    /// Moves on by `dt` seconds, returning the seconds left over once it's finished, or `None` while it's still running
+    fn advance(&mut self, dt: f64) -> Option<f64>;
+}
+
+/// Moves a value from `from` to `to` over `duration` seconds.
+///
+/// Tweens are ticked through a `Timeline` once given a target with `to_target` or `on_update`,
+/// or can be advanced by hand with `advance` and read with `value`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tween<T: Tweenable> {
+    pub from: T,
+    pub to: T,
+    pub duration: f64,
+    pub easing: Easing,
+    elapsed: f64,
+}
+
+impl<T: Tweenable> Tween<T> {
+    pub fn new(from: T, to: T, duration: f64) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            easing: Easing::Linear,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// How far through the tween it is, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            1.0
+        } else {
+            (self.elapsed / self.duration).min(1.0) as f32
+        }
+    }
+
+    pub fn value(&self) -> T {
+        self.from.tween(&self.to, self.easing.apply(self.progress()))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Moves on by `dt` seconds, returning the seconds left over once it's finished
+    pub fn advance(&mut self, dt: f64) -> Option<f64> {
+        self.elapsed += dt;
+        if self.is_finished() {
+            Some(self.elapsed - self.duration)
+        } else {
+            None
+        }
+    }
+
+    /// Makes a track which writes the tween's value into `target` every tick
+    pub fn to_target(self, target: &Animated<T>) -> TweenTrack<T> where T: 'static {
+        let target = target.clone();
+        self.on_update(move |x| target.set(x))
+    }
+
+    /// Makes a track which calls `f` with the tween's value every tick
+    pub fn on_update<F: FnMut(T) + 'static>(self, f: F) -> TweenTrack<T> {
+        TweenTrack {
+            tween: self,
+            target: Box::new(f),
+        }
+    }
+}
+
+/// A `Tween` with somewhere to write its value, see `Tween::to_target` and `Tween::on_update`
+pub struct TweenTrack<T: Tweenable> {
+    tween: Tween<T>,
+    target: Box<dyn FnMut(T)>,
+}
+
+impl<T: Tweenable> Track for TweenTrack<T> {
+    fn advance(&mut self, dt: f64) -> Option<f64> {
+        let left = self.tween.advance(dt);
+        (self.target)(self.tween.value());
+        left
+    }
+}
+
+/// Waits for a number of seconds, as a gap in a `Sequence`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Delay(pub f64);
+
+impl Track for Delay {
+    fn advance(&mut self, dt: f64) -> Option<f64> {
+        self.0 -= dt;
+        if self.0 <= 0.0 {
+            Some(-self.0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Calls a function once, taking no time, such as to start a sound at the end of a `Sequence`
+pub struct Call(Option<Box<dyn FnOnce()>>);
+
+impl Call {
+    pub fn new<F: FnOnce() + 'static>(f: F) -> Self {
+        Call(Some(Box::new(f)))
+    }
+}
+
+impl Track for Call {
+    fn advance(&mut self, dt: f64) -> Option<f64> {
+        if let Some(f) = self.0.take() {
+            f();
+        }
+        Some(dt)
+    }
+}
+
+/// Plays tracks one after another. Time left over by a track which finishes part way through a tick goes to the next
+#[derive(Default)]
+pub struct Sequence {
+    tracks: Vec<Box<dyn Track>>,
+    current: usize,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then<T: Track + 'static>(mut self, track: T) -> Self {
+        self.tracks.push(Box::new(track));
+        self
+    }
+}
+
+impl Track for Sequence {
+    fn advance(&mut self, mut dt: f64) -> Option<f64> {
+        while let Some(track) = self.tracks.get_mut(self.current) {
+            match track.advance(dt) {
+                Some(left) => {
+                    dt = left;
+                    self.current += 1;
+                },
+                None => return None,
+            }
+        }
+        Some(dt)
+    }
+}
+
+/// Plays tracks at the same time, finishing once they all have
+#[derive(Default)]
+pub struct Parallel {
+    tracks: Vec<(Box<dyn Track>, bool)>,
+}
+
+impl Parallel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<T: Track + 'static>(mut self, track: T) -> Self {
+        self.tracks.push((Box::new(track), false));
+        self
+    }
+}
+
+impl Track for Parallel {
+    fn advance(&mut self, dt: f64) -> Option<f64> {
+        let mut left = dt;
+        let mut finished = true;
+        for (track, done) in &mut self.tracks {
+            if *done {
+                continue;
+            }
+            match track.advance(dt) {
+                Some(x) => {
+                    *done = true;
+                    left = left.min(x);
+                },
+                None => finished = false,
+            }
+        }
+        if finished {
+            Some(left)
+        } else {
+            None
+        }
+    }
+}
+
+/// An animation playing on a `Timeline`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnimationId(u64);
+
+/// Plays tracks, each until it finishes.
+///
+/// `AppContainer` ticks the one in `AppConfig::timeline` after every update, with the
+/// scaled time step, so animations slow down and pause along with the game
+#[derive(Default)]
+pub struct Timeline {
+    tracks: Vec<(AnimationId, Box<dyn Track>)>,
+    next_id: u64,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts playing `track` from the next tick
+    pub fn play<T: Track + 'static>(&mut self, track: T) -> AnimationId {
+        let id = AnimationId(self.next_id);
+        self.next_id += 1;
+        self.tracks.push((id, Box::new(track)));
+        id
+    }
+
+    /// Stops an animation where it is, returning whether it was still playing
+    pub fn stop(&mut self, id: AnimationId) -> bool {
+        let len = self.tracks.len();
+        self.tracks.retain(|x| x.0 != id);
+        self.tracks.len() != len
+    }
+
+    pub fn is_playing(&self, id: AnimationId) -> bool {
+        self.tracks.iter().any(|x| x.0 == id)
+    }
+
+    /// The number of animations playing
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+
+    /// Moves every animation on by `dt` seconds, dropping those which finish
+    pub fn advance(&mut self, dt: f64) {
+        let mut i = 0;
+        while i < self.tracks.len() {
+            if self.tracks[i].1.advance(dt).is_some() {
+                self.tracks.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
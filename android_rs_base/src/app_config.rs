@@ -1,19 +1,131 @@
+use piston::input::{Button, Key};
+use crate::touch::TouchFilter;
+use crate::events::{CustomEvent, EventRegistry};
+use crate::input::{PointerHistory, TouchState};
+use crate::gestures::GestureConfig;
+use crate::boot::BootConfig;
+use crate::features::FeatureFlags;
+use crate::animation::Timeline;
+use crate::profiler::FrameProfiler;
+use opengl_graphics::StateAudit;
+use std::any::Any;
+
 /// Configuration for running an app in an `AppContainer<T>`
 pub struct AppConfig {
     pub(crate) num_frames: Option<usize>,
     pub passed_frames: u32, //Max 2.2yrs at 60fps... Kind of overkill
     pub reset_on_start: bool,
+    pub(crate) benchmark_on_first_launch: bool,
+    pub(crate) performance_score: Option<f64>,
+    pub(crate) boot_asset: Option<String>,
+    pub(crate) boot: BootConfig,
+    pub(crate) updates_per_second: u64,
+    pub(crate) max_fps: u64,
+    pub(crate) refresh_rate: Option<f32>,
+    pub(crate) interpolation: f64,
+    pub(crate) fixed_dt: Option<f64>,
+    pub(crate) fixed_accumulator: f64,
+    pub(crate) max_fixed_steps: usize,
+    pub(crate) time_scale: f64,
+    pub(crate) paused: bool,
+    pub(crate) real_dt: f64,
+    pub(crate) real_time: f64,
+    pub(crate) scaled_time: f64,
+    pub(crate) frame_step: bool,
+    pub(crate) pending_steps: usize,
+    pub(crate) step_button: Option<Button>,
+    pub(crate) clear_color: Option<[f32; 4]>,
+    pub(crate) clear_depth: bool,
+    pub(crate) depth_bits: Option<u8>,
+    pub(crate) gles_version: Option<(u8, u8)>,
+    pub(crate) gl_version: (u8, u8),
+    pub(crate) title: String,
+    pub(crate) fullscreen: bool,
+    pub(crate) vsync: bool,
+    pub(crate) samples: u8,
+    pub(crate) srgb: bool,
+    pub(crate) depth_test: bool,
+    pub(crate) state_audit: bool,
+    pub(crate) last_state_audit: Option<StateAudit>,
+    pub(crate) clear_stencil: Option<u8>,
+    pub(crate) capture_requested: bool,
+    pub(crate) recording: bool,
+    pub(crate) pointer_captured: bool,
+    pub(crate) late_input_sampling: bool,
+    pub(crate) shader_hot_reload: bool,
+    pub(crate) latest_pointer: Option<[f64; 2]>,
+    pub(crate) touch_state: TouchState,
+    pub(crate) touch_filter: Option<TouchFilter>,
+    pub(crate) gesture_config: GestureConfig,
+    pub(crate) events: EventRegistry,
+    pub(crate) posted_events: Vec<CustomEvent>,
+    pub(crate) features: FeatureFlags,
+    pub(crate) timeline: Timeline,
+    pub(crate) profiler: FrameProfiler,
 }
 
 impl AppConfig {
     /// Standard config: 
     /// `frames` = `None` to make it run until told not to
     /// `reset_on_start` = `true`
+    /// `benchmark_on_first_launch` = `false`
+    /// `update_hz` = `120`
+    /// `render_hz` = `60`
+    /// `time_scale` = `1.0`, not paused
+    /// `frame_step_mode` = `false`, stepped with `F10`
+    /// `clear_color` = `None`, `clear_depth` = `false`, `clear_stencil` = `None`, so nothing is cleared
     pub fn new() -> Self {
         Self {
             num_frames: None,
             passed_frames: 0,
-            reset_on_start: true
+            reset_on_start: true,
+            benchmark_on_first_launch: false,
+            performance_score: None,
+            boot_asset: None,
+            boot: BootConfig::default(),
+            updates_per_second: 120,
+            max_fps: 60,
+            refresh_rate: None,
+            interpolation: 0.0,
+            fixed_dt: None,
+            fixed_accumulator: 0.0,
+            max_fixed_steps: 8,
+            time_scale: 1.0,
+            paused: false,
+            real_dt: 0.0,
+            real_time: 0.0,
+            scaled_time: 0.0,
+            frame_step: false,
+            pending_steps: 0,
+            step_button: Some(Button::Keyboard(Key::F10)),
+            clear_color: None,
+            clear_depth: false,
+            depth_bits: Some(24),
+            gles_version: if cfg!(target_os = "android") { Some((3, 0)) } else { None },
+            gl_version: (3, 2),
+            title: "rust app".to_string(),
+            fullscreen: true,
+            vsync: false,
+            samples: 0,
+            srgb: true,
+            depth_test: false,
+            state_audit: false,
+            last_state_audit: None,
+            clear_stencil: None,
+            capture_requested: false,
+            recording: false,
+            pointer_captured: false,
+            late_input_sampling: false,
+            shader_hot_reload: false,
+            latest_pointer: None,
+            touch_state: TouchState::new(),
+            touch_filter: None,
+            gesture_config: GestureConfig::new(),
+            events: EventRegistry::new(),
+            posted_events: Vec::new(),
+            features: FeatureFlags::new(),
+            timeline: Timeline::new(),
+            profiler: FrameProfiler::new(),
         }
     }
     /// Sets or resets the number of frames to be run
@@ -27,4 +139,341 @@ impl AppConfig {
         self.reset_on_start = doit;
         self
     }
+    /// When set to true, `AppContainer::init` renders a short benchmark the first time
+    /// the app is launched and stores the resulting score in the app's `Preferences`
+    pub fn benchmark_on_first_launch(mut self, doit: bool) -> Self {
+        self.benchmark_on_first_launch = doit;
+        self
+    }
+    /// Reads the `BootConfig` in the asset `name` in `AppContainer::init`, its settings override those set here. `init` fails when it can't be read
+    pub fn boot_config(mut self, name: &str) -> Self {
+        self.boot_asset = Some(name.to_string());
+        self
+    }
+    /// Gets the values read from the boot config asset, empty if there isn't one
+    pub fn boot(&self) -> &BootConfig {
+        &self.boot
+    }
+    /// Sets how many times per second `AppImpl::update` is called, independently of the render rate.
+    /// Can be changed while running
+    pub fn update_hz(mut self, hz: u64) -> Self {
+        self.set_update_hz(hz);
+        self
+    }
+    /// Sets the maximum number of frames rendered per second, independently of the update rate.
+    /// Can be changed while running
+    pub fn render_hz(mut self, hz: u64) -> Self {
+        self.set_render_hz(hz);
+        self
+    }
+    /// Changes the update rate of a running app, see `update_hz`
+    pub fn set_update_hz(&mut self, hz: u64) {
+        self.updates_per_second = hz.max(1);
+    }
+    /// Changes the render rate of a running app, see `render_hz`
+    pub fn set_render_hz(&mut self, hz: u64) {
+        self.max_fps = hz.max(1);
+    }
+    /// Requests a display refresh rate such as 90 or 120Hz and renders at that rate, `None` to
+    /// leave the display as the system sets it. Only honoured on Android 11 and later, see `request_frame_rate`.
+    /// Can be changed while running
+    pub fn refresh_rate(mut self, hz: Option<f32>) -> Self {
+        self.set_refresh_rate(hz);
+        self
+    }
+    /// Changes the requested refresh rate of a running app, see `refresh_rate`
+    pub fn set_refresh_rate(&mut self, hz: Option<f32>) {
+        self.refresh_rate = hz;
+        if let Some(hz) = hz {
+            self.set_render_hz(hz.round() as u64);
+        }
+    }
+    /// Gets the requested refresh rate
+    pub fn get_refresh_rate(&self) -> Option<f32> {
+        self.refresh_rate
+    }
+    /// How far between the last update and the next one the current frame is being drawn, in `[0, 1]`.
+    /// Use it in `draw_2d`/`draw_shaded` to interpolate between the previous and current state when
+    /// rendering faster than updating. With a fixed timestep this is between fixed updates instead
+    pub fn interpolation(&self) -> f64 {
+        self.interpolation
+    }
+    /// Calls `AppImpl::fixed_update` `hz` times per second of scaled time with a constant `dt`,
+    /// as many times as needed after each update, for deterministic physics
+    pub fn fixed_update_hz(mut self, hz: u64) -> Self {
+        self.set_fixed_update_hz(Some(hz));
+        self
+    }
+    /// Changes or (with `None`) stops the fixed timestep of a running app
+    pub fn set_fixed_update_hz(&mut self, hz: Option<u64>) {
+        self.fixed_dt = hz.filter(|&x| x > 0).map(|x| 1.0 / x as f64);
+        self.fixed_accumulator = 0.0;
+    }
+    /// Gets the constant `dt` passed to `AppImpl::fixed_update`, if a fixed timestep is set
+    pub fn get_fixed_dt(&self) -> Option<f64> {
+        self.fixed_dt
+    }
+    /// Sets the most fixed updates run after a single update (`8` by default). Time past this is dropped
+    /// so a slow device falls behind instead of spending ever longer catching up
+    pub fn max_fixed_steps(mut self, steps: usize) -> Self {
+        self.max_fixed_steps = steps.max(1);
+        self
+    }
+    /// Sets the factor `UpdateArgs::dt` is multiplied by before being passed to `AppImpl::update`,
+    /// for slow-motion or fast-forward effects
+    pub fn time_scale(mut self, scale: f64) -> Self {
+        self.set_time_scale(scale);
+        self
+    }
+    /// Changes the time scale of a running app, see `time_scale`
+    pub fn set_time_scale(&mut self, scale: f64) {
+        self.time_scale = scale.max(0.0);
+    }
+    /// Gets the current time scale
+    pub fn get_time_scale(&self) -> f64 {
+        self.time_scale
+    }
+    /// Pauses or unpauses the app. `AppImpl::update` is still called while paused, but with a `dt` of `0`,
+    /// so things driven by `real_dt` (like a pause menu) keep animating
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+    /// Whether the app is currently paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    /// The unscaled time passed since the last update, regardless of time scale or pausing
+    pub fn real_dt(&self) -> f64 {
+        self.real_dt
+    }
+    /// The unscaled time passed since the app started running
+    pub fn real_time(&self) -> f64 {
+        self.real_time
+    }
+    /// The scaled time passed since the app started running, this is the sum of all `dt`s given to `update`
+    pub fn scaled_time(&self) -> f64 {
+        self.scaled_time
+    }
+    /// When set to true, `update` and `fixed_update` are suspended and only run once per call to `step`
+    /// or press of the step button, so per-frame logic can be inspected one frame at a time
+    pub fn frame_step_mode(mut self, doit: bool) -> Self {
+        self.set_frame_step_mode(doit);
+        self
+    }
+    /// Enables or disables frame stepping on a running app, see `frame_step_mode`
+    pub fn set_frame_step_mode(&mut self, doit: bool) {
+        self.frame_step = doit;
+        self.pending_steps = 0;
+    }
+    /// Whether the app is currently in frame stepping mode
+    pub fn is_frame_stepping(&self) -> bool {
+        self.frame_step
+    }
+    /// Sets the button which advances one frame when in frame stepping mode, `None` to only step through `step`
+    pub fn step_button(mut self, button: Option<Button>) -> Self {
+        self.step_button = button;
+        self
+    }
+    /// Advances exactly one update and render when in frame stepping mode, does nothing otherwise
+    pub fn step(&mut self) {
+        if self.frame_step {
+            self.pending_steps += 1;
+        }
+    }
+    /// Sets the colour the screen is cleared to before each frame is drawn, leave as `None`
+    /// to keep the previous frame's contents (Or to clear manually in `draw_2d`)
+    pub fn clear_color(mut self, color: Option<[f32; 4]>) -> Self {
+        self.clear_color = color;
+        self
+    }
+    /// When set to true, the depth buffer is cleared to `1.0` before each frame is drawn
+    pub fn clear_depth(mut self, doit: bool) -> Self {
+        self.clear_depth = doit;
+        self
+    }
+    /// Sets the number of bits of depth buffer requested from the window, `None` to not request one.
+    /// Defaults to 24
+    pub fn depth_bits(mut self, bits: Option<u8>) -> Self {
+        self.depth_bits = bits;
+        self
+    }
+    /// Sets the OpenGL ES version requested from the window as `(major, minor)`, `None` to ask for
+    /// desktop OpenGL 3.2 first. Defaults to OpenGL ES 3.0 on Android, and `None` elsewhere.
+    ///
+    /// `GlGraphics` picks its shaders to suit whichever context it gets, see `opengl_graphics::Profile`
+    pub fn gles_version(mut self, version: Option<(u8, u8)>) -> Self {
+        self.gles_version = version;
+        self
+    }
+    /// Sets the desktop OpenGL version requested from the window when `gles_version` is `None`. Defaults to 3.2
+    pub fn gl_version(mut self, version: (u8, u8)) -> Self {
+        self.gl_version = version;
+        self
+    }
+    /// Sets the window's title, only seen on desktop. Defaults to "rust app"
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+    /// Sets whether the window covers the whole screen. Defaults to true
+    pub fn fullscreen(mut self, doit: bool) -> Self {
+        self.fullscreen = doit;
+        self
+    }
+    /// Sets whether buffer swaps wait for the display's vertical blank, which stops tearing
+    /// but caps the frame rate to the refresh rate. Defaults to false
+    pub fn vsync(mut self, doit: bool) -> Self {
+        self.vsync = doit;
+        self
+    }
+    /// Sets the number of samples per pixel for multisample anti-aliasing, `0` to turn it off.
+    /// Falls back to no anti-aliasing when the device can't create a context with this many. Defaults to 0
+    pub fn samples(mut self, samples: u8) -> Self {
+        self.samples = samples;
+        self
+    }
+    /// Sets whether the framebuffer is sRGB, so colours written to it are gamma corrected. Defaults to true
+    pub fn srgb(mut self, doit: bool) -> Self {
+        self.srgb = doit;
+        self
+    }
+    /// When set to true, 3D drawn with custom shaders in `draw_shaded` is depth tested (see `GlGraphics::set_depth`),
+    /// and the depth buffer is cleared every frame
+    pub fn depth_test(mut self, doit: bool) -> Self {
+        self.depth_test = doit;
+        if doit {
+            self.clear_depth = true;
+        }
+        self
+    }
+    /// When set to true, the programs, textures and draw states bound while drawing are counted
+    /// (see `GlGraphics::set_state_audit`), to find redundant binds worth batching away
+    pub fn state_audit(mut self, doit: bool) -> Self {
+        self.state_audit = doit;
+        self
+    }
+    /// Gets the binds counted while drawing the previous frame, if `state_audit` is on
+    pub fn get_state_audit(&self) -> Option<StateAudit> {
+        self.last_state_audit
+    }
+    /// Sets the value the stencil buffer is cleared to before each frame is drawn, `None` to not clear it
+    pub fn clear_stencil(mut self, value: Option<u8>) -> Self {
+        self.clear_stencil = value;
+        self
+    }
+    /// Changes the clear colour of a running app, see `clear_color`
+    pub fn set_clear_color(&mut self, color: Option<[f32; 4]>) {
+        self.clear_color = color;
+    }
+    /// Captures the next frame once it has been drawn, passing it to `AppImpl::on_frame_captured`
+    /// a frame or two later, once the GPU has finished with it
+    pub fn capture_frame(&mut self) {
+        self.capture_requested = true;
+    }
+    /// Captures every frame while `recording`, passing each to `AppImpl::on_frame_captured`.
+    /// Frames are read back without waiting on the GPU, and are skipped when the app
+    /// falls behind collecting them rather than slowing down drawing
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+    /// Captures (or releases) the mouse when one is attached, hiding the system cursor and
+    /// sending `Motion::MouseRelative` events instead of positions, for FPS-style controls
+    pub fn set_pointer_capture(&mut self, capture: bool) {
+        self.pointer_captured = capture;
+    }
+    /// Whether the mouse is captured, see `set_pointer_capture`
+    pub fn is_pointer_captured(&self) -> bool {
+        self.pointer_captured
+    }
+    /// When set to true, input which arrived since the last update is handled right before
+    /// drawing too, so drawing apps can follow the finger with less latency
+    pub fn late_input_sampling(mut self, doit: bool) -> Self {
+        self.late_input_sampling = doit;
+        self
+    }
+    /// When set to true, shaders registered with `ShaderStorage::watch` are recompiled before
+    /// drawing whenever their sources change on the device, compile errors go to the Android log
+    pub fn shader_hot_reload(mut self, doit: bool) -> Self {
+        self.shader_hot_reload = doit;
+        self
+    }
+    /// The most recent mouse or touch position, including input sampled just before drawing
+    pub fn latest_pointer(&self) -> Option<[f64; 2]> {
+        self.latest_pointer
+    }
+    /// The recent samples of every pointer with their timestamps, for velocity and gesture code
+    pub fn pointer_history(&self) -> &PointerHistory {
+        self.touch_state.history()
+    }
+    /// Every touch on the screen with its phase, also passed to `AppImpl::touch`
+    pub fn touch_state(&self) -> &TouchState {
+        &self.touch_state
+    }
+    /// Sets how long pointer samples are kept in `pointer_history`, in milliseconds
+    pub fn set_pointer_history_length(&mut self, ms: f64) {
+        self.touch_state.history_mut().max_age_ns = (ms * 1e6) as u64;
+    }
+    /// Runs every touch through `filter` before it reaches the app, remapping pressure and dropping palm touches
+    pub fn touch_filter(mut self, filter: Option<TouchFilter>) -> Self {
+        self.touch_filter = filter;
+        self
+    }
+    /// Gets the touch filter to change its settings while running
+    pub fn get_touch_filter(&mut self) -> Option<&mut TouchFilter> {
+        self.touch_filter.as_mut()
+    }
+    /// Sets the thresholds taps, swipes, pinches and long presses are recognised with
+    pub fn gesture_config(mut self, config: GestureConfig) -> Self {
+        self.gesture_config = config;
+        self
+    }
+    /// Gets the gesture thresholds to change them while running
+    pub fn get_gesture_config(&mut self) -> &mut GestureConfig {
+        &mut self.gesture_config
+    }
+    /// Gets the custom event types the app accepts, register each with `events().register::<T>()`
+    pub fn events(&mut self) -> &mut EventRegistry {
+        &mut self.events
+    }
+    /// Posts a custom event, delivered to `AppImpl::handle_custom_event` (or the user thread) after the current update.
+    /// Panics if `T` wasn't registered through `events`
+    pub fn post_event<T: Any + Send + Sync>(&mut self, value: T) {
+        assert!(self.events.is_registered::<T>(), "Posted unregistered event type {}", std::any::type_name::<T>());
+        self.posted_events.push(CustomEvent::new(value));
+    }
+    /// Adds a feature flag which is `enabled` unless overridden or fetched remotely
+    pub fn feature_flag(mut self, name: &str, enabled: bool) -> Self {
+        self.features.set_default(name, enabled);
+        self
+    }
+    /// Gets the feature flags, to check them with `features().is_enabled(name)`
+    pub fn features(&mut self) -> &mut FeatureFlags {
+        &mut self.features
+    }
+    /// Gets the timeline to play animations on, which is ticked after every update
+    pub fn timeline(&mut self) -> &mut Timeline {
+        &mut self.timeline
+    }
+    /// When set to true, every frame is timed on the CPU and GPU, see `FrameProfiler`
+    pub fn profiling(mut self, doit: bool) -> Self {
+        self.profiler.set_enabled(doit);
+        self
+    }
+    /// When set to true, frame times are drawn over the app, which also turns on profiling
+    pub fn profiling_overlay(mut self, doit: bool) -> Self {
+        self.profiler.set_overlay(doit);
+        self
+    }
+    /// Gets the frame profiler, to read its timings or turn it and its overlay on and off while running
+    pub fn profiler(&mut self) -> &mut FrameProfiler {
+        &mut self.profiler
+    }
+    /// The device performance score measured by the first launch benchmark, if it has been run.
+    /// Measured in thousands of rectangles drawn per second, so higher is better
+    pub fn performance_score(&self) -> Option<f64> {
+        self.performance_score
+    }
 }
@@ -1,14 +1,25 @@
 use crate::app_implementor::*;
 use crate::app_config::*;
 use crate::InputEvent;
-use piston::window::{WindowSettings, OpenGLWindow};
+use crate::events::CustomEvent;
+use crate::gestures::{Gesture, GestureRecognizer};
+use crate::display::request_frame_rate;
+use crate::boot::BootConfig;
+use piston::window::{Api, WindowSettings, Window, OpenGLWindow, AdvancedWindow};
 use piston::event_loop::*;
 use piston::input::*;
 use glutin_window::GlutinWindow;
-use opengl_graphics::{ GlGraphics, OpenGL };
+use opengl_graphics::{ GlGraphics, OpenGL, AsyncReadback };
 use std::thread::JoinHandle;
 use android_glue;
 use crate::storage::{ShaderStorage, ShaderContext};
+use crate::preferences::Preferences;
+use crate::assets::Assets;
+use crate::features::FeatureFlags;
+use crate::state::StateStore;
+use crate::benchmark::{self, PERFORMANCE_SCORE_KEY};
+use crate::profiler::{FrameProfiler, ProfileScope};
+use std::time::{Duration, Instant};
 
 /// A utility struct for running an android application, to not have to worry about the minor
 /// android-specific details when running and rendering an app with piston
@@ -22,7 +33,15 @@ pub struct AppContainer<T: AppImpl> {
     config: AppConfig,
     thread: Option<(JoinHandle<()>, std::sync::mpsc::Sender<InputEvent>)>,
     storage: ShaderStorage,
-
+    preferences: Preferences,
+    assets: Assets,
+    state: StateStore,
+    pointer_captured: bool,
+    start: Instant,
+    gestures: GestureRecognizer,
+    applied_refresh_rate: Option<f32>,
+    readback: Option<AsyncReadback>,
+    gpu_timing: bool,
 }
 
 impl<T: AppImpl> AppContainer<T> {
@@ -31,26 +50,63 @@ impl<T: AppImpl> AppContainer<T> {
     /// `app: T`: an instance of your struct which implements `AppImpl`
     /// `config: AppConfig`: a configuration setting with which to run your app like number of frames or reset options
     /// In more detail:
-    /// 1. Creates a `GlutinWindow`
-    /// 2. Loads Opengl pointers using the window's address
-    /// 3. Prepares channels for use with `android_glue`
-    /// 4. Creates an instance of `AppContainer` and fills in some other members
-    pub fn init(config: AppConfig, data: T::InitializationData) -> Self {
+    /// 1. Reads the boot config asset, if one was set, into `config`
+    /// 2. Creates a `GlutinWindow`
+    /// 3. Loads Opengl pointers using the window's address
+    /// 4. Prepares channels for use with `android_glue`
+    /// 5. Loads the persisted feature flags, and runs the performance benchmark if requested and it hasn't been run before
+    /// 6. Loads the saved `StateStore` and creates the app with it
+    /// 7. Creates an instance of `AppContainer` and fills in some other members
+    ///
+    /// Fails when the boot config can't be read, or the app's internal storage can't be created or written to
+    pub fn init(mut config: AppConfig, data: T::InitializationData) -> Result<Self, String> {
+        if let Some(name) = config.boot_asset.clone() {
+            let boot = BootConfig::load(&name).map_err(|e| format!("Could not read boot config: {}", e))?;
+            boot.merge_into(&mut config);
+            config.boot = boot;
+        }
         let (sender, receiver) = std::sync::mpsc::channel();
         android_glue::add_sender(sender);
-        let mut window: GlutinWindow = WindowSettings::new(
-                "rust app", (200.0, 200.0)
+        let opengl = desktop_opengl(config.gl_version);
+        let api = match config.gles_version {
+            Some((major, minor)) => Api { api: "OpenGL ES".into(), major: major as u32, minor: minor as u32 },
+            None => opengl.into(),
+        };
+        let settings = WindowSettings::new(
+                config.title.clone(), (200.0, 200.0)
             )
-            .fullscreen(true)
-            .graphics_api(OpenGL::V3_2)
-            .build()
-            .unwrap();
+            .fullscreen(config.fullscreen)
+            .vsync(config.vsync)
+            .samples(config.samples)
+            .srgb(config.srgb)
+            .graphics_api(api);
+        let mut window = GlutinWindow::with_depth_buffer(&settings, config.depth_bits).map_err(|e| e.to_string())?;
         opengl_graphics::gl::load_with(|x| window.get_proc_address(x) as *const _);
-        let mut gl = GlGraphics::new(OpenGL::V3_2);
-        let events = Events::new(EventSettings::new());
+        let mut gl = GlGraphics::new(opengl);
+        gl.set_depth(config.depth_test);
+        gl.set_state_audit(config.state_audit);
+        let gpu_timing = config.profiler.is_enabled();
+        gl.set_gpu_timing(gpu_timing);
+        let storage_path = crate::internal_storage_path();
+        std::fs::create_dir_all(&storage_path).map_err(|e| format!("Could not create internal storage {:?}: {}", storage_path, e))?;
+        let mut preferences = Preferences::load(storage_path.join("preferences"));
+        config.performance_score = preferences.get(PERFORMANCE_SCORE_KEY);
+        config.features.attach_storage(Preferences::load(storage_path.join("feature_flags")))?;
+        if config.benchmark_on_first_launch && config.performance_score.is_none() {
+            let score = benchmark::run(&mut window, &mut gl, Duration::from_secs(1));
+            preferences.set(PERFORMANCE_SCORE_KEY, score);
+            preferences.save().map_err(|e| format!("Could not save performance score: {}", e))?;
+            config.performance_score = Some(score);
+        }
+        let events = Events::new(
+            EventSettings::new()
+                .ups(config.updates_per_second)
+                .max_fps(config.max_fps)
+        );
         let mut shaders = ShaderStorage::new();
-        let app = T::new(&mut gl, data, &mut shaders);
-        Self {
+        let state = StateStore::load(storage_path.join("state.json"));
+        let app = T::new(&mut gl, data, &mut shaders, &state);
+        Ok(Self {
             native_event_reciever: receiver,
             window,
             app: Some(app),
@@ -60,7 +116,41 @@ impl<T: AppImpl> AppContainer<T> {
             config,
             thread: None,
             storage: shaders,
-        }
+            preferences,
+            assets: Assets::new(),
+            state,
+            pointer_captured: false,
+            start: Instant::now(),
+            gestures: GestureRecognizer::new(),
+            applied_refresh_rate: None,
+            readback: None,
+            gpu_timing,
+        })
+    }
+
+    /// Gets the persistent preferences of the app, which includes the benchmark's performance score
+    pub fn preferences(&mut self) -> &mut Preferences {
+        &mut self.preferences
+    }
+
+    /// Gets the state saved across the app being closed, which is also given to `AppImpl::signal_pause` and `AppImpl::on_die`
+    pub fn state(&mut self) -> &mut StateStore {
+        &mut self.state
+    }
+
+    /// Gets the app's feature flags, also available through `AppConfig::features`
+    pub fn features(&mut self) -> &mut FeatureFlags {
+        &mut self.config.features
+    }
+
+    /// Gets the frame profiler, also available through `AppConfig::profiler`
+    pub fn profiler(&mut self) -> &mut FrameProfiler {
+        &mut self.config.profiler
+    }
+
+    /// Gets the app's assets, also available while drawing through `ShaderContext::assets`
+    pub fn assets(&self) -> &Assets {
+        &self.assets
     }
 
     /// Prepares for draw, and then calls `self.app.draw` with the parameters it prepared
@@ -68,7 +158,15 @@ impl<T: AppImpl> AppContainer<T> {
         let app_ref = self.app.as_mut().unwrap();
         let ws_ref = &mut self.window_size;
         let sh_ref = &mut self.storage;
+        let assets_ref = &self.assets;
         self.config.passed_frames += 1;
+        self.config.interpolation = match self.config.fixed_dt {
+            Some(dt) => {
+                let scale = if self.config.paused { 0.0 } else { self.config.time_scale };
+                ((self.config.fixed_accumulator + rargs.ext_dt * scale) / dt).min(1.0)
+            },
+            None => (rargs.ext_dt * self.config.updates_per_second as f64).min(1.0),
+        };
         let cfg_ref = &mut self.config;
         if *ws_ref != (rargs.draw_size[0] as usize, rargs.draw_size[1] as usize) {
             let size_new = (rargs.draw_size[0] as usize, rargs.draw_size[1] as usize);
@@ -76,10 +174,43 @@ impl<T: AppImpl> AppContainer<T> {
             *ws_ref = size_new;
         }
 
+        if cfg_ref.shader_hot_reload {
+            if let Err(e) = sh_ref.reload_changed(&mut self.gl) {
+                android_glue::write_log(&e);
+            }
+        }
+        let depth = if cfg_ref.clear_depth { Some(1.0) } else { None };
+        let (color, stencil) = (cfg_ref.clear_color, cfg_ref.clear_stencil);
+        let readback_ref = &mut self.readback;
         self.gl.draw(rargs.viewport(), |c, gl| {
+            cfg_ref.profiler.begin(ProfileScope::Draw);
+            gl.clear_buffers(color, depth, stencil);
             app_ref.draw_2d(c, gl, rargs.clone(), cfg_ref);
-            app_ref.draw_shaded(ShaderContext::new(sh_ref, gl, c, rargs));
+            app_ref.draw_shaded(ShaderContext::new(sh_ref, assets_ref, gl, c, rargs));
+            app_ref.draw_overlay_2d(c, gl, rargs, cfg_ref);
+            cfg_ref.profiler.end(ProfileScope::Draw);
+            if cfg_ref.profiler.is_overlay_shown() {
+                cfg_ref.profiler.draw_overlay(&c, gl);
+            }
+            if cfg_ref.capture_requested || cfg_ref.recording {
+                let readback = readback_ref.get_or_insert_with(|| AsyncReadback::new(3));
+                // A requested capture waits for a free buffer, recorded frames are dropped instead
+                if gl.read_pixels_async(readback, rargs.viewport().rect) {
+                    cfg_ref.capture_requested = false;
+                }
+            }
+            // Ended once the buffers have been swapped
+            cfg_ref.profiler.begin(ProfileScope::Flush);
         });
+        cfg_ref.last_state_audit = self.gl.take_state_audit();
+        if let Some(timing) = self.gl.take_gpu_timing() {
+            cfg_ref.profiler.record_gpu(timing);
+        }
+        if let Some(readback) = &mut self.readback {
+            while let Some(image) = readback.poll() {
+                app_ref.on_frame_captured(image);
+            }
+        }
     }
 
     /// Suspends thread until we get a GainedFocus
@@ -92,11 +223,17 @@ impl<T: AppImpl> AppContainer<T> {
             match recieved {
                 Ok(x) => match x {
                     Event::GainedFocus => { break; },
-                    _ => {}
+                    x => {
+                        self.handle_lifecycle_event(x);
+                        if self.app.is_none() {
+                            break;
+                        }
+                    }
                 },
                 Err(_) => {
                     let app = self.app.take().unwrap();
-                    app.on_die();
+                    app.on_die(&mut self.state);
+                    self.save_state();
                     break;
                 }
             }
@@ -107,7 +244,7 @@ impl<T: AppImpl> AppContainer<T> {
     fn poll_android_events(&mut self) {
         use android_glue::Event;
         let mut flag = false;
-        for event in self.native_event_reciever.try_iter(){
+        while let Ok(event) = self.native_event_reciever.try_recv() {
             match event {
                 Event::LostFocus => {
                     flag = true;
@@ -115,49 +252,225 @@ impl<T: AppImpl> AppContainer<T> {
                 },
                 Event::EventMotion(_) => {/*These are already passed in by piston*/},
                 misc => {
-                    self.app.as_mut().map(move |app| app.handle_android_event(misc));
+                    if !self.handle_lifecycle_event(misc) {
+                        self.app.as_mut().map(move |app| app.handle_android_event(misc));
+                    }
                 }
             }
         }
+        if self.app.is_none() {
+            return;
+        }
         if flag {
-            self.app.as_mut().map(|app| app.signal_pause());
+            let state = &mut self.state;
+            self.app.as_mut().map(|app| app.signal_pause(state));
+            self.save_state();
             self.wait_until_gain_focus();
+            // The window may have been recreated, so requests made to the old one are lost
+            self.applied_refresh_rate = None;
             self.app.as_mut().map(|app| app.refresh());
         }
     }
 
+    /// Passes lifecycle events on to their `AppImpl` callbacks, returning whether `event` was one of them
+    fn handle_lifecycle_event(&mut self, event: android_glue::Event) -> bool {
+        use android_glue::Event;
+        match event {
+            Event::Pause => { self.app.as_mut().map(|app| app.on_pause()); },
+            Event::Resume => { self.app.as_mut().map(|app| app.on_resume()); },
+            Event::LowMemory => { self.app.as_mut().map(|app| app.on_low_memory()); },
+            // android_glue doesn't say which key it was, so the app only hears of releases
+            Event::EventKeyDown => {},
+            Event::EventKeyUp => {
+                if !self.app.as_mut().map(|app| app.on_key_released()).unwrap_or(true) {
+                    self.shut_down();
+                }
+            },
+            Event::Destroy => self.shut_down(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Calls `on_destroy` and `on_die` on the app, after which `run` returns
+    fn shut_down(&mut self) {
+        if let Some(mut app) = self.app.take() {
+            app.on_destroy();
+            app.on_die(&mut self.state);
+            self.save_state();
+        }
+    }
+
+    // Runs from lifecycle events with nobody to return an error to, so failures are logged
+    fn save_state(&self) {
+        if let Err(e) = self.state.save() {
+            android_glue::write_log(&format!("Could not save state: {}", e));
+        }
+    }
+
+    /// Applies any changes to the update/render rates made through the config while running
+    fn apply_rates(&mut self) {
+        let settings = self.events.get_event_settings();
+        if settings.ups != self.config.updates_per_second {
+            self.events.set_ups(self.config.updates_per_second);
+        }
+        if settings.max_fps != self.config.max_fps {
+            self.events.set_max_fps(self.config.max_fps);
+        }
+    }
+
+    /// Requests the refresh rate set in the config when it changes
+    fn apply_refresh_rate(&mut self) {
+        if self.applied_refresh_rate != self.config.refresh_rate {
+            self.applied_refresh_rate = self.config.refresh_rate;
+            request_frame_rate(self.config.refresh_rate.unwrap_or(0.0));
+        }
+    }
+
+    /// Starts or stops timing the GPU when profiling is turned on or off, and keeps the frame budget up to date
+    fn apply_profiling(&mut self) {
+        let enabled = self.config.profiler.is_enabled();
+        if self.gpu_timing != enabled {
+            self.gpu_timing = enabled;
+            self.gl.set_gpu_timing(enabled);
+        }
+        self.config.profiler.set_budget(1000.0 / self.config.max_fps as f64);
+    }
+
+    /// Captures or releases the mouse when the config asks for it
+    fn apply_pointer_capture(&mut self) {
+        if self.pointer_captured != self.config.pointer_captured {
+            self.pointer_captured = self.config.pointer_captured;
+            self.window.set_capture_cursor(self.pointer_captured);
+        }
+    }
+
+    /// Passes custom and input events on to the app (or the user thread)
+    fn handle_window_event(&mut self, e: Event) {
+        match e {
+            Event::Custom(id, event, time) => self.dispatch_custom_event(CustomEvent::from_raw(id, event), time),
+            Event::Input(mut input, time) => {
+                let time_ns = self.start.elapsed().as_nanos() as u64;
+                if let (Input::Move(Motion::Touch(args)), Some(filter)) = (&mut input, &mut self.config.touch_filter) {
+                    match filter.filter((*args).into()) {
+                        Some(filtered) => *args = filtered,
+                        None => return,
+                    }
+                }
+                if let Input::Button(ButtonArgs { state: ButtonState::Press, button, .. }) = input {
+                    if Some(button) == self.config.step_button {
+                        self.config.step();
+                    }
+                }
+                match input {
+                    Input::Move(Motion::MouseCursor(position)) => self.config.latest_pointer = Some(position),
+                    Input::Move(Motion::Touch(args)) => self.config.latest_pointer = Some(args.position()),
+                    _ => {}
+                }
+                self.config.touch_state.record(&input, time_ns);
+                if let Input::Move(Motion::Touch(args)) = input {
+                    if self.thread.is_none() {
+                        let touch_state = &self.config.touch_state;
+                        self.app.as_mut().map(|app| app.touch(touch_state));
+                    }
+                    let gesture = self.gestures.touch(&self.config.gesture_config, &args, time_ns as f64 / 1e6);
+                    self.dispatch_gesture(gesture);
+                }
+                if let Some((_, send)) = &mut self.thread {
+                    send.send(InputEvent::Piston(input, time_ns)).expect("Could not send event");
+                } else {
+                    self.app.as_mut().map(|app| app.input(input, time));
+                }
+            },
+            Event::Loop(_) => {}
+        }
+    }
+
+    /// Passes a recognised gesture on to the app
+    fn dispatch_gesture(&mut self, gesture: Option<Gesture>) {
+        if let (Some(gesture), Some(app)) = (gesture, self.app.as_mut()) {
+            match gesture {
+                Gesture::Tap(position) => app.on_tap(position),
+                Gesture::DoubleTap(position) => app.on_double_tap(position),
+                Gesture::Swipe(direction, velocity) => app.on_swipe(direction, velocity),
+                Gesture::Pinch(scale) => app.on_pinch(scale),
+                Gesture::LongPress(position) => app.on_long_press(position),
+            }
+        }
+    }
+
+    /// Passes a custom event on to the app (or the user thread) if its type was registered
+    fn dispatch_custom_event(&mut self, event: CustomEvent, time: Option<TimeStamp>) {
+        if !self.config.events.accepts(&event) {
+            android_glue::write_log(&format!("Dropped custom event {} of an unregistered type", event.id.0));
+            return;
+        }
+        if let Some((_, send)) = &mut self.thread {
+            send.send(InputEvent::Custom(event)).expect("Could not send event");
+        } else {
+            self.app.as_mut().map(|app| app.handle_custom_event(event, time));
+        }
+    }
+
+    /// Delivers the events posted through `AppConfig::post_event`
+    fn dispatch_posted_events(&mut self) {
+        for event in std::mem::replace(&mut self.config.posted_events, Vec::new()) {
+            self.dispatch_custom_event(event, None);
+        }
+    }
+
+    /// Handles input which arrived since the last update, so it isn't a frame late when drawing
+    fn sample_input(&mut self) {
+        self.poll_android_events();
+        while let Some(e) = self.window.poll_event() {
+            self.handle_window_event(e);
+        }
+    }
+
     fn poll_events(&mut self) -> bool {
         while let Some(e) = self.events.next(&mut self.window) {
+            if self.app.is_none() {
+                return true;
+            }
             match e {
                 Event::Loop(loopargs) => match loopargs {
                     Loop::Render(r_args) => {
+                        if self.config.late_input_sampling {
+                            self.sample_input();
+                            // A `Destroy` among the sampled events shuts the app down
+                            if self.app.is_none() {
+                                return true;
+                            }
+                        }
                         self.draw(r_args);
                     },
                     Loop::Update(u_args) => {
                         self.poll_android_events();
-                        let cfg_ref = &mut self.config;
-                        self.app.as_mut().map(|app| app.update(u_args, cfg_ref));
+                        let now = self.start.elapsed().as_secs_f64() * 1000.0;
+                        let gesture = self.gestures.update(&self.config.gesture_config, now);
+                        self.dispatch_gesture(gesture);
+                        if let Some(app) = self.app.as_mut() {
+                            update_step(app, &mut self.config, u_args.dt, |_| {});
+                        }
+                        self.config.touch_state.clear_ended();
+                        self.apply_rates();
+                        self.apply_pointer_capture();
+                        self.apply_refresh_rate();
+                        self.apply_profiling();
+                        self.dispatch_posted_events();
+                        if let Some(Err(e)) = self.config.features.poll_remote() {
+                            android_glue::write_log(&format!("Could not fetch feature flags: {}", e));
+                        }
                     },
                     Loop::AfterRender(a_args) => {
+                        self.config.profiler.end(ProfileScope::Flush);
+                        self.config.profiler.end_frame();
                         self.app.as_mut().map(|app| app.after_draw(a_args));
                         return true;
                     },
                     _ => {}
                 },
-                Event::Custom(id, event, time) => {
-                    if let Some((_, send)) = &mut self.thread {
-                        send.send(InputEvent::Custom(id, event)).expect("Could not send event");
-                    } else {
-                        self.app.as_mut().map(|app| app.handle_custom_event(id, event, time));
-                    }
-                },
-                Event::Input(input, time) => {
-                    if let Some((_, send)) = &mut self.thread {
-                        send.send(InputEvent::Piston(input)).expect("Could not send event");
-                    } else {
-                        self.app.as_mut().map(|app| app.input(input, time));
-                    }
-                }
+                e => self.handle_window_event(e),
             }
         }
         false
@@ -205,3 +518,63 @@ impl<T: AppImpl> AppContainer<T> {
         }
     }
 }
+
+/// Picks the desktop OpenGL version closest to the one asked for, falling back to 3.2
+pub(crate) fn desktop_opengl(version: (u8, u8)) -> OpenGL {
+    match version {
+        (2, 0) => OpenGL::V2_0,
+        (2, 1) => OpenGL::V2_1,
+        (3, 0) => OpenGL::V3_0,
+        (3, 1) => OpenGL::V3_1,
+        (3, 3) => OpenGL::V3_3,
+        (4, 0) => OpenGL::V4_0,
+        (4, 1) => OpenGL::V4_1,
+        (4, 2) => OpenGL::V4_2,
+        (4, 3) => OpenGL::V4_3,
+        (4, 4) => OpenGL::V4_4,
+        (4, 5) => OpenGL::V4_5,
+        _ => OpenGL::V3_2,
+    }
+}
+
+/// Runs one update of `app` for `dt` real seconds, shared by `AppContainer` and `TestContainer`:
+/// `update`, each fixed step's `fixed_update` and the timeline, with the config's pause, time scale
+/// and frame stepping applied. `on_call` is given the name of each callback made on the app.
+///
+/// Frame stepping only holds back these, the caller's housekeeping should run on every update
+pub(crate) fn update_step<T: AppImpl>(app: &mut T, config: &mut AppConfig, dt: f64, mut on_call: impl FnMut(&'static str)) {
+    if config.frame_step {
+        if config.pending_steps == 0 {
+            return;
+        }
+        config.pending_steps -= 1;
+    }
+    let scale = if config.paused { 0.0 } else { config.time_scale };
+    config.real_dt = dt;
+    config.real_time += dt;
+    config.scaled_time += dt * scale;
+    let dt = dt * scale;
+    config.profiler.begin(ProfileScope::Update);
+    on_call("update");
+    app.update(UpdateArgs { dt }, config);
+    if let Some(fixed_dt) = config.fixed_dt {
+        config.fixed_accumulator += dt;
+        let mut steps = 0;
+        while config.fixed_accumulator >= fixed_dt {
+            if steps == config.max_fixed_steps {
+                config.fixed_accumulator %= fixed_dt;
+                break;
+            }
+            config.fixed_accumulator -= fixed_dt;
+            on_call("fixed_update");
+            app.fixed_update(fixed_dt, config);
+            steps += 1;
+            // The app may have changed or removed the fixed timestep
+            if config.fixed_dt != Some(fixed_dt) {
+                break;
+            }
+        }
+    }
+    config.timeline.advance(dt);
+    config.profiler.end(ProfileScope::Update);
+}
@@ -1,13 +1,14 @@
 #![allow(unused_variables)]
 
-use piston::input::event_id::EventId;
-use std::sync::Arc;
-use std::any::Any;
 use piston::input::{TimeStamp, Input, AfterRenderArgs, RenderArgs, UpdateArgs};
 use opengl_graphics::GlGraphics;
 use graphics::Context;
-use crate::{AppConfig, ShaderStorage};
+use image::RgbaImage;
+use crate::{AppConfig, ShaderStorage, StateStore};
 use crate::storage::ShaderContext;
+use crate::events::CustomEvent;
+use crate::gestures::SwipeDirection;
+use crate::input::TouchState;
 
 /// A trait describing an implementation of a basic android rust app
 pub trait AppImpl: Sized {
@@ -15,10 +16,12 @@ pub trait AppImpl: Sized {
     type InitializationData;
     /// The transform-identifying tag used when rendering.
     /// Creates a new `Self` with graphics initialized.
-    fn new(gl: &mut GlGraphics, data: Self::InitializationData, shaders: &mut ShaderStorage) -> Self;
-    /// When focus is lost, this function is called to let app save states or do anything it needs to do to save
+    /// `state` holds whatever was saved in it before the app was last paused or closed
+    fn new(gl: &mut GlGraphics, data: Self::InitializationData, shaders: &mut ShaderStorage, state: &StateStore) -> Self;
+    /// When focus is lost, this function is called to let app save states or do anything it needs to do to save.
+    /// `state` is saved afterwards, as the system may kill the app at any point while it's paused
     #[inline]
-    fn signal_pause(&mut self) {}
+    fn signal_pause(&mut self, state: &mut StateStore) {}
 
     /// Called just after `signal_pause_change` when focus is gained. Kind of meant to be the opposite to it, just more optional
     #[inline]
@@ -31,19 +34,56 @@ pub trait AppImpl: Sized {
     /// Called when asked to update. Pretty standard piston/glutin_window update
     fn update(&mut self, args: UpdateArgs, cfg: &mut AppConfig);
 
-    /// Called when need to draw
+    /// Called zero or more times after each `update` with a constant `dt` when `AppConfig::fixed_update_hz` is set
+    #[inline]
+    fn fixed_update(&mut self, dt: f64, cfg: &mut AppConfig) {}
+
+    /// Called when need to draw, after `draw_2d` and before `draw_overlay_2d`
     /// Used for drawing with custom shaders
     fn draw_shaded(&mut self, context: ShaderContext) {}
 
-    /// Called when need to draw
+    /// Called when need to draw, before `draw_shaded` so this acts as the background
     /// Initialization and stuff is taken care of behind the scenes
     fn draw_2d(&mut self, c: Context, gl: &mut GlGraphics, args: RenderArgs, cfg: &mut AppConfig);
 
+    /// Called when need to draw, after `draw_shaded` so this is drawn over the 3D content
+    /// Used for UI and anything else which should overlay the scene
+    fn draw_overlay_2d(&mut self, c: Context, gl: &mut GlGraphics, args: RenderArgs, cfg: &mut AppConfig) {}
+
+    /// Called with each frame captured through `AppConfig::capture_frame` or `AppConfig::set_recording`,
+    /// in order, usually a frame or two after it was drawn
+    fn on_frame_captured(&mut self, image: RgbaImage) {}
+
     /// Called after drawing.
     fn after_draw(&mut self, args: AfterRenderArgs) {}
 
-    /// Called when the app is closed.
-    fn on_die(self) {}
+    /// Called when the app is closed, `state` is saved afterwards.
+    fn on_die(self, state: &mut StateStore) {}
+
+    /// Called when the activity is paused, such as when another activity is opened over it
+    #[inline]
+    fn on_pause(&mut self) {}
+
+    /// Called when the activity is resumed after being paused
+    #[inline]
+    fn on_resume(&mut self) {}
+
+    /// Called when the system is running low on memory, caches should be emptied
+    #[inline]
+    fn on_low_memory(&mut self) {}
+
+    /// Called when a key is released, return `false` to shut the app down as a back press would.
+    ///
+    /// android_glue doesn't report which key was released, so the back button can't be told apart
+    /// from the volume keys or a hardware keyboard's, and this keeps the app running by default.
+    /// The system's own handling of the back button still finishes the activity, which ends the app
+    /// through `on_destroy`
+    #[inline]
+    fn on_key_released(&mut self) -> bool { true }
+
+    /// Called when the activity is destroyed, just before `on_die`
+    #[inline]
+    fn on_destroy(&mut self) {}
 
     /// Called at the start of `AppContainer::run` if `config` requires a reset on start
     #[inline]
@@ -56,11 +96,37 @@ pub trait AppImpl: Sized {
     #[inline]
     fn handle_android_event(&mut self, event: android_glue::Event) {}
 
-    /// Called when we get a custom window event
+    /// Called with custom events whose type was registered through `AppConfig::events`,
+    /// use `dispatch_event!` to handle each type
     #[inline]
-    fn handle_custom_event(&mut self, event_id: EventId, event: Arc<dyn Any>, timestamp: Option<TimeStamp>) {}
+    fn handle_custom_event(&mut self, event: CustomEvent, timestamp: Option<TimeStamp>) {}
 
     /// Called when we get an input event
     #[inline]
     fn input(&mut self, input: Input, timestamp: Option<TimeStamp>) {}
+
+    /// Called after each touch event with every touch on the screen, including the one which just ended, if any.
+    /// Isn't called when input goes to a user thread, but `AppConfig::touch_state` is kept up to date either way
+    #[inline]
+    fn touch(&mut self, touches: &TouchState) {}
+
+    /// Called when a finger is briefly touched down and lifted in place, thresholds are set in `AppConfig::gesture_config`
+    #[inline]
+    fn on_tap(&mut self, position: [f64; 2]) {}
+
+    /// Called instead of `on_tap` for the second of two quick taps in the same place
+    #[inline]
+    fn on_double_tap(&mut self, position: [f64; 2]) {}
+
+    /// Called when a finger is flicked across the screen, with its velocity in pixels per second
+    #[inline]
+    fn on_swipe(&mut self, direction: SwipeDirection, velocity: [f64; 2]) {}
+
+    /// Called as two fingers move, with how much the distance between them changed since the last call
+    #[inline]
+    fn on_pinch(&mut self, scale: f64) {}
+
+    /// Called when a finger is held in place, no tap follows when it's lifted
+    #[inline]
+    fn on_long_press(&mut self, position: [f64; 2]) {}
 }
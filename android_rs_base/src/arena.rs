@@ -0,0 +1,161 @@
+use std::ops::{Index, IndexMut};
+
+/// Storage for values which only live for one frame, such as draw submissions.
+///
+/// Values are pushed during the frame and dropped together by `reset`, which keeps the memory
+/// they used so following frames don't allocate once the arena has grown to fit a frame.
+pub struct FrameArena<T> {
+    items: Vec<T>,
+}
+
+impl<T> FrameArena<T> {
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+
+    /// Creates an arena with room for `capacity` values before it first allocates
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { items: Vec::with_capacity(capacity) }
+    }
+
+    /// Adds a value, returning its index in this frame
+    pub fn alloc(&mut self, value: T) -> usize {
+        self.items.push(value);
+        self.items.len() - 1
+    }
+
+    /// Adds a value, returning a reference to it
+    pub fn alloc_mut(&mut self, value: T) -> &mut T {
+        self.items.push(value);
+        self.items.last_mut().unwrap()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.items.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.items.get_mut(index)
+    }
+
+    /// The values added this frame, in order
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// How many values fit before the arena has to allocate
+    pub fn capacity(&self) -> usize {
+        self.items.capacity()
+    }
+
+    /// Drops every value, keeping the memory for the next frame
+    pub fn reset(&mut self) {
+        self.items.clear();
+    }
+}
+
+impl<T> Index<usize> for FrameArena<T> {
+    type Output = T;
+    fn index(&self, index: usize) -> &T {
+        &self.items[index]
+    }
+}
+
+impl<T> IndexMut<usize> for FrameArena<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.items[index]
+    }
+}
+
+/// Objects reused from frame to frame instead of being dropped and created again, such as
+/// particles or UI nodes holding their own `Vec`s or `String`s.
+///
+/// `take` hands out the objects left over from previous frames before creating new ones, and
+/// `reset` returns all of them to the pool without dropping them, so their own allocations are
+/// kept. A reused object still holds whatever it was last set to, so it should be overwritten.
+pub struct Pool<T> {
+    items: Vec<T>,
+    active: usize,
+}
+
+impl<T> Pool<T> {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            active: 0,
+        }
+    }
+
+    /// Gets an unused object, calling `create` only if every object in the pool is in use
+    pub fn take<F: FnOnce() -> T>(&mut self, create: F) -> &mut T {
+        if self.active == self.items.len() {
+            self.items.push(create());
+        }
+        self.active += 1;
+        &mut self.items[self.active - 1]
+    }
+
+    /// Releases the most recently taken object back to the pool
+    pub fn pop(&mut self) {
+        self.active = self.active.saturating_sub(1);
+    }
+
+    /// The objects taken since the last reset
+    pub fn active(&self) -> &[T] {
+        &self.items[..self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut [T] {
+        &mut self.items[..self.active]
+    }
+
+    /// Keeps only the taken objects for which `f` returns `true`, releasing the rest.
+    /// The order of the kept objects isn't preserved
+    pub fn retain<F: FnMut(&mut T) -> bool>(&mut self, mut f: F) {
+        let mut i = 0;
+        while i < self.active {
+            if f(&mut self.items[i]) {
+                i += 1;
+            } else {
+                self.active -= 1;
+                self.items.swap(i, self.active);
+            }
+        }
+    }
+
+    /// The number of objects taken since the last reset
+    pub fn len(&self) -> usize {
+        self.active
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active == 0
+    }
+
+    /// The number of objects kept by the pool, whether they're in use or not
+    pub fn allocated(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Releases every object back to the pool, without dropping them
+    pub fn reset(&mut self) {
+        self.active = 0;
+    }
+
+    /// Drops the objects which aren't in use
+    pub fn shrink(&mut self) {
+        self.items.truncate(self.active);
+    }
+}
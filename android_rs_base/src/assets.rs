@@ -0,0 +1,159 @@
+use asset_pack::{AssetPack, CookedMesh, PackData};
+use std::collections::HashMap;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Reads files from the app's assets, so textures, shaders and models all load the same way.
+///
+/// On Android these come from the `assets` directory packaged in the APK, on other platforms
+/// (when running a desktop build of the app) they're read from the `assets` directory next to
+/// the working directory instead. Cloning an `Assets` shares its prefetched files and mounted packs.
+///
+/// Packs built by the `cook` tool of the `asset_pack` crate can be mounted with `mount_pack`, after
+/// which the assets in them are read from the pack rather than individually.
+#[derive(Clone)]
+pub struct Assets {
+    fallback_root: PathBuf,
+    prefetched: Arc<Mutex<HashMap<String, Result<Vec<u8>, String>>>>,
+    packs: Arc<Mutex<Vec<Arc<AssetPack<PackData>>>>>,
+}
+
+/// The bytes of an asset as read by `Assets::read_data`. Assets in a mounted pack are
+/// borrowed from it rather than copied, keeping the pack alive for as long as they're held
+pub enum AssetData {
+    Packed(Arc<AssetPack<PackData>>, Range<usize>),
+    Owned(Vec<u8>),
+}
+
+impl AssetData {
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            AssetData::Packed(..) => self.to_vec(),
+            AssetData::Owned(x) => x,
+        }
+    }
+}
+
+impl Deref for AssetData {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            AssetData::Packed(pack, range) => &pack.data()[range.clone()],
+            AssetData::Owned(x) => x,
+        }
+    }
+}
+
+impl AsRef<[u8]> for AssetData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self {
+            fallback_root: PathBuf::from("assets"),
+            prefetched: Arc::new(Mutex::new(HashMap::new())),
+            packs: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Sets the directory assets are read from when not running on Android
+    pub fn with_fallback_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.fallback_root = root.into();
+        self
+    }
+
+    /// Loads the cooked asset pack at `path` in the assets, returning how many assets it holds.
+    /// Packs mounted later take priority over earlier ones.
+    ///
+    /// Outside of Android the pack is memory mapped. Assets packaged in an APK can't be mapped
+    /// (they may be compressed), so there the pack is read into memory once; to have it mapped
+    /// instead, copy it out to `internal_storage_path()` and use `mount_pack_file`
+    pub fn mount_pack(&self, path: &str) -> Result<usize, String> {
+        #[cfg(target_os = "android")]
+        let pack = AssetPack::parse(PackData::from(load(&self.fallback_root, path)?));
+        #[cfg(not(target_os = "android"))]
+        let pack = AssetPack::open(&self.fallback_root.join(path));
+        self.mount(pack.map_err(|e| format!("In {}: {}", path, e))?)
+    }
+
+    /// Memory maps the cooked asset pack at `path` on the file system, like `mount_pack`
+    pub fn mount_pack_file(&self, path: &Path) -> Result<usize, String> {
+        self.mount(AssetPack::open(path)?)
+    }
+
+    fn mount(&self, pack: AssetPack<PackData>) -> Result<usize, String> {
+        let len = pack.len();
+        self.packs.lock().unwrap().insert(0, Arc::new(pack));
+        Ok(len)
+    }
+
+    /// Reads the mesh cooked from the model at `path`, which has to be in a mounted pack
+    pub fn read_mesh(&self, path: &str) -> Result<CookedMesh, String> {
+        let packs = self.packs.lock().unwrap();
+        let pack = packs.iter().find(|x| x.contains(path)).ok_or_else(|| format!("{} isn't in a mounted pack", path))?;
+        pack.mesh(path)
+    }
+
+    /// Reads the whole of the asset at `path`, without copying it when it's in a mounted pack,
+    /// otherwise taking it from the prefetched files if it's been loaded already
+    pub fn read_data(&self, path: &str) -> Result<AssetData, String> {
+        let packed = self.packs
+            .lock()
+            .unwrap()
+            .iter()
+            .find_map(|x| x.range(path).map(|range| AssetData::Packed(x.clone(), range)));
+        if let Some(packed) = packed {
+            return Ok(packed);
+        }
+        if let Some(prefetched) = self.prefetched.lock().unwrap().remove(path) {
+            return prefetched.map(AssetData::Owned);
+        }
+        load(&self.fallback_root, path).map(AssetData::Owned)
+    }
+
+    /// Reads the whole of the asset at `path` into its own buffer, see `read_data`
+    pub fn read_bytes(&self, path: &str) -> Result<Vec<u8>, String> {
+        self.read_data(path).map(AssetData::into_vec)
+    }
+
+    /// Reads the asset at `path` as UTF-8 text
+    pub fn read_string(&self, path: &str) -> Result<String, String> {
+        String::from_utf8(self.read_bytes(path)?).map_err(|_| format!("Asset {} isn't valid UTF-8", path))
+    }
+
+    /// Starts loading `paths` on a background thread, so a later `read_bytes` of them doesn't wait on storage.
+    ///
+    /// A prefetched file is kept in memory until it's first read, after which it's read from the assets again.
+    pub fn prefetch(&self, paths: &[&str]) {
+        let paths = paths.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        let root = self.fallback_root.clone();
+        let prefetched = self.prefetched.clone();
+        std::thread::spawn(move || {
+            for path in paths {
+                let data = load(&root, &path);
+                prefetched.lock().unwrap().insert(path, data);
+            }
+        });
+    }
+
+    /// Whether `path` has finished prefetching and not been read yet
+    pub fn is_prefetched(&self, path: &str) -> bool {
+        self.prefetched.lock().unwrap().contains_key(path)
+    }
+}
+
+fn load(fallback_root: &Path, path: &str) -> Result<Vec<u8>, String> {
+    #[cfg(target_os = "android")]
+    {
+        let _ = fallback_root;
+        android_glue::load_asset(path).map_err(|_| format!("Could not load asset {}", path))
+    }
+    #[cfg(not(target_os = "android"))]
+    {
+        std::fs::read(fallback_root.join(path)).map_err(|e| format!("Could not load asset {}: {}", path, e))
+    }
+}
@@ -0,0 +1,205 @@
+use asset_pack::CookedMesh;
+use cgmath::{Matrix, Matrix3, Matrix4, SquareMatrix, Vector3, InnerSpace};
+use matrices::batch;
+use opengl_graphics::Mesh;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+// `Mesh` indices are `u16`
+const MAX_CHUNK_VERTICES: usize = 1 << 16;
+
+/// A mesh added to a `StaticBatcher`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StaticId(u64);
+
+struct Piece {
+    id: StaticId,
+    vertices: Range<usize>,
+    indices: Range<usize>,
+}
+
+// Moves `normals` into `out` by the inverse transpose of `transform`, normalising them
+pub(crate) fn transform_normals(transform: &Matrix4<f32>, normals: &[[f32; 3]], out: &mut [[f32; 3]]) {
+    let upper = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate());
+    let normal_matrix = Matrix4::from(upper.invert().map(|x| x.transpose()).unwrap_or(upper));
+    batch::transform_normals(&normal_matrix, normals, out);
+    for normal in &mut out[..normals.len()] {
+        *normal = Vector3::from(*normal).normalize().into();
+    }
+}
+
+// Meshes merged into one set of buffers. Normals and uvs are either empty or have one per vertex
+#[derive(Default)]
+struct Chunk {
+    pieces: Vec<Piece>,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u16>,
+    mesh: Option<Mesh<[f32; 3]>>,
+    dirty: bool,
+}
+
+impl Chunk {
+    fn add(&mut self, id: StaticId, mesh: &CookedMesh, transform: &Matrix4<f32>) {
+        let start = self.positions.len();
+        // Meshes without normals or uvs in a chunk with them get zeroes, and the other way around
+        if !mesh.normals.is_empty() && self.normals.is_empty() {
+            self.normals.resize(start, [0.0; 3]);
+        }
+        if !mesh.uvs.is_empty() && self.uvs.is_empty() {
+            self.uvs.resize(start, [0.0; 2]);
+        }
+        self.positions.resize(start + mesh.positions.len(), [0.0; 3]);
+        batch::transform_points(transform, &mesh.positions, &mut self.positions[start..]);
+        if !self.normals.is_empty() {
+            self.normals.resize(self.positions.len(), [0.0; 3]);
+            if !mesh.normals.is_empty() {
+                transform_normals(transform, &mesh.normals, &mut self.normals[start..]);
+            }
+        }
+        if !self.uvs.is_empty() {
+            if mesh.uvs.is_empty() {
+                self.uvs.resize(self.positions.len(), [0.0; 2]);
+            } else {
+                self.uvs.extend_from_slice(&mesh.uvs);
+            }
+        }
+        let index_start = self.indices.len();
+        if mesh.indices.is_empty() {
+            self.indices.extend((start..self.positions.len()).map(|x| x as u16));
+        } else {
+            self.indices.extend(mesh.indices.iter().map(|&x| (start + x as usize) as u16));
+        }
+        self.pieces.push(Piece {
+            id,
+            vertices: start..self.positions.len(),
+            indices: index_start..self.indices.len(),
+        });
+        self.dirty = true;
+    }
+
+    fn remove(&mut self, id: StaticId) -> bool {
+        let index = match self.pieces.iter().position(|x| x.id == id) {
+            Some(x) => x,
+            None => return false,
+        };
+        let piece = self.pieces.remove(index);
+        let (vertex_count, index_count) = (piece.vertices.len(), piece.indices.len());
+        self.positions.drain(piece.vertices.clone());
+        if !self.normals.is_empty() {
+            self.normals.drain(piece.vertices.clone());
+        }
+        if !self.uvs.is_empty() {
+            self.uvs.drain(piece.vertices.clone());
+        }
+        self.indices.drain(piece.indices.clone());
+        for index in &mut self.indices[piece.indices.start..] {
+            *index -= vertex_count as u16;
+        }
+        for later in &mut self.pieces[index..] {
+            later.vertices = later.vertices.start - vertex_count..later.vertices.end - vertex_count;
+            later.indices = later.indices.start - index_count..later.indices.end - index_count;
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn upload(&mut self) {
+        self.dirty = false;
+        if self.positions.is_empty() {
+            self.mesh = None;
+            return;
+        }
+        let mut mesh = Mesh::new(&self.positions).with_indices(&self.indices);
+        if !self.normals.is_empty() {
+            mesh = mesh.with_normals(&self.normals);
+        }
+        if !self.uvs.is_empty() {
+            mesh = mesh.with_uvs(&self.uvs);
+        }
+        self.mesh = Some(mesh);
+    }
+}
+
+/// Merges static meshes which share a material into as few meshes as possible, so level geometry
+/// takes one draw call per material rather than one per object.
+///
+/// `M` is whatever tells materials apart, such as a texture name, or an enum of the shaders and
+/// textures a level uses. Meshes are moved into world space by the transform they're added with,
+/// and can be removed again by the `StaticId` they're given. Each material is split into as many
+/// meshes as needed to keep their indices within `u16`.
+///
+/// Changes are uploaded by the next `draw_each`, so meshes should be added all at once when a level loads.
+pub struct StaticBatcher<M: Eq + Hash + Clone> {
+    materials: HashMap<M, Vec<Chunk>>,
+    next_id: u64,
+}
+
+impl<M: Eq + Hash + Clone> StaticBatcher<M> {
+    pub fn new() -> Self {
+        Self {
+            materials: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Adds `mesh` drawn with `material`, baking `transform` into its vertices.
+    /// Fails if the mesh alone has more vertices than a `u16` can index, or it has
+    /// normals or uvs but not one for every vertex
+    pub fn add(&mut self, material: M, mesh: &CookedMesh, transform: &Matrix4<f32>) -> Result<StaticId, String> {
+        let count = mesh.positions.len();
+        if count > MAX_CHUNK_VERTICES {
+            return Err(format!("A mesh of {} vertices is too large to batch", count));
+        }
+        if (!mesh.normals.is_empty() && mesh.normals.len() != count) || (!mesh.uvs.is_empty() && mesh.uvs.len() != count) {
+            return Err("Batched meshes need a normal and uv for every vertex, or none".to_string());
+        }
+        let id = StaticId(self.next_id);
+        self.next_id += 1;
+        let chunks = self.materials.entry(material).or_insert_with(Vec::new);
+        let chunk = match chunks.iter_mut().position(|x| x.positions.len() + count <= MAX_CHUNK_VERTICES) {
+            Some(x) => &mut chunks[x],
+            None => {
+                chunks.push(Chunk::default());
+                chunks.last_mut().unwrap()
+            }
+        };
+        chunk.add(id, mesh, transform);
+        Ok(id)
+    }
+
+    /// Removes a mesh, returning whether it was still there
+    pub fn remove(&mut self, id: StaticId) -> bool {
+        self.materials
+            .values_mut()
+            .flat_map(|x| x.iter_mut())
+            .any(|x| x.remove(id))
+    }
+
+    /// The number of draw calls `draw_each` makes
+    pub fn draw_call_count(&self) -> usize {
+        self.materials
+            .values()
+            .flat_map(|x| x.iter())
+            .filter(|x| !x.positions.is_empty())
+            .count()
+    }
+
+    /// Uploads any changes, then calls `f` with each merged mesh and its material to draw it.
+    /// Has to be called on the GL thread
+    pub fn draw_each<F: FnMut(&M, &mut Mesh<[f32; 3]>)>(&mut self, mut f: F) {
+        for (material, chunks) in &mut self.materials {
+            chunks.retain(|x| !x.pieces.is_empty() || x.mesh.is_some());
+            for chunk in chunks.iter_mut() {
+                if chunk.dirty {
+                    chunk.upload();
+                }
+                if let Some(mesh) = &mut chunk.mesh {
+                    f(material, mesh);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+use glutin_window::GlutinWindow;
+use opengl_graphics::GlGraphics;
+use piston::window::Window;
+use graphics::{Viewport, clear, rectangle};
+
+/// The preferences key the device performance score is stored under
+pub const PERFORMANCE_SCORE_KEY: &str = "performance_score";
+
+/// Renders an increasingly heavy workload of blended rectangles for `duration`
+/// and returns the score, measured in thousands of rectangles per second.
+///
+/// The workload is doubled every frame which finishes within a 60hz frame budget,
+/// so the score ends up reflecting how much the device can draw under pressure.
+pub(crate) fn run(window: &mut GlutinWindow, gl: &mut GlGraphics, duration: Duration) -> f64 {
+    let size = window.draw_size();
+    let viewport = Viewport {
+        rect: [0, 0, size.width as i32, size.height as i32],
+        draw_size: [size.width as u32, size.height as u32],
+        window_size: [size.width, size.height],
+    };
+    let budget = Duration::from_micros(16_667);
+    let start = Instant::now();
+    let mut workload = 64usize;
+    let mut drawn = 0usize;
+    while start.elapsed() < duration {
+        let frame_start = Instant::now();
+        gl.draw(viewport, |c, gl| {
+            clear([0.0, 0.0, 0.0, 1.0], gl);
+            for i in 0..workload {
+                let t = i as f64 / workload as f64;
+                rectangle(
+                    [t as f32, 1.0 - t as f32, 0.5, 0.5],
+                    [t * size.width, (1.0 - t) * size.height, size.width / 8.0, size.height / 8.0],
+                    c.transform,
+                    gl
+                );
+            }
+        });
+        unsafe {
+            opengl_graphics::gl::Finish();
+        }
+        window.swap_buffers();
+        drawn += workload;
+        if frame_start.elapsed() < budget {
+            workload *= 2;
+        }
+    }
+    drawn as f64 / start.elapsed().as_secs_f64() / 1000.0
+}
@@ -0,0 +1,65 @@
+use serde_json::{Map, Value};
+use crate::AppConfig;
+
+/// Settings bundled with the app in a JSON asset, read by `AppContainer::init` when set with `AppConfig::boot_config`.
+///
+/// The keys `update_hz`, `render_hz`, `refresh_rate`, `time_scale` and `clear_color` override the
+/// matching `AppConfig` settings. Anything else (a server URL, a default quality tier) is kept for the app
+/// to read through `AppConfig::boot`, so builds can be configured by swapping the asset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BootConfig {
+    values: Map<String, Value>,
+}
+
+impl BootConfig {
+    /// Loads the JSON object in the asset `name`
+    pub fn load(name: &str) -> Result<Self, String> {
+        let bytes = android_glue::load_asset(name).map_err(|_| format!("Could not load asset {}", name))?;
+        Self::parse(&bytes).map_err(|e| format!("In {}: {}", name, e))
+    }
+
+    /// Parses a JSON object
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        match serde_json::from_slice(bytes).map_err(|e| e.to_string())? {
+            Value::Object(values) => Ok(Self { values }),
+            _ => Err("Boot config isn't an object".to_string()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|x| x.as_str())
+    }
+
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        self.get(key).and_then(|x| x.as_f64())
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(|x| x.as_bool())
+    }
+
+    /// Overrides the settings of `config` this has values for
+    pub(crate) fn merge_into(&self, config: &mut AppConfig) {
+        if let Some(hz) = self.get_f64("update_hz") {
+            config.set_update_hz(hz as u64);
+        }
+        if let Some(hz) = self.get_f64("render_hz") {
+            config.set_render_hz(hz as u64);
+        }
+        if let Some(hz) = self.get_f64("refresh_rate") {
+            config.set_refresh_rate(Some(hz as f32));
+        }
+        if let Some(scale) = self.get_f64("time_scale") {
+            config.set_time_scale(scale);
+        }
+        if let Some(color) = self.get("clear_color").and_then(|x| x.as_array()) {
+            if let [r, g, b, a] = color.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect::<Vec<_>>()[..] {
+                config.set_clear_color(Some([r, g, b, a]));
+            }
+        }
+    }
+}
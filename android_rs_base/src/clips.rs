@@ -0,0 +1,105 @@
+use asset_pack::{ClipSegment, CompressedClip, JointPose};
+use cgmath::{Matrix4, Quaternion, Vector3};
+use crate::assets::{AssetData, Assets};
+
+/// A compressed animation clip (see `asset_pack::AnimationClip::compress`) played straight out
+/// of the assets, decoding its keys a segment at a time as playback reaches them.
+///
+/// Only the header is read when loading, and only the last few segments played are kept decoded,
+/// so a long clip costs little more memory than a short one. Clips in a pack mounted with
+/// `Assets::mount_pack_file` (or with `mount_pack` outside of Android) are memory mapped, so the
+/// segments not being played aren't even read from storage.
+pub struct StreamedClip {
+    clip: CompressedClip<AssetData>,
+    // Most recently used last
+    segments: Vec<ClipSegment>,
+    cached_segments: usize,
+    poses: Vec<JointPose>,
+}
+
+impl StreamedClip {
+    /// Reads the header of the compressed clip at `path`
+    pub fn load(assets: &Assets, path: &str) -> Result<Self, String> {
+        let clip = CompressedClip::parse(assets.read_data(path)?).map_err(|e| format!("In {}: {}", path, e))?;
+        Ok(Self {
+            poses: vec![JointPose::default(); clip.joint_count() as usize],
+            clip,
+            segments: Vec::new(),
+            cached_segments: 2,
+        })
+    }
+
+    /// Sets how many decoded segments are kept, at least one. Two (the default) is enough to
+    /// play forwards or loop without decoding a segment twice
+    pub fn cached_segments(mut self, count: usize) -> Self {
+        self.cached_segments = count.max(1);
+        self
+    }
+
+    /// The length of the clip in seconds
+    pub fn duration(&self) -> f32 {
+        self.clip.duration()
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.poses.len()
+    }
+
+    /// The bytes taken up by the compressed clip, and by the segments kept decoded
+    pub fn memory_size(&self) -> (usize, usize) {
+        (self.clip.compressed_size(), self.segments.iter().map(ClipSegment::memory_size).sum())
+    }
+
+    /// Drops the decoded segments, such as when the clip stops playing for a while
+    pub fn release(&mut self) {
+        self.segments.clear();
+    }
+
+    /// The local pose of each joint at `time`, in seconds and clamped to the clip. Joints and
+    /// properties the clip doesn't animate are left as the identity
+    pub fn sample(&mut self, time: f32) -> Result<&[JointPose], String> {
+        let index = self.clip.segment_at(time);
+        let position = match self.segments.iter().position(|x| x.index() == index) {
+            Some(x) => x,
+            None => {
+                let segment = self.clip.decode_segment(index)?;
+                if self.segments.len() >= self.cached_segments {
+                    self.segments.remove(0);
+                }
+                self.segments.push(segment);
+                self.segments.len() - 1
+            },
+        };
+        let segment = self.segments.remove(position);
+        self.clip.sample_segment(&segment, time, &mut self.poses);
+        self.segments.push(segment);
+        Ok(&self.poses)
+    }
+
+    /// Samples the clip at `time` and fills `pose` with each joint's transform relative to the
+    /// model, as `SkinnedMesh::pose` takes. `parents` gives the parent of each joint, which has to
+    /// come before it
+    pub fn sample_pose(&mut self, time: f32, parents: &[Option<usize>], pose: &mut Vec<Matrix4<f32>>) -> Result<(), String> {
+        self.sample(time)?;
+        pose.clear();
+        for (joint, local) in self.poses.iter().enumerate() {
+            let local = joint_matrix(local);
+            let world = match parents.get(joint).and_then(|x| *x) {
+                Some(parent) if parent < joint => pose[parent] * local,
+                _ => local,
+            };
+            pose.push(world);
+        }
+        Ok(())
+    }
+}
+
+/// The transform of a joint relative to its parent, scaling, then rotating, then translating
+pub fn joint_matrix(pose: &JointPose) -> Matrix4<f32> {
+    let [x, y, z, w] = pose.rotation;
+    let [tx, ty, tz] = pose.translation;
+    let [sx, sy, sz] = pose.scale;
+    Matrix4::from_translation(Vector3::new(tx, ty, tz))
+        * Matrix4::from(Quaternion::new(w, x, y, z))
+        * Matrix4::from_nonuniform_scale(sx, sy, sz)
+}
@@ -0,0 +1,75 @@
+use graphics::color::{gamma_linear_to_srgb, gamma_srgb_to_linear};
+
+/// The colour space a colour or image is encoded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    /// The wider gamut used by most modern phone screens and cameras, with the same transfer curve as sRGB
+    DisplayP3,
+}
+
+// Linear sRGB to linear Display-P3, both with a D65 white point
+const SRGB_TO_P3: [[f32; 3]; 3] = [
+    [0.822_462_1, 0.177_538_0, 0.0],
+    [0.033_194_1, 0.966_805_8, 0.0],
+    [0.017_082_7, 0.072_397_4, 0.910_519_9],
+];
+
+const P3_TO_SRGB: [[f32; 3]; 3] = [
+    [1.224_940_1, -0.224_940_4, 0.0],
+    [-0.042_056_9, 1.042_057_1, 0.0],
+    [-0.019_637_6, -0.078_636_1, 1.098_273_5],
+];
+
+fn transform(matrix: &[[f32; 3]; 3], color: [f32; 4]) -> [f32; 4] {
+    let linear = gamma_srgb_to_linear(color);
+    let mut out = [0.0, 0.0, 0.0, linear[3]];
+    for (out, row) in out.iter_mut().zip(matrix) {
+        *out = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+    }
+    out
+}
+
+/// Converts a gamma encoded sRGB colour to the same colour in Display-P3
+pub fn srgb_to_display_p3(color: [f32; 4]) -> [f32; 4] {
+    gamma_linear_to_srgb(transform(&SRGB_TO_P3, color))
+}
+
+/// Converts a gamma encoded Display-P3 colour to sRGB, clamping colours sRGB can't show.
+///
+/// The window surface is always sRGB, so colours and images authored in Display-P3 (such as
+/// photos from most phone cameras) need to be converted to look right rather than oversaturated
+pub fn display_p3_to_srgb(color: [f32; 4]) -> [f32; 4] {
+    let [r, g, b, a] = transform(&P3_TO_SRGB, color);
+    gamma_linear_to_srgb([r.max(0.0).min(1.0), g.max(0.0).min(1.0), b.max(0.0).min(1.0), a])
+}
+
+/// Whether a Display-P3 colour can be shown exactly on an sRGB surface
+pub fn is_in_srgb_gamut(display_p3: [f32; 4]) -> bool {
+    let [r, g, b, _] = transform(&P3_TO_SRGB, display_p3);
+    [r, g, b].iter().all(|&x| x >= -1e-4 && x <= 1.0 + 1e-4)
+}
+
+/// Converts a colour between colour spaces
+pub fn convert_color(color: [f32; 4], from: ColorSpace, to: ColorSpace) -> [f32; 4] {
+    match (from, to) {
+        (ColorSpace::Srgb, ColorSpace::DisplayP3) => srgb_to_display_p3(color),
+        (ColorSpace::DisplayP3, ColorSpace::Srgb) => display_p3_to_srgb(color),
+        _ => color,
+    }
+}
+
+/// Converts every pixel of an image between colour spaces, for images decoded from Display-P3 files
+pub fn convert_image(image: &mut image::RgbaImage, from: ColorSpace, to: ColorSpace) {
+    if from == to {
+        return;
+    }
+    for pixel in image.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0];
+        let converted = convert_color(color, from, to);
+        for (out, x) in pixel.0.iter_mut().zip(&converted) {
+            *out = (x.max(0.0).min(1.0) * 255.0).round() as u8;
+        }
+    }
+}
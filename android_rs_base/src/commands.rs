@@ -0,0 +1,139 @@
+use std::any::Any;
+use std::collections::VecDeque;
+
+/// An undoable change to a `T`, executed through a `CommandStack<T>`
+pub trait Command<T>: Any {
+    /// Applies the change
+    fn execute(&mut self, target: &mut T);
+    /// Reverts the change made by `execute`
+    fn undo(&mut self, target: &mut T);
+    /// Tries to fold `next`, which has just been executed, into this so they're undone as one,
+    /// such as consecutive drags of the same object. Downcast `next` to check it's mergeable
+    #[allow(unused_variables)]
+    fn merge(&mut self, next: &dyn Any) -> bool { false }
+    /// Roughly how much memory this uses, counted towards `CommandStack::memory_cap`
+    fn memory_size(&self) -> usize { std::mem::size_of_val(self) }
+}
+
+/// A history of executed commands which can be undone and redone.
+///
+/// Tracks whether anything changed since `mark_saved` was last called, so apps can save
+/// in `AppImpl::signal_pause` only when needed.
+pub struct CommandStack<T> {
+    done: VecDeque<Box<dyn Command<T>>>,
+    undone: Vec<Box<dyn Command<T>>>,
+    /// The most commands kept in the history, the oldest are dropped first
+    pub max_commands: usize,
+    /// The most memory (in bytes, by `Command::memory_size`) the history is allowed to use
+    pub memory_cap: usize,
+    memory: usize,
+    // How many commands have to be undone (negative: redone) to get back to the saved state,
+    // `None` if it can't be reached anymore
+    saved_offset: Option<isize>,
+}
+
+impl<T> CommandStack<T> {
+    /// Creates an empty stack keeping up to 100 commands and 16MiB
+    pub fn new() -> Self {
+        Self {
+            done: VecDeque::new(),
+            undone: Vec::new(),
+            max_commands: 100,
+            memory_cap: 16 * 1024 * 1024,
+            memory: 0,
+            saved_offset: Some(0),
+        }
+    }
+
+    /// Executes `command` on `target` and records it, clearing anything which could be redone
+    pub fn execute<C: Command<T>>(&mut self, target: &mut T, mut command: C) {
+        command.execute(target);
+        for redo in self.undone.drain(..) {
+            self.memory -= redo.memory_size();
+        }
+        if let Some(offset) = self.saved_offset {
+            // The saved state was in the redo history, which is now gone
+            self.saved_offset = if offset < 0 { None } else { Some(offset) };
+        }
+        if let Some(top) = self.done.back_mut() {
+            let before = top.memory_size();
+            // Merging into the saved state would make it unreachable
+            if self.saved_offset != Some(0) && top.merge(&command) {
+                self.memory = self.memory - before + top.memory_size();
+                return;
+            }
+        }
+        self.memory += command.memory_size();
+        self.done.push_back(Box::new(command));
+        self.saved_offset = self.saved_offset.map(|x| x + 1);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.done.len() > self.max_commands || (self.memory > self.memory_cap && self.done.len() > 1) {
+            if let Some(oldest) = self.done.pop_front() {
+                self.memory -= oldest.memory_size();
+            }
+            if self.saved_offset.map(|x| x as usize > self.done.len()).unwrap_or(false) {
+                self.saved_offset = None;
+            }
+        }
+    }
+
+    /// Undoes the last command, returning whether there was one
+    pub fn undo(&mut self, target: &mut T) -> bool {
+        match self.done.pop_back() {
+            Some(mut command) => {
+                command.undo(target);
+                self.undone.push(command);
+                self.saved_offset = self.saved_offset.map(|x| x - 1);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Redoes the last undone command, returning whether there was one
+    pub fn redo(&mut self, target: &mut T) -> bool {
+        match self.undone.pop() {
+            Some(mut command) => {
+                command.execute(target);
+                self.done.push_back(command);
+                self.saved_offset = self.saved_offset.map(|x| x + 1);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Forgets the whole history
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+        self.memory = 0;
+        self.saved_offset = if self.saved_offset == Some(0) { Some(0) } else { None };
+    }
+
+    /// Marks the current state as saved
+    pub fn mark_saved(&mut self) {
+        self.saved_offset = Some(0);
+    }
+
+    /// Whether the state has changed since `mark_saved` was last called
+    pub fn is_dirty(&self) -> bool {
+        self.saved_offset != Some(0)
+    }
+
+    /// Roughly how much memory the history uses
+    pub fn memory_used(&self) -> usize {
+        self.memory
+    }
+}
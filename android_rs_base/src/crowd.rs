@@ -0,0 +1,425 @@
+use opengl_graphics::{GLSL, GlGraphics, Instances, Mesh, Texture, gl};
+use opengl_graphics::gl::types::GLuint;
+use opengl_graphics::shader_utils::{Shader, Program};
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUInt, SUMat4x4, SUFloat, SUVec3, SUVec4};
+use graphics::{Context, DrawState};
+use cgmath::Matrix4;
+use matrices::batch;
+use crate::clips::StreamedClip;
+use crate::skinning::{self, FRAGMENT_SHADER};
+use crate::storage::{Drawable, Transforms, ViewProj, glsl_header};
+
+// The texels of the bone texture each joint's matrix takes, its top three rows
+const TEXELS_PER_JOINT: usize = 3;
+
+const VERTEX_SHADER: &str = "uniform mat4 view_proj;
+uniform mat4 model;
+uniform highp sampler2D bone_texture;
+
+in vec3 pos;
+in vec3 normal;
+in vec2 uv;
+in vec4 joints;
+in vec4 weights;
+// Position, and rotation around y in radians
+in vec4 instance_transform;
+// First row of the clip in the bone texture, its frame count, the frame to show and the scale
+in vec4 instance_animation;
+
+out vec3 v_Normal;
+out vec2 v_Uv;
+
+mat4 bone(float joint, int row) {
+    int x = int(joint) * 3;
+    return transpose(mat4(
+        texelFetch(bone_texture, ivec2(x, row), 0),
+        texelFetch(bone_texture, ivec2(x + 1, row), 0),
+        texelFetch(bone_texture, ivec2(x + 2, row), 0),
+        vec4(0.0, 0.0, 0.0, 1.0)
+    ));
+}
+
+mat4 skin(int row) {
+    return bone(joints.x, row) * weights.x +
+           bone(joints.y, row) * weights.y +
+           bone(joints.z, row) * weights.z +
+           bone(joints.w, row) * weights.w;
+}
+
+void main() {
+    float frame_count = max(instance_animation.y, 1.0);
+    float frame = mod(instance_animation.z, frame_count);
+    int first = int(instance_animation.x);
+    float t = fract(frame);
+    mat4 skinned = skin(first + int(frame)) * (1.0 - t) +
+                   skin(first + int(mod(floor(frame) + 1.0, frame_count))) * t;
+
+    float c = cos(instance_transform.w) * instance_animation.w;
+    float s = sin(instance_transform.w) * instance_animation.w;
+    mat4 instance = mat4(
+        c, 0.0, -s, 0.0,
+        0.0, instance_animation.w, 0.0, 0.0,
+        s, 0.0, c, 0.0,
+        instance_transform.xyz, 1.0
+    );
+    mat4 world = model * instance * skinned;
+    v_Normal = mat3(world) * normal;
+    v_Uv = uv;
+    gl_Position = view_proj * world * vec4(pos, 1.0);
+}";
+
+/// The shader `CrowdMesh` draws with, lit like `SkinnedShader`. It only draws meshes, so
+/// nothing can be drawn with it through `shader_draw`
+pub struct CrowdShader {
+    program: Program,
+    view_proj: ShaderUniform<SUMat4x4>,
+    model: ShaderUniform<SUMat4x4>,
+    pub color: ShaderUniform<SUVec4>,
+    light_direction: ShaderUniform<SUVec3>,
+    ambient: ShaderUniform<SUFloat>,
+    pos_buffer: Vec<[f32; 3]>,
+    offset: usize,
+}
+
+impl CrowdShader {
+    /// Sets the direction light travels in and how bright unlit surfaces are, in `[0, 1]`.
+    /// Defaults to straight down with an ambient of `0.3`
+    pub fn set_light(&self, gl: &GlGraphics, direction: [f32; 3], ambient: f32) {
+        let length = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt().max(1e-6);
+        self.light_direction.set(gl, &[direction[0] / length, direction[1] / length, direction[2] / length]);
+        self.ambient.set(gl, ambient);
+    }
+}
+
+impl Shader for CrowdShader {
+    type Vertex = [f32; 3];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("CrowdShader requires a GlGraphics to find its uniforms");
+        let header = glsl_header(graphics.profile()).unwrap();
+        let (_, fragment_header) = skinning::headers(graphics.profile());
+        let program = Program::from_vs_fs(
+            &[header, VERTEX_SHADER].concat(),
+            &[fragment_header.as_str(), FRAGMENT_SHADER].concat(),
+        ).unwrap();
+        graphics.use_program(program.id());
+        graphics.get_uniform::<SUInt>("bone_texture").unwrap().set(graphics, 1);
+        let shader = Self {
+            view_proj: graphics.get_uniform("view_proj").unwrap(),
+            model: graphics.get_uniform("model").unwrap(),
+            color: graphics.get_uniform("color").unwrap(),
+            light_direction: graphics.get_uniform("light_direction").unwrap(),
+            ambient: graphics.get_uniform("ambient").unwrap(),
+            program,
+            pos_buffer: Vec::new(),
+            offset: 0,
+        };
+        shader.color.set(graphics, &[1.0; 4]);
+        shader.set_light(graphics, [0.0, -1.0, 0.0], 0.3);
+        graphics.clear_program();
+        shader
+    }
+
+    // Nothing is ever buffered
+    fn flush(&mut self) {
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 3]> {
+        &mut self.pos_buffer
+    }
+    fn model_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.model)
+    }
+    fn view_proj_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.view_proj)
+    }
+}
+
+/// A character in a `CrowdMesh`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrowdInstance {
+    /// Relative to the current transform when the crowd is drawn
+    pub position: [f32; 3],
+    /// Rotation around the y axis, in radians
+    pub yaw: f32,
+    pub scale: f32,
+    /// The clip played, as returned by `CrowdMesh::add_clip`
+    pub clip: usize,
+    /// How far through its clip the instance is in seconds, wrapping around at its end
+    pub time: f32,
+    /// How quickly `CrowdMesh::advance` moves `time` on
+    pub speed: f32,
+}
+
+impl CrowdInstance {
+    pub fn new(position: [f32; 3], clip: usize) -> Self {
+        Self {
+            position,
+            yaw: 0.0,
+            scale: 1.0,
+            clip,
+            time: 0.0,
+            speed: 1.0,
+        }
+    }
+}
+
+// The rows of a clip in the bone texture
+struct BakedClip {
+    first_row: usize,
+    frames: usize,
+    frame_rate: f32,
+}
+
+/// Many copies of a skinned character, each playing its own looping clip from its own point,
+/// drawn with `CrowdShader` by `ShaderContext::draw` in a single instanced draw call.
+///
+/// Clips are baked ahead of time into a float texture of skinning matrices, a row per frame, which
+/// the vertex shader blends between. Each instance only sends its transform and where it is in its
+/// clip, so hundreds of background characters cost little more than one. Use `SkinnedMesh` for the
+/// characters up close, which need exact poses or blending between clips.
+///
+/// The bone texture is `3 * joints` texels wide and as tall as all the clips' frames, which both
+/// have to fit within the device's largest texture size. `new` and `add_clip` fail when they don't.
+pub struct CrowdMesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u16>,
+    joints: Vec<[f32; 4]>,
+    weights: Vec<[f32; 4]>,
+    inverse_bind: Vec<Matrix4<f32>>,
+    pub texture: Texture,
+    /// Multiplied with the texture
+    pub color: [f32; 4],
+    pub draw_state: DrawState,
+    pub instances: Vec<CrowdInstance>,
+    clips: Vec<BakedClip>,
+    // The bone texture's texels, a row per frame
+    bones: Vec<[f32; 4]>,
+    bone_texture: GLuint,
+    bones_changed: bool,
+    max_texture_size: usize,
+    mesh: Option<Mesh<[f32; 3]>>,
+    instance_buffers: Option<Instances>,
+    instance_transforms: Vec<[f32; 4]>,
+    instance_animations: Vec<[f32; 4]>,
+}
+
+impl Drop for CrowdMesh {
+    fn drop(&mut self) {
+        if self.bone_texture != 0 {
+            unsafe {
+                gl::DeleteTextures(1, &self.bone_texture);
+            }
+        }
+    }
+}
+
+impl CrowdMesh {
+    /// Creates a crowd of a skinned mesh, taking the same vertices and joints as `SkinnedMesh::new`
+    pub fn new(
+        positions: Vec<[f32; 3]>,
+        normals: Vec<[f32; 3]>,
+        uvs: Vec<[f32; 2]>,
+        indices: Vec<u16>,
+        joints: Vec<[u16; 4]>,
+        weights: Vec<[f32; 4]>,
+        inverse_bind: Vec<Matrix4<f32>>,
+        texture: Texture
+    ) -> Result<Self, String> {
+        let count = positions.len();
+        if normals.len() != count || uvs.len() != count || joints.len() != count || weights.len() != count {
+            return Err("Crowd meshes need a normal, uv, joints and weights for every vertex".to_string());
+        }
+        if count > u16::max_value() as usize + 1 {
+            return Err(format!("Crowd meshes can have at most 65536 vertices, not {}", count));
+        }
+        if let Some(&x) = indices.iter().find(|&&x| x as usize >= count) {
+            return Err(format!("Index {} is out of bounds of the {} vertices", x, count));
+        }
+        if let Some(x) = joints.iter().flat_map(|x| x.iter()).find(|&&x| x as usize >= inverse_bind.len()) {
+            return Err(format!("Joint {} is out of bounds of the {} joints", x, inverse_bind.len()));
+        }
+        let mut max_texture_size = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_texture_size);
+        }
+        let max_texture_size = max_texture_size as usize;
+        if inverse_bind.len() * TEXELS_PER_JOINT > max_texture_size {
+            return Err(format!("The bone texture of {} joints is wider than the device's largest texture", inverse_bind.len()));
+        }
+        let weights = weights
+            .into_iter()
+            .map(|x| {
+                let total = x[0] + x[1] + x[2] + x[3];
+                if total > 0.0 { [x[0] / total, x[1] / total, x[2] / total, x[3] / total] } else { [1.0, 0.0, 0.0, 0.0] }
+            })
+            .collect();
+        Ok(Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            joints: joints.iter().map(|x| [x[0] as f32, x[1] as f32, x[2] as f32, x[3] as f32]).collect(),
+            weights,
+            inverse_bind,
+            texture,
+            color: [1.0; 4],
+            draw_state: DrawState::new_alpha(),
+            instances: Vec::new(),
+            clips: Vec::new(),
+            bones: Vec::new(),
+            bone_texture: 0,
+            bones_changed: false,
+            max_texture_size,
+            mesh: None,
+            instance_buffers: None,
+            instance_transforms: Vec::new(),
+            instance_animations: Vec::new(),
+        })
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.inverse_bind.len()
+    }
+
+    pub fn clip_count(&self) -> usize {
+        self.clips.len()
+    }
+
+    /// Bakes a looping clip from the pose of each of its frames, each being the transform of every
+    /// joint relative to the model like `SkinnedMesh::pose`. Returns the clip's index for `CrowdInstance::clip`
+    pub fn add_clip(&mut self, frames: &[Vec<Matrix4<f32>>], frame_rate: f32) -> Result<usize, String> {
+        if frames.is_empty() || frame_rate <= 0.0 {
+            return Err("Clips need at least one frame and a positive frame rate".to_string());
+        }
+        if let Some(frame) = frames.iter().find(|x| x.len() != self.inverse_bind.len()) {
+            return Err(format!("A frame has {} joints, but the mesh has {}", frame.len(), self.inverse_bind.len()));
+        }
+        let first_row = self.bones.len() / (self.inverse_bind.len() * TEXELS_PER_JOINT).max(1);
+        if first_row + frames.len() > self.max_texture_size {
+            return Err(format!("The bone texture would be {} frames tall, more than the device's largest texture", first_row + frames.len()));
+        }
+        let mut skins = self.inverse_bind.clone();
+        for frame in frames {
+            batch::multiply_pairs(frame, &self.inverse_bind, &mut skins);
+            for skin in &skins {
+                // The top three rows, the bottom is always `[0, 0, 0, 1]`
+                for row in 0..TEXELS_PER_JOINT {
+                    self.bones.push([skin.x[row], skin.y[row], skin.z[row], skin.w[row]]);
+                }
+            }
+        }
+        self.bones_changed = true;
+        self.clips.push(BakedClip { first_row, frames: frames.len(), frame_rate });
+        Ok(self.clips.len() - 1)
+    }
+
+    /// Bakes a clip sampled from `clip` at `frame_rate`, see `add_clip` and `StreamedClip::sample_pose`.
+    /// The clip's last frame is left out, as it loops back to the first
+    pub fn add_streamed_clip(&mut self, clip: &mut StreamedClip, parents: &[Option<usize>], frame_rate: f32) -> Result<usize, String> {
+        let count = ((clip.duration() * frame_rate).round() as usize).max(1);
+        let mut frames = Vec::with_capacity(count);
+        for frame in 0..count {
+            let mut pose = Vec::new();
+            clip.sample_pose(frame as f32 / frame_rate, parents, &mut pose)?;
+            frames.push(pose);
+        }
+        clip.release();
+        self.add_clip(&frames, frame_rate)
+    }
+
+    /// Moves every instance on through its clip by `dt` seconds times its speed
+    pub fn advance(&mut self, dt: f32) {
+        for instance in &mut self.instances {
+            instance.time += dt * instance.speed;
+        }
+    }
+
+    fn upload_bones(&mut self) {
+        let width = self.inverse_bind.len() * TEXELS_PER_JOINT;
+        let height = self.bones.len() / width.max(1);
+        unsafe {
+            if self.bone_texture == 0 {
+                gl::GenTextures(1, &mut self.bone_texture);
+            }
+            gl::BindTexture(gl::TEXTURE_2D, self.bone_texture);
+            // Float textures can't be filtered everywhere, the shader fetches texels directly
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA32F as i32,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGBA,
+                gl::FLOAT,
+                self.bones.as_ptr() as *const _,
+            );
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        self.bones_changed = false;
+    }
+}
+
+impl Drawable for CrowdMesh {
+    type Shader = CrowdShader;
+
+    fn draw_with(
+        &mut self,
+        data: &mut CrowdShader,
+        graphics: &mut GlGraphics,
+        _context: &Context,
+        _cache: &mut ViewProj,
+        _transforms: &mut Transforms
+    ) {
+        self.instance_transforms.clear();
+        self.instance_animations.clear();
+        for instance in &self.instances {
+            let clip = match self.clips.get(instance.clip) {
+                Some(x) => x,
+                None => continue,
+            };
+            let [x, y, z] = instance.position;
+            self.instance_transforms.push([x, y, z, instance.yaw]);
+            let frame = (instance.time * clip.frame_rate).rem_euclid(clip.frames as f32);
+            self.instance_animations.push([clip.first_row as f32, clip.frames as f32, frame, instance.scale]);
+        }
+        if self.instance_transforms.is_empty() {
+            return;
+        }
+        if self.bones_changed {
+            self.upload_bones();
+        }
+        if self.mesh.is_none() {
+            self.mesh = Some(Mesh::new(&self.positions)
+                .with_normals(&self.normals)
+                .with_uvs(&self.uvs)
+                .with_indices(&self.indices)
+                .with_attribute("joints", &self.joints)
+                .with_attribute("weights", &self.weights));
+        }
+        let instances = self.instance_buffers.get_or_insert_with(Instances::new);
+        instances.set("instance_transform", &self.instance_transforms);
+        instances.set("instance_animation", &self.instance_animations);
+
+        let (color, bone_texture) = (self.color, self.bone_texture);
+        self.mesh.as_mut().unwrap().draw_instanced(graphics, data, &self.draw_state, Some(&self.texture), instances, |shader, gl| {
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE1);
+                gl::BindTexture(gl::TEXTURE_2D, bone_texture);
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+            shader.color.set(gl, &color);
+        });
+    }
+}
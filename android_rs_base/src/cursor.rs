@@ -0,0 +1,112 @@
+use piston::input::{Input, Motion};
+use opengl_graphics::GlGraphics;
+use graphics::{Context, Line, Polygon, Transformed};
+
+/// What `Cursor::draw` draws
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CursorStyle {
+    /// Nothing, when the system cursor is visible
+    Hidden,
+    /// An arrow with its tip at the cursor
+    Arrow,
+    /// A crosshair centred on the cursor, with a gap of `gap` pixels in the middle
+    Crosshair { size: f64, gap: f64 },
+}
+
+/// Tracks the mouse from input events and draws a custom cursor.
+///
+/// When the pointer is captured (see `AppConfig::set_pointer_capture`) the cursor is moved by relative
+/// motion and kept on screen, or kept centred with `lock_to_center` for first person cameras.
+pub struct Cursor {
+    pub style: CursorStyle,
+    pub color: [f32; 4],
+    /// Multiplies relative motion
+    pub sensitivity: f64,
+    /// Keeps the cursor in the middle of the screen while relative motion is still accumulated
+    pub lock_to_center: bool,
+    position: [f64; 2],
+    delta: [f64; 2],
+    size: [f64; 2],
+    inside: bool,
+}
+
+impl Cursor {
+    /// Creates a crosshair cursor
+    pub fn new() -> Self {
+        Self {
+            style: CursorStyle::Crosshair { size: 10.0, gap: 3.0 },
+            color: [1.0, 1.0, 1.0, 0.9],
+            sensitivity: 1.0,
+            lock_to_center: false,
+            position: [0.0, 0.0],
+            delta: [0.0, 0.0],
+            size: [0.0, 0.0],
+            inside: true,
+        }
+    }
+
+    /// Updates the cursor from an input event, pass every event from `AppImpl::input` here
+    pub fn input(&mut self, input: &Input) {
+        match *input {
+            Input::Move(Motion::MouseCursor(position)) => {
+                self.delta[0] += position[0] - self.position[0];
+                self.delta[1] += position[1] - self.position[1];
+                self.position = position;
+            },
+            Input::Move(Motion::MouseRelative([dx, dy])) => {
+                let (dx, dy) = (dx * self.sensitivity, dy * self.sensitivity);
+                self.delta[0] += dx;
+                self.delta[1] += dy;
+                self.position[0] = (self.position[0] + dx).max(0.0).min(self.size[0]);
+                self.position[1] = (self.position[1] + dy).max(0.0).min(self.size[1]);
+            },
+            Input::Cursor(inside) => self.inside = inside,
+            Input::Resize(args) => self.size = args.window_size,
+            _ => {}
+        }
+        if self.lock_to_center {
+            self.position = [self.size[0] / 2.0, self.size[1] / 2.0];
+        }
+    }
+
+    /// Sets the size of the screen the cursor is kept inside, it's otherwise taken from resize events
+    pub fn set_screen_size(&mut self, size: [f64; 2]) {
+        self.size = size;
+    }
+
+    /// The position of the cursor in window coordinates
+    pub fn position(&self) -> [f64; 2] {
+        self.position
+    }
+
+    /// Takes the motion accumulated since this was last called, for turning a camera
+    pub fn take_delta(&mut self) -> [f64; 2] {
+        std::mem::replace(&mut self.delta, [0.0, 0.0])
+    }
+
+    /// Draws the cursor, call this last in `draw_overlay_2d` so it's on top
+    pub fn draw(&self, c: &Context, gl: &mut GlGraphics) {
+        if !self.inside {
+            return;
+        }
+        let [x, y] = self.position;
+        match self.style {
+            CursorStyle::Hidden => {},
+            CursorStyle::Arrow => {
+                const ARROW: [[f64; 2]; 7] = [
+                    [0.0, 0.0], [0.0, 16.0], [4.0, 12.0], [7.0, 18.0],
+                    [9.0, 17.0], [6.0, 11.0], [11.0, 11.0],
+                ];
+                Polygon::new(self.color).draw(&ARROW, &c.draw_state, c.transform.trans(x, y), gl);
+            },
+            CursorStyle::Crosshair { size, gap } => {
+                let line = Line::new(self.color, 1.0);
+                let transform = c.transform.trans(x, y);
+                line.draw([-size, 0.0, -gap, 0.0], &c.draw_state, transform, gl);
+                line.draw([gap, 0.0, size, 0.0], &c.draw_state, transform, gl);
+                line.draw([0.0, -size, 0.0, -gap], &c.draw_state, transform, gl);
+                line.draw([0.0, gap, 0.0, size], &c.draw_state, transform, gl);
+            },
+        }
+    }
+}
@@ -0,0 +1,38 @@
+/// Asks the system to run the display at `rate` frames per second, returning whether the request was made.
+///
+/// Needs Android 11 (API 30), where `ANativeWindow_setFrameRate` was added; always fails on older
+/// versions and other platforms. The system picks the closest refresh rate the display supports, and
+/// may ignore the request entirely (in battery saver, for example). `0.0` clears the request
+pub fn request_frame_rate(rate: f32) -> bool {
+    #[cfg(target_os = "android")]
+    {
+        use std::os::raw::{c_char, c_int, c_void};
+        extern "C" {
+            fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+            fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        }
+        const RTLD_NOW: c_int = 2;
+        // ANATIVEWINDOW_FRAME_RATE_COMPATIBILITY_DEFAULT, as games adapt to whatever they're given
+        const COMPATIBILITY_DEFAULT: i8 = 0;
+        type SetFrameRate = unsafe extern "C" fn(*const c_void, f32, i8) -> i32;
+        unsafe {
+            // Looked up at runtime, linking to it would stop the app loading before API 30
+            let library = dlopen(b"libandroid.so\0".as_ptr() as *const c_char, RTLD_NOW);
+            if library.is_null() {
+                return false;
+            }
+            let symbol = dlsym(library, b"ANativeWindow_setFrameRate\0".as_ptr() as *const c_char);
+            let window = android_glue::get_native_window();
+            if symbol.is_null() || window.is_null() {
+                return false;
+            }
+            let set_frame_rate: SetFrameRate = std::mem::transmute(symbol);
+            return set_frame_rate(window, rate, COMPATIBILITY_DEFAULT) == 0;
+        }
+    }
+    #[cfg(not(target_os = "android"))]
+    {
+        let _ = rate;
+        false
+    }
+}
@@ -0,0 +1,248 @@
+use opengl_graphics::{GLSL, GlGraphics, RenderTarget, gl};
+use opengl_graphics::gl::types::GLuint;
+use opengl_graphics::shader_utils::{Shader, Program, DynamicAttribute};
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUInt, SUMat4x4, SUFloat, SUVec2};
+use graphics::{DrawState, Viewport};
+use graphics::draw_state::Blend;
+use crate::post::{PostShader, fit_target};
+use crate::storage::glsl_header;
+
+const VERTEX_SHADER: &str = "uniform mat4 view_proj;
+uniform mat4 model;
+
+in vec3 pos;
+in vec2 uv;
+
+out vec2 v_UV;
+
+void main() {
+    v_UV = uv;
+    gl_Position = view_proj * model * vec4(pos, 1.0);
+}";
+
+// Offsets can be negative, so each axis is split over two channels and blended additively:
+// r/g hold the positive/negative x offset and b/a the positive/negative y offset
+const FRAGMENT_SHADER: &str = "precision mediump float;
+uniform sampler2D s_texture;
+uniform float strength;
+uniform vec2 scroll;
+uniform vec2 ring;
+
+in vec2 v_UV;
+
+out vec4 outColor;
+
+void main() {
+    vec2 offset;
+    if (ring.y > 0.0) {
+        vec2 from_center = v_UV - 0.5;
+        float dist = length(from_center);
+        float profile = 1.0 - clamp(abs(dist - ring.x) / ring.y, 0.0, 1.0);
+        offset = from_center / max(dist, 0.0001) * profile;
+    } else {
+        vec4 sample_color = texture(s_texture, v_UV + scroll);
+        offset = (sample_color.rg * 2.0 - 1.0) * sample_color.a;
+    }
+    offset *= strength;
+    outColor = vec4(max(offset.x, 0.0), max(-offset.x, 0.0), max(offset.y, 0.0), max(-offset.y, 0.0));
+}";
+
+const COMPOSITE_FRAGMENT_SHADER: &str = "precision mediump float;
+uniform sampler2D s_texture;
+uniform sampler2D s_offsets;
+uniform vec2 texel_size;
+uniform float strength;
+
+in vec2 v_UV;
+
+out vec4 outColor;
+
+void main() {
+    vec4 encoded = texture(s_offsets, v_UV);
+    vec2 offset = vec2(encoded.r - encoded.g, encoded.b - encoded.a) * strength * texel_size;
+    outColor = texture(s_texture, v_UV + offset);
+}";
+
+/// The shader drawables use to write into the offset buffer of `Distortion`.
+///
+/// Offsets are either read from the texture given when drawing (red and green hold the
+/// x and y offset mapped to `[0, 1]`, alpha fades it out), or when `ring` has a non-zero
+/// width, form a ring pushing outwards from the centre of the uvs for shockwaves.
+pub struct DistortionShader {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 3]>,
+    uv: DynamicAttribute<[f32; 2]>,
+    pub view_proj: ShaderUniform<SUMat4x4>,
+    model: ShaderUniform<SUMat4x4>,
+    /// How much to distort by, from `0` to `1`
+    pub strength: ShaderUniform<SUFloat>,
+    /// Added to the uvs the texture is sampled with, animate this for heat haze and water
+    pub scroll: ShaderUniform<SUVec2>,
+    /// The radius and width of a shockwave ring in uv space
+    pub ring: ShaderUniform<SUVec2>,
+    pos_buffer: Vec<[f32; 3]>,
+    uv_buffer: Vec<[f32; 2]>,
+    offset: usize,
+    texture_id: GLuint,
+}
+
+impl Drop for DistortionShader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl Shader for DistortionShader {
+    type Vertex = [f32; 3];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("DistortionShader requires a GlGraphics to find its uniforms");
+        let header = glsl_header(graphics.profile()).unwrap();
+        let program = Program::from_vs_fs(
+            &[header, VERTEX_SHADER].concat(),
+            &[header, FRAGMENT_SHADER].concat(),
+        ).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        let shader = Self {
+            pos: DynamicAttribute::xyz(program.id(), "pos").unwrap(),
+            uv: DynamicAttribute::uv(program.id(), "uv").unwrap(),
+            view_proj: graphics.get_uniform("view_proj").unwrap(),
+            model: graphics.get_uniform("model").unwrap(),
+            strength: graphics.get_uniform("strength").unwrap(),
+            scroll: graphics.get_uniform("scroll").unwrap(),
+            ring: graphics.get_uniform("ring").unwrap(),
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 3]; 1024],
+            uv_buffer: vec![[0.0; 2]; 1024],
+            offset: 0,
+            texture_id: 0,
+        };
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture_id);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            self.uv.bind_vao(self.vao);
+            self.uv.set(&self.uv_buffer[..self.offset]);
+            gl::DrawArrays(gl::TRIANGLES, 0, self.offset as i32);
+            gl::BindVertexArray(0);
+        }
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 3]> {
+        &mut self.pos_buffer
+    }
+    fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> {
+        Some(&mut self.uv_buffer)
+    }
+    fn texture_id(&mut self) -> Option<&mut GLuint> {
+        Some(&mut self.texture_id)
+    }
+    fn has_texture(&self) -> bool {
+        true
+    }
+    fn model_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.model)
+    }
+}
+
+/// A post effect which warps the scene by offsets drawn into a separate buffer,
+/// for heat haze, shockwaves and water.
+///
+/// Draw the scene between `begin_scene` and `end_scene`, and anything distorting
+/// it with a `DistortionShader` between `begin_offsets` and `end_offsets`, before
+/// drawing the warped scene to the screen with `draw`.
+pub struct Distortion {
+    /// The largest offset in pixels
+    pub strength: f32,
+    scene: Option<RenderTarget>,
+    offsets: Option<RenderTarget>,
+    shader: PostShader,
+    texel_size: ShaderUniform<SUVec2>,
+    strength_uniform: ShaderUniform<SUFloat>,
+}
+
+impl Distortion {
+    /// Creates a distortion pass with a strength of 16 pixels
+    pub fn new(gl: &mut GlGraphics) -> Self {
+        let shader = PostShader::with_fragment(gl, COMPOSITE_FRAGMENT_SHADER).unwrap();
+        gl.use_program(shader.program());
+        gl.get_uniform::<SUInt>("s_offsets").unwrap().set(gl, 1);
+        let distortion = Self {
+            strength: 16.0,
+            scene: None,
+            offsets: None,
+            texel_size: gl.get_uniform("texel_size").unwrap(),
+            strength_uniform: gl.get_uniform("strength").unwrap(),
+            shader,
+        };
+        gl.clear_program();
+        distortion
+    }
+
+    /// Starts drawing the scene which gets distorted
+    pub fn begin_scene(&mut self, gl: &mut GlGraphics, viewport: Viewport) {
+        fit_target(&mut self.scene, viewport).bind(gl);
+    }
+
+    /// Goes back to drawing to the screen with `viewport`
+    pub fn end_scene(&mut self, gl: &mut GlGraphics, viewport: Viewport) {
+        if let Some(scene) = &self.scene {
+            scene.unbind(gl, viewport);
+        }
+    }
+
+    /// Starts drawing offsets, clearing them
+    pub fn begin_offsets(&mut self, gl: &mut GlGraphics, viewport: Viewport) {
+        fit_target(&mut self.offsets, viewport).bind(gl);
+        gl.clear_buffers(Some([0.0; 4]), None, None);
+    }
+
+    /// Goes back to drawing to the screen with `viewport`
+    pub fn end_offsets(&mut self, gl: &mut GlGraphics, viewport: Viewport) {
+        if let Some(offsets) = &self.offsets {
+            offsets.unbind(gl, viewport);
+        }
+    }
+
+    /// The draw state to draw offsets with, so overlapping distortions add up
+    pub fn offset_draw_state() -> DrawState {
+        DrawState::default().blend(Blend::Add)
+    }
+
+    /// Draws the distorted scene over the screen
+    pub fn draw(&mut self, gl: &mut GlGraphics) {
+        let (scene, offsets) = match (&self.scene, &self.offsets) {
+            (Some(scene), Some(offsets)) => (scene, offsets),
+            _ => return,
+        };
+        let size = scene.viewport().draw_size;
+        let texel_size = [1.0 / size[0] as f32, 1.0 / size[1] as f32];
+        let strength = self.strength;
+        let (texel_uniform, strength_uniform) = (&self.texel_size, &self.strength_uniform);
+        self.shader.set_texture(1, offsets.texture());
+        self.shader.draw(gl, scene.texture(), &DrawState::default(), |_, gl| {
+            texel_uniform.set(gl, &texel_size);
+            strength_uniform.set(gl, strength);
+        });
+    }
+}
@@ -0,0 +1,64 @@
+/// Embeds a GLSL program in the binary and generates a struct with a typed field for each of its uniforms,
+/// so they're looked up once instead of by name every frame.
+///
+/// The paths are relative to the file the macro is used in, as with `include_str!`. The shaders are
+/// compiled and every uniform is checked to exist in `new`, which fails with the offending name otherwise.
+/// In debug builds, files with the same names in `shader_override_path()` are used instead when present.
+///
+/// ```ignore
+/// include_shader! {
+///     pub struct GlowShader {
+///         vertex: "../assets/shaders/glow.vert",
+///         fragment: "../assets/shaders/glow.frag",
+///         pub tint: SUVec4,
+///         pub intensity: SUFloat,
+///     }
+/// }
+///
+/// let glow = GlowShader::new(gl)?;
+/// glow.bind(gl);
+/// glow.intensity.set(gl, 2.5);
+/// ```
+#[macro_export]
+macro_rules! include_shader {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            vertex: $vertex:expr,
+            fragment: $fragment:expr,
+            $($uniform_vis:vis $uniform:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            pub program: $crate::__opengl_graphics::shader_utils::Program,
+            $($uniform_vis $uniform: $crate::__opengl_graphics::shader_uniforms::ShaderUniform<$ty>,)*
+        }
+
+        impl $name {
+            pub const VERTEX_SOURCE: &'static str = include_str!($vertex);
+            pub const FRAGMENT_SOURCE: &'static str = include_str!($fragment);
+
+            /// Compiles the program and finds its uniforms
+            pub fn new(gl: &mut $crate::__opengl_graphics::GlGraphics) -> Result<Self, String> {
+                let file_name = |path: &'static str| std::path::Path::new(path).file_name().and_then(|x| x.to_str()).unwrap_or(path);
+                let vertex = $crate::shader_source(file_name($vertex), Self::VERTEX_SOURCE);
+                let fragment = $crate::shader_source(file_name($fragment), Self::FRAGMENT_SOURCE);
+                let program = $crate::__opengl_graphics::shader_utils::Program::from_vs_fs(&vertex, &fragment)
+                    .map_err(|e| format!("{} failed to compile: {}", stringify!($name), e))?;
+                gl.use_program(program.id());
+                $(let $uniform = gl.get_uniform::<$ty>(stringify!($uniform));)*
+                gl.clear_program();
+                Ok(Self {
+                    $($uniform: $uniform.ok_or_else(|| format!("{} has no uniform {}", stringify!($name), stringify!($uniform)))?,)*
+                    program,
+                })
+            }
+
+            /// Makes this the program in use, so its uniforms can be set
+            pub fn bind(&self, gl: &mut $crate::__opengl_graphics::GlGraphics) {
+                gl.use_program(self.program.id());
+            }
+        }
+    };
+}
@@ -0,0 +1,130 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::{Map, Value};
+
+/// An append-only log of structured events, written as one JSON object per line.
+///
+/// Each line holds the event's `name`, the `session` id of the log, the time in milliseconds since
+/// the unix epoch (`time`) and since the log was opened (`uptime`), and any fields given to `log`.
+/// When the current file grows past the size limit it's renamed to `<name>.1` (older files shifting
+/// up to `<name>.<max_files>`, after which they're deleted), so apps can upload and remove the
+/// rotated files with their own backend without the log growing forever.
+pub struct EventLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+    session: String,
+    opened: SystemTime,
+}
+
+impl EventLog {
+    /// Opens the log at `path`, appending to it, with a new session id.
+    /// Files are rotated at 1 MiB, keeping 4 old ones
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        let opened = SystemTime::now();
+        let nanos = opened.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size: 1 << 20,
+            max_files: 4,
+            session: format!("{:x}-{:x}", nanos, std::process::id()),
+            opened,
+        })
+    }
+
+    /// Opens `events.log` in the app's internal storage
+    pub fn open_default() -> io::Result<Self> {
+        Self::open(crate::internal_storage_path().join("events.log"))
+    }
+
+    /// Sets the size in bytes after which the file is rotated, and how many rotated files are kept
+    pub fn rotation(mut self, max_size: u64, max_files: usize) -> Self {
+        self.max_size = max_size;
+        self.max_files = max_files;
+        self
+    }
+
+    /// The id shared by every event logged since this log was opened
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// The path of the file being written to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The rotated files which exist, oldest first, ready to be uploaded and deleted
+    pub fn rotated_files(&self) -> Vec<PathBuf> {
+        (1..=self.max_files)
+            .rev()
+            .map(|x| self.rotated_path(x))
+            .filter(|x| x.exists())
+            .collect()
+    }
+
+    /// Appends the event `name` with `fields`, which should be a JSON object (anything else is stored under `value`)
+    pub fn log(&mut self, name: &str, fields: Value) -> io::Result<()> {
+        let now = SystemTime::now();
+        let mut line = match fields {
+            Value::Object(fields) => fields,
+            Value::Null => Map::new(),
+            value => {
+                let mut map = Map::new();
+                map.insert("value".to_string(), value);
+                map
+            },
+        };
+        line.insert("name".to_string(), name.into());
+        line.insert("session".to_string(), self.session.clone().into());
+        line.insert("time".to_string(), (now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64).into());
+        line.insert("uptime".to_string(), (now.duration_since(self.opened).unwrap_or_default().as_millis() as u64).into());
+        let mut line = Value::Object(line).to_string();
+        line.push('\n');
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Waits for logged events to reach storage, such as before the app is paused
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Moves the current file to `<name>.1`, shifting older ones along, and starts a new one
+    pub fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            self.file.set_len(0)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", index));
+        self.path.with_file_name(name)
+    }
+}
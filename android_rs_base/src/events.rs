@@ -0,0 +1,104 @@
+use piston::input::event_id::EventId;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A custom event, identified by the name of its type.
+///
+/// Created with `CustomEvent::new` or posted with `AppConfig::post_event`, and read back with
+/// `downcast_ref` or the `dispatch_event!` macro.
+#[derive(Clone)]
+pub struct CustomEvent {
+    pub id: EventId,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl CustomEvent {
+    /// Wraps `value` in an event whose id is the name of `T`
+    pub fn new<T: Any + Send + Sync>(value: T) -> Self {
+        Self {
+            id: event_id::<T>(),
+            value: Arc::new(value),
+        }
+    }
+
+    /// Wraps an event received from piston
+    pub fn from_raw(id: EventId, value: Arc<dyn Any + Send + Sync>) -> Self {
+        Self { id, value }
+    }
+
+    /// Whether this holds a `T`
+    pub fn is<T: Any>(&self) -> bool {
+        self.value.is::<T>()
+    }
+
+    /// Gets the value if it's a `T`
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    /// Gets the value as a piston custom event payload
+    pub fn value(&self) -> &Arc<dyn Any + Send + Sync> {
+        &self.value
+    }
+}
+
+/// The id used for custom events holding a `T`
+pub fn event_id<T: Any>() -> EventId {
+    EventId(std::any::type_name::<T>())
+}
+
+/// The custom event types an app accepts, see `AppConfig::events`.
+///
+/// Events with an unregistered id, or whose value isn't of the type registered under their id,
+/// are dropped before reaching the app or the user thread.
+pub struct EventRegistry {
+    types: HashMap<&'static str, TypeId>,
+}
+
+impl EventRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self { types: HashMap::new() }
+    }
+
+    /// Accepts events holding a `T`, returning their id
+    pub fn register<T: Any + Send + Sync>(&mut self) -> EventId {
+        let id = event_id::<T>();
+        self.types.insert(id.0, TypeId::of::<T>());
+        id
+    }
+
+    /// Whether `T` has been registered
+    pub fn is_registered<T: Any>(&self) -> bool {
+        self.types.get(event_id::<T>().0) == Some(&TypeId::of::<T>())
+    }
+
+    /// Whether `event` holds the type registered under its id
+    pub fn accepts(&self, event: &CustomEvent) -> bool {
+        self.types.get(event.id.0) == Some(&(*event.value).type_id())
+    }
+}
+
+/// Calls the handler matching the type of a `CustomEvent`, evaluating to whether one was found.
+///
+/// ```ignore
+/// fn handle_custom_event(&mut self, event: CustomEvent, timestamp: Option<TimeStamp>) {
+///     dispatch_event!(event,
+///         ScoreChanged => |e: &ScoreChanged| self.score = e.score,
+///         LevelLoaded => |e: &LevelLoaded| self.start(e),
+///     );
+/// }
+/// ```
+#[macro_export]
+macro_rules! dispatch_event {
+    ($event:expr, $($ty:ty => $handler:expr),* $(,)?) => {{
+        let event: &$crate::CustomEvent = &$event;
+        $(if let Some(value) = event.downcast_ref::<$ty>() {
+            ($handler)(value);
+            true
+        } else)* {
+            false
+        }
+    }};
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use crate::preferences::Preferences;
+
+// Remote values are persisted with this prefix so they're known before the next fetch completes
+const REMOTE_PREFIX: &str = "remote.";
+const OVERRIDE_PREFIX: &str = "override.";
+
+/// Feature flags used to turn experimental parts of an app on and off at runtime.
+///
+/// A flag's value is its persisted override if it has one, otherwise the last value fetched
+/// remotely, otherwise its default as given to `AppConfig::feature_flag`. Unknown flags are off.
+/// Overrides and remote values are kept in the app's internal storage once `AppContainer::init` has run.
+pub struct FeatureFlags {
+    defaults: HashMap<String, bool>,
+    remote: HashMap<String, bool>,
+    overrides: HashMap<String, bool>,
+    storage: Option<Preferences>,
+    pending: Option<Receiver<Result<String, String>>>,
+}
+
+impl FeatureFlags {
+    pub fn new() -> Self {
+        Self {
+            defaults: HashMap::new(),
+            remote: HashMap::new(),
+            overrides: HashMap::new(),
+            storage: None,
+            pending: None,
+        }
+    }
+
+    /// Sets the value `name` has when nothing else sets it
+    pub fn set_default(&mut self, name: &str, enabled: bool) {
+        self.defaults.insert(name.to_string(), enabled);
+    }
+
+    /// Whether the flag `name` is on
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.overrides.get(name)
+            .or_else(|| self.remote.get(name))
+            .or_else(|| self.defaults.get(name))
+            .cloned()
+            .unwrap_or(false)
+    }
+
+    /// Forces `name` on or off, persisting across launches until cleared.
+    /// The override applies even when persisting it fails
+    pub fn set_override(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        self.overrides.insert(name.to_string(), enabled);
+        if let Some(storage) = self.storage.as_mut() {
+            storage.set(&format!("{}{}", OVERRIDE_PREFIX, name), enabled);
+        }
+        self.save()
+    }
+
+    /// Removes the override of `name`, returning whether it had one
+    pub fn clear_override(&mut self, name: &str) -> Result<bool, String> {
+        if let Some(storage) = self.storage.as_mut() {
+            storage.remove(&format!("{}{}", OVERRIDE_PREFIX, name));
+        }
+        let removed = self.overrides.remove(name).is_some();
+        self.save()?;
+        Ok(removed)
+    }
+
+    /// The names of every flag with a default, remote value or override
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self.defaults.keys()
+            .chain(self.remote.keys())
+            .chain(self.overrides.keys())
+            .map(|x| x.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Fetches remote values on a background thread with `fetch`, which gives a JSON object of flag
+    /// names to booleans (or an error), such as the body of a request to the app's backend.
+    ///
+    /// The values are applied by `poll_remote`, which `AppContainer` calls after each update.
+    /// Replaces any fetch still in progress
+    pub fn fetch_remote<F: FnOnce() -> Result<String, String> + Send + 'static>(&mut self, fetch: F) {
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(fetch());
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Whether a remote fetch is in progress
+    pub fn is_fetching(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Applies the result of a finished remote fetch, returning it (or `None` if there's nothing new)
+    pub fn poll_remote(&mut self) -> Option<Result<(), String>> {
+        let result = match self.pending.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err("The remote fetch panicked".to_string()),
+        };
+        self.pending = None;
+        Some(result.and_then(|json| self.apply_remote(&json)))
+    }
+
+    /// Replaces the remote values with those in a JSON object of flag names to booleans
+    pub fn apply_remote(&mut self, json: &str) -> Result<(), String> {
+        let values = match serde_json::from_str(json).map_err(|e| e.to_string())? {
+            serde_json::Value::Object(values) => values,
+            _ => return Err("Remote feature flags aren't an object".to_string()),
+        };
+        let remote = values
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_bool()?)))
+            .collect::<HashMap<_, _>>();
+        if let Some(storage) = self.storage.as_mut() {
+            for name in self.remote.keys() {
+                storage.remove(&format!("{}{}", REMOTE_PREFIX, name));
+            }
+            for (name, &enabled) in &remote {
+                storage.set(&format!("{}{}", REMOTE_PREFIX, name), enabled);
+            }
+        }
+        self.remote = remote;
+        self.save()
+    }
+
+    // Reads persisted overrides and remote values from `storage`, and keeps it to persist changes in
+    pub(crate) fn attach_storage(&mut self, mut storage: Preferences) -> Result<(), String> {
+        for name in storage.keys() {
+            if let Some(flag) = name.strip_prefix(OVERRIDE_PREFIX) {
+                if let Some(enabled) = storage.get(name) {
+                    self.overrides.entry(flag.to_string()).or_insert(enabled);
+                }
+            } else if let Some(flag) = name.strip_prefix(REMOTE_PREFIX) {
+                if let Some(enabled) = storage.get(name) {
+                    self.remote.entry(flag.to_string()).or_insert(enabled);
+                }
+            }
+        }
+        // Overrides made before the storage was attached
+        for (name, &enabled) in &self.overrides {
+            storage.set(&format!("{}{}", OVERRIDE_PREFIX, name), enabled);
+        }
+        self.storage = Some(storage);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        match self.storage.as_ref() {
+            Some(storage) => storage.save().map_err(|e| format!("Could not save feature flags: {}", e)),
+            None => Ok(()),
+        }
+    }
+}
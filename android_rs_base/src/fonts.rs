@@ -0,0 +1,11 @@
+use opengl_graphics::{GlyphCache, TextureSettings, Filter};
+
+/// Loads the TrueType font in the asset `name` into a glyph cache, for use with `GlGraphics::draw_text`.
+///
+/// The font's data is kept for the rest of the app's life, so load each font once (usually in `AppImpl::new`)
+pub fn load_font(name: &str) -> Result<GlyphCache<'static>, String> {
+    let bytes = android_glue::load_asset(name).map_err(|_| format!("Could not load asset {}", name))?;
+    let bytes: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+    let settings = TextureSettings::new().filter(Filter::Linear);
+    GlyphCache::from_bytes(bytes, (), settings).map_err(|_| format!("{} isn't a valid font", name))
+}
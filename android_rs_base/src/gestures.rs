@@ -0,0 +1,183 @@
+use piston::input::{Touch, TouchArgs};
+use std::collections::HashMap;
+
+/// The direction of a swipe, by its dominant axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// A gesture recognised by a `GestureRecognizer`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Tap([f64; 2]),
+    DoubleTap([f64; 2]),
+    /// The direction and velocity in pixels per second of a single finger swipe
+    Swipe(SwipeDirection, [f64; 2]),
+    /// How much the distance between two fingers changed since the last pinch
+    Pinch(f64),
+    LongPress([f64; 2]),
+}
+
+/// The thresholds gestures are recognised with, set with `AppConfig::gesture_config`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GestureConfig {
+    /// How far (in pixels) a finger can move and still be tapping or long pressing
+    pub tap_slop: f64,
+    /// The longest a tap can last, in milliseconds
+    pub tap_timeout: f64,
+    /// The longest time between two taps of a double tap, in milliseconds
+    pub double_tap_timeout: f64,
+    /// How long a finger has to stay down for a long press, in milliseconds
+    pub long_press_timeout: f64,
+    /// The shortest distance a swipe covers, in pixels
+    pub swipe_min_distance: f64,
+    /// The slowest a swipe can be, in pixels per second
+    pub swipe_min_velocity: f64,
+}
+
+impl GestureConfig {
+    /// Thresholds close to Android's defaults
+    pub fn new() -> Self {
+        Self {
+            tap_slop: 24.0,
+            tap_timeout: 300.0,
+            double_tap_timeout: 300.0,
+            long_press_timeout: 500.0,
+            swipe_min_distance: 80.0,
+            swipe_min_velocity: 300.0,
+        }
+    }
+}
+
+struct TrackedTouch {
+    start: [f64; 2],
+    start_time: f64,
+    position: [f64; 2],
+}
+
+/// Turns touches into taps, double taps, swipes, pinches and long presses.
+///
+/// `AppContainer` runs one and passes what it finds to the `on_*` gesture methods of `AppImpl`.
+/// Only gestures made with a single finger from start to end produce taps, swipes and long presses.
+pub struct GestureRecognizer {
+    touches: HashMap<i64, TrackedTouch>,
+    // Whether more than one finger has been down since the first touched
+    multi_touch: bool,
+    long_pressed: bool,
+    last_tap: Option<(f64, [f64; 2])>,
+    pinch_distance: Option<f64>,
+}
+
+impl GestureRecognizer {
+    pub fn new() -> Self {
+        Self {
+            touches: HashMap::new(),
+            multi_touch: false,
+            long_pressed: false,
+            last_tap: None,
+            pinch_distance: None,
+        }
+    }
+
+    /// Handles a touch which happened at `time` milliseconds, returning the gesture it completes, if any
+    pub fn touch(&mut self, config: &GestureConfig, args: &TouchArgs, time: f64) -> Option<Gesture> {
+        let position = args.position();
+        match args.touch {
+            Touch::Start => {
+                if self.touches.is_empty() {
+                    self.multi_touch = false;
+                    self.long_pressed = false;
+                }
+                self.touches.insert(args.id, TrackedTouch { start: position, start_time: time, position });
+                if self.touches.len() > 1 {
+                    self.multi_touch = true;
+                    self.pinch_distance = self.two_finger_distance();
+                }
+                None
+            },
+            Touch::Move => {
+                self.touches.get_mut(&args.id)?.position = position;
+                let distance = self.two_finger_distance()?;
+                let previous = self.pinch_distance.replace(distance)?;
+                if previous > 0.0 {
+                    Some(Gesture::Pinch(distance / previous))
+                } else {
+                    None
+                }
+            },
+            Touch::Cancel => {
+                self.touches.remove(&args.id);
+                self.pinch_distance = None;
+                self.multi_touch = true;
+                None
+            },
+            Touch::End => {
+                let touch = self.touches.remove(&args.id)?;
+                self.pinch_distance = self.two_finger_distance();
+                if self.multi_touch || self.long_pressed {
+                    return None;
+                }
+                let delta = [position[0] - touch.start[0], position[1] - touch.start[1]];
+                let distance = (delta[0] * delta[0] + delta[1] * delta[1]).sqrt();
+                let duration = time - touch.start_time;
+                if distance <= config.tap_slop {
+                    if duration > config.tap_timeout {
+                        return None;
+                    }
+                    return match self.last_tap.take() {
+                        Some((last_time, last)) if time - last_time <= config.double_tap_timeout
+                            && (position[0] - last[0]).hypot(position[1] - last[1]) <= config.tap_slop * 2.0 => {
+                            Some(Gesture::DoubleTap(position))
+                        },
+                        _ => {
+                            self.last_tap = Some((time, position));
+                            Some(Gesture::Tap(position))
+                        },
+                    };
+                }
+                let seconds = (duration / 1000.0).max(0.001);
+                let velocity = [delta[0] / seconds, delta[1] / seconds];
+                if distance < config.swipe_min_distance || distance / seconds < config.swipe_min_velocity {
+                    return None;
+                }
+                let direction = if delta[0].abs() > delta[1].abs() {
+                    if delta[0] > 0.0 { SwipeDirection::Right } else { SwipeDirection::Left }
+                } else if delta[1] > 0.0 {
+                    SwipeDirection::Down
+                } else {
+                    SwipeDirection::Up
+                };
+                Some(Gesture::Swipe(direction, velocity))
+            },
+        }
+    }
+
+    /// Checks for gestures which complete by waiting, such as long presses, at `time` milliseconds
+    pub fn update(&mut self, config: &GestureConfig, time: f64) -> Option<Gesture> {
+        if self.multi_touch || self.long_pressed || self.touches.len() != 1 {
+            return None;
+        }
+        let touch = self.touches.values().next()?;
+        let moved = (touch.position[0] - touch.start[0]).hypot(touch.position[1] - touch.start[1]);
+        if moved <= config.tap_slop && time - touch.start_time >= config.long_press_timeout {
+            self.long_pressed = true;
+            self.last_tap = None;
+            Some(Gesture::LongPress(touch.position))
+        } else {
+            None
+        }
+    }
+
+    fn two_finger_distance(&self) -> Option<f64> {
+        if self.touches.len() != 2 {
+            return None;
+        }
+        let mut positions = self.touches.values().map(|x| x.position);
+        let (a, b) = (positions.next()?, positions.next()?);
+        Some((a[0] - b[0]).hypot(a[1] - b[1]))
+    }
+}
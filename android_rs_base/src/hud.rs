@@ -0,0 +1,211 @@
+use graphics::{Context, Transformed};
+
+/// A point on the screen HUD elements can be anchored to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// The position of the anchor as a fraction of the screen, and the direction offsets move in
+    fn placement(self) -> ([f64; 2], [f64; 2]) {
+        match self {
+            Anchor::TopLeft => ([0.0, 0.0], [1.0, 1.0]),
+            Anchor::Top => ([0.5, 0.0], [1.0, 1.0]),
+            Anchor::TopRight => ([1.0, 0.0], [-1.0, 1.0]),
+            Anchor::Left => ([0.0, 0.5], [1.0, 1.0]),
+            Anchor::Center => ([0.5, 0.5], [1.0, 1.0]),
+            Anchor::Right => ([1.0, 0.5], [-1.0, 1.0]),
+            Anchor::BottomLeft => ([0.0, 1.0], [1.0, -1.0]),
+            Anchor::Bottom => ([0.5, 1.0], [1.0, -1.0]),
+            Anchor::BottomRight => ([1.0, 1.0], [-1.0, -1.0]),
+        }
+    }
+}
+
+/// Insets from each edge of the screen which shouldn't be drawn over, such as notches and rounded corners, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SafeArea {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+/// Converts between the three units layouts are made in:
+///
+/// - Pixels (px), the coordinates used when drawing and given by touch events
+/// - Density independent pixels (dp), which are the same physical size on every screen, 160 to an inch
+/// - Virtual units, a fixed number of which span the shorter side of the screen, so a layout in them
+///   covers the same fraction of a phone and of a tablet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Units {
+    /// How many pixels make up one dp
+    pub dp_scale: f64,
+    /// How many pixels make up one virtual unit
+    pub virtual_scale: f64,
+}
+
+impl Units {
+    /// The smallest size touch targets should be, in dp, as recommended by Android's guidelines
+    pub const MIN_TOUCH_TARGET_DP: f64 = 48.0;
+
+    /// Creates units for a screen of `size` pixels whose shorter side is `virtual_size` virtual units,
+    /// using the screen's density
+    pub fn new(size: [f64; 2], virtual_size: f64) -> Self {
+        Self {
+            dp_scale: screen_density(),
+            virtual_scale: size[0].min(size[1]) / virtual_size.max(std::f64::EPSILON),
+        }
+    }
+
+    /// Creates units for the viewport of `c`, see `new`
+    pub fn from_context(c: &Context, virtual_size: f64) -> Self {
+        Self::new(c.viewport.map(|x| x.window_size).unwrap_or([0.0, 0.0]), virtual_size)
+    }
+
+    pub fn dp_to_px(&self, dp: f64) -> f64 {
+        dp * self.dp_scale
+    }
+
+    pub fn px_to_dp(&self, px: f64) -> f64 {
+        px / self.dp_scale
+    }
+
+    pub fn virtual_to_px(&self, units: f64) -> f64 {
+        units * self.virtual_scale
+    }
+
+    pub fn px_to_virtual(&self, px: f64) -> f64 {
+        px / self.virtual_scale
+    }
+
+    pub fn dp_to_virtual(&self, dp: f64) -> f64 {
+        self.px_to_virtual(self.dp_to_px(dp))
+    }
+
+    pub fn virtual_to_dp(&self, units: f64) -> f64 {
+        self.px_to_dp(self.virtual_to_px(units))
+    }
+
+    /// Converts a point (such as a touch position) from pixels to dp
+    pub fn point_to_dp(&self, point: [f64; 2]) -> [f64; 2] {
+        [self.px_to_dp(point[0]), self.px_to_dp(point[1])]
+    }
+
+    /// Converts a point from pixels to virtual units
+    pub fn point_to_virtual(&self, point: [f64; 2]) -> [f64; 2] {
+        [self.px_to_virtual(point[0]), self.px_to_virtual(point[1])]
+    }
+
+    /// Gets `c` scaled so one unit is one dp
+    pub fn dp_context(&self, c: &Context) -> Context {
+        c.zoom(self.dp_scale)
+    }
+
+    /// Gets `c` scaled so one unit is one virtual unit
+    pub fn virtual_context(&self, c: &Context) -> Context {
+        c.zoom(self.virtual_scale)
+    }
+
+    /// Whether `touch` (in pixels) hits `rect` (`[x, y, w, h]` in pixels), growing the rectangle to at least
+    /// `MIN_TOUCH_TARGET_DP` in each direction so small buttons stay easy to press
+    pub fn hit_test(&self, touch: [f64; 2], rect: [f64; 4]) -> bool {
+        let min = self.dp_to_px(Self::MIN_TOUCH_TARGET_DP);
+        let (grow_x, grow_y) = (((min - rect[2]) / 2.0).max(0.0), ((min - rect[3]) / 2.0).max(0.0));
+        touch[0] >= rect[0] - grow_x && touch[0] <= rect[0] + rect[2] + grow_x
+            && touch[1] >= rect[1] - grow_y && touch[1] <= rect[1] + rect[3] + grow_y
+    }
+}
+
+/// Places 2D HUD elements relative to the edges of the screen, in density independent pixels (dp).
+///
+/// Create one at the start of `draw_overlay_2d` and draw each element with the `Context` from `anchor`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hud {
+    /// The size of the screen in pixels
+    pub size: [f64; 2],
+    /// How many pixels make up one dp
+    pub dp_scale: f64,
+    pub safe_area: SafeArea,
+}
+
+impl Hud {
+    /// Creates a HUD covering the viewport of `c`, using the screen's density and no safe area
+    pub fn new(c: &Context) -> Self {
+        Self {
+            size: c.viewport.map(|x| x.window_size).unwrap_or([0.0, 0.0]),
+            dp_scale: screen_density(),
+            safe_area: SafeArea::default(),
+        }
+    }
+
+    /// Sets the area to keep clear of
+    pub fn with_safe_area(mut self, safe_area: SafeArea) -> Self {
+        self.safe_area = safe_area;
+        self
+    }
+
+    /// Converts dp to pixels
+    pub fn dp(&self, dp: f64) -> f64 {
+        dp * self.dp_scale
+    }
+
+    /// Gets units for the HUD's screen with `virtual_size` virtual units along its shorter side
+    pub fn units(&self, virtual_size: f64) -> Units {
+        Units {
+            dp_scale: self.dp_scale,
+            virtual_scale: self.size[0].min(self.size[1]) / virtual_size.max(std::f64::EPSILON),
+        }
+    }
+
+    /// Gets the position in pixels of `anchor` moved `offset` dp towards the middle of the screen
+    pub fn position(&self, anchor: Anchor, offset: [f64; 2]) -> [f64; 2] {
+        let ([fx, fy], [dx, dy]) = anchor.placement();
+        let area = &self.safe_area;
+        let width = self.size[0] - area.left - area.right;
+        let height = self.size[1] - area.top - area.bottom;
+        [
+            area.left + width * fx + self.dp(offset[0]) * dx,
+            area.top + height * fy + self.dp(offset[1]) * dy,
+        ]
+    }
+
+    /// Gets `c` with its origin at `position(anchor, offset)` and scaled so one unit is one dp.
+    ///
+    /// Elements anchored to the right or bottom should be drawn in negative coordinates to stay on screen,
+    /// such as a rectangle at `[-100.0, -40.0, 100.0, 40.0]` for `BottomRight`.
+    pub fn anchor(&self, c: &Context, anchor: Anchor, offset: [f64; 2]) -> Context {
+        let [x, y] = self.position(anchor, offset);
+        c.trans(x, y).zoom(self.dp_scale)
+    }
+}
+
+/// Gets the number of pixels per dp of the screen, `1.0` if it can't be determined
+pub fn screen_density() -> f64 {
+    #[cfg(target_os = "android")]
+    {
+        use std::os::raw::{c_char, c_int};
+        extern "C" {
+            fn __system_property_get(name: *const c_char, value: *mut c_char) -> c_int;
+        }
+        // PROP_VALUE_MAX is 92
+        let mut value = [0 as c_char; 92];
+        let len = unsafe { __system_property_get(b"ro.sf.lcd_density\0".as_ptr() as *const c_char, value.as_mut_ptr()) };
+        if len > 0 {
+            let bytes = value[..len as usize].iter().map(|&x| x as u8).collect::<Vec<_>>();
+            if let Some(dpi) = std::str::from_utf8(&bytes).ok().and_then(|x| x.trim().parse::<f64>().ok()) {
+                return dpi / 160.0;
+            }
+        }
+    }
+    1.0
+}
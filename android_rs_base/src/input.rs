@@ -1,9 +1,216 @@
-use piston::input::Input;
-use piston::input::event_id::EventId;
-use std::sync::Arc;
-use std::any::Any;
+use piston::input::{Input, Motion, Touch};
+use std::collections::{HashMap, VecDeque};
+use crate::events::CustomEvent;
 
+/// An event sent to the user thread, see `AppContainer::spawn_user_thread`
 pub enum InputEvent {
-    Piston(Input),
-    Custom(EventId, Arc<dyn Any + Send + Sync>)
+    /// An input event and when it was received, in nanoseconds since the app started
+    Piston(Input, u64),
+    Custom(CustomEvent)
+}
+
+/// A position a pointer was at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointerSample {
+    /// When the sample was received, in nanoseconds since the app started
+    pub time_ns: u64,
+    pub position: [f64; 2],
+    /// The pressure of touches in `[0, 1]`, `1` for the mouse
+    pub pressure: f64,
+}
+
+/// The recent positions of every pointer, oldest first, read with `AppConfig::pointer_history`.
+///
+/// The mouse is stored with the id `-1`. Samples are stamped when the container receives them,
+/// as the windowing layer doesn't pass on the batched history Android keeps for each touch.
+pub struct PointerHistory {
+    /// How long samples are kept, in nanoseconds
+    pub max_age_ns: u64,
+    pointers: HashMap<i64, VecDeque<PointerSample>>,
+}
+
+impl PointerHistory {
+    /// The id the mouse's samples are stored under
+    pub const MOUSE_ID: i64 = -1;
+
+    /// Creates a history keeping 100ms of samples
+    pub fn new() -> Self {
+        Self {
+            max_age_ns: 100_000_000,
+            pointers: HashMap::new(),
+        }
+    }
+
+    /// Records the pointer in `input`, if any
+    pub(crate) fn record(&mut self, input: &Input, time_ns: u64) {
+        let (id, position, pressure) = match *input {
+            Input::Move(Motion::MouseCursor(position)) => (Self::MOUSE_ID, position, 1.0),
+            Input::Move(Motion::Touch(args)) => {
+                if args.touch == Touch::Start {
+                    self.pointers.remove(&args.id);
+                }
+                (args.id, args.position(), args.pressure())
+            },
+            _ => return,
+        };
+        let max_age = self.max_age_ns;
+        let samples = self.pointers.entry(id).or_insert_with(VecDeque::new);
+        samples.push_back(PointerSample { time_ns, position, pressure });
+        while samples.front().map(|x| time_ns - x.time_ns > max_age).unwrap_or(false) {
+            samples.pop_front();
+        }
+    }
+
+    /// Gets the samples of pointer `id`, oldest first. Kept after a touch ends until it's reused
+    pub fn samples(&self, id: i64) -> impl Iterator<Item = &PointerSample> {
+        self.pointers.get(&id).into_iter().flatten()
+    }
+
+    /// Gets the most recent sample of pointer `id`
+    pub fn latest(&self, id: i64) -> Option<PointerSample> {
+        self.pointers.get(&id).and_then(|x| x.back()).copied()
+    }
+
+    /// The average velocity of pointer `id` over its history, in pixels per second
+    pub fn velocity(&self, id: i64) -> Option<[f64; 2]> {
+        let samples = self.pointers.get(&id)?;
+        let (first, last) = (samples.front()?, samples.back()?);
+        let dt = (last.time_ns - first.time_ns) as f64 / 1e9;
+        if dt <= 0.0 {
+            return None;
+        }
+        Some([
+            (last.position[0] - first.position[0]) / dt,
+            (last.position[1] - first.position[1]) / dt,
+        ])
+    }
+}
+
+/// What a touch did last
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    Down,
+    Move,
+    Up,
+    /// The touch was taken over by the system, such as by a gesture to leave the app
+    Cancel,
+}
+
+/// A finger (or stylus) on the screen, see `TouchState`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TouchPointer {
+    pub id: i64,
+    pub phase: TouchPhase,
+    pub position: [f64; 2],
+    /// Where the touch went down
+    pub start_position: [f64; 2],
+    /// The pressure in `[0, 1]`
+    pub pressure: f64,
+    /// When the touch went down, in nanoseconds since the app started
+    pub down_time_ns: u64,
+}
+
+impl TouchPointer {
+    /// Whether the finger is still on the screen
+    pub fn is_active(&self) -> bool {
+        self.phase == TouchPhase::Down || self.phase == TouchPhase::Move
+    }
+
+    /// How far the touch has moved since it went down
+    pub fn delta(&self) -> [f64; 2] {
+        [self.position[0] - self.start_position[0], self.position[1] - self.start_position[1]]
+    }
+}
+
+/// Every touch currently on the screen, in the order they went down, read with `AppConfig::touch_state`
+/// and passed to `AppImpl::touch` after each touch event.
+///
+/// Touches which are lifted or cancelled stay with that phase until the end of the next update,
+/// so `update` sees every touch end even when it went down and up between two updates.
+/// Each touch's recent samples are in `history`, under the touch's id.
+pub struct TouchState {
+    pointers: Vec<TouchPointer>,
+    history: PointerHistory,
+}
+
+impl TouchState {
+    pub fn new() -> Self {
+        Self {
+            pointers: Vec::new(),
+            history: PointerHistory::new(),
+        }
+    }
+
+    /// Records a pointer event in the history, and updates the touches if it's a touch
+    pub(crate) fn record(&mut self, input: &Input, time_ns: u64) {
+        self.history.record(input, time_ns);
+        let args = match *input {
+            Input::Move(Motion::Touch(ref args)) => args,
+            _ => return,
+        };
+        let (position, pressure) = (args.position(), args.pressure());
+        if args.touch == Touch::Start {
+            // An ended touch with the same id is kept for this update, until `clear_ended` drops it
+            self.pointers.retain(|x| x.id != args.id || !x.is_active());
+            self.pointers.push(TouchPointer {
+                id: args.id,
+                phase: TouchPhase::Down,
+                position,
+                start_position: position,
+                pressure,
+                down_time_ns: time_ns,
+            });
+            return;
+        }
+        let pointer = match self.pointers.iter_mut().find(|x| x.id == args.id && x.is_active()) {
+            Some(x) => x,
+            None => return,
+        };
+        pointer.phase = match args.touch {
+            Touch::End => TouchPhase::Up,
+            Touch::Cancel => TouchPhase::Cancel,
+            _ => TouchPhase::Move,
+        };
+        pointer.position = position;
+        pointer.pressure = pressure;
+    }
+
+    /// Drops the touches which ended, called after each update
+    pub(crate) fn clear_ended(&mut self) {
+        self.pointers.retain(TouchPointer::is_active);
+    }
+
+    /// Every touch, including those which ended since the last update
+    pub fn pointers(&self) -> &[TouchPointer] {
+        &self.pointers
+    }
+
+    /// The touches still on the screen
+    pub fn active(&self) -> impl Iterator<Item = &TouchPointer> {
+        self.pointers.iter().filter(|x| x.is_active())
+    }
+
+    pub fn get(&self, id: i64) -> Option<&TouchPointer> {
+        // Newest first, in case the id was reused since its last touch ended
+        self.pointers.iter().rev().find(|x| x.id == id)
+    }
+
+    /// The number of touches still on the screen
+    pub fn count(&self) -> usize {
+        self.active().count()
+    }
+
+    /// The first of the touches still on the screen to have gone down
+    pub fn primary(&self) -> Option<&TouchPointer> {
+        self.active().next()
+    }
+
+    /// The recent samples of every touch and the mouse, also read with `AppConfig::pointer_history`
+    pub fn history(&self) -> &PointerHistory {
+        &self.history
+    }
+
+    pub(crate) fn history_mut(&mut self) -> &mut PointerHistory {
+        &mut self.history
+    }
 }
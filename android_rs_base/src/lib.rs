@@ -3,16 +3,110 @@ mod app_implementor;
 mod app_config;
 mod input;
 mod storage;
+mod preferences;
+mod benchmark;
+mod profiler;
+mod shadows;
+mod shaders_3d;
+mod pbr;
+mod models;
+mod skinning;
+mod lighting_2d;
+mod light_probes;
+mod lighting;
+mod post;
+mod outlines;
+mod distortion;
+mod water;
+mod weather;
+mod share;
+mod hud;
+mod cursor;
+mod touch;
+mod commands;
+mod params;
+mod tracks;
+mod embed;
+mod events;
+mod gestures;
+mod color;
+mod display;
+mod boot;
+mod fonts;
+mod assets;
+mod features;
+mod event_log;
+mod arena;
+mod state;
+mod streaming;
+mod animation;
+mod clips;
+mod crowd;
+mod batching;
+mod sprite;
+mod tilemap;
+#[cfg(feature = "test-harness")]
+mod testing;
 
 pub use self::app_config::*;
 pub use self::app_container::*;
 pub use self::app_implementor::*;
 pub use self::storage::*;
-pub use self::input::InputEvent;
+pub use self::input::{InputEvent, PointerSample, PointerHistory, TouchPhase, TouchPointer, TouchState};
+pub use self::events::{CustomEvent, EventRegistry, event_id};
+pub use self::preferences::Preferences;
+pub use self::benchmark::PERFORMANCE_SCORE_KEY;
+pub use self::profiler::{FrameProfiler, ProfileScope, ProfileStats};
+pub use self::shadows::{BlobShadow, BlobShadowShader, ShadowSettings};
+pub use self::shaders_3d::{Colored3d, Textured3d, ColoredMesh3d, TexturedMesh3d};
+pub use self::pbr::{PbrMaterial, PbrShader, PbrMesh};
+pub use self::models::Model;
+pub use self::skinning::{SkinnedShader, SkinnedMesh, SkinningMode, MAX_PALETTE_JOINTS};
+pub use self::lighting_2d::{Light2d, Spot2d, Lighting2d, LightShader2d, NormalMapShader2d, MAX_LIGHTS_2D, MAX_OCCLUDER_SEGMENTS};
+pub use self::light_probes::{SH_AMBIENT_GLSL, ShProbe, LightProbes, ShUniforms};
+pub use self::lighting::{LIGHTING_GLSL, MAX_DIRECTIONAL_LIGHTS, MAX_POINT_LIGHTS, DirectionalLight, PointLight, LightSet, LightUniforms, apply_lights};
+pub use self::post::{PostShader, POST_VERTEX_SHADER};
+pub use self::outlines::{Outlines, outline_id_color};
+pub use self::distortion::{Distortion, DistortionShader};
+pub use self::water::{Water, WaterShader};
+pub use self::weather::{FOG_GLSL, Fog, FogMode, FogUniforms, ColorGradient, DayNightCycle, WeatherKind, WeatherParticles};
+pub use self::share::{shared_files_path, save_for_sharing};
+pub use self::hud::{Anchor, SafeArea, Hud, Units, screen_density};
+pub use self::cursor::{Cursor, CursorStyle};
+pub use self::color::{ColorSpace, srgb_to_display_p3, display_p3_to_srgb, is_in_srgb_gamut, convert_color, convert_image};
+pub use self::display::request_frame_rate;
+pub use self::boot::BootConfig;
+pub use self::fonts::load_font;
+pub use self::assets::{Assets, AssetData};
+pub use self::features::FeatureFlags;
+pub use self::event_log::EventLog;
+pub use self::arena::{FrameArena, Pool};
+pub use self::state::StateStore;
+pub use self::streaming::{TextureStreamer, StreamId};
+pub use self::animation::{Easing, Tweenable, Animated, Track, Tween, TweenTrack, Delay, Call, Sequence, Parallel, AnimationId, Timeline};
+pub use self::clips::{StreamedClip, joint_matrix};
+pub use self::crowd::{CrowdShader, CrowdMesh, CrowdInstance};
+pub use self::batching::{StaticBatcher, StaticId};
+pub use self::sprite::Sprite;
+pub use self::tilemap::{TileMap, TileLayer, Tileset, TILE_FLIPPED_HORIZONTALLY, TILE_FLIPPED_VERTICALLY, TILE_FLIPPED_DIAGONALLY, TILE_GID_MASK};
+#[cfg(feature = "test-harness")]
+pub use self::testing::TestContainer;
+pub use self::gestures::{Gesture, GestureConfig, GestureRecognizer, SwipeDirection};
+pub use self::touch::{PredictedTouch, TouchPredictor, PressureCurve, TouchContact, TouchFilter};
+pub use self::commands::{Command, CommandStack};
+pub use self::params::{ParamFile, ParamValue};
+pub use self::tracks::{TrackInputs, Function, Expr, Curve, UniformTrack};
 
 // Useful to have pre-imported
 
 pub use piston::input::{RenderArgs, UpdateArgs};
+pub use image::RgbaImage;
+pub use matrices::nav::{NavMesh, NavGrid, NavSettings};
+
+#[doc(hidden)]
+pub use opengl_graphics as __opengl_graphics;
+
+use std::path::PathBuf;
 
 /// Sets RUST_BACKTRACE=1 to enable backtraces in android, useful to get backtraces
 pub fn enable_backtrace() {
@@ -20,3 +114,25 @@ pub fn enable_backtrace() {
     let key = "RUST_BACKTRACE";
     env::set_var(key, "1");
 }
+
+/// Gets the app's internal storage directory, which `AppContainer::init` creates.
+///
+/// On Android this is `/data/data/<package>/files`. On desktop it's a `storage` directory next
+/// to the executable, or in the working directory when the executable's can't be found
+pub fn internal_storage_path() -> PathBuf {
+    if cfg!(target_os = "android") {
+        let cmdline = std::fs::read("/proc/self/cmdline").unwrap_or_default();
+        let package = cmdline
+            .split(|&x| x == 0)
+            .next()
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .unwrap_or_default();
+        PathBuf::from("/data/data").join(package).join("files")
+    } else {
+        std::env::current_exe()
+            .ok()
+            .and_then(|x| x.parent().map(|x| x.to_path_buf()))
+            .unwrap_or_default()
+            .join("storage")
+    }
+}
@@ -0,0 +1,229 @@
+use opengl_graphics::GlGraphics;
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUVec3Array};
+use cgmath::{Point3, Vector3, InnerSpace, MetricSpace};
+use image::RgbaImage;
+
+/// GLSL for ambient light from spherical harmonics, to paste into fragment shaders after the
+/// precision statement. Lit shaders add `sh_ambient(normal) * albedo` to their direct lighting,
+/// with the normal in world space. Set the uniforms with `ShUniforms`.
+pub const SH_AMBIENT_GLSL: &str = "
+uniform vec3 sh_coefficients[9];
+
+vec3 sh_ambient(vec3 n) {
+    return max(
+        sh_coefficients[0] * 0.282095 +
+        sh_coefficients[1] * 0.488603 * n.y +
+        sh_coefficients[2] * 0.488603 * n.z +
+        sh_coefficients[3] * 0.488603 * n.x +
+        sh_coefficients[4] * 1.092548 * n.x * n.y +
+        sh_coefficients[5] * 1.092548 * n.y * n.z +
+        sh_coefficients[6] * 0.315392 * (3.0 * n.z * n.z - 1.0) +
+        sh_coefficients[7] * 1.092548 * n.x * n.z +
+        sh_coefficients[8] * 0.546274 * (n.x * n.x - n.y * n.y),
+        vec3(0.0));
+}
+";
+
+// How much each band is blurred by the cosine lobe, turning radiance into irradiance
+const BAND_CONVOLUTION: [f32; 3] = [std::f32::consts::PI, std::f32::consts::PI * 2.0 / 3.0, std::f32::consts::PI / 4.0];
+
+/// The ambient light arriving from every direction at a point, as the first nine spherical
+/// harmonics of the irradiance (in linear colour). This is enough to light diffuse surfaces
+/// with the overall colour of the sky, ground and nearby walls, without any reflections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShProbe {
+    pub coefficients: [[f32; 3]; 9],
+}
+
+impl Default for ShProbe {
+    fn default() -> Self {
+        Self::uniform([0.0; 3])
+    }
+}
+
+impl ShProbe {
+    /// A probe which lights every direction with `color`, like a flat ambient term
+    pub fn uniform(color: [f32; 3]) -> Self {
+        let mut coefficients = [[0.0; 3]; 9];
+        coefficients[0] = [color[0] / 0.282095, color[1] / 0.282095, color[2] / 0.282095];
+        Self { coefficients }
+    }
+
+    /// Bakes the irradiance of a cubemap given as its six square faces, in the order
+    /// `+x, -x, +y, -y, +z, -z` and laid out as OpenGL expects them. Texels are sRGB.
+    ///
+    /// Every texel is visited, so this is best done while loading and with small faces
+    /// (16 by 16 is plenty, as only the low frequencies are kept).
+    pub fn from_cubemap(faces: &[RgbaImage; 6]) -> Self {
+        let mut coefficients = [[0.0f32; 3]; 9];
+        let mut total_weight = 0.0;
+        for (face, image) in faces.iter().enumerate() {
+            let (width, height) = image.dimensions();
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let u = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+                let direction = cube_direction(face, u, v);
+                // The solid angle the texel covers, smaller towards the corners
+                let weight = 4.0 / ((1.0 + u * u + v * v).powf(1.5) * width as f32 * height as f32);
+                let color = [srgb_to_linear(pixel[0]), srgb_to_linear(pixel[1]), srgb_to_linear(pixel[2])];
+                for (coefficient, basis) in coefficients.iter_mut().zip(sh_basis(direction).iter()) {
+                    for channel in 0..3 {
+                        coefficient[channel] += color[channel] * basis * weight;
+                    }
+                }
+                total_weight += weight;
+            }
+        }
+        // Corrects for the sampling error, the weights should add up to the whole sphere
+        let normalize = if total_weight > 0.0 { 4.0 * std::f32::consts::PI / total_weight } else { 0.0 };
+        for (i, coefficient) in coefficients.iter_mut().enumerate() {
+            let band = match i { 0 => 0, 1..=3 => 1, _ => 2 };
+            for channel in coefficient.iter_mut() {
+                *channel *= normalize * BAND_CONVOLUTION[band] / std::f32::consts::PI;
+            }
+        }
+        Self { coefficients }
+    }
+
+    /// The diffuse light arriving at a surface facing `normal`, as `sh_ambient` computes it
+    pub fn evaluate(&self, normal: Vector3<f32>) -> [f32; 3] {
+        let basis = sh_basis(normal.normalize());
+        let mut color = [0.0f32; 3];
+        for (coefficient, basis) in self.coefficients.iter().zip(basis.iter()) {
+            for channel in 0..3 {
+                color[channel] += coefficient[channel] * basis;
+            }
+        }
+        [color[0].max(0.0), color[1].max(0.0), color[2].max(0.0)]
+    }
+
+    /// Blends towards `other` by `t`
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut coefficients = self.coefficients;
+        for (lhs, rhs) in coefficients.iter_mut().zip(other.coefficients.iter()) {
+            for channel in 0..3 {
+                lhs[channel] += (rhs[channel] - lhs[channel]) * t;
+            }
+        }
+        Self { coefficients }
+    }
+}
+
+/// Probes placed around a scene, stored in `ShaderStorage::light_probes`. Each object samples
+/// them once at its position before drawing, so moving objects pick up the ambient light of
+/// the room they're in, without any per-pixel cost beyond `sh_ambient`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LightProbes {
+    probes: Vec<(Point3<f32>, ShProbe)>,
+    /// Used when there are no probes
+    pub fallback: ShProbe,
+}
+
+impl LightProbes {
+    /// The number of nearby probes blended when sampling
+    pub const BLENDED: usize = 4;
+
+    pub fn new(fallback: ShProbe) -> Self {
+        Self {
+            probes: Vec::new(),
+            fallback,
+        }
+    }
+
+    /// Places `probe` at `position`, usually baked from a cubemap rendered there
+    pub fn add(&mut self, position: Point3<f32>, probe: ShProbe) {
+        self.probes.push((position, probe));
+    }
+
+    pub fn clear(&mut self) {
+        self.probes.clear();
+    }
+
+    pub fn probes(&self) -> &[(Point3<f32>, ShProbe)] {
+        &self.probes
+    }
+
+    /// The ambient light at `position`, blending the nearest `BLENDED` probes by inverse
+    /// squared distance. Sampling every probe, so keep them to a few dozen per scene
+    pub fn sample(&self, position: Point3<f32>) -> ShProbe {
+        let mut nearest = self.probes
+            .iter()
+            .map(|(x, probe)| (x.distance2(position), probe))
+            .collect::<Vec<_>>();
+        nearest.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        nearest.truncate(Self::BLENDED);
+        match nearest.first() {
+            None => return self.fallback,
+            Some(&(distance, probe)) if distance < 1e-6 => return *probe,
+            _ => {}
+        }
+        let mut result = [[0.0f32; 3]; 9];
+        let total = nearest.iter().map(|x| 1.0 / x.0).sum::<f32>();
+        for (distance, probe) in nearest {
+            let weight = 1.0 / distance / total;
+            for (lhs, rhs) in result.iter_mut().zip(probe.coefficients.iter()) {
+                for channel in 0..3 {
+                    lhs[channel] += rhs[channel] * weight;
+                }
+            }
+        }
+        ShProbe { coefficients: result }
+    }
+}
+
+/// The uniform declared by `SH_AMBIENT_GLSL` in a shader
+#[derive(Clone, Copy)]
+pub struct ShUniforms {
+    coefficients: ShaderUniform<SUVec3Array>,
+}
+
+impl ShUniforms {
+    /// Finds the uniform of the program currently in use, or `None` if it doesn't use `SH_AMBIENT_GLSL`
+    pub fn find(gl: &GlGraphics) -> Option<Self> {
+        Some(Self {
+            coefficients: gl.get_uniform("sh_coefficients")?,
+        })
+    }
+
+    /// Sets the uniform to `probe`, the shader needs to be in use
+    pub fn set(&self, gl: &GlGraphics, probe: &ShProbe) {
+        self.coefficients.set(gl, &probe.coefficients[..]);
+    }
+}
+
+// The first nine real spherical harmonics at `n`, matching `SH_AMBIENT_GLSL`
+fn sh_basis(n: Vector3<f32>) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * n.y,
+        0.488603 * n.z,
+        0.488603 * n.x,
+        1.092548 * n.x * n.y,
+        1.092548 * n.y * n.z,
+        0.315392 * (3.0 * n.z * n.z - 1.0),
+        1.092548 * n.x * n.z,
+        0.546274 * (n.x * n.x - n.y * n.y),
+    ]
+}
+
+// The direction through `u, v` (in `-1..1`, from the top left) of a cubemap face
+fn cube_direction(face: usize, u: f32, v: f32) -> Vector3<f32> {
+    let direction = match face {
+        0 => Vector3::new(1.0, -v, -u),
+        1 => Vector3::new(-1.0, -v, u),
+        2 => Vector3::new(u, 1.0, v),
+        3 => Vector3::new(u, -1.0, -v),
+        4 => Vector3::new(u, -v, 1.0),
+        _ => Vector3::new(-u, -v, -1.0),
+    };
+    direction.normalize()
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,172 @@
+use opengl_graphics::GlGraphics;
+use opengl_graphics::shader_utils::Shader;
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUInt, SUVec3, SUVec3Array, SUVec4Array};
+use cgmath::{Point3, Vector3, InnerSpace, MetricSpace};
+
+/// The most directional lights uploaded to a shader
+pub const MAX_DIRECTIONAL_LIGHTS: usize = 4;
+/// The most point lights uploaded to a shader
+pub const MAX_POINT_LIGHTS: usize = 8;
+
+/// GLSL declaring the standard light uniforms `apply_lights` uploads, to paste into fragment shaders
+/// after the precision statement. `lambert(world_position, normal)` sums the diffuse light reaching
+/// a surface, multiply it with the surface's colour. Any of the uniforms can be left out of a shader
+/// which declares them itself instead.
+pub const LIGHTING_GLSL: &str = "
+uniform vec3 ambient_light;
+uniform int directional_light_count;
+uniform vec3 directional_light_directions[4];
+uniform vec3 directional_light_colors[4];
+uniform int point_light_count;
+uniform vec4 point_light_positions[8];
+uniform vec3 point_light_colors[8];
+
+vec3 lambert(vec3 world_position, vec3 normal) {
+    vec3 light = ambient_light;
+    for (int i = 0; i < directional_light_count; i++) {
+        light += directional_light_colors[i] * max(dot(normal, -directional_light_directions[i]), 0.0);
+    }
+    for (int i = 0; i < point_light_count; i++) {
+        vec3 to_light = point_light_positions[i].xyz - world_position;
+        float distance = length(to_light);
+        float falloff = clamp(1.0 - distance / point_light_positions[i].w, 0.0, 1.0);
+        light += point_light_colors[i] * falloff * falloff * max(dot(normal, to_light / max(distance, 1e-4)), 0.0);
+    }
+    return light;
+}
+";
+
+/// Light travelling in one direction everywhere, like the sun
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// The direction light travels in, so pointing down for a sun overhead
+    pub direction: Vector3<f32>,
+    /// The colour, multiplied by the intensity
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3<f32>, color: [f32; 3], intensity: f32) -> Self {
+        Self { direction, color, intensity }
+    }
+}
+
+/// Light shining in every direction from a point, fading out smoothly by `range`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Point3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// The distance at which the light has faded out completely
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn new(position: Point3<f32>, color: [f32; 3], intensity: f32, range: f32) -> Self {
+        Self { position, color, intensity, range }
+    }
+}
+
+/// The lights of a scene, stored in `ShaderStorage::lights` and uploaded to shaders with `apply_lights`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightSet {
+    /// Light reaching every surface equally
+    pub ambient: [f32; 3],
+    pub directional: Vec<DirectionalLight>,
+    pub points: Vec<PointLight>,
+}
+
+impl Default for LightSet {
+    fn default() -> Self {
+        Self {
+            ambient: [0.1; 3],
+            directional: Vec::new(),
+            points: Vec::new(),
+        }
+    }
+}
+
+impl LightSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sorts the point lights from closest to furthest from `position`, so an object at `position`
+    /// is lit by the nearest `MAX_POINT_LIGHTS` when there are more
+    pub fn sort_points_by_distance(&mut self, position: Point3<f32>) {
+        self.points.sort_by(|a, b| {
+            a.position.distance2(position)
+                .partial_cmp(&b.position.distance2(position))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+}
+
+/// The standard light uniforms of a shader (see `LIGHTING_GLSL`), found by name.
+/// Finding them looks every name up, so keep these in the shader when drawing often
+#[derive(Clone, Copy)]
+pub struct LightUniforms {
+    ambient: Option<ShaderUniform<SUVec3>>,
+    directional_count: Option<ShaderUniform<SUInt>>,
+    directional_directions: Option<ShaderUniform<SUVec3Array>>,
+    directional_colors: Option<ShaderUniform<SUVec3Array>>,
+    point_count: Option<ShaderUniform<SUInt>>,
+    point_positions: Option<ShaderUniform<SUVec4Array>>,
+    point_colors: Option<ShaderUniform<SUVec3Array>>,
+}
+
+impl LightUniforms {
+    /// Finds whichever of the uniforms the program currently in use declares
+    pub fn find(gl: &GlGraphics) -> Self {
+        Self {
+            ambient: gl.get_uniform("ambient_light"),
+            directional_count: gl.get_uniform("directional_light_count"),
+            directional_directions: gl.get_uniform("directional_light_directions"),
+            directional_colors: gl.get_uniform("directional_light_colors"),
+            point_count: gl.get_uniform("point_light_count"),
+            point_positions: gl.get_uniform("point_light_positions"),
+            point_colors: gl.get_uniform("point_light_colors"),
+        }
+    }
+
+    /// Sets the uniforms to `lights`, the shader needs to be in use. Only the first
+    /// `MAX_DIRECTIONAL_LIGHTS` directional and `MAX_POINT_LIGHTS` point lights are uploaded
+    pub fn set(&self, gl: &GlGraphics, lights: &LightSet) {
+        let scale = |color: [f32; 3], intensity: f32| [color[0] * intensity, color[1] * intensity, color[2] * intensity];
+        let directional = &lights.directional[..lights.directional.len().min(MAX_DIRECTIONAL_LIGHTS)];
+        let points = &lights.points[..lights.points.len().min(MAX_POINT_LIGHTS)];
+        if let Some(x) = self.ambient {
+            x.set(gl, &lights.ambient);
+        }
+        if let Some(x) = self.directional_count {
+            x.set(gl, directional.len() as i32);
+        }
+        if let (Some(x), false) = (self.directional_directions, directional.is_empty()) {
+            let directions = directional.iter().map(|x| x.direction.normalize().into()).collect::<Vec<[f32; 3]>>();
+            x.set(gl, &directions[..]);
+        }
+        if let (Some(x), false) = (self.directional_colors, directional.is_empty()) {
+            let colors = directional.iter().map(|x| scale(x.color, x.intensity)).collect::<Vec<_>>();
+            x.set(gl, &colors[..]);
+        }
+        if let Some(x) = self.point_count {
+            x.set(gl, points.len() as i32);
+        }
+        if let (Some(x), false) = (self.point_positions, points.is_empty()) {
+            let positions = points.iter().map(|x| [x.position.x, x.position.y, x.position.z, x.range]).collect::<Vec<_>>();
+            x.set(gl, &positions[..]);
+        }
+        if let (Some(x), false) = (self.point_colors, points.is_empty()) {
+            let colors = points.iter().map(|x| scale(x.color, x.intensity)).collect::<Vec<_>>();
+            x.set(gl, &colors[..]);
+        }
+    }
+}
+
+/// Uploads `lights` to the standard light uniforms `shader` declares (see `LIGHTING_GLSL`),
+/// using its program. Uniforms the shader doesn't declare are skipped
+pub fn apply_lights<S: Shader>(gl: &mut GlGraphics, shader: &S, lights: &LightSet) {
+    gl.use_program(shader.program());
+    LightUniforms::find(gl).set(gl, lights);
+}
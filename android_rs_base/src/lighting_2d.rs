@@ -0,0 +1,681 @@
+use opengl_graphics::{GLSL, GlGraphics, RenderTarget, Texture, gl};
+use opengl_graphics::gl::types::GLuint;
+use opengl_graphics::shader_utils::{Shader, Program, DynamicAttribute};
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUInt, SUVec2, SUVec3, SUVec4, SUVec2Array, SUVec3Array, SUVec4Array};
+use opengl_graphics::ImageSize;
+use graphics::{Context, DrawState, Image, Viewport};
+use graphics::draw_state::Blend;
+use crate::storage::glsl_header;
+
+/// The maximum number of lights which affect each tile of a normal mapped sprite
+pub const MAX_LIGHTS_2D: usize = 8;
+/// The maximum number of occluder edges which cast shadows, extra edges are ignored
+pub const MAX_OCCLUDER_SEGMENTS: usize = 32;
+
+const HEADER: &str = "precision highp float;
+";
+
+// Shared between both lighting shaders, so normal mapped sprites are shadowed the same way
+const LIGHT_FUNCTIONS: &str = "
+uniform vec4 segments[32];
+uniform int segment_count;
+
+bool intersects(vec2 a, vec2 b, vec2 c, vec2 d) {
+    vec2 r = b - a;
+    vec2 s = d - c;
+    float denom = r.x * s.y - r.y * s.x;
+    if (abs(denom) < 0.0001) {
+        return false;
+    }
+    vec2 ca = c - a;
+    float t = (ca.x * s.y - ca.y * s.x) / denom;
+    float u = (ca.x * r.y - ca.y * r.x) / denom;
+    return t > 0.0 && t < 1.0 && u >= 0.0 && u <= 1.0;
+}
+
+const vec2 OFFSETS[5] = vec2[5](
+    vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(-1.0, 0.0), vec2(0.0, 1.0), vec2(0.0, -1.0)
+);
+
+// Fraction of the light's disc visible from `p`, which gives soft shadow edges
+float visibility(vec2 p, vec2 light, float size) {
+    float lit = 0.0;
+    for (int k = 0; k < 5; k++) {
+        vec2 sample_pos = light + OFFSETS[k] * size;
+        bool blocked = false;
+        for (int i = 0; i < 32; i++) {
+            if (i >= segment_count) {
+                break;
+            }
+            if (intersects(p, sample_pos, segments[i].xy, segments[i].zw)) {
+                blocked = true;
+                break;
+            }
+        }
+        if (!blocked) {
+            lit += 0.2;
+        }
+    }
+    return lit;
+}
+
+// params: radius, size, cos(outer angle), cos(inner angle). Point lights have an outer cosine of -1
+float light_amount(vec2 p, vec2 light, vec4 params, vec2 dir) {
+    vec2 to_p = p - light;
+    float falloff = clamp(1.0 - length(to_p) / params.x, 0.0, 1.0);
+    falloff *= falloff;
+    if (params.z > -1.0) {
+        falloff *= smoothstep(params.z, params.w, dot(normalize(to_p), dir));
+    }
+    if (falloff <= 0.0) {
+        return 0.0;
+    }
+    return falloff * visibility(p, light, params.y);
+}
+";
+
+// Draws into the light map, which isn't flipped so it reads back the right way up
+const LIGHT_VERTEX: &str = "
+uniform vec2 screen_size;
+
+in vec2 pos;
+
+out vec2 v_Pos;
+
+void main() {
+    v_Pos = pos;
+    gl_Position = vec4(pos / screen_size * 2.0 - 1.0, 0.0, 1.0);
+}";
+
+const LIGHT_FRAGMENT: &str = "
+uniform vec2 light_pos;
+uniform vec4 light_color;
+uniform vec4 light_params;
+uniform vec2 light_dir;
+
+in vec2 v_Pos;
+
+out vec4 outColor;
+
+void main() {
+    float amount = light_amount(v_Pos, light_pos, light_params, light_dir);
+    outColor = vec4(light_color.rgb * light_color.a * amount, 1.0);
+}";
+
+const NORMAL_VERTEX: &str = "
+uniform vec2 screen_size;
+
+in vec2 pos;
+in vec2 uv;
+
+out vec2 v_Pos;
+out vec2 v_UV;
+
+void main() {
+    v_Pos = pos;
+    v_UV = uv;
+    vec2 ndc = pos / screen_size * 2.0 - 1.0;
+    gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);
+}";
+
+const NORMAL_FRAGMENT: &str = "
+uniform sampler2D s_texture;
+uniform sampler2D s_normal;
+uniform vec3 ambient;
+uniform int light_count;
+uniform vec3 light_positions[8];
+uniform vec4 light_colors[8];
+uniform vec4 light_params[8];
+uniform vec2 light_dirs[8];
+
+in vec2 v_Pos;
+in vec2 v_UV;
+
+out vec4 outColor;
+
+void main() {
+    vec4 albedo = texture(s_texture, v_UV);
+    // Normal maps have y pointing up, but y points down on screen
+    vec3 normal = normalize(texture(s_normal, v_UV).rgb * 2.0 - 1.0) * vec3(1.0, -1.0, 1.0);
+    vec3 lit = ambient;
+    for (int i = 0; i < 8; i++) {
+        if (i >= light_count) {
+            break;
+        }
+        vec3 to_light = vec3(light_positions[i].xy - v_Pos, light_positions[i].z);
+        float diffuse = max(dot(normal, normalize(to_light)), 0.0);
+        float amount = light_amount(v_Pos, light_positions[i].xy, light_params[i], light_dirs[i]);
+        lit += light_colors[i].rgb * light_colors[i].a * diffuse * amount;
+    }
+    outColor = vec4(albedo.rgb * lit, albedo.a);
+}";
+
+/// The cone of a spot light
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spot2d {
+    /// The direction the spot light points in
+    pub direction: [f32; 2],
+    /// Half the angle of the cone in radians, nothing outside of it is lit
+    pub outer_angle: f32,
+    /// Half the angle of the fully lit part of the cone in radians
+    pub inner_angle: f32,
+}
+
+/// A 2D light, in the same coordinates as those drawn to with `Context::transform`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light2d {
+    pub position: [f64; 2],
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// The distance at which the light has faded out completely
+    pub radius: f32,
+    /// The radius of the light's source, larger lights cast softer shadows
+    pub size: f32,
+    /// How high above the screen the light is, only affects normal mapped sprites
+    pub height: f32,
+    /// Makes this a spot light when `Some`
+    pub spot: Option<Spot2d>,
+}
+
+impl Light2d {
+    /// Creates a point light of intensity `1`
+    pub fn point(position: [f64; 2], color: [f32; 3], radius: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity: 1.0,
+            radius,
+            size: 0.0,
+            height: radius * 0.25,
+            spot: None,
+        }
+    }
+
+    /// Turns this into a spot light
+    pub fn spot(mut self, direction: [f32; 2], outer_angle: f32, inner_angle: f32) -> Self {
+        self.spot = Some(Spot2d {
+            direction,
+            outer_angle,
+            inner_angle,
+        });
+        self
+    }
+
+    fn params(&self) -> [f32; 4] {
+        match self.spot {
+            Some(spot) => [self.radius, self.size, spot.outer_angle.cos(), spot.inner_angle.cos()],
+            None => [self.radius, self.size, -1.0, -1.0],
+        }
+    }
+
+    fn direction(&self) -> [f32; 2] {
+        match self.spot {
+            Some(Spot2d { direction: [x, y], .. }) => {
+                let len = (x * x + y * y).sqrt().max(std::f32::EPSILON);
+                [x / len, y / len]
+            },
+            None => [1.0, 0.0],
+        }
+    }
+
+    fn color(&self) -> [f32; 4] {
+        [self.color[0], self.color[1], self.color[2], self.intensity]
+    }
+}
+
+/// Draws a single light into the light map
+pub struct LightShader2d {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 2]>,
+    screen_size: ShaderUniform<SUVec2>,
+    segments: ShaderUniform<SUVec4Array>,
+    segment_count: ShaderUniform<SUInt>,
+    light_pos: ShaderUniform<SUVec2>,
+    light_color: ShaderUniform<SUVec4>,
+    light_params: ShaderUniform<SUVec4>,
+    light_dir: ShaderUniform<SUVec2>,
+    pos_buffer: Vec<[f32; 2]>,
+    offset: usize,
+}
+
+impl Drop for LightShader2d {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl Shader for LightShader2d {
+    type Vertex = [f32; 2];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("LightShader2d requires a GlGraphics to find its uniforms");
+        let version = glsl_header(graphics.profile()).unwrap();
+        let program = Program::from_vs_fs(
+            &[version, HEADER, LIGHT_VERTEX].concat(),
+            &[version, HEADER, LIGHT_FUNCTIONS, LIGHT_FRAGMENT].concat(),
+        ).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        let shader = Self {
+            pos: DynamicAttribute::xy(program.id(), "pos").unwrap(),
+            screen_size: graphics.get_uniform("screen_size").unwrap(),
+            segments: graphics.get_uniform("segments").unwrap(),
+            segment_count: graphics.get_uniform("segment_count").unwrap(),
+            light_pos: graphics.get_uniform("light_pos").unwrap(),
+            light_color: graphics.get_uniform("light_color").unwrap(),
+            light_params: graphics.get_uniform("light_params").unwrap(),
+            light_dir: graphics.get_uniform("light_dir").unwrap(),
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 2]; 6],
+            offset: 0,
+        };
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            gl::DrawArrays(gl::TRIANGLES, 0, self.offset as i32);
+            gl::BindVertexArray(0);
+        }
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 2]> {
+        &mut self.pos_buffer
+    }
+}
+
+/// Draws sprites lit per-pixel using a normal map
+pub struct NormalMapShader2d {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 2]>,
+    uv: DynamicAttribute<[f32; 2]>,
+    screen_size: ShaderUniform<SUVec2>,
+    segments: ShaderUniform<SUVec4Array>,
+    segment_count: ShaderUniform<SUInt>,
+    ambient: ShaderUniform<SUVec3>,
+    light_count: ShaderUniform<SUInt>,
+    light_positions: ShaderUniform<SUVec3Array>,
+    light_colors: ShaderUniform<SUVec4Array>,
+    light_params: ShaderUniform<SUVec4Array>,
+    light_dirs: ShaderUniform<SUVec2Array>,
+    pos_buffer: Vec<[f32; 2]>,
+    uv_buffer: Vec<[f32; 2]>,
+    offset: usize,
+    texture_id: GLuint,
+    normal_map_id: GLuint,
+}
+
+impl Drop for NormalMapShader2d {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl Shader for NormalMapShader2d {
+    type Vertex = [f32; 2];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("NormalMapShader2d requires a GlGraphics to find its uniforms");
+        let version = glsl_header(graphics.profile()).unwrap();
+        let program = Program::from_vs_fs(
+            &[version, HEADER, NORMAL_VERTEX].concat(),
+            &[version, HEADER, LIGHT_FUNCTIONS, NORMAL_FRAGMENT].concat(),
+        ).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        let normal_sampler = graphics.get_uniform::<SUInt>("s_normal").unwrap();
+        normal_sampler.set(graphics, 1);
+        let shader = Self {
+            pos: DynamicAttribute::xy(program.id(), "pos").unwrap(),
+            uv: DynamicAttribute::uv(program.id(), "uv").unwrap(),
+            screen_size: graphics.get_uniform("screen_size").unwrap(),
+            segments: graphics.get_uniform("segments").unwrap(),
+            segment_count: graphics.get_uniform("segment_count").unwrap(),
+            ambient: graphics.get_uniform("ambient").unwrap(),
+            light_count: graphics.get_uniform("light_count").unwrap(),
+            light_positions: graphics.get_uniform("light_positions").unwrap(),
+            light_colors: graphics.get_uniform("light_colors").unwrap(),
+            light_params: graphics.get_uniform("light_params").unwrap(),
+            light_dirs: graphics.get_uniform("light_dirs").unwrap(),
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 2]; 6],
+            uv_buffer: vec![[0.0; 2]; 6],
+            offset: 0,
+            texture_id: 0,
+            normal_map_id: 0,
+        };
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, self.normal_map_id);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture_id);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            self.uv.bind_vao(self.vao);
+            self.uv.set(&self.uv_buffer[..self.offset]);
+            gl::DrawArrays(gl::TRIANGLES, 0, self.offset as i32);
+            gl::BindVertexArray(0);
+        }
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 2]> {
+        &mut self.pos_buffer
+    }
+    fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> {
+        Some(&mut self.uv_buffer)
+    }
+    fn texture_id(&mut self) -> Option<&mut GLuint> {
+        Some(&mut self.texture_id)
+    }
+    fn has_texture(&self) -> bool {
+        true
+    }
+}
+
+/// Lights sorted into a grid of screen tiles, so each part of the screen
+/// is only lit by the lights which reach it
+struct LightTiles {
+    tile_size: f64,
+    columns: usize,
+    rows: usize,
+    // The lights reaching each tile, row by row, strongest first
+    lights: Vec<Vec<usize>>,
+}
+
+impl LightTiles {
+    fn new() -> Self {
+        Self {
+            tile_size: 0.0,
+            columns: 0,
+            rows: 0,
+            lights: Vec::new(),
+        }
+    }
+
+    /// Sorts `lights` into tiles of `tile_size` covering the screen, keeping the
+    /// `MAX_LIGHTS_2D` strongest at the middle of each tile
+    fn build(&mut self, lights: &[Light2d], screen_size: [f64; 2], tile_size: f64) {
+        let tile_size = tile_size.max(1.0);
+        self.tile_size = tile_size;
+        self.columns = (screen_size[0] / tile_size).ceil().max(1.0) as usize;
+        self.rows = (screen_size[1] / tile_size).ceil().max(1.0) as usize;
+        self.lights.resize(self.columns * self.rows, Vec::new());
+        self.lights.iter_mut().for_each(Vec::clear);
+        for (i, light) in lights.iter().enumerate() {
+            let radius = light.radius as f64;
+            let (x, y) = (light.position[0], light.position[1]);
+            let (columns, rows) = match (self.span(x - radius, x + radius, self.columns), self.span(y - radius, y + radius, self.rows)) {
+                (Some(columns), Some(rows)) => (columns, rows),
+                _ => continue,
+            };
+            for row in rows.0..=rows.1 {
+                for column in columns.0..=columns.1 {
+                    // The closest point of the tile to the light
+                    let cx = x.max(column as f64 * tile_size).min((column + 1) as f64 * tile_size);
+                    let cy = y.max(row as f64 * tile_size).min((row + 1) as f64 * tile_size);
+                    if (cx - x).powi(2) + (cy - y).powi(2) < radius * radius {
+                        self.lights[row * self.columns + column].push(i);
+                    }
+                }
+            }
+        }
+        for (index, tile) in self.lights.iter_mut().enumerate() {
+            if tile.len() <= MAX_LIGHTS_2D {
+                continue;
+            }
+            let center = [
+                ((index % self.columns) as f64 + 0.5) * tile_size,
+                ((index / self.columns) as f64 + 0.5) * tile_size,
+            ];
+            let strength = |light: &Light2d| {
+                let distance = ((light.position[0] - center[0]).powi(2) + (light.position[1] - center[1]).powi(2)).sqrt();
+                let falloff = (1.0 - distance as f32 / light.radius).max(0.0);
+                light.intensity * falloff * falloff * (light.color[0] + light.color[1] + light.color[2])
+            };
+            tile.sort_by(|&a, &b| strength(&lights[b]).partial_cmp(&strength(&lights[a])).unwrap_or(std::cmp::Ordering::Equal));
+            tile.truncate(MAX_LIGHTS_2D);
+        }
+    }
+
+    /// The first and last of `count` tiles which `start..end` overlaps, if any
+    fn span(&self, start: f64, end: f64, count: usize) -> Option<(usize, usize)> {
+        let first = (start / self.tile_size).floor();
+        let last = (end / self.tile_size).floor();
+        if last < 0.0 || first >= count as f64 {
+            return None;
+        }
+        Some((first.max(0.0) as usize, (last as usize).min(count - 1)))
+    }
+
+    /// Splits `rect` into the parts lit by the same lights, merging neighbouring tiles on
+    /// the same row which share them. Parts outside of the screen are left out
+    fn pieces(&self, rect: [f64; 4]) -> Vec<([f64; 4], &[usize])> {
+        let mut pieces = Vec::new();
+        let (columns, rows) = match (self.span(rect[0], rect[0] + rect[2], self.columns), self.span(rect[1], rect[1] + rect[3], self.rows)) {
+            (Some(columns), Some(rows)) => (columns, rows),
+            _ => return pieces,
+        };
+        let tile_size = self.tile_size;
+        for row in rows.0..=rows.1 {
+            let y0 = rect[1].max(row as f64 * tile_size);
+            let y1 = (rect[1] + rect[3]).min((row + 1) as f64 * tile_size);
+            let mut column = columns.0;
+            while column <= columns.1 {
+                let lights = &self.lights[row * self.columns + column][..];
+                let mut end = column;
+                while end < columns.1 && self.lights[row * self.columns + end + 1] == lights {
+                    end += 1;
+                }
+                let x0 = rect[0].max(column as f64 * tile_size);
+                let x1 = (rect[0] + rect[2]).min((end + 1) as f64 * tile_size);
+                if x1 > x0 && y1 > y0 {
+                    pieces.push(([x0, y0, x1 - x0, y1 - y0], lights));
+                }
+                column = end + 1;
+            }
+        }
+        pieces
+    }
+}
+
+/// Gets the two triangles of the rectangle `[x, y, w, h]`
+fn quad(rect: [f64; 4]) -> [[f32; 2]; 6] {
+    let (x0, y0) = (rect[0] as f32, rect[1] as f32);
+    let (x1, y1) = ((rect[0] + rect[2]) as f32, (rect[1] + rect[3]) as f32);
+    [
+        [x0, y0], [x1, y0], [x1, y1],
+        [x0, y0], [x1, y1], [x0, y1],
+    ]
+}
+
+/// A 2D lighting pass.
+///
+/// Lights are accumulated into a light map (with soft shadows cast by the occluders)
+/// using `render`, which is then multiplied over everything drawn before `apply` is called.
+/// Normal mapped sprites are lit per-pixel instead, and should be drawn after `apply`.
+///
+/// `render` also sorts the lights into tiles of the screen, and normal mapped sprites are
+/// drawn a tile at a time with only the strongest `MAX_LIGHTS_2D` lights reaching each tile,
+/// so scenes with dozens of lights cost about the same per pixel as those with a few.
+pub struct Lighting2d {
+    /// The colour of unlit areas
+    pub ambient: [f32; 3],
+    pub lights: Vec<Light2d>,
+    /// Closed polygons which cast shadows
+    pub occluders: Vec<Vec<[f64; 2]>>,
+    /// The width and height of the tiles lights are sorted into, `64` by default.
+    /// Smaller tiles light more accurately, but split normal mapped sprites into more draws
+    pub tile_size: f64,
+    light_shader: LightShader2d,
+    normal_shader: NormalMapShader2d,
+    light_map: Option<RenderTarget>,
+    tiles: LightTiles,
+}
+
+impl Lighting2d {
+    /// Creates a lighting pass with no lights, compiling its shaders
+    pub fn new(gl: &mut GlGraphics) -> Self {
+        Self {
+            ambient: [0.1, 0.1, 0.1],
+            lights: Vec::new(),
+            occluders: Vec::new(),
+            tile_size: 64.0,
+            light_shader: LightShader2d::new(GLSL::V1_20, Some(gl)),
+            normal_shader: NormalMapShader2d::new(GLSL::V1_20, Some(gl)),
+            light_map: None,
+            tiles: LightTiles::new(),
+        }
+    }
+
+    fn segments(&self) -> Vec<[f32; 4]> {
+        self.occluders
+            .iter()
+            .flat_map(|polygon| {
+                polygon
+                    .iter()
+                    .zip(polygon.iter().cycle().skip(1))
+                    .map(|(a, b)| [a[0] as f32, a[1] as f32, b[0] as f32, b[1] as f32])
+            })
+            .take(MAX_OCCLUDER_SEGMENTS)
+            .collect()
+    }
+
+    /// Renders the lights into the light map, resizing it to fit `viewport` if needed,
+    /// and sorts them into tiles for normal mapped sprites. Lights off the screen are skipped
+    pub fn render(&mut self, gl: &mut GlGraphics, viewport: Viewport) {
+        self.tiles.build(&self.lights, viewport.window_size, self.tile_size);
+        let size = (viewport.draw_size[0], viewport.draw_size[1]);
+        if self.light_map.as_ref().map(|x| x.texture().get_size()) != Some(size) {
+            self.light_map = Some(RenderTarget::new(size.0, size.1).expect("Could not create light map"));
+        }
+        let light_map = self.light_map.as_ref().unwrap();
+        light_map.bind(gl);
+        gl.clear_buffers(Some([self.ambient[0], self.ambient[1], self.ambient[2], 1.0]), None, None);
+
+        let screen_size = [viewport.window_size[0] as f32, viewport.window_size[1] as f32];
+        let segments = self.segments();
+        let additive = DrawState::default().blend(Blend::Add);
+        for light in &self.lights {
+            let radius = light.radius as f64;
+            let rect = [light.position[0] - radius, light.position[1] - radius, radius * 2.0, radius * 2.0];
+            if rect[0] + rect[2] < 0.0 || rect[1] + rect[3] < 0.0 ||
+               rect[0] > viewport.window_size[0] || rect[1] > viewport.window_size[1] {
+                continue;
+            }
+            gl.shader_draw(&mut self.light_shader, &additive, &quad(rect), None, None, None, None, |shader, gl| {
+                shader.screen_size.set(gl, &screen_size);
+                shader.segments.set(gl, &segments[..]);
+                shader.segment_count.set(gl, segments.len() as i32);
+                shader.light_pos.set(gl, &[light.position[0] as f32, light.position[1] as f32]);
+                shader.light_color.set(gl, &light.color());
+                shader.light_params.set(gl, &light.params());
+                shader.light_dir.set(gl, &light.direction());
+            });
+        }
+        light_map.unbind(gl, viewport);
+    }
+
+    /// Multiplies everything drawn so far by the light map rendered in `render`
+    pub fn apply(&self, c: &Context, gl: &mut GlGraphics) {
+        if let (Some(light_map), Some(viewport)) = (&self.light_map, c.viewport) {
+            Image::new()
+                .rect([0.0, 0.0, viewport.window_size[0], viewport.window_size[1]])
+                .draw(
+                    light_map.texture(),
+                    &DrawState::default().blend(Blend::Multiply),
+                    c.view,
+                    gl
+                );
+        }
+    }
+
+    /// Draws `texture` in `rect` lit per-pixel by the lights using `normal_map`, which
+    /// is expected to be in tangent space with y pointing up. Sprites aren't rotated,
+    /// so `rect` is in the same coordinates as the lights.
+    ///
+    /// The sprite is drawn in pieces, one for each run of tiles lit by the same lights as
+    /// they were sorted in the last `render` (or now, if the screen's size has changed since)
+    pub fn draw_normal_mapped(&mut self, gl: &mut GlGraphics, viewport: Viewport, texture: &Texture, normal_map: &Texture, rect: [f64; 4]) {
+        let tile_size = self.tile_size.max(1.0);
+        let (columns, rows) = ((viewport.window_size[0] / tile_size).ceil().max(1.0) as usize, (viewport.window_size[1] / tile_size).ceil().max(1.0) as usize);
+        if (self.tiles.columns, self.tiles.rows, self.tiles.tile_size) != (columns, rows, tile_size) {
+            self.tiles.build(&self.lights, viewport.window_size, tile_size);
+        }
+        let screen_size = [viewport.window_size[0] as f32, viewport.window_size[1] as f32];
+        let segments = self.segments();
+        let ambient = self.ambient;
+        self.normal_shader.normal_map_id = normal_map.get_id();
+        for (piece, indices) in self.tiles.pieces(rect) {
+            let lights = indices.iter().map(|&i| &self.lights[i]).collect::<Vec<_>>();
+            let positions = lights
+                .iter()
+                .map(|x| [x.position[0] as f32, x.position[1] as f32, x.height])
+                .collect::<Vec<_>>();
+            let colors = lights.iter().map(|x| x.color()).collect::<Vec<_>>();
+            let params = lights.iter().map(|x| x.params()).collect::<Vec<_>>();
+            let directions = lights.iter().map(|x| x.direction()).collect::<Vec<_>>();
+            let uvs = quad([
+                (piece[0] - rect[0]) / rect[2],
+                (piece[1] - rect[1]) / rect[3],
+                piece[2] / rect[2],
+                piece[3] / rect[3],
+            ]);
+            gl.shader_draw(
+                &mut self.normal_shader,
+                &DrawState::new_alpha(),
+                &quad(piece),
+                None,
+                Some((texture, &uvs)),
+                None,
+                None,
+                |shader, gl| {
+                    shader.screen_size.set(gl, &screen_size);
+                    shader.segments.set(gl, &segments[..]);
+                    shader.segment_count.set(gl, segments.len() as i32);
+                    shader.ambient.set(gl, &ambient);
+                    shader.light_count.set(gl, positions.len() as i32);
+                    shader.light_positions.set(gl, &positions[..]);
+                    shader.light_colors.set(gl, &colors[..]);
+                    shader.light_params.set(gl, &params[..]);
+                    shader.light_dirs.set(gl, &directions[..]);
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,636 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use asset_pack::{parse_mtl, parse_obj_groups, CookedMesh, ObjMaterial};
+use opengl_graphics::{GlGraphics, Texture, TextureSettings};
+use graphics::Context;
+use cgmath::{EuclideanSpace, Matrix3, Matrix4, Point3, Quaternion, SquareMatrix, Vector3, InnerSpace};
+use serde_json::Value;
+use matrices::batch;
+use matrices::nav::{NavMesh, NavSettings};
+use crate::assets::Assets;
+use crate::batching::transform_normals;
+use crate::pbr::{PbrMaterial, PbrMesh, PbrShader, BUFFER_SIZE};
+use crate::storage::{Drawable, Transforms, ViewProj};
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_JSON: u32 = 0x4E4F_534A;
+const GLB_BIN: u32 = 0x004E_4942;
+
+/// A model loaded from an OBJ or glTF file in the assets, made of a `PbrMesh` for each material.
+///
+/// Parts are split so each fits in `PbrShader`'s buffers, and normals are generated for those
+/// without any. Textures are loaded once per model, with mipmaps.
+pub struct Model {
+    pub parts: Vec<PbrMesh>,
+    bounds: (Point3<f32>, f32),
+    warnings: Vec<String>,
+}
+
+impl Model {
+    pub fn new(parts: Vec<PbrMesh>) -> Self {
+        let mut min = Point3::new(std::f32::INFINITY, std::f32::INFINITY, std::f32::INFINITY);
+        let mut max = Point3::new(std::f32::NEG_INFINITY, std::f32::NEG_INFINITY, std::f32::NEG_INFINITY);
+        for position in parts.iter().flat_map(|x| x.positions.iter()) {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        let bounds = if parts.iter().all(|x| x.positions.is_empty()) {
+            (Point3::new(0.0, 0.0, 0.0), 0.0)
+        } else {
+            (Point3::from_vec((min.to_vec() + max.to_vec()) * 0.5), (max - min).magnitude() * 0.5)
+        };
+        Self { parts, bounds, warnings: Vec::new() }
+    }
+
+    /// The problems found while loading which didn't stop the model from loading, such as
+    /// missing materials or textures, or parts which aren't made of triangles
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Loads an OBJ file, or a glTF file (`.gltf` or `.glb`), by the extension of `path`
+    pub fn load(assets: &Assets, path: &str) -> Result<Self, String> {
+        if path.ends_with(".gltf") || path.ends_with(".glb") {
+            Self::from_gltf(assets, path)
+        } else {
+            Self::from_obj(assets, path)
+        }
+    }
+
+    /// Loads an OBJ file and the MTL files it uses, which are all looked up relative to it.
+    ///
+    /// MTL materials become metallic-roughness ones: `Pr` and `Pm` are used when given, otherwise
+    /// the surface isn't metallic and its roughness comes from `Ns`. Models cooked into a mounted
+    /// pack have lost their materials, so they're loaded as a single untextured part
+    pub fn from_obj(assets: &Assets, path: &str) -> Result<Self, String> {
+        if let Ok(mesh) = assets.read_mesh(path) {
+            return Ok(Self::new(split_parts(mesh, &obj_default_material())));
+        }
+        let source = assets.read_string(path)?;
+        let obj = parse_obj_groups(&source).map_err(|e| format!("In {}: {}", path, e))?;
+        let mut materials = HashMap::new();
+        let mut textures = TextureCache::new(assets);
+        for library in &obj.material_libraries {
+            let library = relative_path(path, library);
+            match assets.read_string(&library).and_then(|x| parse_mtl(&x)) {
+                Ok(x) => materials.extend(x.into_iter().map(|x| (x.name.clone(), (library.clone(), x)))),
+                Err(e) => textures.warnings.push(format!("Could not load the materials of {} from {}: {}", path, library, e)),
+            }
+        }
+        let mut parts = Vec::new();
+        for group in obj.groups {
+            let material = match group.material.as_ref().and_then(|x| materials.get(x)) {
+                Some((library, material)) => obj_material(material, library, &mut textures),
+                None => obj_default_material(),
+            };
+            parts.extend(split_parts(group.mesh, &material));
+        }
+        let mut model = Self::new(parts);
+        model.warnings = textures.warnings;
+        Ok(model)
+    }
+
+    /// Loads a glTF 2.0 file, either as JSON with its buffers and images beside it (or embedded
+    /// as data URIs), or as a single binary `.glb`.
+    ///
+    /// The meshes of the default scene are moved into place by their nodes' transforms, and take
+    /// their metallic-roughness materials along. Only triangles are drawn, and skins, morph targets,
+    /// animations and material extensions are skipped
+    pub fn from_gltf(assets: &Assets, path: &str) -> Result<Self, String> {
+        let data = assets.read_data(path)?;
+        let gltf = Gltf::parse(assets, path, &data).map_err(|e| format!("In {}: {}", path, e))?;
+        gltf.model().map_err(|e| format!("In {}: {}", path, e))
+    }
+
+    /// Bakes the walkable triangles of every part into a `NavMesh`, for a model of a level
+    pub fn nav_mesh(&self, settings: &NavSettings) -> NavMesh {
+        let (mut positions, mut indices) = (Vec::new(), Vec::new());
+        for part in &self.parts {
+            let first = positions.len() as u32;
+            positions.extend_from_slice(&part.positions);
+            match &part.indices {
+                Some(x) => indices.extend(x.iter().map(|&x| first + x as u32)),
+                None => indices.extend(first..positions.len() as u32),
+            }
+        }
+        NavMesh::bake(&positions, &indices, settings)
+    }
+}
+
+impl Drawable for Model {
+    type Shader = PbrShader;
+
+    fn draw_with(
+        &mut self,
+        data: &mut PbrShader,
+        graphics: &mut GlGraphics,
+        context: &Context,
+        cache: &mut ViewProj,
+        transforms: &mut Transforms
+    ) {
+        for part in &mut self.parts {
+            part.draw_with(data, graphics, context, cache, transforms);
+        }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Point3<f32>, f32)> {
+        Some(self.bounds)
+    }
+}
+
+// Resolves `path` relative to the directory of the asset `base`
+pub(crate) fn relative_path(base: &str, path: &str) -> String {
+    match base.rfind('/') {
+        Some(x) => format!("{}/{}", &base[..x], path),
+        None => path.to_string(),
+    }
+}
+
+// Loads each texture of a model once, collecting the model's warnings along the way
+struct TextureCache<'a> {
+    assets: &'a Assets,
+    textures: HashMap<String, Rc<Texture>>,
+    warnings: Vec<String>,
+}
+
+impl<'a> TextureCache<'a> {
+    fn new(assets: &'a Assets) -> Self {
+        Self {
+            assets,
+            textures: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // Loads the image at `path`, warning why when it can't
+    fn load(&mut self, path: &str) -> Option<Rc<Texture>> {
+        if let Some(texture) = self.textures.get(path) {
+            return Some(texture.clone());
+        }
+        match self.assets.read_data(path) {
+            Ok(data) => self.decode(path, &data),
+            Err(e) => {
+                self.warnings.push(format!("Could not load the texture {}: {}", path, e));
+                None
+            }
+        }
+    }
+
+    // Decodes the image data `data`, cached by `key`
+    fn decode(&mut self, key: &str, data: &[u8]) -> Option<Rc<Texture>> {
+        if let Some(texture) = self.textures.get(key) {
+            return Some(texture.clone());
+        }
+        let image = match image::load_from_memory(data) {
+            Ok(x) => x.to_rgba(),
+            Err(e) => {
+                self.warnings.push(format!("Could not decode the texture {}: {}", key, e));
+                return None;
+            }
+        };
+        let mut settings = TextureSettings::new();
+        settings.set_generate_mipmap(true);
+        let texture = Rc::new(Texture::from_image(&image, &settings));
+        self.textures.insert(key.to_string(), texture.clone());
+        Some(texture)
+    }
+}
+
+fn obj_default_material() -> PbrMaterial {
+    PbrMaterial {
+        metallic: 0.0,
+        ..PbrMaterial::default()
+    }
+}
+
+fn obj_material(material: &ObjMaterial, library: &str, textures: &mut TextureCache) -> PbrMaterial {
+    let mut texture = |path: &Option<String>| path.as_ref().and_then(|x| textures.load(&relative_path(library, x)));
+    let [r, g, b] = material.diffuse;
+    PbrMaterial {
+        base_color: [r, g, b, material.opacity],
+        base_color_texture: texture(&material.diffuse_texture),
+        metallic: material.metallic.unwrap_or(0.0),
+        // Roughly matches the width of a Phong highlight with exponent `Ns`
+        roughness: material.roughness
+            .or_else(|| material.shininess.map(|x| (2.0 / (x.max(0.0) + 2.0)).sqrt()))
+            .unwrap_or(1.0),
+        normal_texture: texture(&material.normal_texture),
+        emissive: material.emissive,
+        emissive_texture: texture(&material.emissive_texture),
+        ..PbrMaterial::default()
+    }
+}
+
+// Splits a mesh into parts with few enough vertices for `PbrShader`, adding normals (and texture
+// coordinates at the origin) if it has none
+fn split_parts(mut mesh: CookedMesh, material: &PbrMaterial) -> Vec<PbrMesh> {
+    if mesh.indices.is_empty() {
+        mesh.indices = (0..mesh.positions.len() as u32).collect();
+    }
+    if mesh.normals.len() != mesh.positions.len() {
+        mesh.normals = smooth_normals(&mesh.positions, &mesh.indices);
+    }
+    if mesh.uvs.len() != mesh.positions.len() {
+        mesh.uvs = vec![[0.0; 2]; mesh.positions.len()];
+    }
+
+    let mut parts = Vec::new();
+    let mut part = PbrMesh::new(Vec::new(), Vec::new(), Vec::new(), Some(Vec::new()), material.clone());
+    let mut remap = HashMap::new();
+    for triangle in mesh.indices.chunks_exact(3) {
+        let new = triangle.iter().filter(|x| !remap.contains_key(*x)).count();
+        if part.positions.len() + new > BUFFER_SIZE {
+            parts.push(std::mem::replace(&mut part, PbrMesh::new(Vec::new(), Vec::new(), Vec::new(), Some(Vec::new()), material.clone())));
+            remap.clear();
+        }
+        for &vertex in triangle {
+            let index = *remap.entry(vertex).or_insert_with(|| {
+                let vertex = vertex as usize;
+                part.positions.push(mesh.positions[vertex]);
+                part.normals.push(mesh.normals[vertex]);
+                part.uvs.push(mesh.uvs[vertex]);
+                part.positions.len() as u16 - 1
+            });
+            part.indices.as_mut().unwrap().push(index);
+        }
+    }
+    if !part.positions.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+// Normals averaged from the triangles around each vertex, weighted by their area
+fn smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector3::new(0.0f32, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let (a_position, b_position, c_position) = (Vector3::from(positions[a]), Vector3::from(positions[b]), Vector3::from(positions[c]));
+        let normal = (b_position - a_position).cross(c_position - a_position);
+        for &vertex in &[a, b, c] {
+            normals[vertex] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|x| if x.magnitude2() > 0.0 { x.normalize().into() } else { [0.0, 1.0, 0.0] })
+        .collect()
+}
+
+// A glTF file with its buffers loaded
+struct Gltf<'a> {
+    assets: &'a Assets,
+    path: &'a str,
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl<'a> Gltf<'a> {
+    fn parse(assets: &'a Assets, path: &'a str, data: &[u8]) -> Result<Self, String> {
+        let (json, mut binary) = if data.starts_with(GLB_MAGIC) {
+            let mut chunks = HashMap::new();
+            let mut offset = 12;
+            while offset + 8 <= data.len() {
+                let length = u32_at(data, offset)? as usize;
+                let chunk = data.get(offset + 8..offset + 8 + length).ok_or("Unexpected end of GLB file")?;
+                chunks.entry(u32_at(data, offset + 4)?).or_insert(chunk);
+                offset += 8 + length;
+            }
+            let json = chunks.get(&GLB_JSON).ok_or("GLB file has no JSON chunk")?;
+            (serde_json::from_slice::<Value>(json), chunks.get(&GLB_BIN).map(|x| x.to_vec()))
+        } else {
+            (serde_json::from_slice::<Value>(data), None)
+        };
+        let json = json.map_err(|e| format!("Invalid glTF: {}", e))?;
+
+        let mut buffers = Vec::new();
+        for buffer in array(&json, "buffers") {
+            let data = match buffer.get("uri").and_then(Value::as_str) {
+                Some(uri) if uri.starts_with("data:") => data_uri(uri)?,
+                Some(uri) => assets.read_bytes(&relative_path(path, uri))?,
+                None => binary.take().ok_or("A buffer has no data")?,
+            };
+            if data.len() < usize_at(buffer, "byteLength").unwrap_or(0) {
+                return Err("A buffer is shorter than its byteLength".to_string());
+            }
+            buffers.push(data);
+        }
+        Ok(Self { assets, path, json, buffers })
+    }
+
+    fn model(&self) -> Result<Model, String> {
+        let mut textures = TextureCache::new(self.assets);
+        let materials = array(&self.json, "materials")
+            .iter()
+            .map(|x| self.material(x, &mut textures))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut parts = Vec::new();
+        let scene = self.json.get("scene").and_then(Value::as_u64).unwrap_or(0) as usize;
+        match array(&self.json, "scenes").get(scene) {
+            Some(scene) => {
+                for node in array(scene, "nodes") {
+                    let node = node.as_u64().ok_or("Invalid node index")? as usize;
+                    self.add_node(node, Matrix4::identity(), &materials, &mut parts, &mut textures.warnings, 0)?;
+                }
+            },
+            // Without scenes every mesh is drawn where it is
+            None => {
+                for mesh in array(&self.json, "meshes") {
+                    self.add_mesh(mesh, Matrix4::identity(), &materials, &mut parts, &mut textures.warnings)?;
+                }
+            },
+        }
+        let mut model = Model::new(parts);
+        model.warnings = textures.warnings;
+        Ok(model)
+    }
+
+    fn add_node(&self, index: usize, parent: Matrix4<f32>, materials: &[PbrMaterial], parts: &mut Vec<PbrMesh>, warnings: &mut Vec<String>, depth: usize) -> Result<(), String> {
+        let node = array(&self.json, "nodes").get(index).ok_or("Invalid node index")?;
+        if depth > 64 {
+            return Err("Nodes are nested too deeply".to_string());
+        }
+        let transform = parent * node_transform(node);
+        if let Some(mesh) = usize_at(node, "mesh") {
+            let mesh = array(&self.json, "meshes").get(mesh).ok_or("Invalid mesh index")?;
+            self.add_mesh(mesh, transform, materials, parts, warnings)?;
+        }
+        for child in array(node, "children") {
+            let child = child.as_u64().ok_or("Invalid node index")? as usize;
+            self.add_node(child, transform, materials, parts, warnings, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn add_mesh(&self, mesh: &Value, transform: Matrix4<f32>, materials: &[PbrMaterial], parts: &mut Vec<PbrMesh>, warnings: &mut Vec<String>) -> Result<(), String> {
+        let upper = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate());
+        // Mirroring transforms turn the triangles inside out
+        let flip = upper.determinant() < 0.0;
+        for primitive in array(mesh, "primitives") {
+            if usize_at(primitive, "mode").unwrap_or(4) != 4 {
+                warnings.push(format!("Skipped a primitive of {} which isn't made of triangles", self.path));
+                continue;
+            }
+            let attributes = primitive.get("attributes").ok_or("A primitive has no attributes")?;
+            let positions = match usize_at(attributes, "POSITION") {
+                Some(x) => self.vectors::<[f32; 3]>(x)?,
+                None => continue,
+            };
+            let normals = match usize_at(attributes, "NORMAL") {
+                Some(x) => self.vectors::<[f32; 3]>(x)?,
+                None => Vec::new(),
+            };
+            let uvs = match usize_at(attributes, "TEXCOORD_0") {
+                Some(x) => self.vectors::<[f32; 2]>(x)?,
+                None => Vec::new(),
+            };
+            let mut indices = match usize_at(primitive, "indices") {
+                Some(x) => self.indices(x)?,
+                None => (0..positions.len() as u32).collect(),
+            };
+            if let Some(&x) = indices.iter().find(|&&x| x as usize >= positions.len()) {
+                return Err(format!("Index {} is out of bounds of its {} vertices", x, positions.len()));
+            }
+            if flip {
+                for triangle in indices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+            }
+            let mut mesh = CookedMesh {
+                positions: vec![[0.0; 3]; positions.len()],
+                normals: vec![[0.0; 3]; normals.len()],
+                uvs,
+                tangents: Vec::new(),
+                indices,
+            };
+            batch::transform_points(&transform, &positions, &mut mesh.positions);
+            transform_normals(&transform, &normals, &mut mesh.normals);
+            let material = match usize_at(primitive, "material") {
+                Some(x) => materials.get(x).ok_or("Invalid material index")?.clone(),
+                None => PbrMaterial::default(),
+            };
+            parts.extend(split_parts(mesh, &material));
+        }
+        Ok(())
+    }
+
+    fn material(&self, material: &Value, textures: &mut TextureCache) -> Result<PbrMaterial, String> {
+        let mut texture = |info: Option<&Value>| match info.and_then(|x| usize_at(x, "index")) {
+            Some(x) => self.texture(x, textures),
+            None => Ok(None),
+        };
+        let empty = Value::Null;
+        let pbr = material.get("pbrMetallicRoughness").unwrap_or(&empty);
+        let defaults = PbrMaterial::default();
+        let base_color = floats(pbr.get("baseColorFactor"));
+        let emissive = floats(material.get("emissiveFactor"));
+        Ok(PbrMaterial {
+            base_color: if base_color.len() == 4 { [base_color[0], base_color[1], base_color[2], base_color[3]] } else { defaults.base_color },
+            base_color_texture: texture(pbr.get("baseColorTexture"))?,
+            metallic: float_at(pbr, "metallicFactor").unwrap_or(defaults.metallic),
+            roughness: float_at(pbr, "roughnessFactor").unwrap_or(defaults.roughness),
+            metallic_roughness_texture: texture(pbr.get("metallicRoughnessTexture"))?,
+            normal_texture: texture(material.get("normalTexture"))?,
+            normal_scale: material.get("normalTexture").and_then(|x| float_at(x, "scale")).unwrap_or(defaults.normal_scale),
+            occlusion_texture: texture(material.get("occlusionTexture"))?,
+            occlusion_strength: material.get("occlusionTexture").and_then(|x| float_at(x, "strength")).unwrap_or(defaults.occlusion_strength),
+            emissive: if emissive.len() == 3 { [emissive[0], emissive[1], emissive[2]] } else { defaults.emissive },
+            emissive_texture: texture(material.get("emissiveTexture"))?,
+        })
+    }
+
+    fn texture(&self, index: usize, textures: &mut TextureCache) -> Result<Option<Rc<Texture>>, String> {
+        let texture = array(&self.json, "textures").get(index).ok_or("Invalid texture index")?;
+        let source = match usize_at(texture, "source") {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let image = array(&self.json, "images").get(source).ok_or("Invalid image index")?;
+        Ok(match (image.get("uri").and_then(Value::as_str), usize_at(image, "bufferView")) {
+            (Some(uri), _) if uri.starts_with("data:") => textures.decode(&format!("{}#image{}", self.path, source), &data_uri(uri)?),
+            (Some(uri), _) => textures.load(&relative_path(self.path, uri)),
+            (None, Some(view)) => textures.decode(&format!("{}#image{}", self.path, source), self.view(view)?.0),
+            (None, None) => None,
+        })
+    }
+
+    // The bytes of a buffer view, and its stride if it has one
+    fn view(&self, index: usize) -> Result<(&[u8], Option<usize>), String> {
+        let view = array(&self.json, "bufferViews").get(index).ok_or("Invalid buffer view index")?;
+        let buffer = self.buffers.get(usize_at(view, "buffer").unwrap_or(0)).ok_or("Invalid buffer index")?;
+        let start = usize_at(view, "byteOffset").unwrap_or(0);
+        let length = usize_at(view, "byteLength").ok_or("A buffer view has no byteLength")?;
+        let data = buffer.get(start..start + length).ok_or("A buffer view is outside of its buffer")?;
+        Ok((data, usize_at(view, "byteStride")))
+    }
+
+    // Reads each component of each element of an accessor as a float, along with how many
+    // components the elements have
+    fn accessor(&self, index: usize) -> Result<(Vec<f32>, usize), String> {
+        let accessor = array(&self.json, "accessors").get(index).ok_or("Invalid accessor index")?;
+        if accessor.get("sparse").is_some() {
+            return Err("Sparse accessors aren't supported".to_string());
+        }
+        let count = usize_at(accessor, "count").ok_or("An accessor has no count")?;
+        let components = match accessor.get("type").and_then(Value::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            x => return Err(format!("Unsupported accessor type {:?}", x)),
+        };
+        let component_type = usize_at(accessor, "componentType").ok_or("An accessor has no componentType")?;
+        let normalized = accessor.get("normalized").and_then(Value::as_bool).unwrap_or(false);
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            x => return Err(format!("Unsupported component type {}", x)),
+        };
+        let view = match usize_at(accessor, "bufferView") {
+            Some(x) => x,
+            None => return Ok((vec![0.0; count * components], components)),
+        };
+        let (data, stride) = self.view(view)?;
+        let stride = stride.unwrap_or(size * components);
+        let offset = usize_at(accessor, "byteOffset").unwrap_or(0);
+        if count > 0 && offset + stride * (count - 1) + size * components > data.len() {
+            return Err("An accessor is outside of its buffer view".to_string());
+        }
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let at = offset + element * stride + component * size;
+                let bytes = &data[at..at + size];
+                let value = match component_type {
+                    5120 => bytes[0] as i8 as f32,
+                    5121 => bytes[0] as f32,
+                    5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+                    5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+                    5125 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32,
+                    _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                };
+                values.push(match (normalized, component_type) {
+                    (true, 5120) => (value / 127.0).max(-1.0),
+                    (true, 5121) => value / 255.0,
+                    (true, 5122) => (value / 32767.0).max(-1.0),
+                    (true, 5123) => value / 65535.0,
+                    _ => value,
+                });
+            }
+        }
+        Ok((values, components))
+    }
+
+    fn vectors<T: Default + AsMut<[f32]>>(&self, index: usize) -> Result<Vec<T>, String> {
+        let (values, components) = self.accessor(index)?;
+        let mut vectors = Vec::with_capacity(values.len() / components);
+        for element in values.chunks_exact(components) {
+            let mut vector = T::default();
+            if vector.as_mut().len() != components {
+                return Err(format!("Expected {} components but an accessor has {}", vector.as_mut().len(), components));
+            }
+            vector.as_mut().copy_from_slice(element);
+            vectors.push(vector);
+        }
+        Ok(vectors)
+    }
+
+    fn indices(&self, index: usize) -> Result<Vec<u32>, String> {
+        let accessor = array(&self.json, "accessors").get(index).ok_or("Invalid accessor index")?;
+        // Floats can't hold every `u32`, so those are read directly
+        if usize_at(accessor, "componentType") == Some(5125) && accessor.get("sparse").is_none() {
+            let count = usize_at(accessor, "count").ok_or("An accessor has no count")?;
+            let (data, stride) = self.view(usize_at(accessor, "bufferView").ok_or("Indices need a buffer view")?)?;
+            let (offset, stride) = (usize_at(accessor, "byteOffset").unwrap_or(0), stride.unwrap_or(4));
+            return (0..count).map(|x| u32_at(data, offset + x * stride)).collect();
+        }
+        let (values, _) = self.accessor(index)?;
+        Ok(values.into_iter().map(|x| x as u32).collect())
+    }
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).and_then(Value::as_array).map(|x| &x[..]).unwrap_or(&[])
+}
+
+fn usize_at(value: &Value, key: &str) -> Option<usize> {
+    value.get(key).and_then(Value::as_u64).map(|x| x as usize)
+}
+
+fn float_at(value: &Value, key: &str) -> Option<f32> {
+    value.get(key).and_then(Value::as_f64).map(|x| x as f32)
+}
+
+fn floats(value: Option<&Value>) -> Vec<f32> {
+    value
+        .and_then(Value::as_array)
+        .map(|x| x.iter().filter_map(Value::as_f64).map(|x| x as f32).collect())
+        .unwrap_or_default()
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, String> {
+    match data.get(offset..offset + 4) {
+        Some(x) => Ok(u32::from_le_bytes([x[0], x[1], x[2], x[3]])),
+        None => Err("Unexpected end of glTF data".to_string()),
+    }
+}
+
+fn node_transform(node: &Value) -> Matrix4<f32> {
+    let m = floats(node.get("matrix"));
+    if m.len() == 16 {
+        // Column major, like cgmath
+        return Matrix4::new(m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10], m[11], m[12], m[13], m[14], m[15]);
+    }
+    let translation = floats(node.get("translation"));
+    let rotation = floats(node.get("rotation"));
+    let scale = floats(node.get("scale"));
+    let mut transform = Matrix4::identity();
+    if translation.len() == 3 {
+        transform = transform * Matrix4::from_translation(Vector3::new(translation[0], translation[1], translation[2]));
+    }
+    if rotation.len() == 4 {
+        // Stored as x, y, z, w
+        transform = transform * Matrix4::from(Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]).normalize());
+    }
+    if scale.len() == 3 {
+        transform = transform * Matrix4::from_nonuniform_scale(scale[0], scale[1], scale[2]);
+    }
+    transform
+}
+
+// Decodes a base64 `data:` URI, as glTF embeds buffers and images
+fn data_uri(uri: &str) -> Result<Vec<u8>, String> {
+    let comma = uri.find(',').ok_or("Invalid data URI")?;
+    if !uri[..comma].ends_with(";base64") {
+        return Err("Only base64 data URIs are supported".to_string());
+    }
+    decode_base64(&uri[comma + 1..]).map_err(|_| "Invalid base64 in data URI".to_string())
+}
+
+// Decodes base64 of either alphabet, skipping whitespace
+pub(crate) fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            x if x.is_ascii_whitespace() => continue,
+            _ => return Err("Invalid base64".to_string()),
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Ok(data)
+}
@@ -0,0 +1,115 @@
+use opengl_graphics::{GlGraphics, RenderTarget};
+use opengl_graphics::shader_utils::Shader;
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUFloat, SUVec2, SUVec4};
+use graphics::{DrawState, Viewport};
+use crate::post::{PostShader, fit_target};
+
+const OUTLINE_FRAGMENT_SHADER: &str = "precision mediump float;
+uniform sampler2D s_texture;
+uniform vec2 texel_size;
+uniform float thickness;
+uniform vec4 outline_color;
+uniform vec4 fill_color;
+
+in vec2 v_UV;
+
+out vec4 outColor;
+
+void main() {
+    vec4 center = texture(s_texture, v_UV);
+    float edge = 0.0;
+    for (int i = 0; i < 8; i++) {
+        float angle = float(i) * 0.7853982;
+        vec2 offset = vec2(cos(angle), sin(angle)) * thickness * texel_size;
+        if (distance(texture(s_texture, v_UV + offset), center) > 0.005) {
+            edge = 1.0;
+        }
+    }
+    vec4 fill = fill_color * center.a;
+    outColor = mix(fill, outline_color, edge);
+}";
+
+/// Gets the colour to draw an object with into the mask of `Outlines`.
+///
+/// Objects drawn with different ids get an outline between them, `0` is reserved for the background.
+pub fn outline_id_color(id: u8) -> [f32; 4] {
+    // Few levels per channel so ids stay distinct after the srgb conversion of colours
+    [
+        (id & 0b111) as f32 / 7.0,
+        ((id >> 3) & 0b111) as f32 / 7.0,
+        ((id >> 6) & 0b11) as f32 / 3.0,
+        if id == 0 { 0.0 } else { 1.0 },
+    ]
+}
+
+/// Screen-space outlines around objects drawn into a mask, for selections and stylized rendering.
+///
+/// Draw the objects to outline between `begin_mask` and `end_mask` in flat colours
+/// from `outline_id_color`, then call `draw` to draw the outlines over the scene.
+pub struct Outlines {
+    pub color: [f32; 4],
+    /// A colour drawn over the inside of outlined objects, transparent by default
+    pub fill_color: [f32; 4],
+    /// The thickness of the outline in pixels
+    pub thickness: f32,
+    mask: Option<RenderTarget>,
+    shader: PostShader,
+    texel_size: ShaderUniform<SUVec2>,
+    thickness_uniform: ShaderUniform<SUFloat>,
+    color_uniform: ShaderUniform<SUVec4>,
+    fill_uniform: ShaderUniform<SUVec4>,
+}
+
+impl Outlines {
+    /// Creates an outline pass with an orange outline two pixels thick
+    pub fn new(gl: &mut GlGraphics) -> Self {
+        let shader = PostShader::with_fragment(gl, OUTLINE_FRAGMENT_SHADER).unwrap();
+        gl.use_program(shader.program());
+        let outlines = Self {
+            color: [1.0, 0.6, 0.0, 1.0],
+            fill_color: [0.0; 4],
+            thickness: 2.0,
+            mask: None,
+            texel_size: gl.get_uniform("texel_size").unwrap(),
+            thickness_uniform: gl.get_uniform("thickness").unwrap(),
+            color_uniform: gl.get_uniform("outline_color").unwrap(),
+            fill_uniform: gl.get_uniform("fill_color").unwrap(),
+            shader,
+        };
+        gl.clear_program();
+        outlines
+    }
+
+    /// Starts drawing into the mask, clearing it
+    pub fn begin_mask(&mut self, gl: &mut GlGraphics, viewport: Viewport) {
+        let mask = fit_target(&mut self.mask, viewport);
+        mask.bind(gl);
+        gl.clear_buffers(Some([0.0; 4]), None, None);
+    }
+
+    /// Goes back to drawing to the screen with `viewport`
+    pub fn end_mask(&mut self, gl: &mut GlGraphics, viewport: Viewport) {
+        if let Some(mask) = &self.mask {
+            mask.unbind(gl, viewport);
+        }
+    }
+
+    /// Draws the outlines of everything in the mask over the screen
+    pub fn draw(&mut self, gl: &mut GlGraphics) {
+        let mask = match &self.mask {
+            Some(x) => x,
+            None => return,
+        };
+        let size = mask.viewport().draw_size;
+        let texel_size = [1.0 / size[0] as f32, 1.0 / size[1] as f32];
+        let (thickness, color, fill_color) = (self.thickness, self.color, self.fill_color);
+        let (texel_uniform, thickness_uniform, color_uniform, fill_uniform) =
+            (&self.texel_size, &self.thickness_uniform, &self.color_uniform, &self.fill_uniform);
+        self.shader.draw(gl, mask.texture(), &DrawState::new_alpha(), |_, gl| {
+            texel_uniform.set(gl, &texel_size);
+            thickness_uniform.set(gl, thickness);
+            color_uniform.set(gl, &color);
+            fill_uniform.set(gl, &fill_color);
+        });
+    }
+}
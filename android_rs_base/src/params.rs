@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use opengl_graphics::GlGraphics;
+use opengl_graphics::shader_uniforms::{SUFloat, SUInt, SUVec2, SUVec3, SUVec4};
+use serde_json::Value;
+use crate::tracks::{Curve, Expr, TrackInputs, UniformTrack};
+
+/// A value from a parameter file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamValue {
+    Int(i32),
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+}
+
+impl ParamValue {
+    fn from_json(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(x) if x.is_i64() => Some(ParamValue::Int(x.as_i64()? as i32)),
+            Value::Number(x) => Some(ParamValue::Float(x.as_f64()? as f32)),
+            Value::Array(items) => {
+                let items = items.iter().map(|x| x.as_f64().map(|x| x as f32)).collect::<Option<Vec<_>>>()?;
+                match items.len() {
+                    1 => Some(ParamValue::Float(items[0])),
+                    2 => Some(ParamValue::Vec2([items[0], items[1]])),
+                    3 => Some(ParamValue::Vec3([items[0], items[1], items[2]])),
+                    4 => Some(ParamValue::Vec4([items[0], items[1], items[2], items[3]])),
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Parses a value, expression(s) or curve into a track
+    fn track_from_json(value: &Value) -> Result<UniformTrack, String> {
+        if let Some(constant) = Self::from_json(value) {
+            return Ok(UniformTrack::Constant(constant));
+        }
+        match value {
+            Value::String(source) => Ok(UniformTrack::Expressions(vec![Expr::parse(source)?])),
+            Value::Array(items) if !items.is_empty() && items.len() <= 4 => items
+                .iter()
+                .map(|x| match x {
+                    Value::String(source) => Expr::parse(source),
+                    x => x.as_f64().map(|x| Expr::Number(x as f32)).ok_or_else(|| format!("Invalid component {}", x)),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(UniformTrack::Expressions),
+            Value::Object(object) => {
+                let keys = object
+                    .get("keys")
+                    .and_then(|x| x.as_array())
+                    .ok_or_else(|| "Curves need an array of [time, value] keys".to_string())?
+                    .iter()
+                    .map(|x| match x.as_array().map(|x| x.as_slice()) {
+                        Some([t, v]) => Some([t.as_f64()? as f32, v.as_f64()? as f32]),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| "Curve keys must be [time, value] pairs".to_string())?;
+                let looped = object.get("loop").and_then(|x| x.as_bool()).unwrap_or(false);
+                Ok(UniformTrack::Curve(Curve { keys, looped }))
+            },
+            x => Err(format!("Invalid parameter {}", x)),
+        }
+    }
+
+    /// Sets the uniform called `name` in the program in use to this, returning whether it exists
+    pub fn set(&self, gl: &GlGraphics, name: &str) -> bool {
+        match self {
+            ParamValue::Int(x) => gl.get_uniform::<SUInt>(name).map(|u| u.set(gl, *x)).is_some(),
+            ParamValue::Float(x) => gl.get_uniform::<SUFloat>(name).map(|u| u.set(gl, *x)).is_some(),
+            ParamValue::Vec2(x) => gl.get_uniform::<SUVec2>(name).map(|u| u.set(gl, x)).is_some(),
+            ParamValue::Vec3(x) => gl.get_uniform::<SUVec3>(name).map(|u| u.set(gl, x)).is_some(),
+            ParamValue::Vec4(x) => gl.get_uniform::<SUVec4>(name).map(|u| u.set(gl, x)).is_some(),
+        }
+    }
+}
+
+/// Uniform values loaded from a JSON object in the app's assets, such as
+/// `{ "tint": [1.0, 0.5, 0.2, 1.0], "intensity": 2.5 }`, so materials can be tuned without rebuilding.
+///
+/// Values can also be animated: a string is an `Expr` such as `"0.5 + 0.5 * sin(time)"`, arrays can
+/// mix numbers and expressions for vectors, and `{ "keys": [[0, 0], [1, 2]], "loop": true }` is a `Curve`
+/// over time. These are evaluated by `apply_animated`.
+///
+/// In debug builds, a file with the same name in `<internal storage>/assets` (pushed with
+/// `adb push`) takes priority over the asset and is reloaded by `poll` whenever it changes.
+pub struct ParamFile {
+    name: String,
+    values: HashMap<String, UniformTrack>,
+    modified: Option<SystemTime>,
+}
+
+impl ParamFile {
+    /// Loads the parameter file `name` from the assets
+    pub fn load(name: &str) -> Result<Self, String> {
+        let mut file = Self {
+            name: name.to_string(),
+            values: HashMap::new(),
+            modified: None,
+        };
+        file.reload()?;
+        Ok(file)
+    }
+
+    fn override_path(&self) -> PathBuf {
+        crate::internal_storage_path().join("assets").join(&self.name)
+    }
+
+    fn reload(&mut self) -> Result<(), String> {
+        let bytes = if cfg!(debug_assertions) && self.override_path().exists() {
+            let path = self.override_path();
+            self.modified = std::fs::metadata(&path).and_then(|x| x.modified()).ok();
+            std::fs::read(&path).map_err(|e| format!("Could not read {:?}: {}", path, e))?
+        } else {
+            android_glue::load_asset(&self.name).map_err(|_| format!("Could not load asset {}", self.name))?
+        };
+        let json: Value = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid parameter file {}: {}", self.name, e))?;
+        let object = json.as_object().ok_or_else(|| format!("Parameter file {} isn't an object", self.name))?;
+        self.values = object
+            .iter()
+            .map(|(name, value)| {
+                ParamValue::track_from_json(value)
+                    .map(|x| (name.clone(), x))
+                    .map_err(|e| format!("In {} of {}: {}", name, self.name, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Reloads the file if it was changed on the device, returning whether it was.
+    /// Only does anything in debug builds. When the changed file can't be read the
+    /// previous values are kept, so a typo doesn't break the running app
+    pub fn poll(&mut self) -> Result<bool, String> {
+        if !cfg!(debug_assertions) {
+            return Ok(false);
+        }
+        let modified = std::fs::metadata(self.override_path()).and_then(|x| x.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+        // `reload` records the new modification time first, so a broken file is only retried once it's changed again
+        self.reload().map(|()| true)
+    }
+
+    /// Gets a parameter, evaluating animated ones at the default inputs
+    pub fn get(&self, name: &str) -> Option<ParamValue> {
+        self.get_animated(name, &TrackInputs::default())
+    }
+
+    /// Gets a parameter evaluated at `inputs`
+    pub fn get_animated(&self, name: &str, inputs: &TrackInputs) -> Option<ParamValue> {
+        self.values.get(name).map(|x| x.eval(inputs))
+    }
+
+    /// Sets every parameter with a matching uniform in the program in use, call this in the
+    /// uniforms closure passed to `GlGraphics::shader_draw`. Animated parameters use the default inputs
+    pub fn apply(&self, gl: &GlGraphics) {
+        self.apply_animated(gl, &TrackInputs::default());
+    }
+
+    /// Like `apply`, evaluating animated parameters at `inputs`
+    pub fn apply_animated(&self, gl: &GlGraphics, inputs: &TrackInputs) {
+        for (name, track) in &self.values {
+            track.eval(inputs).set(gl, name);
+        }
+    }
+}
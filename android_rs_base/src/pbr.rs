@@ -0,0 +1,437 @@
+use std::rc::Rc;
+use opengl_graphics::{GLSL, GlGraphics, Texture, TextureSettings, gl};
+use opengl_graphics::gl::types::GLuint;
+use opengl_graphics::shader_utils::{Shader, Program, DynamicAttribute, ElementBuffer};
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUInt, SUMat4x4, SUFloat, SUVec3, SUVec4};
+use graphics::{Context, DrawState};
+use cgmath::{Matrix4, SquareMatrix, Point3, EuclideanSpace, Transform as Transformation};
+use image::{Rgba, RgbaImage};
+use crate::storage::{Drawable, Transforms, ViewProj, glsl_header};
+use crate::light_probes::{SH_AMBIENT_GLSL, ShProbe, ShUniforms};
+
+pub(crate) const BUFFER_SIZE: usize = 1 << 14;
+
+const VERTEX_SHADER: &str = "uniform mat4 view_proj;
+uniform mat4 model;
+
+in vec3 pos;
+in vec3 normal;
+in vec2 uv;
+
+out vec3 v_World;
+out vec3 v_Normal;
+out vec2 v_Uv;
+
+void main() {
+    vec4 world = model * vec4(pos, 1.0);
+    v_World = world.xyz;
+    v_Normal = mat3(model) * normal;
+    v_Uv = uv;
+    gl_Position = view_proj * world;
+}";
+
+const FRAGMENT_HEADER: &str = "precision mediump float;
+";
+
+const FRAGMENT_SHADER: &str = "
+uniform sampler2D s_base_color;
+uniform sampler2D s_normal;
+uniform sampler2D s_metallic_roughness;
+uniform sampler2D s_occlusion;
+uniform sampler2D s_emissive;
+
+uniform vec4 base_color_factor;
+uniform float metallic_factor;
+uniform float roughness_factor;
+uniform float normal_scale;
+uniform float occlusion_strength;
+uniform vec3 emissive_factor;
+
+uniform vec3 eye;
+uniform vec3 light_direction;
+uniform vec3 light_color;
+
+in vec3 v_World;
+in vec3 v_Normal;
+in vec2 v_Uv;
+
+out vec4 outColor;
+
+const float PI = 3.14159265;
+
+vec3 srgb_to_linear(vec3 color) {
+    return pow(color, vec3(2.2));
+}
+
+// Perturbs the normal without tangents, building the frame from screen space derivatives
+vec3 perturb_normal(vec3 n, vec3 to_eye) {
+    vec3 mapped = texture(s_normal, v_Uv).xyz * 2.0 - 1.0;
+    mapped.xy *= normal_scale;
+    vec3 dp1 = dFdx(-to_eye);
+    vec3 dp2 = dFdy(-to_eye);
+    vec2 duv1 = dFdx(v_Uv);
+    vec2 duv2 = dFdy(v_Uv);
+    vec3 dp2perp = cross(dp2, n);
+    vec3 dp1perp = cross(n, dp1);
+    vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+    float scale = inversesqrt(max(dot(t, t), dot(b, b)));
+    if (scale > 1e8) {
+        return n;
+    }
+    return normalize(mat3(t * scale, b * scale, n) * mapped);
+}
+
+// Fits the split sum environment BRDF, so no lookup texture is needed
+vec3 env_brdf(vec3 specular_color, float roughness, float n_dot_v) {
+    const vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+    const vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+    vec4 r = roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    vec2 ab = vec2(-1.04, 1.04) * a004 + r.zw;
+    return specular_color * ab.x + ab.y;
+}
+
+void main() {
+    vec4 base_texel = texture(s_base_color, v_Uv);
+    vec4 base_color = base_color_factor * vec4(srgb_to_linear(base_texel.rgb), base_texel.a);
+    vec4 metallic_roughness = texture(s_metallic_roughness, v_Uv);
+    float roughness = clamp(roughness_factor * metallic_roughness.g, 0.04, 1.0);
+    float metallic = clamp(metallic_factor * metallic_roughness.b, 0.0, 1.0);
+    float occlusion = mix(1.0, texture(s_occlusion, v_Uv).r, occlusion_strength);
+    vec3 emissive = emissive_factor * srgb_to_linear(texture(s_emissive, v_Uv).rgb);
+
+    vec3 to_eye = eye - v_World;
+    vec3 v = normalize(to_eye);
+    vec3 n = perturb_normal(normalize(v_Normal), to_eye);
+    if (!gl_FrontFacing) {
+        n = -n;
+    }
+    float n_dot_v = max(dot(n, v), 1e-4);
+
+    vec3 diffuse_color = base_color.rgb * (1.0 - metallic);
+    vec3 specular_color = mix(vec3(0.04), base_color.rgb, metallic);
+
+    // The directional light, with GGX distribution, Smith visibility and Schlick fresnel
+    vec3 l = -light_direction;
+    vec3 h = normalize(l + v);
+    float n_dot_l = max(dot(n, l), 0.0);
+    float n_dot_h = max(dot(n, h), 0.0);
+    float alpha = roughness * roughness;
+    float alpha2 = alpha * alpha;
+    float denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    float distribution = alpha2 / (PI * denominator * denominator);
+    float k = alpha * 0.5;
+    float visibility = 0.25 / ((n_dot_l * (1.0 - k) + k) * (n_dot_v * (1.0 - k) + k));
+    vec3 fresnel = specular_color + (1.0 - specular_color) * pow(1.0 - max(dot(v, h), 0.0), 5.0);
+    vec3 direct = ((1.0 - fresnel) * diffuse_color / PI + fresnel * distribution * visibility) * light_color * n_dot_l;
+
+    // Image based lighting from the probe, the specular part seeing it along a blurred reflection
+    vec3 reflected = reflect(-v, n);
+    vec3 ambient_diffuse = sh_ambient(n) * diffuse_color;
+    vec3 ambient_specular = sh_ambient(normalize(mix(reflected, n, alpha))) * env_brdf(specular_color, roughness, n_dot_v);
+
+    outColor = vec4(direct + (ambient_diffuse + ambient_specular) * occlusion + emissive, base_color.a);
+}";
+
+/// A metallic-roughness material, with the same meaning as in glTF. Each factor is multiplied
+/// with its texture when there is one; colour textures are sRGB, the rest are linear.
+#[derive(Clone)]
+pub struct PbrMaterial {
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<Rc<Texture>>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Roughness is read from the green channel and metalness from the blue one
+    pub metallic_roughness_texture: Option<Rc<Texture>>,
+    /// A tangent space normal map, with y pointing up
+    pub normal_texture: Option<Rc<Texture>>,
+    pub normal_scale: f32,
+    /// Ambient occlusion, read from the red channel. Only darkens the ambient light
+    pub occlusion_texture: Option<Rc<Texture>>,
+    pub occlusion_strength: f32,
+    pub emissive: [f32; 3],
+    pub emissive_texture: Option<Rc<Texture>>,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive: [0.0; 3],
+            emissive_texture: None,
+        }
+    }
+}
+
+/// A physically based shader for `PbrMaterial`s, lit by one directional light and by image based
+/// lighting from a `ShProbe` (usually sampled from `ShaderStorage::light_probes`).
+///
+/// There's no cubemap to reflect, so the specular part of the image based lighting reads the
+/// probe along the reflection, which suits rough surfaces much better than mirror-like ones.
+pub struct PbrShader {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 3]>,
+    normal: DynamicAttribute<[f32; 3]>,
+    uv: DynamicAttribute<[f32; 2]>,
+    view_proj: ShaderUniform<SUMat4x4>,
+    model: ShaderUniform<SUMat4x4>,
+    eye: ShaderUniform<SUVec3>,
+    light_direction: ShaderUniform<SUVec3>,
+    light_color: ShaderUniform<SUVec3>,
+    base_color: ShaderUniform<SUVec4>,
+    metallic: ShaderUniform<SUFloat>,
+    roughness: ShaderUniform<SUFloat>,
+    normal_scale: ShaderUniform<SUFloat>,
+    occlusion_strength: ShaderUniform<SUFloat>,
+    emissive: ShaderUniform<SUVec3>,
+    ambient: ShUniforms,
+    pos_buffer: Vec<[f32; 3]>,
+    normal_buffer: Vec<[f32; 3]>,
+    uv_buffer: Vec<[f32; 2]>,
+    indices: Vec<u16>,
+    ebo: ElementBuffer,
+    offset: usize,
+    // The base colour texture is drawn with, then the normal, metallic-roughness, occlusion and emissive textures
+    texture_id: GLuint,
+    textures: [GLuint; 4],
+    // Stand in for missing textures
+    white: Rc<Texture>,
+    flat_normal: Rc<Texture>,
+}
+
+impl Drop for PbrShader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl PbrShader {
+    /// Sets the direction light travels in and its colour, which can be brighter than `1.0`.
+    /// Defaults to straight down and white
+    pub fn set_light(&self, gl: &GlGraphics, direction: [f32; 3], color: [f32; 3]) {
+        let length = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt().max(1e-6);
+        self.light_direction.set(gl, &[direction[0] / length, direction[1] / length, direction[2] / length]);
+        self.light_color.set(gl, &color);
+    }
+
+    /// Sets the image based lighting to `probe`
+    pub fn set_ambient(&self, gl: &GlGraphics, probe: &ShProbe) {
+        self.ambient.set(gl, probe);
+    }
+
+    /// Sets the camera position, which specular highlights depend on
+    pub fn set_eye(&self, gl: &GlGraphics, eye: Point3<f32>) {
+        self.eye.set(gl, eye.as_ref());
+    }
+
+    /// Sets the factors of `material` and the textures besides the base colour,
+    /// which is passed when drawing
+    pub fn set_material(&mut self, gl: &GlGraphics, material: &PbrMaterial) {
+        self.base_color.set(gl, &material.base_color);
+        self.metallic.set(gl, material.metallic);
+        self.roughness.set(gl, material.roughness);
+        self.normal_scale.set(gl, material.normal_scale);
+        self.occlusion_strength.set(gl, material.occlusion_strength);
+        self.emissive.set(gl, &material.emissive);
+        let id = |texture: &Option<Rc<Texture>>, default: &Rc<Texture>| texture.as_ref().unwrap_or(default).get_id();
+        self.textures = [
+            id(&material.normal_texture, &self.flat_normal),
+            id(&material.metallic_roughness_texture, &self.white),
+            id(&material.occlusion_texture, &self.white),
+            id(&material.emissive_texture, &self.white),
+        ];
+    }
+
+    /// The texture to draw with when `material` has no base colour texture
+    pub fn base_color_texture(&self, material: &PbrMaterial) -> Rc<Texture> {
+        material.base_color_texture.clone().unwrap_or_else(|| self.white.clone())
+    }
+}
+
+impl Shader for PbrShader {
+    type Vertex = [f32; 3];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("PbrShader requires a GlGraphics to find its uniforms");
+        let header = glsl_header(graphics.profile()).unwrap();
+        let fragment = format!("{}{}{}{}", header, FRAGMENT_HEADER, SH_AMBIENT_GLSL, FRAGMENT_SHADER);
+        let program = Program::from_vs_fs(&[header, VERTEX_SHADER].concat(), &fragment).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        for (unit, name) in ["s_normal", "s_metallic_roughness", "s_occlusion", "s_emissive"].iter().enumerate() {
+            graphics.get_uniform::<SUInt>(name).unwrap().set(graphics, unit as i32 + 1);
+        }
+        let pixel = |color: [u8; 4]| {
+            let image = RgbaImage::from_pixel(1, 1, Rgba(color));
+            Rc::new(Texture::from_image(&image, &TextureSettings::new()))
+        };
+        let mut shader = Self {
+            pos: DynamicAttribute::xyz(program.id(), "pos").unwrap(),
+            normal: DynamicAttribute::xyz(program.id(), "normal").unwrap(),
+            uv: DynamicAttribute::uv(program.id(), "uv").unwrap(),
+            view_proj: graphics.get_uniform("view_proj").unwrap(),
+            model: graphics.get_uniform("model").unwrap(),
+            eye: graphics.get_uniform("eye").unwrap(),
+            light_direction: graphics.get_uniform("light_direction").unwrap(),
+            light_color: graphics.get_uniform("light_color").unwrap(),
+            base_color: graphics.get_uniform("base_color_factor").unwrap(),
+            metallic: graphics.get_uniform("metallic_factor").unwrap(),
+            roughness: graphics.get_uniform("roughness_factor").unwrap(),
+            normal_scale: graphics.get_uniform("normal_scale").unwrap(),
+            occlusion_strength: graphics.get_uniform("occlusion_strength").unwrap(),
+            emissive: graphics.get_uniform("emissive_factor").unwrap(),
+            ambient: ShUniforms::find(graphics).unwrap(),
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 3]; BUFFER_SIZE],
+            normal_buffer: vec![[0.0; 3]; BUFFER_SIZE],
+            uv_buffer: vec![[0.0; 2]; BUFFER_SIZE],
+            indices: Vec::new(),
+            ebo: ElementBuffer::new(),
+            offset: 0,
+            texture_id: 0,
+            textures: [0; 4],
+            white: pixel([255; 4]),
+            flat_normal: pixel([128, 128, 255, 255]),
+        };
+        shader.set_material(graphics, &PbrMaterial::default());
+        shader.set_light(graphics, [0.0, -1.0, 0.0], [1.0; 3]);
+        shader.set_ambient(graphics, &ShProbe::uniform([0.1; 3]));
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            for (unit, &texture) in self.textures.iter().enumerate() {
+                gl::ActiveTexture(gl::TEXTURE1 + unit as u32);
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+            }
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture_id);
+            gl::Enable(gl::CULL_FACE);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            self.normal.bind_vao(self.vao);
+            self.normal.set(&self.normal_buffer[..self.offset]);
+            self.uv.bind_vao(self.vao);
+            self.uv.set(&self.uv_buffer[..self.offset]);
+            self.ebo.draw(&self.indices, self.offset);
+            gl::Disable(gl::CULL_FACE);
+            gl::BindVertexArray(0);
+        }
+        self.indices.clear();
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 3]> {
+        &mut self.pos_buffer
+    }
+    fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> {
+        Some(&mut self.uv_buffer)
+    }
+    fn index_buffer(&mut self) -> Option<&mut Vec<u16>> {
+        Some(&mut self.indices)
+    }
+    fn normal_buffer(&mut self) -> Option<&mut Vec<[f32; 3]>> {
+        Some(&mut self.normal_buffer)
+    }
+    fn texture_id(&mut self) -> Option<&mut GLuint> {
+        Some(&mut self.texture_id)
+    }
+    fn has_texture(&self) -> bool {
+        true
+    }
+    fn model_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.model)
+    }
+    fn view_proj_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.view_proj)
+    }
+}
+
+/// A mesh with a `PbrMaterial`, drawn with `PbrShader` by `ShaderContext::draw`.
+///
+/// Set `ambient` from `ShaderStorage::light_probes` at the mesh's position to light it
+/// with its surroundings, it's a dim grey by default.
+pub struct PbrMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// Draws the vertices in order when `None`
+    pub indices: Option<Vec<u16>>,
+    pub material: PbrMaterial,
+    pub ambient: ShProbe,
+    pub draw_state: DrawState,
+}
+
+impl PbrMesh {
+    pub fn new(positions: Vec<[f32; 3]>, normals: Vec<[f32; 3]>, uvs: Vec<[f32; 2]>, indices: Option<Vec<u16>>, material: PbrMaterial) -> Self {
+        Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+            ambient: ShProbe::uniform([0.1; 3]),
+            draw_state: DrawState::new_alpha(),
+        }
+    }
+}
+
+impl Drawable for PbrMesh {
+    type Shader = PbrShader;
+
+    fn draw_with(
+        &mut self,
+        data: &mut PbrShader,
+        graphics: &mut GlGraphics,
+        _context: &Context,
+        cache: &mut ViewProj,
+        _transforms: &mut Transforms
+    ) {
+        let eye = cache.view
+            .invert()
+            .unwrap_or_else(Matrix4::identity)
+            .transform_point(Point3::origin());
+        let base_color = data.base_color_texture(&self.material);
+        let (material, ambient) = (&self.material, &self.ambient);
+        if let Err(e) = graphics.try_shader_draw(
+            data,
+            &self.draw_state,
+            &self.positions,
+            self.indices.as_ref().map(|x| &x[..]),
+            Some((&base_color, &self.uvs)),
+            None,
+            Some(&self.normals),
+            |shader, gl| {
+                shader.set_material(gl, material);
+                shader.set_ambient(gl, ambient);
+                shader.set_eye(gl, eye);
+            }
+        ) {
+            android_glue::write_log(&format!("Could not draw PBR mesh: {}", e));
+        }
+    }
+}
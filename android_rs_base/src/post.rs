@@ -0,0 +1,167 @@
+use opengl_graphics::{GLSL, GlGraphics, RenderTarget, Texture, gl};
+use opengl_graphics::gl::types::{GLenum, GLuint};
+use opengl_graphics::shader_utils::{Shader, Program, DynamicAttribute};
+use opengl_graphics::ImageSize;
+use graphics::{DrawState, Viewport};
+use crate::storage::glsl_header;
+
+/// The vertex shader used by every `PostShader`, passes `v_UV` to the fragment shader.
+/// It has no `#version` line, `glsl_header` gives the one to start it with
+pub const POST_VERTEX_SHADER: &str = "in vec2 pos;
+in vec2 uv;
+
+out vec2 v_UV;
+
+void main() {
+    v_UV = uv;
+    gl_Position = vec4(pos, 0.0, 1.0);
+}";
+
+const COPY_FRAGMENT_SHADER: &str = "precision mediump float;
+uniform sampler2D s_texture;
+
+in vec2 v_UV;
+
+out vec4 outColor;
+
+void main() {
+    outColor = texture(s_texture, v_UV);
+}";
+
+const FULLSCREEN_QUAD: [[f32; 2]; 6] = [
+    [-1.0, -1.0], [1.0, -1.0], [1.0, 1.0],
+    [-1.0, -1.0], [1.0, 1.0], [-1.0, 1.0],
+];
+
+const FULLSCREEN_UVS: [[f32; 2]; 6] = [
+    [0.0, 0.0], [1.0, 0.0], [1.0, 1.0],
+    [0.0, 0.0], [1.0, 1.0], [0.0, 1.0],
+];
+
+/// A shader which draws a texture over the whole viewport through a custom fragment shader.
+///
+/// The texture is bound to unit 0 and read through `s_texture` with the coordinates in `v_UV`.
+/// `PostShader::new` creates one which copies the texture as-is.
+pub struct PostShader {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 2]>,
+    uv: DynamicAttribute<[f32; 2]>,
+    pos_buffer: Vec<[f32; 2]>,
+    uv_buffer: Vec<[f32; 2]>,
+    offset: usize,
+    texture_id: GLuint,
+    extra_textures: Vec<(GLenum, GLuint)>,
+}
+
+impl Drop for PostShader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl PostShader {
+    /// Creates a post shader from the source of a fragment shader, written without a `#version`
+    /// line: both shaders start with the one `glsl_header` gives for `gl`'s profile
+    pub fn with_fragment(gl: &GlGraphics, fragment_shader: &str) -> Result<Self, String> {
+        let header = glsl_header(gl.profile())?;
+        let program = Program::from_vs_fs(
+            &[header, POST_VERTEX_SHADER].concat(),
+            &[header, fragment_shader].concat(),
+        )?;
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        Ok(Self {
+            pos: DynamicAttribute::xy(program.id(), "pos")?,
+            uv: DynamicAttribute::uv(program.id(), "uv")?,
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 2]; 6],
+            uv_buffer: vec![[0.0; 2]; 6],
+            offset: 0,
+            texture_id: 0,
+            extra_textures: Vec::new(),
+        })
+    }
+
+    /// Binds `texture` to texture unit `unit` whenever this draws, alongside the main texture in unit 0.
+    /// The sampler uniform reading it needs to be set to `unit`
+    pub fn set_texture(&mut self, unit: u32, texture: &Texture) {
+        let unit = gl::TEXTURE0 + unit;
+        self.extra_textures.retain(|&(x, _)| x != unit);
+        self.extra_textures.push((unit, texture.get_id()));
+    }
+
+    /// Draws `texture` over the whole viewport. `uniforms` is called with this bound to set its uniforms
+    pub fn draw<F: FnOnce(&mut Self, &mut GlGraphics)>(&mut self, gl: &mut GlGraphics, texture: &Texture, draw_state: &DrawState, uniforms: F) {
+        gl.shader_draw(
+            self,
+            draw_state,
+            &FULLSCREEN_QUAD,
+            None,
+            Some((texture, &FULLSCREEN_UVS)),
+            None,
+            None,
+            uniforms
+        );
+    }
+}
+
+impl Shader for PostShader {
+    type Vertex = [f32; 2];
+    fn new(_glsl: GLSL, gl: Option<&mut GlGraphics>) -> Self {
+        let gl = gl.expect("PostShader requires a GlGraphics to pick its GLSL version");
+        Self::with_fragment(gl, COPY_FRAGMENT_SHADER).unwrap()
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            for &(unit, id) in &self.extra_textures {
+                gl::ActiveTexture(unit);
+                gl::BindTexture(gl::TEXTURE_2D, id);
+            }
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture_id);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            self.uv.bind_vao(self.vao);
+            self.uv.set(&self.uv_buffer[..self.offset]);
+            gl::DrawArrays(gl::TRIANGLES, 0, self.offset as i32);
+            gl::BindVertexArray(0);
+        }
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 2]> {
+        &mut self.pos_buffer
+    }
+    fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> {
+        Some(&mut self.uv_buffer)
+    }
+    fn texture_id(&mut self) -> Option<&mut GLuint> {
+        Some(&mut self.texture_id)
+    }
+    fn has_texture(&self) -> bool {
+        true
+    }
+}
+
+/// Makes sure `target` exists and is the size of `viewport`'s drawing area, recreating it if not
+pub(crate) fn fit_target(target: &mut Option<RenderTarget>, viewport: Viewport) -> &RenderTarget {
+    let size = (viewport.draw_size[0], viewport.draw_size[1]);
+    if target.as_ref().map(|x| x.texture().get_size()) != Some(size) {
+        *target = Some(RenderTarget::new(size.0, size.1).expect("Could not create render target"));
+    }
+    target.as_ref().unwrap()
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::io;
+
+/// A small persistent key-value store kept in the app's internal storage.
+///
+/// Values are stored as `key=value` lines and parsed back on request, with backslashes, `=` and line
+/// breaks in keys and values escaped by a backslash.
+pub struct Preferences {
+    path: PathBuf,
+    values: HashMap<String, String>,
+}
+
+impl Preferences {
+    /// Loads the preferences stored at `path`, or starts empty if there are none yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let values = std::fs::read_to_string(&path)
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(parse_line)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            path,
+            values,
+        }
+    }
+
+    /// Gets the value of `key` parsed as `T`, `None` if it is missing or fails to parse
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.values.get(key).and_then(|x| x.parse().ok())
+    }
+
+    /// Sets the value of `key`, this is not written to disk until `save` is called
+    pub fn set<T: ToString>(&mut self, key: &str, value: T) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    /// The keys which have a value
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(|x| x.as_str())
+    }
+
+    /// Removes `key`, returning whether it was present
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.values.remove(key).is_some()
+    }
+
+    /// Writes the preferences back to disk
+    pub fn save(&self) -> io::Result<()> {
+        let contents = self.values
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", escape(key), escape(value)))
+            .collect::<String>();
+        std::fs::write(&self.path, contents)
+    }
+}
+
+// Escapes the characters which would break a `key=value` line apart
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '=' => escaped.push_str("\\="),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Splits a line at its first unescaped `=`, unescaping the key and value on either side
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let (mut key, mut value) = (String::new(), String::new());
+    let mut in_value = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                c => c,
+            },
+            '=' if !in_value => {
+                in_value = true;
+                continue;
+            },
+            c => c,
+        };
+        if in_value { value.push(c) } else { key.push(c) }
+    }
+    if in_value {
+        Some((key, value))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Preferences;
+
+    #[test]
+    fn round_trip_escaped() {
+        let path = std::env::temp_dir().join(format!("preferences_round_trip_{}", std::process::id()));
+        let pairs = [
+            ("plain", "value"),
+            ("key=with=equals", "a=b"),
+            ("multi\nline", "first\r\nsecond\n"),
+            ("back\\slash", "ends with \\"),
+            ("", "=\\n"),
+        ];
+        let mut preferences = Preferences::load(&path);
+        for &(key, value) in pairs.iter() {
+            preferences.set(key, value);
+        }
+        preferences.save().unwrap();
+        let loaded = Preferences::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.keys().count(), pairs.len());
+        for &(key, value) in pairs.iter() {
+            assert_eq!(loaded.get::<String>(key).as_deref(), Some(value));
+        }
+    }
+}
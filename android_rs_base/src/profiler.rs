@@ -0,0 +1,320 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+use graphics::{Context, Graphics, rectangle};
+use opengl_graphics::GpuTiming;
+use crate::hud::{Anchor, Hud};
+
+const SCOPE_COUNT: usize = 3;
+// The overlay's measurements, in dp
+const OVERLAY_MARGIN: f64 = 8.0;
+const BAR_WIDTH: f64 = 2.0;
+const GRAPH_HEIGHT: f64 = 60.0;
+const GRAPH_GAP: f64 = 4.0;
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const BUDGET_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+const GPU_COLOR: [f32; 4] = [0.9, 0.2, 0.3, 1.0];
+
+/// A part of every frame `FrameProfiler` times on the CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProfileScope {
+    /// `AppImpl::update`, the fixed updates after it and the timeline, summed over every update run in the frame
+    Update,
+    /// `draw_2d`, `draw_shaded` and `draw_overlay_2d`
+    Draw,
+    /// Drawing what's still batched and swapping buffers, which is where the CPU waits for the GPU when it falls behind
+    Flush,
+}
+
+impl ProfileScope {
+    pub const ALL: [ProfileScope; SCOPE_COUNT] = [ProfileScope::Update, ProfileScope::Draw, ProfileScope::Flush];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The colour the scope is drawn in by the overlay
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            ProfileScope::Update => [0.3, 0.6, 1.0, 1.0],
+            ProfileScope::Draw => [0.3, 0.9, 0.4, 1.0],
+            ProfileScope::Flush => [1.0, 0.7, 0.2, 1.0],
+        }
+    }
+}
+
+/// Statistics over the frames a `FrameProfiler` keeps, in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProfileStats {
+    pub average: f64,
+    pub max: f64,
+    /// The most recent frame's
+    pub last: f64,
+}
+
+// The most recent samples of a measurement, oldest first
+struct History {
+    samples: VecDeque<f64>,
+    sum: f64,
+}
+
+impl History {
+    fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            sum: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f64, length: usize) {
+        self.samples.push_back(sample);
+        self.sum += sample;
+        self.truncate(length);
+    }
+
+    fn truncate(&mut self, length: usize) {
+        while self.samples.len() > length {
+            self.sum -= self.samples.pop_front().unwrap();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.samples.clear();
+        self.sum = 0.0;
+    }
+
+    fn stats(&self) -> ProfileStats {
+        if self.samples.is_empty() {
+            return ProfileStats::default();
+        }
+        ProfileStats {
+            average: self.sum / self.samples.len() as f64,
+            max: self.samples.iter().cloned().fold(0.0, f64::max),
+            last: *self.samples.back().unwrap(),
+        }
+    }
+}
+
+/// Times each frame on the CPU in `ProfileScope`s and on the GPU with timer queries, keeping
+/// rolling statistics over the last frames to tell whether the app is CPU or GPU bound.
+///
+/// Turn it on with `AppConfig::profiling` and read it through `AppConfig::profiler` (or
+/// `AppContainer::profiler`). `AppConfig::profiling_overlay` also draws the recent frames as bar
+/// graphs in the top left corner, the CPU's scopes stacked above the GPU's time, each with a line at
+/// the frame budget halfway up.
+///
+/// GPU times need `GL_EXT_disjoint_timer_query` (or `GL_ARB_timer_query` on desktop), which most
+/// but not all Android devices have, and lag a frame or two behind. Only what's drawn through
+/// `GlGraphics` is timed, see `GlGraphics::set_gpu_timing`.
+pub struct FrameProfiler {
+    enabled: bool,
+    overlay: bool,
+    length: usize,
+    budget: f64,
+    scopes: [History; SCOPE_COUNT],
+    // The time spent in each scope in the frame so far
+    current: [f64; SCOPE_COUNT],
+    started: [Option<Instant>; SCOPE_COUNT],
+    gpu: History,
+    gpu_flushes: u32,
+    frame_times: History,
+    last_frame: Option<Instant>,
+}
+
+impl FrameProfiler {
+    /// A disabled profiler keeping the last 120 frames
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            overlay: false,
+            length: 120,
+            budget: 1000.0 / 60.0,
+            scopes: [History::new(), History::new(), History::new()],
+            current: [0.0; SCOPE_COUNT],
+            started: [None; SCOPE_COUNT],
+            gpu: History::new(),
+            gpu_flushes: 0,
+            frame_times: History::new(),
+            last_frame: None,
+        }
+    }
+
+    /// Starts or stops profiling, stopping drops the frames kept so far
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.overlay = false;
+            self.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Shows or hides the overlay, showing it starts profiling
+    pub fn set_overlay(&mut self, overlay: bool) {
+        self.overlay = overlay;
+        if overlay {
+            self.enabled = true;
+        }
+    }
+
+    pub fn is_overlay_shown(&self) -> bool {
+        self.overlay
+    }
+
+    /// Sets how many frames the statistics and the overlay cover
+    pub fn set_history_length(&mut self, frames: usize) {
+        self.length = frames.max(1);
+        for history in self.scopes.iter_mut().chain(Some(&mut self.gpu)).chain(Some(&mut self.frame_times)) {
+            history.truncate(self.length);
+        }
+    }
+
+    pub fn history_length(&self) -> usize {
+        self.length
+    }
+
+    /// The time each frame has at the render rate set in `AppConfig`, in milliseconds
+    pub fn budget(&self) -> f64 {
+        self.budget
+    }
+
+    pub(crate) fn set_budget(&mut self, budget: f64) {
+        self.budget = budget;
+    }
+
+    /// Drops the frames kept so far
+    pub fn clear(&mut self) {
+        for history in self.scopes.iter_mut().chain(Some(&mut self.gpu)).chain(Some(&mut self.frame_times)) {
+            history.clear();
+        }
+        self.current = [0.0; SCOPE_COUNT];
+        self.started = [None; SCOPE_COUNT];
+        self.gpu_flushes = 0;
+        self.last_frame = None;
+    }
+
+    pub(crate) fn begin(&mut self, scope: ProfileScope) {
+        if self.enabled {
+            self.started[scope.index()] = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn end(&mut self, scope: ProfileScope) {
+        if let Some(start) = self.started[scope.index()].take() {
+            self.current[scope.index()] += start.elapsed().as_secs_f64() * 1000.0;
+        }
+    }
+
+    pub(crate) fn record_gpu(&mut self, timing: GpuTiming) {
+        if self.enabled {
+            self.gpu.push(timing.milliseconds, self.length);
+            self.gpu_flushes = timing.flushes;
+        }
+    }
+
+    /// Adds the frame timed so far to the statistics, after its buffers were swapped
+    pub(crate) fn end_frame(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        for (history, time) in self.scopes.iter_mut().zip(self.current.iter_mut()) {
+            history.push(*time, self.length);
+            *time = 0.0;
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last_frame {
+            self.frame_times.push((now - last).as_secs_f64() * 1000.0, self.length);
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// The time spent in `scope` per frame
+    pub fn scope(&self, scope: ProfileScope) -> ProfileStats {
+        self.scopes[scope.index()].stats()
+    }
+
+    /// The time spent in all the scopes together per frame
+    pub fn cpu(&self) -> ProfileStats {
+        let mut totals = History::new();
+        for frame in 0..self.scopes[0].samples.len() {
+            totals.push(self.scopes.iter().map(|x| x.samples[frame]).sum(), self.length);
+        }
+        totals.stats()
+    }
+
+    /// The time the GPU spent drawing each frame, `None` until timings come in, which they never
+    /// do on devices without timer queries
+    pub fn gpu(&self) -> Option<ProfileStats> {
+        if self.gpu.samples.is_empty() {
+            None
+        } else {
+            Some(self.gpu.stats())
+        }
+    }
+
+    /// How many flushes the GPU time of the last timed frame was spread over, each being a draw call or more
+    pub fn gpu_flushes(&self) -> u32 {
+        self.gpu_flushes
+    }
+
+    /// The time between the ends of consecutive frames
+    pub fn frame_time(&self) -> ProfileStats {
+        self.frame_times.stats()
+    }
+
+    /// The average frames per second
+    pub fn fps(&self) -> f64 {
+        let average = self.frame_times.stats().average;
+        if average > 0.0 { 1000.0 / average } else { 0.0 }
+    }
+
+    /// Whether the GPU takes longer with each frame than the CPU's updates and draws, `None` without GPU
+    /// times. The flush scope is left out, as it includes waiting for the GPU
+    pub fn is_gpu_bound(&self) -> Option<bool> {
+        let cpu = self.scope(ProfileScope::Update).average + self.scope(ProfileScope::Draw).average;
+        self.gpu().map(|gpu| gpu.average > cpu)
+    }
+
+    /// Draws the overlay described in `FrameProfiler`'s docs, which the app container does after
+    /// `draw_overlay_2d` when it's shown
+    pub fn draw_overlay<G: Graphics>(&self, c: &Context, g: &mut G) {
+        let c = Hud::new(c).anchor(c, Anchor::TopLeft, [OVERLAY_MARGIN; 2]);
+        let width = BAR_WIDTH * self.length as f64;
+        let gpu_top = GRAPH_HEIGHT + GRAPH_GAP;
+        // Each graph shows up to twice the budget
+        let scale = GRAPH_HEIGHT / (2.0 * self.budget).max(1e-3);
+        rectangle(BACKGROUND_COLOR, [0.0, 0.0, width, gpu_top + GRAPH_HEIGHT], c.transform, g);
+
+        // The newest frames are on the right
+        let frames = self.scopes[0].samples.len();
+        for frame in 0..frames {
+            let x = width - (frames - frame) as f64 * BAR_WIDTH;
+            let mut bottom = GRAPH_HEIGHT;
+            for &scope in &ProfileScope::ALL {
+                let height = (self.scopes[scope.index()].samples[frame] * scale).min(bottom);
+                if height > 0.0 {
+                    rectangle(scope.color(), [x, bottom - height, BAR_WIDTH * 0.75, height], c.transform, g);
+                }
+                bottom -= height;
+            }
+        }
+        let frames = self.gpu.samples.len();
+        for (frame, &time) in self.gpu.samples.iter().enumerate() {
+            let x = width - (frames - frame) as f64 * BAR_WIDTH;
+            let height = (time * scale).min(GRAPH_HEIGHT);
+            rectangle(GPU_COLOR, [x, gpu_top + GRAPH_HEIGHT - height, BAR_WIDTH * 0.75, height], c.transform, g);
+        }
+
+        for &top in &[0.0, gpu_top] {
+            rectangle(BUDGET_COLOR, [0.0, top + GRAPH_HEIGHT / 2.0, width, 1.0], c.transform, g);
+        }
+    }
+}
+
+impl Default for FrameProfiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
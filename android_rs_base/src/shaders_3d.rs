@@ -0,0 +1,427 @@
+use opengl_graphics::{GLSL, GlGraphics, Texture, gl};
+use opengl_graphics::gl::types::GLuint;
+use opengl_graphics::shader_utils::{Shader, Program, DynamicAttribute, ElementBuffer};
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUMat4x4, SUFloat, SUVec3, SUVec4};
+use graphics::{Context, DrawState};
+use crate::storage::{Drawable, Transforms, ViewProj, glsl_header};
+
+// The most vertices `Colored3d` and `Textured3d` take in one draw
+const BUFFER_SIZE: usize = 1 << 14;
+
+const COLORED_VERTEX_SHADER: &str = "uniform mat4 view_proj;
+uniform mat4 model;
+
+in vec3 pos;
+in vec3 normal;
+in vec4 color;
+
+out vec3 v_Normal;
+out vec4 v_Color;
+
+void main() {
+    v_Normal = mat3(model) * normal;
+    v_Color = color;
+    gl_Position = view_proj * model * vec4(pos, 1.0);
+}";
+
+const COLORED_FRAGMENT_SHADER: &str = "precision mediump float;
+uniform vec3 light_direction;
+uniform float ambient;
+
+in vec3 v_Normal;
+in vec4 v_Color;
+
+out vec4 outColor;
+
+void main() {
+    float diffuse = max(dot(normalize(v_Normal), -light_direction), 0.0);
+    outColor = vec4(v_Color.rgb * (ambient + (1.0 - ambient) * diffuse), v_Color.a);
+}";
+
+const TEXTURED_VERTEX_SHADER: &str = "uniform mat4 view_proj;
+uniform mat4 model;
+
+in vec3 pos;
+in vec3 normal;
+in vec2 uv;
+
+out vec3 v_Normal;
+out vec2 v_Uv;
+
+void main() {
+    v_Normal = mat3(model) * normal;
+    v_Uv = uv;
+    gl_Position = view_proj * model * vec4(pos, 1.0);
+}";
+
+const TEXTURED_FRAGMENT_SHADER: &str = "precision mediump float;
+uniform sampler2D s_texture;
+uniform vec4 color;
+uniform vec3 light_direction;
+uniform float ambient;
+
+in vec3 v_Normal;
+in vec2 v_Uv;
+
+out vec4 outColor;
+
+void main() {
+    vec4 albedo = texture(s_texture, v_Uv) * color;
+    float diffuse = max(dot(normalize(v_Normal), -light_direction), 0.0);
+    outColor = vec4(albedo.rgb * (ambient + (1.0 - ambient) * diffuse), albedo.a);
+}";
+
+/// A 3D version of the built-in `Colored` shader, taking positions, normals, vertex colours and
+/// optionally indices, lit by a single directional light. The model and view-projection matrices
+/// are set by `ShaderContext::draw`, the light with `set_light` while the shader is in use.
+pub struct Colored3d {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 3]>,
+    normal: DynamicAttribute<[f32; 3]>,
+    color: DynamicAttribute<[f32; 4]>,
+    view_proj: ShaderUniform<SUMat4x4>,
+    model: ShaderUniform<SUMat4x4>,
+    light_direction: ShaderUniform<SUVec3>,
+    ambient: ShaderUniform<SUFloat>,
+    pos_buffer: Vec<[f32; 3]>,
+    normal_buffer: Vec<[f32; 3]>,
+    color_buffer: Vec<[f32; 4]>,
+    indices: Vec<u16>,
+    ebo: ElementBuffer,
+    offset: usize,
+}
+
+impl Drop for Colored3d {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl Colored3d {
+    /// Sets the direction light travels in and how bright unlit surfaces are, in `[0, 1]`.
+    /// Defaults to straight down with an ambient of `0.3`
+    pub fn set_light(&self, gl: &GlGraphics, direction: [f32; 3], ambient: f32) {
+        self.light_direction.set(gl, &normalize(direction));
+        self.ambient.set(gl, ambient);
+    }
+}
+
+impl Shader for Colored3d {
+    type Vertex = [f32; 3];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("Colored3d requires a GlGraphics to find its uniforms");
+        let header = glsl_header(graphics.profile()).unwrap();
+        let program = Program::from_vs_fs(
+            &[header, COLORED_VERTEX_SHADER].concat(),
+            &[header, COLORED_FRAGMENT_SHADER].concat(),
+        ).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        let shader = Self {
+            pos: DynamicAttribute::xyz(program.id(), "pos").unwrap(),
+            normal: DynamicAttribute::xyz(program.id(), "normal").unwrap(),
+            color: DynamicAttribute::rgba(program.id(), "color").unwrap(),
+            view_proj: graphics.get_uniform("view_proj").unwrap(),
+            model: graphics.get_uniform("model").unwrap(),
+            light_direction: graphics.get_uniform("light_direction").unwrap(),
+            ambient: graphics.get_uniform("ambient").unwrap(),
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 3]; BUFFER_SIZE],
+            normal_buffer: vec![[0.0; 3]; BUFFER_SIZE],
+            color_buffer: vec![[0.0; 4]; BUFFER_SIZE],
+            indices: Vec::new(),
+            ebo: ElementBuffer::new(),
+            offset: 0,
+        };
+        shader.set_light(graphics, [0.0, -1.0, 0.0], 0.3);
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::Enable(gl::CULL_FACE);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            self.normal.bind_vao(self.vao);
+            self.normal.set(&self.normal_buffer[..self.offset]);
+            self.color.bind_vao(self.vao);
+            self.color.set(&self.color_buffer[..self.offset]);
+            self.ebo.draw(&self.indices, self.offset);
+            gl::Disable(gl::CULL_FACE);
+            gl::BindVertexArray(0);
+        }
+        self.indices.clear();
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 3]> {
+        &mut self.pos_buffer
+    }
+    fn colour_buffer(&mut self) -> Option<&mut Vec<[f32; 4]>> {
+        Some(&mut self.color_buffer)
+    }
+    fn index_buffer(&mut self) -> Option<&mut Vec<u16>> {
+        Some(&mut self.indices)
+    }
+    fn normal_buffer(&mut self) -> Option<&mut Vec<[f32; 3]>> {
+        Some(&mut self.normal_buffer)
+    }
+    fn model_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.model)
+    }
+    fn view_proj_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.view_proj)
+    }
+}
+
+/// A 3D version of the built-in `Textured` shader, taking positions, normals, uvs and optionally
+/// indices, tinted by a colour and lit like `Colored3d`.
+pub struct Textured3d {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 3]>,
+    normal: DynamicAttribute<[f32; 3]>,
+    uv: DynamicAttribute<[f32; 2]>,
+    view_proj: ShaderUniform<SUMat4x4>,
+    model: ShaderUniform<SUMat4x4>,
+    pub color: ShaderUniform<SUVec4>,
+    light_direction: ShaderUniform<SUVec3>,
+    ambient: ShaderUniform<SUFloat>,
+    pos_buffer: Vec<[f32; 3]>,
+    normal_buffer: Vec<[f32; 3]>,
+    uv_buffer: Vec<[f32; 2]>,
+    indices: Vec<u16>,
+    ebo: ElementBuffer,
+    offset: usize,
+    texture_id: GLuint,
+}
+
+impl Drop for Textured3d {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl Textured3d {
+    /// Sets the direction light travels in and how bright unlit surfaces are, in `[0, 1]`.
+    /// Defaults to straight down with an ambient of `0.3`
+    pub fn set_light(&self, gl: &GlGraphics, direction: [f32; 3], ambient: f32) {
+        self.light_direction.set(gl, &normalize(direction));
+        self.ambient.set(gl, ambient);
+    }
+}
+
+impl Shader for Textured3d {
+    type Vertex = [f32; 3];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("Textured3d requires a GlGraphics to find its uniforms");
+        let header = glsl_header(graphics.profile()).unwrap();
+        let program = Program::from_vs_fs(
+            &[header, TEXTURED_VERTEX_SHADER].concat(),
+            &[header, TEXTURED_FRAGMENT_SHADER].concat(),
+        ).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        let shader = Self {
+            pos: DynamicAttribute::xyz(program.id(), "pos").unwrap(),
+            normal: DynamicAttribute::xyz(program.id(), "normal").unwrap(),
+            uv: DynamicAttribute::uv(program.id(), "uv").unwrap(),
+            view_proj: graphics.get_uniform("view_proj").unwrap(),
+            model: graphics.get_uniform("model").unwrap(),
+            color: graphics.get_uniform("color").unwrap(),
+            light_direction: graphics.get_uniform("light_direction").unwrap(),
+            ambient: graphics.get_uniform("ambient").unwrap(),
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 3]; BUFFER_SIZE],
+            normal_buffer: vec![[0.0; 3]; BUFFER_SIZE],
+            uv_buffer: vec![[0.0; 2]; BUFFER_SIZE],
+            indices: Vec::new(),
+            ebo: ElementBuffer::new(),
+            offset: 0,
+            texture_id: 0,
+        };
+        shader.color.set(graphics, &[1.0; 4]);
+        shader.set_light(graphics, [0.0, -1.0, 0.0], 0.3);
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture_id);
+            gl::Enable(gl::CULL_FACE);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            self.normal.bind_vao(self.vao);
+            self.normal.set(&self.normal_buffer[..self.offset]);
+            self.uv.bind_vao(self.vao);
+            self.uv.set(&self.uv_buffer[..self.offset]);
+            self.ebo.draw(&self.indices, self.offset);
+            gl::Disable(gl::CULL_FACE);
+            gl::BindVertexArray(0);
+        }
+        self.indices.clear();
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 3]> {
+        &mut self.pos_buffer
+    }
+    fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> {
+        Some(&mut self.uv_buffer)
+    }
+    fn index_buffer(&mut self) -> Option<&mut Vec<u16>> {
+        Some(&mut self.indices)
+    }
+    fn normal_buffer(&mut self) -> Option<&mut Vec<[f32; 3]>> {
+        Some(&mut self.normal_buffer)
+    }
+    fn texture_id(&mut self) -> Option<&mut GLuint> {
+        Some(&mut self.texture_id)
+    }
+    fn has_texture(&self) -> bool {
+        true
+    }
+    fn model_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.model)
+    }
+    fn view_proj_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.view_proj)
+    }
+}
+
+/// Vertex coloured triangles drawn with `Colored3d` by `ShaderContext::draw`, for simple 3D
+/// scenes without writing a shader. Triangles are counter-clockwise when seen from the front
+pub struct ColoredMesh3d {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 4]>,
+    /// Draws the vertices in order when `None`
+    pub indices: Option<Vec<u16>>,
+    pub draw_state: DrawState,
+}
+
+impl ColoredMesh3d {
+    pub fn new(positions: Vec<[f32; 3]>, normals: Vec<[f32; 3]>, colors: Vec<[f32; 4]>, indices: Option<Vec<u16>>) -> Self {
+        Self {
+            positions,
+            normals,
+            colors,
+            indices,
+            draw_state: DrawState::new_alpha(),
+        }
+    }
+}
+
+impl Drawable for ColoredMesh3d {
+    type Shader = Colored3d;
+
+    fn draw_with(
+        &mut self,
+        data: &mut Colored3d,
+        graphics: &mut GlGraphics,
+        _context: &Context,
+        _cache: &mut ViewProj,
+        _transforms: &mut Transforms
+    ) {
+        if let Err(e) = graphics.try_shader_draw(
+            data,
+            &self.draw_state,
+            &self.positions,
+            self.indices.as_ref().map(|x| &x[..]),
+            None,
+            Some(&self.colors),
+            Some(&self.normals),
+            |_, _| {}
+        ) {
+            android_glue::write_log(&format!("Could not draw coloured mesh: {}", e));
+        }
+    }
+}
+
+/// Textured triangles drawn with `Textured3d` by `ShaderContext::draw`, see `ColoredMesh3d`
+pub struct TexturedMesh3d {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// Draws the vertices in order when `None`
+    pub indices: Option<Vec<u16>>,
+    pub texture: Texture,
+    /// Multiplied with the texture
+    pub color: [f32; 4],
+    pub draw_state: DrawState,
+}
+
+impl TexturedMesh3d {
+    pub fn new(positions: Vec<[f32; 3]>, normals: Vec<[f32; 3]>, uvs: Vec<[f32; 2]>, indices: Option<Vec<u16>>, texture: Texture) -> Self {
+        Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            texture,
+            color: [1.0; 4],
+            draw_state: DrawState::new_alpha(),
+        }
+    }
+}
+
+impl Drawable for TexturedMesh3d {
+    type Shader = Textured3d;
+
+    fn draw_with(
+        &mut self,
+        data: &mut Textured3d,
+        graphics: &mut GlGraphics,
+        _context: &Context,
+        _cache: &mut ViewProj,
+        _transforms: &mut Transforms
+    ) {
+        let color = self.color;
+        if let Err(e) = graphics.try_shader_draw(
+            data,
+            &self.draw_state,
+            &self.positions,
+            self.indices.as_ref().map(|x| &x[..]),
+            Some((&self.texture, &self.uvs)),
+            None,
+            Some(&self.normals),
+            |shader, gl| shader.color.set(gl, &color)
+        ) {
+            android_glue::write_log(&format!("Could not draw textured mesh: {}", e));
+        }
+    }
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt().max(1e-6);
+    [v[0] / length, v[1] / length, v[2] / length]
+}
@@ -0,0 +1,163 @@
+use opengl_graphics::{GLSL, GlGraphics, gl};
+use opengl_graphics::gl::types::GLuint;
+use opengl_graphics::shader_utils::{Shader, Program, DynamicAttribute};
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUMat4x4, SUVec3, SUFloat};
+use cgmath::{Point3, Vector3, InnerSpace};
+use crate::storage::glsl_header;
+
+const VERTEX_SHADER: &str = "uniform mat4 view_proj;
+
+in vec3 pos;
+
+out vec3 v_Pos;
+
+void main() {
+    v_Pos = pos;
+    gl_Position = view_proj * vec4(pos, 1.0);
+}";
+
+const FRAGMENT_SHADER: &str = "precision mediump float;
+uniform vec3 center;
+uniform float radius;
+uniform float opacity;
+
+in vec3 v_Pos;
+
+out vec4 outColor;
+
+void main() {
+    float dist = length(v_Pos - center) / radius;
+    outColor = vec4(0.0, 0.0, 0.0, opacity * (1.0 - smoothstep(0.0, 1.0, dist)));
+}";
+
+/// A cheap round shadow projected onto the ground plane under a drawable.
+/// Returned from `Drawable::blob_shadow` to give a drawable a shadow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlobShadow {
+    /// The world-space position of the object casting the shadow
+    pub position: Point3<f32>,
+    /// The radius of the shadow when the object is touching the ground
+    pub radius: f32,
+    /// The opacity of the shadow when the object is touching the ground, in `[0, 1]`
+    pub opacity: f32,
+}
+
+/// Describes the ground plane blob shadows are projected onto, stored in `ShaderStorage::shadows`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    /// The height (y coordinate) of the ground plane
+    pub ground_height: f32,
+    /// The direction light travels in, shadows are projected along this. Should point downwards
+    pub light_direction: Vector3<f32>,
+    /// The height above the ground at which shadows have faded out completely
+    pub fade_height: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            ground_height: 0.0,
+            light_direction: -Vector3::unit_y(),
+            fade_height: 10.0,
+        }
+    }
+}
+
+impl ShadowSettings {
+    /// Projects `shadow` onto the ground, returning the centre, radius and opacity of the
+    /// projected shadow, or `None` if it isn't visible
+    pub fn project(&self, shadow: &BlobShadow) -> Option<(Point3<f32>, f32, f32)> {
+        let height = shadow.position.y - self.ground_height;
+        let direction = self.light_direction.normalize();
+        if height < 0.0 || direction.y >= 0.0 || height >= self.fade_height {
+            return None;
+        }
+        let fade = height / self.fade_height;
+        let center = shadow.position + direction * (height / -direction.y);
+        Some((center, shadow.radius * (1.0 + fade), shadow.opacity * (1.0 - fade)))
+    }
+}
+
+/// The shader used to draw `BlobShadow`s, a quad on the ground plane with a radial falloff
+pub struct BlobShadowShader {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 3]>,
+    pub view_proj: ShaderUniform<SUMat4x4>,
+    pub center: ShaderUniform<SUVec3>,
+    pub radius: ShaderUniform<SUFloat>,
+    pub opacity: ShaderUniform<SUFloat>,
+    pos_buffer: Vec<[f32; 3]>,
+    offset: usize,
+}
+
+impl Drop for BlobShadowShader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl Shader for BlobShadowShader {
+    type Vertex = [f32; 3];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("BlobShadowShader requires a GlGraphics to find its uniforms");
+        let header = glsl_header(graphics.profile()).unwrap();
+        let program = Program::from_vs_fs(
+            &[header, VERTEX_SHADER].concat(),
+            &[header, FRAGMENT_SHADER].concat(),
+        ).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        let shader = Self {
+            pos: DynamicAttribute::xyz(program.id(), "pos").unwrap(),
+            view_proj: graphics.get_uniform("view_proj").unwrap(),
+            center: graphics.get_uniform("center").unwrap(),
+            radius: graphics.get_uniform("radius").unwrap(),
+            opacity: graphics.get_uniform("opacity").unwrap(),
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 3]; 6],
+            offset: 0,
+        };
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            gl::DrawArrays(gl::TRIANGLES, 0, self.offset as i32);
+            gl::BindVertexArray(0);
+        }
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 3]> {
+        &mut self.pos_buffer
+    }
+}
+
+/// Gets the two triangles of a ground-aligned square of half-width `radius` around `center`
+pub(crate) fn shadow_quad(center: Point3<f32>, radius: f32) -> [[f32; 3]; 6] {
+    // Lift the quad slightly to avoid fighting with the ground
+    let y = center.y + 0.001;
+    let (x0, x1) = (center.x - radius, center.x + radius);
+    let (z0, z1) = (center.z - radius, center.z + radius);
+    [
+        [x0, y, z0], [x1, y, z0], [x1, y, z1],
+        [x0, y, z0], [x1, y, z1], [x0, y, z1],
+    ]
+}
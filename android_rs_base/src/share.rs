@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+use image::{ImageResult, RgbaImage};
+
+/// Gets the directory images are saved to for sharing (`/data/data/<package>/cache/shared`), creating it if needed.
+///
+/// Declare it as `<cache-path name="shared" path="shared/"/>` in the paths of the app's
+/// `FileProvider` so the files can be handed to other apps.
+pub fn shared_files_path() -> PathBuf {
+    let path = crate::internal_storage_path()
+        .with_file_name("cache")
+        .join("shared");
+    let _ = std::fs::create_dir_all(&path);
+    path
+}
+
+/// Saves `image` as a PNG called `name` in `shared_files_path`, returning its path.
+///
+/// Pair this with `AppConfig::capture_frame` (or `RenderTarget::read_pixels`) to share screenshots.
+/// Launching the share sheet itself needs the activity, so has to be done from Java through
+/// `FileProvider::getUriForFile` with `Intent.FLAG_GRANT_READ_URI_PERMISSION`.
+pub fn save_for_sharing(image: &RgbaImage, name: &str) -> ImageResult<PathBuf> {
+    let path = shared_files_path().join(name).with_extension("png");
+    image.save(&path)?;
+    Ok(path)
+}
@@ -0,0 +1,418 @@
+use opengl_graphics::{GLSL, GlGraphics, Mesh, Profile, Texture, gl};
+use opengl_graphics::gl::types::GLuint;
+use opengl_graphics::shader_utils::{Shader, Program, DynamicAttribute, ElementBuffer};
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUInt, SUMat4x4, SUMat4x4Array, SUFloat, SUVec3, SUVec4};
+use graphics::{Context, DrawState};
+use cgmath::{Matrix3, Matrix4, Point3, SquareMatrix, Vector3, InnerSpace, Zero, Transform as Transformation};
+use matrices::batch;
+use crate::storage::{Drawable, Transforms, ViewProj, glsl_header};
+
+// The most vertices a skinned mesh can have, the size of `SkinnedShader`'s buffers
+const BUFFER_SIZE: usize = 1 << 14;
+/// The most joints `SkinnedShader` skins on the GPU, devices with fewer uniforms fit fewer
+pub const MAX_PALETTE_JOINTS: usize = 64;
+// Uniform vectors kept for the matrices and the rest of the vertex shader
+const RESERVED_UNIFORM_VECTORS: usize = 16;
+
+const VERTEX_SHADER: &str = "
+uniform mat4 view_proj;
+uniform mat4 model;
+uniform mat4 bones[PALETTE_SIZE];
+uniform int gpu_skinning;
+
+attribute vec3 pos;
+attribute vec3 normal;
+attribute vec2 uv;
+attribute vec4 joints;
+attribute vec4 weights;
+
+varying vec3 v_Normal;
+varying vec2 v_Uv;
+
+void main() {
+    mat4 skin = mat4(1.0);
+    if (gpu_skinning != 0) {
+        skin = bones[int(joints.x)] * weights.x +
+               bones[int(joints.y)] * weights.y +
+               bones[int(joints.z)] * weights.z +
+               bones[int(joints.w)] * weights.w;
+    }
+    v_Normal = (model * skin * vec4(normal, 0.0)).xyz;
+    v_Uv = uv;
+    gl_Position = view_proj * model * skin * vec4(pos, 1.0);
+}";
+
+// The skinning shaders are written in GLSL ES 1.00, so the CPU skinning fallback works on GLES2.
+// Later versions start them with these, mapping the old names onto the new ones
+const VERTEX_UPGRADE: &str = "#define attribute in
+#define varying out
+";
+
+const FRAGMENT_UPGRADE: &str = "#define varying in
+#define texture2D texture
+#define gl_FragColor outColor
+out mediump vec4 outColor;
+";
+
+// The lines to start the vertex and fragment shaders with on `profile`
+pub(crate) fn headers(profile: Profile) -> (String, String) {
+    match glsl_header(profile) {
+        Ok(version) => ([version, VERTEX_UPGRADE].concat(), [version, FRAGMENT_UPGRADE].concat()),
+        Err(_) => ("#version 100\n".to_string(), "#version 100\n".to_string()),
+    }
+}
+
+// Shared with `CrowdShader`, which starts it with the same header from `headers`
+pub(crate) const FRAGMENT_SHADER: &str = "precision mediump float;
+uniform sampler2D s_texture;
+uniform vec4 color;
+uniform vec3 light_direction;
+uniform float ambient;
+
+varying vec3 v_Normal;
+varying vec2 v_Uv;
+
+void main() {
+    vec4 albedo = texture2D(s_texture, v_Uv) * color;
+    float diffuse = max(dot(normalize(v_Normal), -light_direction), 0.0);
+    gl_FragColor = vec4(albedo.rgb * (ambient + (1.0 - ambient) * diffuse), albedo.a);
+}";
+
+/// How a `SkinnedMesh` is skinned, decided when it's first drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkinningMode {
+    /// Joint matrices are uploaded to a uniform array and the vertices skinned in the vertex
+    /// shader, with the mesh kept on the GPU
+    Gpu,
+    /// Vertices are skinned on the CPU and written to the shader's vertex buffers every frame,
+    /// for skeletons with more joints than the device's uniforms can hold
+    Cpu,
+}
+
+/// A textured shader for `SkinnedMesh`, lit like `Textured3d`, which skins on the GPU when the
+/// skeleton fits in its bone palette.
+///
+/// The palette is sized when the shader is created from `GlGraphics::max_vertex_uniform_vectors`,
+/// up to `MAX_PALETTE_JOINTS`, so GLES2-class devices with few uniforms get a small one and
+/// larger skeletons fall back to `SkinningMode::Cpu`. Vertices drawn through `shader_draw` are
+/// taken as already skinned, which is how the CPU path draws.
+pub struct SkinnedShader {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 3]>,
+    normal: DynamicAttribute<[f32; 3]>,
+    uv: DynamicAttribute<[f32; 2]>,
+    view_proj: ShaderUniform<SUMat4x4>,
+    model: ShaderUniform<SUMat4x4>,
+    bones: ShaderUniform<SUMat4x4Array>,
+    gpu_skinning: ShaderUniform<SUInt>,
+    pub color: ShaderUniform<SUVec4>,
+    light_direction: ShaderUniform<SUVec3>,
+    ambient: ShaderUniform<SUFloat>,
+    palette_size: usize,
+    pos_buffer: Vec<[f32; 3]>,
+    normal_buffer: Vec<[f32; 3]>,
+    uv_buffer: Vec<[f32; 2]>,
+    indices: Vec<u16>,
+    ebo: ElementBuffer,
+    offset: usize,
+    texture_id: GLuint,
+}
+
+impl Drop for SkinnedShader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl SkinnedShader {
+    /// Sets the direction light travels in and how bright unlit surfaces are, in `[0, 1]`.
+    /// Defaults to straight down with an ambient of `0.3`
+    pub fn set_light(&self, gl: &GlGraphics, direction: [f32; 3], ambient: f32) {
+        let length = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt().max(1e-6);
+        self.light_direction.set(gl, &[direction[0] / length, direction[1] / length, direction[2] / length]);
+        self.ambient.set(gl, ambient);
+    }
+
+    /// The most joints a skeleton can have to be skinned on the GPU
+    pub fn palette_size(&self) -> usize {
+        self.palette_size
+    }
+}
+
+impl Shader for SkinnedShader {
+    type Vertex = [f32; 3];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("SkinnedShader requires a GlGraphics to find its uniforms");
+        let palette_size = (graphics.max_vertex_uniform_vectors().saturating_sub(RESERVED_UNIFORM_VECTORS) / 4)
+            .min(MAX_PALETTE_JOINTS)
+            .max(1);
+        let (vertex_header, fragment_header) = headers(graphics.profile());
+        let vertex = format!("{}#define PALETTE_SIZE {}\n{}", vertex_header, palette_size, VERTEX_SHADER);
+        let program = Program::from_vs_fs(&vertex, &[fragment_header.as_str(), FRAGMENT_SHADER].concat()).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        let shader = Self {
+            pos: DynamicAttribute::xyz(program.id(), "pos").unwrap(),
+            normal: DynamicAttribute::xyz(program.id(), "normal").unwrap(),
+            uv: DynamicAttribute::uv(program.id(), "uv").unwrap(),
+            view_proj: graphics.get_uniform("view_proj").unwrap(),
+            model: graphics.get_uniform("model").unwrap(),
+            bones: graphics.get_uniform("bones").unwrap(),
+            gpu_skinning: graphics.get_uniform("gpu_skinning").unwrap(),
+            color: graphics.get_uniform("color").unwrap(),
+            light_direction: graphics.get_uniform("light_direction").unwrap(),
+            ambient: graphics.get_uniform("ambient").unwrap(),
+            palette_size,
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 3]; BUFFER_SIZE],
+            normal_buffer: vec![[0.0; 3]; BUFFER_SIZE],
+            uv_buffer: vec![[0.0; 2]; BUFFER_SIZE],
+            indices: Vec::new(),
+            ebo: ElementBuffer::new(),
+            offset: 0,
+            texture_id: 0,
+        };
+        shader.gpu_skinning.set(graphics, 0);
+        shader.color.set(graphics, &[1.0; 4]);
+        shader.set_light(graphics, [0.0, -1.0, 0.0], 0.3);
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture_id);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            self.normal.bind_vao(self.vao);
+            self.normal.set(&self.normal_buffer[..self.offset]);
+            self.uv.bind_vao(self.vao);
+            self.uv.set(&self.uv_buffer[..self.offset]);
+            self.ebo.draw(&self.indices, self.offset);
+            gl::BindVertexArray(0);
+        }
+        self.indices.clear();
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 3]> {
+        &mut self.pos_buffer
+    }
+    fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> {
+        Some(&mut self.uv_buffer)
+    }
+    fn index_buffer(&mut self) -> Option<&mut Vec<u16>> {
+        Some(&mut self.indices)
+    }
+    fn normal_buffer(&mut self) -> Option<&mut Vec<[f32; 3]>> {
+        Some(&mut self.normal_buffer)
+    }
+    fn texture_id(&mut self) -> Option<&mut GLuint> {
+        Some(&mut self.texture_id)
+    }
+    fn has_texture(&self) -> bool {
+        true
+    }
+    fn model_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.model)
+    }
+    fn view_proj_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.view_proj)
+    }
+}
+
+/// A textured mesh deformed by a skeleton, drawn with `SkinnedShader` by `ShaderContext::draw`.
+///
+/// Each vertex is moved by up to four joints. Animate it by setting `pose` every frame, which
+/// is skinned on the GPU or the CPU depending on what the device supports (see `SkinningMode`).
+pub struct SkinnedMesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u16>,
+    joints: Vec<[u16; 4]>,
+    weights: Vec<[f32; 4]>,
+    inverse_bind: Vec<Matrix4<f32>>,
+    /// The transform of each joint relative to the model, starting at the bind pose
+    pub pose: Vec<Matrix4<f32>>,
+    pub texture: Texture,
+    /// Multiplied with the texture
+    pub color: [f32; 4],
+    pub draw_state: DrawState,
+    mode: Option<SkinningMode>,
+    // Kept on the GPU when skinning there
+    mesh: Option<Mesh<[f32; 3]>>,
+    skin: Vec<Matrix4<f32>>,
+    palette: Vec<[f32; 16]>,
+    skinned_positions: Vec<[f32; 3]>,
+    skinned_normals: Vec<[f32; 3]>,
+}
+
+impl SkinnedMesh {
+    /// Creates a mesh in its bind pose, with the joints and weights of each vertex and the
+    /// inverse of each joint's bind transform (as glTF skins store them). Weights are normalized.
+    pub fn new(
+        positions: Vec<[f32; 3]>,
+        normals: Vec<[f32; 3]>,
+        uvs: Vec<[f32; 2]>,
+        indices: Vec<u16>,
+        joints: Vec<[u16; 4]>,
+        weights: Vec<[f32; 4]>,
+        inverse_bind: Vec<Matrix4<f32>>,
+        texture: Texture
+    ) -> Result<Self, String> {
+        let count = positions.len();
+        if normals.len() != count || uvs.len() != count || joints.len() != count || weights.len() != count {
+            return Err("Skinned meshes need a normal, uv, joints and weights for every vertex".to_string());
+        }
+        if count > BUFFER_SIZE {
+            return Err(format!("Skinned meshes can have at most {} vertices, not {}", BUFFER_SIZE, count));
+        }
+        if let Some(&x) = indices.iter().find(|&&x| x as usize >= count) {
+            return Err(format!("Index {} is out of bounds of the {} vertices", x, count));
+        }
+        if let Some(x) = joints.iter().flat_map(|x| x.iter()).find(|&&x| x as usize >= inverse_bind.len()) {
+            return Err(format!("Joint {} is out of bounds of the {} joints", x, inverse_bind.len()));
+        }
+        let weights = weights
+            .into_iter()
+            .map(|x| {
+                let total = x[0] + x[1] + x[2] + x[3];
+                if total > 0.0 { [x[0] / total, x[1] / total, x[2] / total, x[3] / total] } else { [1.0, 0.0, 0.0, 0.0] }
+            })
+            .collect();
+        let pose = inverse_bind.iter().map(|x| x.invert().unwrap_or_else(Matrix4::identity)).collect();
+        Ok(Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            joints,
+            weights,
+            inverse_bind,
+            pose,
+            texture,
+            color: [1.0; 4],
+            draw_state: DrawState::new_alpha(),
+            mode: None,
+            mesh: None,
+            skin: Vec::new(),
+            palette: Vec::new(),
+            skinned_positions: Vec::new(),
+            skinned_normals: Vec::new(),
+        })
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.inverse_bind.len()
+    }
+
+    /// How the mesh is skinned, `None` until it's first drawn
+    pub fn mode(&self) -> Option<SkinningMode> {
+        self.mode
+    }
+
+    // The matrix moving each joint's vertices from the bind pose into `pose`
+    fn update_skin(&mut self) {
+        // Joints missing from `pose` stay in the bind pose
+        let posed = self.pose.len().min(self.inverse_bind.len());
+        self.skin.clear();
+        self.skin.extend_from_slice(&self.inverse_bind);
+        batch::multiply_pairs(&self.pose[..posed], &self.inverse_bind[..posed], &mut self.skin[..posed]);
+    }
+
+    fn skin_on_cpu(&mut self) {
+        self.skinned_positions.clear();
+        self.skinned_normals.clear();
+        for i in 0..self.positions.len() {
+            let mut matrix = Matrix4::zero();
+            for (&joint, &weight) in self.joints[i].iter().zip(self.weights[i].iter()) {
+                if weight > 0.0 {
+                    matrix += self.skin[joint as usize] * weight;
+                }
+            }
+            let upper = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+            let normal = upper * Vector3::from(self.normals[i]);
+            self.skinned_positions.push(matrix.transform_point(Point3::from(self.positions[i])).into());
+            self.skinned_normals.push(if normal.magnitude2() > 0.0 { normal.normalize().into() } else { self.normals[i] });
+        }
+    }
+}
+
+impl Drawable for SkinnedMesh {
+    type Shader = SkinnedShader;
+
+    fn draw_with(
+        &mut self,
+        data: &mut SkinnedShader,
+        graphics: &mut GlGraphics,
+        _context: &Context,
+        _cache: &mut ViewProj,
+        _transforms: &mut Transforms
+    ) {
+        let fits = self.inverse_bind.len() <= data.palette_size();
+        let mode = *self.mode.get_or_insert(if fits { SkinningMode::Gpu } else { SkinningMode::Cpu });
+        self.update_skin();
+        let color = self.color;
+        match mode {
+            SkinningMode::Gpu => {
+                if self.mesh.is_none() {
+                    let joints = self.joints
+                        .iter()
+                        .map(|x| [x[0] as f32, x[1] as f32, x[2] as f32, x[3] as f32])
+                        .collect::<Vec<_>>();
+                    self.mesh = Some(Mesh::new(&self.positions)
+                        .with_normals(&self.normals)
+                        .with_uvs(&self.uvs)
+                        .with_indices(&self.indices)
+                        .with_attribute("joints", &joints)
+                        .with_attribute("weights", &self.weights));
+                }
+                self.palette.clear();
+                self.palette.extend(self.skin.iter().map(|x| {
+                    let matrix: &[f32; 16] = x.as_ref();
+                    *matrix
+                }));
+                let palette = &self.palette;
+                self.mesh.as_mut().unwrap().draw(graphics, data, &self.draw_state, Some(&self.texture), |shader, gl| {
+                    shader.gpu_skinning.set(gl, 1);
+                    shader.bones.set(gl, &palette[..]);
+                    shader.color.set(gl, &color);
+                });
+                // Leaves the shader's own buffers unskinned, for the next draw
+                graphics.use_program(data.program());
+                data.gpu_skinning.set(graphics, 0);
+                graphics.clear_program();
+            },
+            SkinningMode::Cpu => {
+                self.skin_on_cpu();
+                if let Err(e) = graphics.try_shader_draw(
+                    data,
+                    &self.draw_state,
+                    &self.skinned_positions,
+                    Some(&self.indices[..]),
+                    Some((&self.texture, &self.uvs)),
+                    None,
+                    Some(&self.skinned_normals),
+                    |shader, gl| shader.color.set(gl, &color)
+                ) {
+                    android_glue::write_log(&format!("Could not draw skinned mesh: {}", e));
+                }
+            },
+        }
+    }
+}
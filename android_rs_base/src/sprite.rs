@@ -0,0 +1,132 @@
+use std::rc::Rc;
+use opengl_graphics::{GlGraphics, Texture};
+use graphics::{Context, DrawState, Graphics, ImageSize};
+use graphics::math::transform_pos;
+use cgmath::{Matrix4, Vector4};
+use crate::storage::{Drawable, ShaderContext, Transforms, ViewProj};
+
+/// A textured quad for 2D scenes, drawn with `ShaderContext::draw` through the regular 2D
+/// batching, so sprites sharing a texture are drawn together.
+///
+/// Sprites are placed in the same coordinates as `Context::transform` (pixels, by default),
+/// moved by the current transform of `ShaderContext::transforms` so they can be grouped under
+/// a parent. The transform's `x` and `y` are used, so pushing one with `scale_xyz`,
+/// `rotate_axis` around `z` and `translate_by` moves a whole group of sprites.
+pub struct Sprite {
+    pub texture: Rc<Texture>,
+    /// The part of the texture drawn, in pixels as `[x, y, width, height]`, or the whole of it
+    pub region: Option<[f64; 4]>,
+    /// Where the sprite is placed
+    pub position: [f64; 2],
+    /// The point of the sprite placed at `position` and rotated around, from `[0, 0]` at the
+    /// top left to `[1, 1]` at the bottom right
+    pub anchor: [f64; 2],
+    /// Multiplies the size of the region, negative values flip the sprite
+    pub scale: [f64; 2],
+    /// Clockwise, in radians
+    pub rotation: f64,
+    /// Multiplied with the texture
+    pub tint: [f32; 4],
+    /// Sprites with a higher `z_order` are drawn over lower ones by `Sprite::draw_sorted`
+    pub z_order: i32,
+    pub draw_state: DrawState,
+}
+
+impl Sprite {
+    /// A sprite of the whole of `texture`, anchored at its centre
+    pub fn new(texture: Rc<Texture>) -> Self {
+        Self {
+            texture,
+            region: None,
+            position: [0.0; 2],
+            anchor: [0.5; 2],
+            scale: [1.0; 2],
+            rotation: 0.0,
+            tint: [1.0; 4],
+            z_order: 0,
+            draw_state: DrawState::new_alpha(),
+        }
+    }
+
+    pub fn with_region(mut self, region: [f64; 4]) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn with_position(mut self, position: [f64; 2]) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_anchor(mut self, anchor: [f64; 2]) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// The width and height drawn, before the transform
+    pub fn size(&self) -> [f64; 2] {
+        let (width, height) = self.texture.get_size();
+        let region = self.region.unwrap_or([0.0, 0.0, width as f64, height as f64]);
+        [region[2] * self.scale[0], region[3] * self.scale[1]]
+    }
+
+    /// Draws `sprites` from the lowest `z_order` to the highest, keeping the order of those
+    /// with the same one
+    pub fn draw_sorted(context: &mut ShaderContext, sprites: &mut [Sprite]) {
+        let mut order = (0..sprites.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| sprites[i].z_order);
+        for i in order {
+            context.draw(&mut sprites[i]);
+        }
+    }
+
+    // The corners in the order top left, top right, bottom right, bottom left, before the transform
+    fn corners(&self) -> [[f64; 2]; 4] {
+        let size = self.size();
+        let (sin, cos) = self.rotation.sin_cos();
+        let left = -self.anchor[0] * size[0];
+        let top = -self.anchor[1] * size[1];
+        let corner = |x: f64, y: f64| [
+            self.position[0] + x * cos - y * sin,
+            self.position[1] + x * sin + y * cos,
+        ];
+        [
+            corner(left, top),
+            corner(left + size[0], top),
+            corner(left + size[0], top + size[1]),
+            corner(left, top + size[1]),
+        ]
+    }
+}
+
+impl Drawable for Sprite {
+    type Shader = ();
+
+    fn draw_with(
+        &mut self,
+        _data: &mut (),
+        graphics: &mut GlGraphics,
+        context: &Context,
+        _cache: &mut ViewProj,
+        transforms: &mut Transforms
+    ) {
+        let model: Matrix4<f32> = *transforms.current();
+        let positions = self.corners()
+            .iter()
+            .map(|&[x, y]| {
+                let moved = model * Vector4::new(x as f32, y as f32, 0.0, 1.0);
+                let [x, y] = transform_pos(context.transform, [moved.x as f64, moved.y as f64]);
+                [x as f32, y as f32]
+            })
+            .collect::<Vec<_>>();
+        let (width, height) = self.texture.get_size();
+        let (width, height) = (width as f64, height as f64);
+        let region = self.region.unwrap_or([0.0, 0.0, width, height]);
+        let (u0, v0) = ((region[0] / width) as f32, (region[1] / height) as f32);
+        let (u1, v1) = (((region[0] + region[2]) / width) as f32, ((region[1] + region[3]) / height) as f32);
+        let uvs = [[u0, v0], [u1, v0], [u1, v1], [u0, v1]];
+        let triangle_positions = [positions[0], positions[1], positions[2], positions[0], positions[2], positions[3]];
+        let triangle_uvs = [uvs[0], uvs[1], uvs[2], uvs[0], uvs[2], uvs[3]];
+        graphics.tri_list_uv(&self.draw_state, &self.tint, &self.texture, |f| f(&triangle_positions, &triangle_uvs));
+    }
+}
@@ -0,0 +1,62 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// State kept across the app being killed by the system, stored as JSON in the app's internal storage.
+///
+/// `AppContainer` loads it before `AppImpl::new`, hands it to `AppImpl::signal_pause` and
+/// `AppImpl::on_die` to be filled in, and saves it after each, so the app can resume where it left off.
+pub struct StateStore {
+    path: PathBuf,
+    values: Map<String, Value>,
+}
+
+impl StateStore {
+    /// Loads the state stored at `path`, or starts empty if there is none yet or it can't be read
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let values = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(Value::Object(values)) => Some(values),
+                _ => None,
+            })
+            .unwrap_or_default();
+        Self { path, values }
+    }
+
+    /// Gets the value of `key` deserialized as `T`, `None` if it is missing or has a different type
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.values.get(key).and_then(|x| serde_json::from_value(x.clone()).ok())
+    }
+
+    /// Sets the value of `key`, this is not written to disk until `save` is called
+    pub fn set<T: Serialize>(&mut self, key: &str, value: &T) -> Result<(), String> {
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        self.values.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    /// Removes `key`, returning whether it was present
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.values.remove(key).is_some()
+    }
+
+    /// Removes everything, such as once a saved game has been finished
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Writes the state back to disk, replacing the old file only once the new one is written
+    pub fn save(&self) -> io::Result<()> {
+        let temporary = self.path.with_extension("tmp");
+        std::fs::write(&temporary, Value::Object(self.values.clone()).to_string())?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
@@ -1,18 +1,41 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::cmp::Ordering;
 use opengl_graphics::shader_utils::Shader;
 use std::any::{TypeId, Any};
-use opengl_graphics::{GLSL, GlGraphics};
-use graphics::Context;
+use opengl_graphics::{GLSL, GlGraphics, Profile};
+use graphics::{Context, Viewport};
 use piston::input::RenderArgs;
-use cgmath::{Matrix4, SquareMatrix, Vector3, Quaternion, Rotation3, Rad, Transform as Transformation, Point3, EuclideanSpace};
+use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4, Quaternion, Rotation3, Rad, Transform as Transformation, Point3, EuclideanSpace, InnerSpace};
 use matrices::{TransformHierarchy, Transform as BasicTransform};
+pub use matrices::scene::{SceneGraph, NodeId};
+pub use matrices::TransformQ;
+use graphics::DrawState;
+use crate::shadows::{BlobShadow, BlobShadowShader, ShadowSettings, shadow_quad};
+use crate::weather::Fog;
+use crate::light_probes::LightProbes;
+use crate::lighting::LightSet;
+use crate::assets::Assets;
 
 pub type Transforms = TransformHierarchy<Matrix4<f32>, fn(Matrix4<f32>, Matrix4<f32>, Matrix4<f32>) -> Matrix4<f32>>;
 pub type Transform = BasicTransform<Matrix4<f32>>;
 
 pub struct ShaderStorage {
     shaders: HashMap<TypeId, Box<dyn Any>>,
-    pub cache: ViewProj
+    watched: HashMap<TypeId, WatchedShader>,
+    pub cache: ViewProj,
+    pub shadows: ShadowSettings,
+    /// The fog shaders using `FOG_GLSL` should draw with
+    pub fog: Fog,
+    /// The ambient light probes shaders using `SH_AMBIENT_GLSL` sample for each object
+    pub light_probes: LightProbes,
+    /// The lights shaders using the standard light uniforms are given by `apply_lights`
+    pub lights: LightSet,
+    /// When set, `ShaderContext::draw` skips drawables whose `Drawable::bounding_sphere`
+    /// covers less than this many pixels across on screen
+    pub min_screen_size: Option<f32>,
 }
 
 pub struct ViewProj {
@@ -59,13 +82,238 @@ impl ViewProj {
     pub fn set_projection(&mut self, projection: Matrix4<f32>) {
         self.projection = projection;
     }
+
+    /// Gets the view-space depth of the origin of `transform`, larger values are further from the camera
+    pub fn view_depth(&self, transform: &Transform) -> f32 {
+        let world = transform.to_matrix(|s, r, t| s * r * t);
+        -self.view.transform_point(world.transform_point(Point3::origin())).z
+    }
+
+    /// Projects `point` onto the screen, giving its position in the coordinates used when drawing in 2D
+    /// (as with `Context::transform`), or `None` when it's behind the camera.
+    /// Useful for drawing health bars and name tags over 3D objects
+    pub fn project(&self, point: Point3<f32>, viewport: Viewport) -> Option<[f64; 2]> {
+        let clip = self.projection * self.view * point.to_homogeneous();
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let (x, y) = ((clip.x / clip.w) as f64, (clip.y / clip.w) as f64);
+        let rect = viewport.rect;
+        let pixel_x = rect[0] as f64 + (x + 1.0) * 0.5 * rect[2] as f64;
+        let pixel_y = rect[1] as f64 + (y + 1.0) * 0.5 * rect[3] as f64;
+        let scale_x = viewport.window_size[0] / viewport.draw_size[0] as f64;
+        let scale_y = viewport.window_size[1] / viewport.draw_size[1] as f64;
+        Some([pixel_x * scale_x, (viewport.draw_size[1] as f64 - pixel_y) * scale_y])
+    }
+
+    /// Gets this camera mirrored in the horizontal plane at `height`, as used for planar reflections
+    pub fn reflected(&self, height: f32) -> ViewProj {
+        let mirror = Matrix4::from_translation(Vector3::new(0.0, height, 0.0))
+            * Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0)
+            * Matrix4::from_translation(Vector3::new(0.0, -height, 0.0));
+        ViewProj {
+            view: self.view * mirror,
+            projection: self.projection,
+        }
+    }
+
+    /// Sorts `items` from furthest to closest to the camera, which is the order
+    /// transparent objects need to be drawn in to blend correctly.
+    /// `transform` gets the transform of each item.
+    pub fn sort_back_to_front<T, F: Fn(&T) -> &Transform>(&self, items: &mut [T], transform: F) {
+        items.sort_by(|a, b| {
+            self.view_depth(transform(b))
+                .partial_cmp(&self.view_depth(transform(a)))
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+}
+
+/// How a `Camera` projects the scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// `fov` is the vertical field of view in radians
+    Perspective { fov: f32, near: f32, far: f32 },
+    /// `height` is how much of the world fits vertically on screen
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+/// A ray cast from the camera through a point on the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    /// Normalized
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    /// The point `distance` along the ray
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// Where the ray hits the horizontal plane at `height`, if it does, for picking points on the ground
+    pub fn intersect_ground(&self, height: f32) -> Option<Point3<f32>> {
+        if self.direction.y.abs() <= std::f32::EPSILON {
+            return None;
+        }
+        let distance = (height - self.origin.y) / self.direction.y;
+        if distance < 0.0 {
+            None
+        } else {
+            Some(self.at(distance))
+        }
+    }
+}
+
+/// A camera with a position, an orientation and a projection, written into a `ViewProj` with `apply`.
+///
+/// Looks down `-z` when its yaw, pitch and roll (in radians) are `0`. Positive yaw turns left
+/// and positive pitch looks up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub projection: Projection,
+    /// Width over height of the screen, set from the viewport with `set_aspect`
+    pub aspect: f32,
+}
+
+impl Camera {
+    /// Creates a perspective camera at the origin with a vertical field of view of `fov` radians
+    pub fn perspective(fov: f32, near: f32, far: f32) -> Self {
+        Self::with_projection(Projection::Perspective { fov, near, far })
+    }
+
+    /// Creates an orthographic camera at the origin showing `height` world units vertically
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Self {
+        Self::with_projection(Projection::Orthographic { height, near, far })
+    }
+
+    fn with_projection(projection: Projection) -> Self {
+        Self {
+            position: Point3::origin(),
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            projection,
+            aspect: 1.0,
+        }
+    }
+
+    /// Turns the camera to face `target`, resetting its roll
+    pub fn look_at(&mut self, target: Point3<f32>) {
+        let direction = target - self.position;
+        if direction.magnitude2() <= std::f32::EPSILON {
+            return;
+        }
+        let direction = direction.normalize();
+        self.yaw = (-direction.x).atan2(-direction.z);
+        self.pitch = direction.y.max(-1.0).min(1.0).asin();
+        self.roll = 0.0;
+    }
+
+    /// Sets the aspect ratio from the size of `viewport`
+    pub fn set_aspect(&mut self, viewport: Viewport) {
+        self.aspect = viewport.rect[2] as f32 / viewport.rect[3].max(1) as f32;
+    }
+
+    /// Sets the vertical field of view of a perspective camera, in radians
+    pub fn set_fov(&mut self, fov: f32) {
+        if let Projection::Perspective { fov: ref mut x, .. } = self.projection {
+            *x = fov;
+        }
+    }
+
+    /// Sets the near and far planes
+    pub fn set_clip_planes(&mut self, near_plane: f32, far_plane: f32) {
+        match self.projection {
+            Projection::Perspective { ref mut near, ref mut far, .. }
+            | Projection::Orthographic { ref mut near, ref mut far, .. } => {
+                *near = near_plane;
+                *far = far_plane;
+            },
+        }
+    }
+
+    /// The orientation of the camera
+    pub fn rotation(&self) -> Quaternion<f32> {
+        Quaternion::from_angle_y(Rad(self.yaw))
+            * Quaternion::from_angle_x(Rad(self.pitch))
+            * Quaternion::from_angle_z(Rad(self.roll))
+    }
+
+    /// The direction the camera is looking in
+    pub fn forward(&self) -> Vector3<f32> {
+        self.rotation() * -Vector3::unit_z()
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.rotation() * Vector3::unit_x()
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.rotation() * Vector3::unit_y()
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from(self.rotation().conjugate()) * Matrix4::from_translation(-self.position.to_vec())
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective { fov, near, far } => cgmath::perspective(Rad(fov), self.aspect, near, far),
+            Projection::Orthographic { height, near, far } => {
+                let (half_width, half_height) = (height * self.aspect / 2.0, height / 2.0);
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, near, far)
+            },
+        }
+    }
+
+    /// Writes the camera's matrices into `view_proj`, usually `ShaderStorage::cache`
+    pub fn apply(&self, view_proj: &mut ViewProj) {
+        view_proj.view = self.view_matrix();
+        view_proj.projection = self.projection_matrix();
+    }
+
+    /// Casts a ray from the camera through `position`, in the coordinates used when drawing in 2D
+    /// (as given by touch and mouse events). The opposite of `ViewProj::project`
+    pub fn screen_ray(&self, position: [f64; 2], viewport: Viewport) -> Ray {
+        let rect = viewport.rect;
+        let scale_x = viewport.window_size[0] / viewport.draw_size[0] as f64;
+        let scale_y = viewport.window_size[1] / viewport.draw_size[1] as f64;
+        let pixel_x = position[0] / scale_x;
+        let pixel_y = viewport.draw_size[1] as f64 - position[1] / scale_y;
+        let x = ((pixel_x - rect[0] as f64) / rect[2] as f64 * 2.0 - 1.0) as f32;
+        let y = ((pixel_y - rect[1] as f64) / rect[3] as f64 * 2.0 - 1.0) as f32;
+        let inverse = (self.projection_matrix() * self.view_matrix())
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        let unproject = |z: f32| {
+            let point = inverse * Vector4::new(x, y, z, 1.0);
+            Point3::from_homogeneous(point)
+        };
+        let (near, far) = (unproject(-1.0), unproject(1.0));
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
 }
 
 impl ShaderStorage {
     pub fn new() -> Self {
         Self {
             shaders: HashMap::new(),
+            watched: HashMap::new(),
             cache: ViewProj::default(),
+            shadows: ShadowSettings::default(),
+            fog: Fog::default(),
+            light_probes: LightProbes::default(),
+            lights: LightSet::default(),
+            min_screen_size: None,
         }
     }
 
@@ -81,6 +329,115 @@ impl ShaderStorage {
             &mut self.cache,
         )
     }
+
+    /// Drops the cached `T`, so it's compiled again (and its attributes and uniforms found again) the next time it's used
+    pub fn invalidate<T: Any>(&mut self) {
+        self.shaders.remove(&TypeId::of::<T>());
+    }
+
+    /// Compiles `T` again right away, keeping the old one if `Shader::new` panics, as it does on compile errors
+    pub fn reload<T: Any + Shader>(&mut self, graphics: &mut GlGraphics) -> Result<(), String> {
+        let shader = create_shader::<T>(graphics)?;
+        self.shaders.insert(TypeId::of::<T>(), shader);
+        Ok(())
+    }
+
+    /// Reloads `T` whenever one of the files named in `sources` changes in `shader_override_path()`,
+    /// once `reload_changed` is called (which `AppContainer` does every frame with `AppConfig::shader_hot_reload`).
+    /// `T::new` should read those files through `shader_source` so it picks the changes up
+    pub fn watch<T: Any + Shader>(&mut self, sources: &[&str]) {
+        let files = sources
+            .iter()
+            .map(|name| {
+                let path = shader_override_path().join(name);
+                let modified = modified_time(&path);
+                (path, modified)
+            })
+            .collect();
+        self.watched.insert(TypeId::of::<T>(), WatchedShader { files, create: create_shader::<T> });
+    }
+
+    /// Reloads every watched shader whose sources changed, returning how many were reloaded.
+    /// Shaders which fail to compile keep their previous program, and their errors are returned
+    /// together once the others have been reloaded
+    pub fn reload_changed(&mut self, graphics: &mut GlGraphics) -> Result<usize, String> {
+        let mut reloaded = 0;
+        let mut errors = Vec::new();
+        for (id, watched) in &mut self.watched {
+            let mut changed = false;
+            for (path, modified) in &mut watched.files {
+                let current = modified_time(path);
+                if current != *modified {
+                    *modified = current;
+                    changed = true;
+                }
+            }
+            if !changed {
+                continue;
+            }
+            match (watched.create)(graphics) {
+                Ok(shader) => {
+                    self.shaders.insert(*id, shader);
+                    reloaded += 1;
+                },
+                Err(e) => errors.push(format!("Could not reload shader: {}", e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(reloaded)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
+
+struct WatchedShader {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    create: fn(&mut GlGraphics) -> Result<Box<dyn Any>, String>,
+}
+
+fn create_shader<T: Any + Shader>(graphics: &mut GlGraphics) -> Result<Box<dyn Any>, String> {
+    let shader = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| T::new(GLSL::V1_20, Some(&mut *graphics))))
+        .map_err(|e| {
+            e.downcast_ref::<String>()
+                .cloned()
+                .or_else(|| e.downcast_ref::<&str>().map(|x| x.to_string()))
+                .unwrap_or_else(|| "Shader::new panicked".to_string())
+        })?;
+    // The new program may reuse the old one's id, which would otherwise be skipped as already in use
+    graphics.clear_program();
+    Ok(Box::new(shader))
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+/// The directory shader sources can be overridden from, `<internal storage>/shaders`.
+/// Push edited sources there with `adb push` while the app runs
+pub fn shader_override_path() -> PathBuf {
+    crate::internal_storage_path().join("shaders")
+}
+
+/// Gets the source of the shader file `name`: its contents in `shader_override_path()` when there is
+/// one in a debug build, otherwise `embedded`. Use this in `Shader::new` to make a shader hot reloadable
+pub fn shader_source(name: &str, embedded: &'static str) -> Cow<'static, str> {
+    if cfg!(debug_assertions) {
+        if let Ok(source) = std::fs::read_to_string(shader_override_path().join(name)) {
+            return Cow::Owned(source);
+        }
+    }
+    Cow::Borrowed(embedded)
+}
+
+/// The `#version` line to start this crate's shaders with on `profile`. They're written in GLSL ES 3.00,
+/// which desktop GLSL 1.50 accepts too, so there's no version of them for OpenGL ES 2
+pub fn glsl_header(profile: Profile) -> Result<&'static str, String> {
+    match profile {
+        Profile::Gles3 => Ok("#version 300 es\n"),
+        Profile::Desktop(_) => Ok("#version 150\n"),
+        Profile::Gles2 => Err("These shaders need OpenGL ES 3.0".to_string()),
+    }
 }
 
 pub trait Drawable {
@@ -94,6 +451,17 @@ pub trait Drawable {
         transforms: &mut Transforms
     );
 
+    /// The blob shadow to draw under this, if any. Drawn before `draw_with` is called
+    fn blob_shadow(&self) -> Option<BlobShadow> { None }
+
+    /// A sphere (centre and radius, relative to the current transform) which encloses everything
+    /// this draws, used to skip it when it's too small to see, see `ShaderStorage::min_screen_size`
+    fn bounding_sphere(&self) -> Option<(Point3<f32>, f32)> { None }
+
+    /// The transform of this relative to its parent, pushed before it and its children are
+    /// drawn, so the model uniform and `bounding_sphere` are in this item's own space
+    fn transform(&self) -> Option<Transform> { None }
+
     #[allow(unused_variables)]
     fn draw_children(&mut self, context: &mut ShaderContext) {}
     #[allow(unused_variables)]
@@ -105,6 +473,7 @@ pub trait Drawable {
 
 pub struct ShaderContext<'a, 'b> {
     pub shaders: &'a mut ShaderStorage,
+    pub assets: &'a Assets,
     pub gl: &'b mut GlGraphics,
     pub c: Context,
     pub rargs: RenderArgs,
@@ -112,21 +481,121 @@ pub struct ShaderContext<'a, 'b> {
 }
 
 impl<'a, 'b> ShaderContext<'a, 'b> {
-    pub fn new(s: &'a mut ShaderStorage, gl: &'b mut GlGraphics, c: Context, rargs: RenderArgs) -> Self {
+    pub fn new(s: &'a mut ShaderStorage, assets: &'a Assets, gl: &'b mut GlGraphics, c: Context, rargs: RenderArgs) -> Self {
         Self {
             gl,
             c,
             shaders: s,
+            assets,
             rargs,
             transforms: TransformHierarchy::new(Matrix4::identity(), |s, r, t| s * r * t),
         }
     }
+    /// Draws everything in `f` as seen from `view_proj` instead of the storage's camera,
+    /// optionally restricted to `rect` (in pixels, with the origin at the bottom left).
+    /// Useful for split-screen, minimaps and picture-in-picture views.
+    ///
+    /// Any changes made to the camera in `f` are written back to `view_proj`.
+    pub fn with_camera<F: FnOnce(&mut Self)>(&mut self, view_proj: &mut ViewProj, rect: Option<[i32; 4]>, f: F) {
+        std::mem::swap(&mut self.shaders.cache, view_proj);
+        let old_context = self.c;
+        let old_viewport = self.gl.get_current_viewport();
+        if let (Some(rect), Some(old)) = (rect, old_viewport) {
+            let viewport = Viewport { rect, ..old };
+            self.gl.set_viewport(viewport);
+            self.c = Context::new_viewport(viewport);
+        }
+        f(self);
+        if let (Some(_), Some(old)) = (rect, old_viewport) {
+            self.gl.set_viewport(old);
+        }
+        self.c = old_context;
+        std::mem::swap(&mut self.shaders.cache, view_proj);
+    }
+
+    /// Draws a blob shadow onto the ground plane described by `ShaderStorage::shadows`
+    pub fn draw_blob_shadow(&mut self, shadow: &BlobShadow) {
+        let (center, radius, opacity) = match self.shaders.shadows.project(shadow) {
+            Some(x) => x,
+            None => return,
+        };
+        let (shader, mats) = self.shaders.get::<BlobShadowShader>(GLSL::V1_20, &mut self.gl);
+        let view_proj = mats.projection * mats.view;
+        self.gl.shader_draw(
+            shader,
+            &DrawState::new_alpha(),
+            &shadow_quad(center, radius),
+            None,
+            None,
+            None,
+            None,
+            |shader, gl| {
+                shader.view_proj.set(gl, view_proj.as_ref());
+                shader.center.set(gl, center.as_ref());
+                shader.radius.set(gl, radius);
+                shader.opacity.set(gl, opacity);
+            }
+        );
+    }
+
+    /// The diameter in pixels a sphere at `center` with `radius` covers on screen, under the current transform.
+    /// Spheres the camera is inside of, or which are behind it, count as infinitely large
+    pub fn screen_size(&self, center: Point3<f32>, radius: f32) -> f32 {
+        let model = *self.transforms.current();
+        let cache = &self.shaders.cache;
+        let clip = cache.projection * cache.view * model * center.to_homogeneous();
+        if clip.w <= 0.0 {
+            return std::f32::INFINITY;
+        }
+        // The largest amount the model matrix stretches any axis by
+        let scale = [model.x, model.y, model.z]
+            .iter()
+            .map(|x| x.truncate().magnitude())
+            .fold(0.0, f32::max);
+        let height = self.c.viewport.map(|x| x.rect[3] as f32).unwrap_or(self.rargs.draw_size[1] as f32);
+        let size = radius * scale * cache.projection.y.y / clip.w * height;
+        if size.is_nan() {
+            std::f32::INFINITY
+        } else {
+            size
+        }
+    }
+
     pub fn draw<T: Drawable>(&mut self, item: &mut T) where T::Shader: Any {
+        match item.transform() {
+            Some(transform) => {
+                let local = item_transforms(self.transforms.current(), &transform);
+                let outer = std::mem::replace(&mut self.transforms, local);
+                self.draw_local(item);
+                self.transforms = outer;
+            }
+            None => self.draw_local(item),
+        }
+    }
+
+    // Draws `item` in the current transform, which already includes the item's own
+    fn draw_local<T: Drawable>(&mut self, item: &mut T) where T::Shader: Any {
+        if let (Some(min_size), Some((center, radius))) = (self.shaders.min_screen_size, item.bounding_sphere()) {
+            if self.screen_size(center, radius) < min_size {
+                return;
+            }
+        }
+        if let Some(shadow) = item.blob_shadow() {
+            self.draw_blob_shadow(&shadow);
+        }
         let (
             shader,
             mats
         ) = self.shaders.get::<T::Shader>(GLSL::V1_20, &mut self.gl);
         item.prepare_draw(shader, mats, &mut self.transforms);
+        if let Some(model) = shader.model_uniform() {
+            self.gl.use_program(shader.program());
+            model.set(self.gl, self.transforms.current().as_ref());
+        }
+        if let Some(view_proj) = shader.view_proj_uniform() {
+            self.gl.use_program(shader.program());
+            view_proj.set(self.gl, (mats.projection * mats.view).as_ref());
+        }
         item.draw_with(
             shader,
             &mut self.gl,
@@ -136,4 +605,46 @@ impl<'a, 'b> ShaderContext<'a, 'b> {
         );
         item.draw_children(self);
     }
+
+    /// Draws every node of `scene` with `draw`, in place of the current transform
+    /// multiplied by the node's world matrix. Parents are drawn before their children
+    pub fn draw_scene<T: Drawable>(&mut self, scene: &mut SceneGraph<T>) where T::Shader: Any {
+        let base = *self.transforms.current();
+        scene.for_each_world(|_, world, item| {
+            let outer = std::mem::replace(
+                &mut self.transforms,
+                TransformHierarchy::new(base * world, |s, r, t| s * r * t),
+            );
+            self.draw(item);
+            self.transforms = outer;
+        });
+    }
+}
+
+// The transforms to draw an item with its own `transform` in, under `parent`
+fn item_transforms(parent: &Matrix4<f32>, transform: &Transform) -> Transforms {
+    TransformHierarchy::new(parent * transform.to_matrix(|s, r, t| s * r * t), |s, r, t| s * r * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{item_transforms, Transform};
+    use cgmath::{Matrix4, Rad, SquareMatrix, Vector3};
+
+    #[test]
+    fn nested_model_matrix() {
+        let mut parent = Transform::identity();
+        parent.translate_by(Vector3::new(1., 2., 3.));
+        parent.rotate_axis(Vector3::unit_y(), Rad(0.5));
+        let mut child = Transform::identity();
+        child.translate_by(Vector3::new(0., 1., 0.));
+        child.scale(2.);
+
+        let outer = item_transforms(&Matrix4::identity(), &parent);
+        let inner = item_transforms(outer.current(), &child);
+        let expected = parent.to_matrix(|s, r, t| s * r * t) * child.to_matrix(|s, r, t| s * r * t);
+        let uploaded: &[f32; 16] = inner.current().as_ref();
+        let expected: &[f32; 16] = expected.as_ref();
+        assert!(uploaded.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-5));
+    }
 }
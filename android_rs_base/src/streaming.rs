@@ -0,0 +1,321 @@
+use crate::assets::Assets;
+use image::{imageops, RgbaImage};
+use opengl_graphics::{gl, Texture};
+use opengl_graphics::gl::types::GLuint;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// A texture added to a `TextureStreamer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamId(usize);
+
+// A level of a texture's mip chain, decoded on a background thread
+struct DecodedLevel {
+    texture: usize,
+    // Of the full image
+    size: (u32, u32),
+    level: usize,
+    image: RgbaImage,
+}
+
+struct StreamedTexture {
+    path: String,
+    texture: Option<Texture>,
+    size: (u32, u32),
+    // Decoded levels, level 0 being the full image
+    levels: Vec<Option<RgbaImage>>,
+    // The highest detail level uploaded, or `levels.len()` when none are
+    base_level: usize,
+    wanted_size: f32,
+    // The level `wanted_size` called for at the last update
+    last_wanted_level: usize,
+    error: Option<String>,
+}
+
+impl StreamedTexture {
+    fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    // The level which is at least `wanted_size` pixels across
+    fn wanted_level(&self) -> usize {
+        let largest = self.size.0.max(self.size.1) as f32;
+        if self.wanted_size <= 0.0 {
+            return self.level_count().saturating_sub(1);
+        }
+        let level = (largest / self.wanted_size).log2().floor().max(0.0) as usize;
+        level.min(self.level_count().saturating_sub(1))
+    }
+
+    fn bytes_from(&self, base_level: usize) -> usize {
+        (base_level..self.level_count())
+            .map(|x| level_bytes(self.size, x))
+            .sum()
+    }
+}
+
+fn level_size((width, height): (u32, u32), level: usize) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+fn level_bytes(size: (u32, u32), level: usize) -> usize {
+    let (width, height) = level_size(size, level);
+    width as usize * height as usize * 4
+}
+
+/// Streams textures in a mip level at a time, so a scene can be drawn as soon as the smallest levels
+/// of its textures are in, and only the textures seen up close take up memory for their full detail.
+///
+/// Each texture is decoded and has its mip chain built on a background thread, which sends the
+/// levels back smallest first. Every frame `request` how large each texture appears on screen, then
+/// call `update` on the GL thread: it uploads the levels needed to draw that size sharply, and drops
+/// the detail from textures seen from far away, keeping what's uploaded within `budget` bytes.
+///
+/// Each texture keeps its decoded levels in memory, so detail which was dropped can come back
+/// without decoding the image again.
+pub struct TextureStreamer {
+    assets: Assets,
+    budget: usize,
+    uploads_per_update: usize,
+    textures: Vec<StreamedTexture>,
+    sender: Sender<Result<DecodedLevel, (usize, String)>>,
+    receiver: Receiver<Result<DecodedLevel, (usize, String)>>,
+}
+
+impl TextureStreamer {
+    /// Creates a streamer reading images from `assets`, which keeps at most `budget` bytes of texture uploaded
+    pub fn new(assets: &Assets, budget: usize) -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            assets: assets.clone(),
+            budget,
+            uploads_per_update: 4,
+            textures: Vec::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Sets how many mip levels `update` uploads at most, to keep it from stalling a frame
+    pub fn uploads_per_update(mut self, uploads: usize) -> Self {
+        self.uploads_per_update = uploads.max(1);
+        self
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+    }
+
+    pub fn get_budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Starts loading the image asset at `path`, which can be drawn once `texture` returns it
+    pub fn load(&mut self, path: &str) -> StreamId {
+        let index = self.textures.len();
+        self.textures.push(StreamedTexture {
+            path: path.to_string(),
+            texture: None,
+            size: (0, 0),
+            levels: Vec::new(),
+            base_level: 0,
+            wanted_size: 0.0,
+            last_wanted_level: 0,
+            error: None,
+        });
+        let assets = self.assets.clone();
+        let sender = self.sender.clone();
+        let path = path.to_string();
+        std::thread::spawn(move || {
+            let image = assets
+                .read_data(&path)
+                .and_then(|data| image::load_from_memory(&data).map_err(|e| format!("Could not decode {}: {}", path, e)))
+                .map(|x| x.to_rgba());
+            let mut level = match image {
+                Ok(x) => x,
+                Err(e) => {
+                    let _ = sender.send(Err((index, e)));
+                    return;
+                }
+            };
+            let size = level.dimensions();
+            let mut chain = Vec::new();
+            loop {
+                let (width, height) = level.dimensions();
+                if width == 1 && height == 1 {
+                    chain.push(level);
+                    break;
+                }
+                let smaller = imageops::resize(&level, (width / 2).max(1), (height / 2).max(1), imageops::FilterType::Triangle);
+                chain.push(std::mem::replace(&mut level, smaller));
+            }
+            for (level, image) in chain.into_iter().enumerate().rev() {
+                if sender.send(Ok(DecodedLevel { texture: index, size, level, image })).is_err() {
+                    return;
+                }
+            }
+        });
+        StreamId(index)
+    }
+
+    /// Notes that `id` is drawn `pixels` across on screen this frame, so `update` brings in enough detail
+    /// for it. Textures which aren't requested before an `update` are brought down to their smallest level
+    pub fn request(&mut self, id: StreamId, pixels: f32) {
+        let texture = &mut self.textures[id.0];
+        texture.wanted_size = texture.wanted_size.max(pixels);
+    }
+
+    /// Gets the texture to draw `id` with, once its first level has been uploaded.
+    /// Its size is always that of the full image, whichever levels are uploaded
+    pub fn texture(&self, id: StreamId) -> Option<&Texture> {
+        self.textures[id.0].texture.as_ref()
+    }
+
+    /// Gets the error `id` failed to load with, if it did
+    pub fn error(&self, id: StreamId) -> Option<&str> {
+        self.textures[id.0].error.as_deref()
+    }
+
+    /// Whether every level `id` was last requested at is uploaded
+    pub fn is_sharp(&self, id: StreamId) -> bool {
+        let texture = &self.textures[id.0];
+        texture.texture.is_some() && texture.base_level <= texture.last_wanted_level
+    }
+
+    /// The number of bytes of texture currently uploaded
+    pub fn resident_bytes(&self) -> usize {
+        self.textures
+            .iter()
+            .filter(|x| x.texture.is_some())
+            .map(|x| x.bytes_from(x.base_level))
+            .sum()
+    }
+
+    /// Takes in decoded levels, then uploads and drops levels to match what's been requested since the last update.
+    /// Has to be called on the GL thread. Textures which fail to load keep their error for `error`
+    pub fn update(&mut self) {
+        while let Ok(decoded) = self.receiver.try_recv() {
+            match decoded {
+                Ok(DecodedLevel { texture, size, level, image }) => {
+                    let texture = &mut self.textures[texture];
+                    if texture.levels.is_empty() {
+                        // The smallest level arrives first, and its index is the number of levels below it
+                        texture.levels = (0..=level).map(|_| None).collect();
+                        texture.base_level = level + 1;
+                        texture.size = size;
+                    }
+                    texture.levels[level] = Some(image);
+                },
+                Err((texture, e)) => self.textures[texture].error = Some(e),
+            }
+        }
+
+        let mut targets = self.textures.iter().map(StreamedTexture::wanted_level).collect::<Vec<_>>();
+        let mut total = self.textures
+            .iter()
+            .zip(&targets)
+            .map(|(x, &target)| x.bytes_from(target))
+            .sum::<usize>();
+        // Drop a level from whichever texture has the most bytes per pixel it's seen at, until it all fits
+        while total > self.budget {
+            let worst = self.textures
+                .iter()
+                .zip(&targets)
+                .enumerate()
+                .filter(|(_, (x, &target))| target + 1 < x.level_count())
+                .max_by(|(_, (a, &a_target)), (_, (b, &b_target))| {
+                    let a = level_bytes(a.size, a_target) as f32 / a.wanted_size.max(1.0);
+                    let b = level_bytes(b.size, b_target) as f32 / b.wanted_size.max(1.0);
+                    a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .map(|(index, _)| index);
+            match worst {
+                Some(index) => {
+                    total -= level_bytes(self.textures[index].size, targets[index]);
+                    targets[index] += 1;
+                },
+                None => break,
+            }
+        }
+
+        let mut uploads = 0;
+        for (texture, target) in self.textures.iter_mut().zip(targets) {
+            texture.last_wanted_level = texture.wanted_level();
+            texture.wanted_size = 0.0;
+            if texture.levels.is_empty() {
+                continue;
+            }
+            let mut base_level = texture.base_level;
+            // Drop detail that's no longer wanted
+            while base_level < target && base_level + 1 < texture.level_count() {
+                if let Some(x) = &texture.texture {
+                    free_level(x.get_id(), base_level);
+                }
+                base_level += 1;
+            }
+            // Bring in detail in order, stopping at a level which hasn't been decoded yet
+            while base_level > target && uploads < self.uploads_per_update {
+                let image = match &texture.levels[base_level - 1] {
+                    Some(x) => x,
+                    None => break,
+                };
+                let id = match &texture.texture {
+                    Some(x) => x.get_id(),
+                    None => {
+                        let id = create_texture(texture.level_count());
+                        texture.texture = Some(Texture::new(id, texture.size.0, texture.size.1));
+                        id
+                    }
+                };
+                upload_level(id, base_level - 1, image);
+                base_level -= 1;
+                uploads += 1;
+            }
+            if base_level != texture.base_level {
+                texture.base_level = base_level;
+                if let Some(x) = &texture.texture {
+                    unsafe {
+                        gl::BindTexture(gl::TEXTURE_2D, x.get_id());
+                        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, base_level as i32);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn create_texture(level_count: usize) -> GLuint {
+    let mut id = 0;
+    unsafe {
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, level_count as i32 - 1);
+    }
+    id
+}
+
+fn upload_level(id: GLuint, level: usize, image: &RgbaImage) {
+    unsafe {
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            level as i32,
+            gl::RGBA as i32,
+            image.width() as i32,
+            image.height() as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            image.as_ptr() as *const _,
+        );
+    }
+}
+
+// Respecifies a level as empty, which frees its storage
+fn free_level(id: GLuint, level: usize) {
+    unsafe {
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexImage2D(gl::TEXTURE_2D, level as i32, gl::RGBA as i32, 0, 0, 0, gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::app_implementor::AppImpl;
+use crate::app_config::AppConfig;
+use crate::events::CustomEvent;
+use crate::gestures::{Gesture, GestureRecognizer};
+use crate::storage::{ShaderStorage, ShaderContext};
+use crate::assets::Assets;
+use crate::state::StateStore;
+use crate::app_container::{desktop_opengl, update_step};
+use piston::window::{Api, WindowSettings};
+use piston::input::*;
+use glutin_window::HeadlessContext;
+use opengl_graphics::GlGraphics;
+use image::RgbaImage;
+
+/// Runs an `AppImpl` without a device, for testing it on desktop.
+///
+/// Instead of a window, drawing goes to an offscreen OpenGL context (which needs a display
+/// or an EGL driver on the machine running the tests), and nothing runs on its own:
+/// tests step the app with `update` and `render`, and inject input and android events.
+/// Every callback the container makes on the app is recorded by name in `calls`.
+///
+/// Only available with the `test-harness` feature.
+pub struct TestContainer<T: AppImpl> {
+    _context: HeadlessContext,
+    app: Option<T>,
+    gl: GlGraphics,
+    config: AppConfig,
+    storage: ShaderStorage,
+    assets: Assets,
+    state: StateStore,
+    gestures: GestureRecognizer,
+    size: (usize, usize),
+    window_size: (usize, usize),
+    time: f64,
+    calls: Vec<&'static str>,
+}
+
+impl<T: AppImpl> TestContainer<T> {
+    /// Creates the app with a framebuffer of `size` pixels, starting from `state`
+    pub fn new(config: AppConfig, data: T::InitializationData, state: StateStore, size: (usize, usize)) -> Result<Self, String> {
+        let opengl = desktop_opengl(config.gl_version);
+        let api = match config.gles_version {
+            Some((major, minor)) => Api { api: "OpenGL ES".into(), major: major as u32, minor: minor as u32 },
+            None => opengl.into(),
+        };
+        let settings = WindowSettings::new(config.title.clone(), (size.0 as f64, size.1 as f64))
+            .samples(config.samples)
+            .srgb(config.srgb)
+            .graphics_api(api);
+        let context = HeadlessContext::new(&settings, config.depth_bits).map_err(|e| e.to_string())?;
+        opengl_graphics::gl::load_with(|x| context.get_proc_address(x) as *const _);
+        let mut gl = GlGraphics::new(opengl);
+        gl.set_depth(config.depth_test);
+        gl.set_state_audit(config.state_audit);
+        let mut storage = ShaderStorage::new();
+        let app = T::new(&mut gl, data, &mut storage, &state);
+        Ok(Self {
+            _context: context,
+            app: Some(app),
+            gl,
+            config,
+            storage,
+            assets: Assets::new(),
+            state,
+            gestures: GestureRecognizer::new(),
+            size,
+            window_size: (0, 0),
+            time: 0.0,
+            calls: vec!["new"],
+        })
+    }
+
+    /// Gets the app, `None` once it has been shut down
+    pub fn app(&mut self) -> Option<&mut T> {
+        self.app.as_mut()
+    }
+
+    /// Gets the config, as the app sees it
+    pub fn config(&mut self) -> &mut AppConfig {
+        &mut self.config
+    }
+
+    /// Gets the back-end the app draws with
+    pub fn gl(&mut self) -> &mut GlGraphics {
+        &mut self.gl
+    }
+
+    /// Gets the state given to `AppImpl::signal_pause` and `AppImpl::on_die`
+    pub fn state(&mut self) -> &mut StateStore {
+        &mut self.state
+    }
+
+    /// Gets the names of the `AppImpl` callbacks made so far, in order
+    pub fn calls(&self) -> &[&'static str] {
+        &self.calls
+    }
+
+    /// Forgets the callbacks recorded so far
+    pub fn clear_calls(&mut self) {
+        self.calls.clear();
+    }
+
+    /// Whether the app is still running, it stops after `on_die`
+    pub fn is_running(&self) -> bool {
+        self.app.is_some()
+    }
+
+    /// Calls `AppImpl::update` with `dt` seconds, applying the pause, time scale, fixed timestep and frame stepping of the config
+    pub fn update(&mut self, dt: f64) {
+        if self.app.is_none() {
+            return;
+        }
+        self.time += dt;
+        let gesture = self.gestures.update(&self.config.gesture_config, self.time * 1000.0);
+        self.dispatch_gesture(gesture);
+        let calls = &mut self.calls;
+        if let Some(app) = self.app.as_mut() {
+            update_step(app, &mut self.config, dt, |name| calls.push(name));
+        }
+        self.config.touch_state.clear_ended();
+        for event in std::mem::replace(&mut self.config.posted_events, Vec::new()) {
+            self.custom_event(event);
+        }
+    }
+
+    /// Draws a frame offscreen, through `draw_2d`, `draw_shaded`, `draw_overlay_2d` and `after_draw`
+    pub fn render(&mut self) {
+        let app_ref = match self.app.as_mut() {
+            Some(app) => app,
+            None => return,
+        };
+        let rargs = RenderArgs {
+            ext_dt: 0.0,
+            window_size: [self.size.0 as f64, self.size.1 as f64],
+            draw_size: [self.size.0 as u32, self.size.1 as u32],
+        };
+        self.config.passed_frames += 1;
+        if self.window_size != self.size {
+            self.calls.push("on_size_change");
+            app_ref.on_size_change(&self.size, &self.window_size, &mut self.storage);
+            self.window_size = self.size;
+        }
+        let cfg_ref = &mut self.config;
+        let (sh_ref, assets_ref, calls) = (&mut self.storage, &self.assets, &mut self.calls);
+        let depth = if cfg_ref.clear_depth { Some(1.0) } else { None };
+        let (color, stencil) = (cfg_ref.clear_color, cfg_ref.clear_stencil);
+        self.gl.draw(rargs.viewport(), |c, gl| {
+            gl.clear_buffers(color, depth, stencil);
+            calls.push("draw_2d");
+            app_ref.draw_2d(c, gl, rargs, cfg_ref);
+            calls.push("draw_shaded");
+            app_ref.draw_shaded(ShaderContext::new(sh_ref, assets_ref, gl, c, rargs));
+            calls.push("draw_overlay_2d");
+            app_ref.draw_overlay_2d(c, gl, rargs, cfg_ref);
+        });
+        cfg_ref.last_state_audit = self.gl.take_state_audit();
+        self.calls.push("after_draw");
+        app_ref.after_draw(AfterRenderArgs);
+    }
+
+    /// Runs an update of `dt` seconds, then draws a frame
+    pub fn frame(&mut self, dt: f64) {
+        self.update(dt);
+        self.render();
+    }
+
+    /// Reads back the last frame drawn, with the first row at the top
+    pub fn read_frame(&mut self) -> RgbaImage {
+        let (width, height) = (self.size.0 as i32, self.size.1 as i32);
+        self.gl.read_pixels([0, 0, width, height])
+    }
+
+    /// Passes `input` to the app as the window would, also feeding touches to `AppImpl::touch` and the gesture recognizer
+    pub fn input(&mut self, input: Input) {
+        if self.app.is_none() {
+            return;
+        }
+        let time_ns = (self.time * 1e9) as u64;
+        match input {
+            Input::Move(Motion::MouseCursor(position)) => self.config.latest_pointer = Some(position),
+            Input::Move(Motion::Touch(args)) => self.config.latest_pointer = Some(args.position()),
+            _ => {}
+        }
+        self.config.touch_state.record(&input, time_ns);
+        if let Input::Move(Motion::Touch(args)) = input {
+            let touch_state = &self.config.touch_state;
+            self.calls.push("touch");
+            self.app.as_mut().map(|app| app.touch(touch_state));
+            let gesture = self.gestures.touch(&self.config.gesture_config, &args, self.time * 1000.0);
+            self.dispatch_gesture(gesture);
+        }
+        self.calls.push("input");
+        self.app.as_mut().map(|app| app.input(input, None));
+    }
+
+    /// Passes `event` to the app as the activity would, pausing and resuming on focus changes
+    pub fn android_event(&mut self, event: android_glue::Event) {
+        use android_glue::Event;
+        let app = match self.app.as_mut() {
+            Some(app) => app,
+            None => return,
+        };
+        match event {
+            Event::LostFocus => {
+                self.calls.push("signal_pause");
+                app.signal_pause(&mut self.state);
+            },
+            Event::GainedFocus => {
+                self.calls.push("refresh");
+                app.refresh();
+            },
+            Event::Pause => {
+                self.calls.push("on_pause");
+                app.on_pause();
+            },
+            Event::Resume => {
+                self.calls.push("on_resume");
+                app.on_resume();
+            },
+            Event::LowMemory => {
+                self.calls.push("on_low_memory");
+                app.on_low_memory();
+            },
+            Event::EventKeyDown | Event::EventMotion(_) => {},
+            Event::EventKeyUp => {
+                self.calls.push("on_key_released");
+                if !app.on_key_released() {
+                    self.shut_down();
+                }
+            },
+            Event::Destroy => self.shut_down(),
+            misc => {
+                self.calls.push("handle_android_event");
+                app.handle_android_event(misc);
+            }
+        }
+    }
+
+    /// Passes `event` to the app if its type was registered through `AppConfig::events`
+    pub fn custom_event(&mut self, event: CustomEvent) {
+        if !self.config.events.accepts(&event) {
+            return;
+        }
+        if let Some(app) = self.app.as_mut() {
+            self.calls.push("handle_custom_event");
+            app.handle_custom_event(event, None);
+        }
+    }
+
+    /// Calls `on_destroy` and `on_die`, after which the app stops receiving anything.
+    /// Unlike `AppContainer`, the state isn't saved
+    pub fn shut_down(&mut self) {
+        if let Some(mut app) = self.app.take() {
+            self.calls.push("on_destroy");
+            app.on_destroy();
+            self.calls.push("on_die");
+            app.on_die(&mut self.state);
+        }
+    }
+
+    fn dispatch_gesture(&mut self, gesture: Option<Gesture>) {
+        if let (Some(gesture), Some(app)) = (gesture, self.app.as_mut()) {
+            match gesture {
+                Gesture::Tap(position) => {
+                    self.calls.push("on_tap");
+                    app.on_tap(position);
+                },
+                Gesture::DoubleTap(position) => {
+                    self.calls.push("on_double_tap");
+                    app.on_double_tap(position);
+                },
+                Gesture::Swipe(direction, velocity) => {
+                    self.calls.push("on_swipe");
+                    app.on_swipe(direction, velocity);
+                },
+                Gesture::Pinch(scale) => {
+                    self.calls.push("on_pinch");
+                    app.on_pinch(scale);
+                },
+                Gesture::LongPress(position) => {
+                    self.calls.push("on_long_press");
+                    app.on_long_press(position);
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,744 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use opengl_graphics::{Filter, GlGraphics, Texture, TextureSettings};
+use graphics::{Context, DrawState, Graphics, ImageSize, BACK_END_MAX_VERTEX_COUNT};
+use graphics::math::{multiply, transform_pos, translate, Matrix2d};
+use serde_json::Value;
+use matrices::nav::NavGrid;
+use crate::assets::Assets;
+use crate::models::{decode_base64, relative_path};
+use crate::storage::{Drawable, Transforms, ViewProj};
+
+/// Set in a tile of a `TileLayer` to mirror it left to right
+pub const TILE_FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+/// Set in a tile of a `TileLayer` to mirror it top to bottom
+pub const TILE_FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+/// Set in a tile of a `TileLayer` to swap its x and y axes, done before the other flips
+pub const TILE_FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+/// The bits of a tile in a `TileLayer` which pick the tile, the rest flip it
+pub const TILE_GID_MASK: u32 = 0x1FFF_FFFF;
+
+/// An image cut into a grid of tiles. Tiles are numbered from `first_gid`, left to right then top to bottom
+pub struct Tileset {
+    pub first_gid: u32,
+    pub texture: Rc<Texture>,
+    /// The size of each tile in the texture, in pixels
+    pub tile_size: [u32; 2],
+    pub columns: u32,
+    pub tile_count: u32,
+    /// The pixels around the edge of the texture before the first tiles
+    pub margin: u32,
+    /// The pixels between neighbouring tiles
+    pub spacing: u32,
+}
+
+impl Tileset {
+    /// A tileset filling `texture` with tiles of `tile_size`, with no margin or spacing
+    pub fn new(first_gid: u32, texture: Rc<Texture>, tile_size: [u32; 2]) -> Self {
+        Self::with_spacing(first_gid, texture, tile_size, 0, 0)
+    }
+
+    /// A tileset filling `texture` with tiles of `tile_size`, `spacing` pixels apart and `margin` pixels from the edges
+    pub fn with_spacing(first_gid: u32, texture: Rc<Texture>, tile_size: [u32; 2], margin: u32, spacing: u32) -> Self {
+        let (width, height) = texture.get_size();
+        let fit = |size: u32, tile: u32| (size.saturating_sub(margin * 2) + spacing) / (tile + spacing).max(1);
+        let columns = fit(width, tile_size[0]);
+        Self {
+            first_gid,
+            tile_size,
+            columns,
+            tile_count: columns * fit(height, tile_size[1]),
+            margin,
+            spacing,
+            texture,
+        }
+    }
+
+    /// Whether the tile `gid` (without flip bits) is in this tileset
+    pub fn contains(&self, gid: u32) -> bool {
+        gid >= self.first_gid && gid - self.first_gid < self.tile_count
+    }
+
+    // The texture coordinates of the tile `gid`, `[left, top, right, bottom]`
+    fn uv(&self, gid: u32) -> [f32; 4] {
+        let index = gid - self.first_gid;
+        let (width, height) = self.texture.get_size();
+        let x = self.margin + (index % self.columns.max(1)) * (self.tile_size[0] + self.spacing);
+        let y = self.margin + (index / self.columns.max(1)) * (self.tile_size[1] + self.spacing);
+        [
+            x as f32 / width as f32,
+            y as f32 / height as f32,
+            (x + self.tile_size[0]) as f32 / width as f32,
+            (y + self.tile_size[1]) as f32 / height as f32,
+        ]
+    }
+}
+
+/// A grid of tiles, drawn in the order of `TileMap::layers`. Each tile is the gid of a tile
+/// in one of the map's tilesets, possibly with the `TILE_FLIPPED_*` bits set, or `0` for none
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    /// Row by row from the top left
+    pub tiles: Vec<u32>,
+    /// How much the layer moves with `TileMap::camera`, `[1, 1]` moves with the map and
+    /// smaller values are for backgrounds further away
+    pub parallax: [f64; 2],
+    /// Where the layer is drawn relative to the map, in pixels
+    pub offset: [f64; 2],
+    pub opacity: f32,
+    pub visible: bool,
+}
+
+impl TileLayer {
+    /// An empty layer
+    pub fn new(name: &str, width: usize, height: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            width,
+            height,
+            tiles: vec![0; width * height],
+            parallax: [1.0; 2],
+            offset: [0.0; 2],
+            opacity: 1.0,
+            visible: true,
+        }
+    }
+
+    /// The tile at `x`, `y`, with its flip bits, or `0` outside of the layer
+    pub fn get(&self, x: usize, y: usize) -> u32 {
+        if x < self.width && y < self.height {
+            self.tiles[y * self.width + x]
+        } else {
+            0
+        }
+    }
+
+    /// Sets the tile at `x`, `y`, doing nothing outside of the layer
+    pub fn set(&mut self, x: usize, y: usize, tile: u32) {
+        if x < self.width && y < self.height {
+            self.tiles[y * self.width + x] = tile;
+        }
+    }
+}
+
+/// A 2D map of tile layers, loaded from Tiled (`.tmx` or `.json`/`.tmj`) or built by hand.
+///
+/// Drawn with `ShaderContext::draw`, in the coordinates of `Context::transform` moved by the
+/// current transform, like a `Sprite`. Only the tiles in view are drawn, all of a layer's tiles
+/// from one tileset in a single call, and layers using the same tileset and opacity are batched
+/// together by the 2D renderer.
+pub struct TileMap {
+    /// The size of the map in tiles
+    pub width: usize,
+    pub height: usize,
+    /// The size of a cell of the grid in pixels, tiles from tilesets with bigger tiles are
+    /// drawn from the bottom left of their cell, as Tiled does
+    pub tile_size: [u32; 2],
+    pub layers: Vec<TileLayer>,
+    pub tilesets: Vec<Tileset>,
+    /// The point of the map drawn at the origin, in pixels, with each layer moved by its parallax
+    pub camera: [f64; 2],
+    pub draw_state: DrawState,
+    // Vertices and texture coordinates of the tiles in view from each tileset, kept between draws
+    batches: Vec<(Vec<[f32; 2]>, Vec<[f32; 2]>)>,
+}
+
+impl TileMap {
+    /// An empty map without layers or tilesets
+    pub fn new(width: usize, height: usize, tile_size: [u32; 2]) -> Self {
+        Self {
+            width,
+            height,
+            tile_size,
+            layers: Vec::new(),
+            tilesets: Vec::new(),
+            camera: [0.0; 2],
+            draw_state: DrawState::new_alpha(),
+            batches: Vec::new(),
+        }
+    }
+
+    /// Loads a Tiled map saved as TMX or JSON, by the extension of `path`.
+    ///
+    /// Only orthogonal, finite maps are supported, with tile layers stored as CSV or uncompressed
+    /// base64. Tilesets can be embedded or external (`.tsx` or `.json`/`.tsj`), but must use a
+    /// single image. Object and image layers are skipped, and group layers are flattened into
+    /// the layers they contain
+    pub fn load(assets: &Assets, path: &str) -> Result<Self, String> {
+        let text = assets.read_string(path)?;
+        if path.ends_with(".tmx") {
+            Self::from_tmx(assets, path, &text)
+        } else {
+            Self::from_tiled_json(assets, path, &text)
+        }
+    }
+
+    /// Reads a Tiled map saved as JSON, with files it uses looked up relative to `path`
+    pub fn from_tiled_json(assets: &Assets, path: &str, text: &str) -> Result<Self, String> {
+        let json = serde_json::from_str::<Value>(text).map_err(|e| format!("{}: {}", path, e))?;
+        let mut map = Self::from_properties(path, |key| json.get(key).map(json_string))?;
+        let mut textures = HashMap::new();
+        for tileset in array(&json, "tilesets") {
+            let first_gid = tileset.get("firstgid").and_then(Value::as_u64).unwrap_or(1) as u32;
+            let info = match tileset.get("source").and_then(Value::as_str) {
+                Some(source) => load_tileset(assets, &relative_path(path, source))?,
+                None => tileset_json(path, tileset),
+            };
+            map.tilesets.push(info.build(first_gid, assets, &mut textures)?);
+        }
+        add_json_layers(&mut map, &json, &Group::default())?;
+        Ok(map)
+    }
+
+    /// Reads a Tiled map saved as TMX, with files it uses looked up relative to `path`
+    pub fn from_tmx(assets: &Assets, path: &str, text: &str) -> Result<Self, String> {
+        let tags = xml_tags(text)?;
+        let root = tags.iter().find(|x| x.name == "map").ok_or_else(|| format!("{} has no map", path))?;
+        let mut map = Self::from_properties(path, |key| root.attribute(key).map(str::to_string))?;
+        let mut textures = HashMap::new();
+        let mut groups = vec![Group::default()];
+        let mut i = 0;
+        while i < tags.len() {
+            let tag = &tags[i];
+            match (tag.name, tag.closing) {
+                ("tileset", false) => {
+                    let first_gid = tag.number("firstgid").unwrap_or(1.0) as u32;
+                    let info = match tag.attribute("source") {
+                        Some(source) => load_tileset(assets, &relative_path(path, source))?,
+                        None => tileset_tmx(path, &tags[i..]),
+                    };
+                    map.tilesets.push(info.build(first_gid, assets, &mut textures)?);
+                    i += skip_element(&tags[i..]);
+                    continue;
+                },
+                ("group", false) => {
+                    let group = groups.last().unwrap().inner(|key| tag.number(key), tag.attribute("visible") != Some("0"));
+                    if !tag.empty {
+                        groups.push(group);
+                    }
+                },
+                ("group", true) if groups.len() > 1 => {
+                    groups.pop();
+                },
+                ("layer", false) => {
+                    let mut layer = TileLayer::new(
+                        tag.attribute("name").unwrap_or(""),
+                        tag.number("width").unwrap_or(map.width as f64) as usize,
+                        tag.number("height").unwrap_or(map.height as f64) as usize,
+                    );
+                    groups.last().unwrap().apply(&mut layer, |key| tag.number(key), tag.attribute("visible") != Some("0"));
+                    let end = skip_element(&tags[i..]);
+                    layer.tiles = tmx_tiles(&tags[i..i + end])?;
+                    map.add_layer(layer)?;
+                    i += end;
+                    continue;
+                },
+                _ => {},
+            }
+            i += 1;
+        }
+        Ok(map)
+    }
+
+    // An empty map from the attributes of a Tiled map
+    fn from_properties<F: Fn(&str) -> Option<String>>(path: &str, property: F) -> Result<Self, String> {
+        let number = |key: &str| property(key).and_then(|x| x.parse::<u32>().ok());
+        if property("orientation").map_or(false, |x| x != "orthogonal") {
+            return Err(format!("{} isn't orthogonal, the only orientation supported", path));
+        }
+        if property("infinite").map_or(false, |x| x == "1" || x == "true") {
+            return Err(format!("{} is infinite, which isn't supported", path));
+        }
+        match (number("width"), number("height"), number("tilewidth"), number("tileheight")) {
+            (Some(width), Some(height), Some(tile_width), Some(tile_height)) => {
+                Ok(Self::new(width as usize, height as usize, [tile_width, tile_height]))
+            },
+            _ => Err(format!("{} is missing its size or tile size", path)),
+        }
+    }
+
+    // Adds a layer read from a file, checking it has a tile for each cell
+    fn add_layer(&mut self, layer: TileLayer) -> Result<(), String> {
+        if layer.tiles.len() != layer.width * layer.height {
+            return Err(format!("The layer {} has {} tiles but is {}x{}", layer.name, layer.tiles.len(), layer.width, layer.height));
+        }
+        self.layers.push(layer);
+        Ok(())
+    }
+
+    /// The first layer called `name`
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|x| x.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut TileLayer> {
+        self.layers.iter_mut().find(|x| x.name == name)
+    }
+
+    /// The index of the tileset the tile `gid` is from, ignoring its flip bits
+    pub fn tileset_of(&self, gid: u32) -> Option<usize> {
+        let gid = gid & TILE_GID_MASK;
+        self.tilesets.iter().position(|x| x.contains(gid))
+    }
+
+    /// The size of the map in pixels
+    pub fn pixel_size(&self) -> [f64; 2] {
+        [(self.width as u32 * self.tile_size[0]) as f64, (self.height as u32 * self.tile_size[1]) as f64]
+    }
+
+    /// The cell of the grid containing `point`, in the pixels of a layer with a parallax of `[1, 1]`
+    /// and no offset, or `None` outside of the map
+    pub fn cell_at(&self, point: [f64; 2]) -> Option<[usize; 2]> {
+        let x = (point[0] / self.tile_size[0] as f64).floor();
+        let y = (point[1] / self.tile_size[1] as f64).floor();
+        if x >= 0.0 && y >= 0.0 && (x as usize) < self.width && (y as usize) < self.height {
+            Some([x as usize, y as usize])
+        } else {
+            None
+        }
+    }
+
+    /// A `NavGrid` over the layer called `name` in the pixels of `cell_at`, with the cells whose tiles
+    /// are `blocked` left unwalkable. `blocked` is given each tile without its flip bits, `0` for none
+    pub fn nav_grid(&self, name: &str, blocked: impl Fn(u32) -> bool) -> Option<NavGrid> {
+        let layer = self.layer(name)?;
+        let mut grid = NavGrid::new(layer.width, layer.height, [self.tile_size[0] as f32, self.tile_size[1] as f32]);
+        for y in 0..layer.height {
+            for x in 0..layer.width {
+                if blocked(layer.get(x, y) & TILE_GID_MASK) {
+                    grid.set_walkable(x, y, false);
+                }
+            }
+        }
+        Some(grid)
+    }
+}
+
+impl Drawable for TileMap {
+    type Shader = ();
+
+    fn draw_with(
+        &mut self,
+        _data: &mut (),
+        graphics: &mut GlGraphics,
+        context: &Context,
+        _cache: &mut ViewProj,
+        transforms: &mut Transforms
+    ) {
+        let model = transforms.current();
+        let model: Matrix2d = [
+            [model.x.x as f64, model.y.x as f64, model.w.x as f64],
+            [model.x.y as f64, model.y.y as f64, model.w.y as f64],
+        ];
+        let (cell_width, cell_height) = (self.tile_size[0] as f64, self.tile_size[1] as f64);
+        // Tiles bigger than a cell stick out above and to the right of it
+        let overhang = self.tilesets.iter().fold([0.0f64; 2], |overhang, x| [
+            overhang[0].max(x.tile_size[0] as f64 - cell_width),
+            overhang[1].max(x.tile_size[1] as f64 - cell_height),
+        ]);
+        self.batches.resize_with(self.tilesets.len(), Default::default);
+
+        for layer in self.layers.iter().filter(|x| x.visible && x.opacity > 0.0) {
+            let shift = [
+                layer.offset[0] - self.camera[0] * layer.parallax[0],
+                layer.offset[1] - self.camera[1] * layer.parallax[1],
+            ];
+            let transform = multiply(context.transform, multiply(model, translate(shift)));
+            let inverse = match invert(transform) {
+                Some(x) => x,
+                None => continue,
+            };
+            // The part of the layer in view, found from the corners of the screen
+            let (mut min, mut max) = ([std::f64::INFINITY; 2], [std::f64::NEG_INFINITY; 2]);
+            for &corner in &[[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]] {
+                let [x, y] = transform_pos(inverse, corner);
+                min = [min[0].min(x), min[1].min(y)];
+                max = [max[0].max(x), max[1].max(y)];
+            }
+            let range = |min: f64, max: f64, cell: f64, count: usize| {
+                let start = (min / cell).floor().max(0.0).min(count as f64) as usize;
+                let end = (max / cell).ceil().max(0.0).min(count as f64) as usize;
+                start..end
+            };
+            let columns = range(min[0] - overhang[0], max[0], cell_width, layer.width);
+            let rows = range(min[1], max[1] + overhang[1], cell_height, layer.height);
+
+            for y in rows {
+                for x in columns.clone() {
+                    let tile = layer.tiles[y * layer.width + x];
+                    let gid = tile & TILE_GID_MASK;
+                    let index = match self.tilesets.iter().position(|x| x.contains(gid)) {
+                        Some(x) => x,
+                        None => continue,
+                    };
+                    let tileset = &self.tilesets[index];
+                    let [u0, v0, u1, v1] = tileset.uv(gid);
+                    let mut uvs = [[u0, v0], [u1, v0], [u1, v1], [u0, v1]];
+                    if tile & TILE_FLIPPED_DIAGONALLY != 0 {
+                        uvs.swap(1, 3);
+                    }
+                    if tile & TILE_FLIPPED_HORIZONTALLY != 0 {
+                        uvs = [uvs[1], uvs[0], uvs[3], uvs[2]];
+                    }
+                    if tile & TILE_FLIPPED_VERTICALLY != 0 {
+                        uvs = [uvs[3], uvs[2], uvs[1], uvs[0]];
+                    }
+                    let (width, height) = (tileset.tile_size[0] as f64, tileset.tile_size[1] as f64);
+                    let left = x as f64 * cell_width;
+                    let top = (y + 1) as f64 * cell_height - height;
+                    let corners = [[left, top], [left + width, top], [left + width, top + height], [left, top + height]];
+                    let (vertices, coordinates) = &mut self.batches[index];
+                    for &i in &[0, 1, 2, 0, 2, 3] {
+                        let [px, py] = transform_pos(transform, corners[i]);
+                        vertices.push([px as f32, py as f32]);
+                        coordinates.push(uvs[i]);
+                    }
+                }
+            }
+
+            let color = [1.0, 1.0, 1.0, layer.opacity];
+            for (tileset, (vertices, uvs)) in self.tilesets.iter().zip(&mut self.batches) {
+                if vertices.is_empty() {
+                    continue;
+                }
+                {
+                    // Whole tiles in each chunk, so no triangle is split across flushes
+                    let chunk = BACK_END_MAX_VERTEX_COUNT / 6 * 6;
+                    let (vertices, uvs) = (&*vertices, &*uvs);
+                    graphics.tri_list_uv(&self.draw_state, &color, &tileset.texture, |f| {
+                        for (vertices, uvs) in vertices.chunks(chunk).zip(uvs.chunks(chunk)) {
+                            f(vertices, uvs);
+                        }
+                    });
+                }
+                vertices.clear();
+                uvs.clear();
+            }
+        }
+    }
+}
+
+fn invert(m: Matrix2d) -> Option<Matrix2d> {
+    let determinant = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let (a, b) = (m[1][1] / determinant, -m[0][1] / determinant);
+    let (c, d) = (-m[1][0] / determinant, m[0][0] / determinant);
+    Some([
+        [a, b, -(a * m[0][2] + b * m[1][2])],
+        [c, d, -(c * m[0][2] + d * m[1][2])],
+    ])
+}
+
+// The parallax, offset, opacity and visibility group layers pass on to the layers in them
+#[derive(Clone, Copy)]
+struct Group {
+    parallax: [f64; 2],
+    offset: [f64; 2],
+    opacity: f32,
+    visible: bool,
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Self {
+            parallax: [1.0; 2],
+            offset: [0.0; 2],
+            opacity: 1.0,
+            visible: true,
+        }
+    }
+}
+
+impl Group {
+    // A group inside this one with the Tiled layer attributes `number` finds
+    fn inner<F: Fn(&str) -> Option<f64>>(&self, number: F, visible: bool) -> Self {
+        Self {
+            parallax: [
+                self.parallax[0] * number("parallaxx").unwrap_or(1.0),
+                self.parallax[1] * number("parallaxy").unwrap_or(1.0),
+            ],
+            offset: [
+                self.offset[0] + number("offsetx").unwrap_or(0.0),
+                self.offset[1] + number("offsety").unwrap_or(0.0),
+            ],
+            opacity: self.opacity * number("opacity").unwrap_or(1.0) as f32,
+            visible: self.visible && visible,
+        }
+    }
+
+    // Sets up `layer` inside this group from the Tiled layer attributes `number` finds
+    fn apply<F: Fn(&str) -> Option<f64>>(&self, layer: &mut TileLayer, number: F, visible: bool) {
+        let own = self.inner(number, visible);
+        layer.parallax = own.parallax;
+        layer.offset = own.offset;
+        layer.opacity = own.opacity;
+        layer.visible = own.visible;
+    }
+}
+
+// A tileset read from a file, before its image is loaded
+struct TilesetInfo {
+    image: Option<String>,
+    tile_size: [u32; 2],
+    columns: Option<u32>,
+    tile_count: Option<u32>,
+    margin: u32,
+    spacing: u32,
+    source: String,
+}
+
+impl TilesetInfo {
+    // Loads the image, sharing it with tilesets using the same one
+    fn build(self, first_gid: u32, assets: &Assets, textures: &mut HashMap<String, Rc<Texture>>) -> Result<Tileset, String> {
+        let path = self.image.ok_or_else(|| format!("The tileset in {} has no image, collections of images aren't supported", self.source))?;
+        let texture = match textures.get(&path) {
+            Some(x) => x.clone(),
+            None => {
+                let data = assets.read_data(&path)?;
+                let decoded = image::load_from_memory(&data)
+                    .map_err(|e| format!("Could not decode the tileset image {}: {}", path, e))?
+                    .to_rgba();
+                // Linear filtering would bleed neighbouring tiles into each other
+                let texture = Rc::new(Texture::from_image(&decoded, &TextureSettings::new().filter(Filter::Nearest)));
+                textures.insert(path, texture.clone());
+                texture
+            },
+        };
+        let mut tileset = Tileset::with_spacing(first_gid, texture, self.tile_size, self.margin, self.spacing);
+        tileset.columns = self.columns.filter(|&x| x > 0).unwrap_or(tileset.columns);
+        tileset.tile_count = self.tile_count.unwrap_or(tileset.tile_count);
+        Ok(tileset)
+    }
+}
+
+// Loads an external tileset, as TSX or JSON by the extension of `path`
+fn load_tileset(assets: &Assets, path: &str) -> Result<TilesetInfo, String> {
+    let text = assets.read_string(path)?;
+    if path.ends_with(".tsx") {
+        let tags = xml_tags(&text)?;
+        let start = tags.iter().position(|x| x.name == "tileset").ok_or_else(|| format!("{} has no tileset", path))?;
+        Ok(tileset_tmx(path, &tags[start..]))
+    } else {
+        let json = serde_json::from_str::<Value>(&text).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(tileset_json(path, &json))
+    }
+}
+
+// A tileset in the JSON format, from the file at `path`
+fn tileset_json(path: &str, tileset: &Value) -> TilesetInfo {
+    let number = |key: &str| tileset.get(key).and_then(Value::as_u64).map(|x| x as u32);
+    TilesetInfo {
+        image: tileset.get("image").and_then(Value::as_str).map(|x| relative_path(path, x)),
+        tile_size: [number("tilewidth").unwrap_or(0), number("tileheight").unwrap_or(0)],
+        columns: number("columns"),
+        tile_count: number("tilecount"),
+        margin: number("margin").unwrap_or(0),
+        spacing: number("spacing").unwrap_or(0),
+        source: path.to_string(),
+    }
+}
+
+// A tileset in TMX, from the `tileset` tag starting `tags`
+fn tileset_tmx(path: &str, tags: &[Tag]) -> TilesetInfo {
+    let tileset = &tags[0];
+    let number = |key: &str| tileset.number(key).map(|x| x as u32);
+    // The tileset's own image, rather than those of single tiles in collections
+    let image = tags[..skip_element(tags)]
+        .iter()
+        .scan(0, |depth, tag| {
+            let outer = *depth == 0;
+            if tag.name == "tile" && !tag.empty {
+                *depth += if tag.closing { -1 } else { 1 };
+            }
+            Some((outer, tag))
+        })
+        .find(|(outer, tag)| *outer && tag.name == "image" && !tag.closing)
+        .and_then(|(_, tag)| tag.attribute("source"))
+        .map(|x| relative_path(path, x));
+    TilesetInfo {
+        image,
+        tile_size: [number("tilewidth").unwrap_or(0), number("tileheight").unwrap_or(0)],
+        columns: number("columns"),
+        tile_count: number("tilecount"),
+        margin: number("margin").unwrap_or(0),
+        spacing: number("spacing").unwrap_or(0),
+        source: path.to_string(),
+    }
+}
+
+// Adds the tile layers of `parent` (the map or a group) to `map`, going into groups
+fn add_json_layers(map: &mut TileMap, parent: &Value, group: &Group) -> Result<(), String> {
+    for layer in array(parent, "layers") {
+        let number = |key: &str| layer.get(key).and_then(Value::as_f64);
+        let visible = layer.get("visible").and_then(Value::as_bool).unwrap_or(true);
+        match layer.get("type").and_then(Value::as_str) {
+            Some("group") => add_json_layers(map, layer, &group.inner(number, visible))?,
+            Some("tilelayer") => {
+                let mut tiles = TileLayer::new(
+                    layer.get("name").and_then(Value::as_str).unwrap_or(""),
+                    number("width").map_or(map.width, |x| x as usize),
+                    number("height").map_or(map.height, |x| x as usize),
+                );
+                group.apply(&mut tiles, number, visible);
+                if layer.get("chunks").is_some() {
+                    return Err(format!("The layer {} is infinite, which isn't supported", tiles.name));
+                }
+                tiles.tiles = match layer.get("data") {
+                    Some(Value::String(data)) => decode_tiles(
+                        layer.get("encoding").and_then(Value::as_str).unwrap_or("base64"),
+                        layer.get("compression").and_then(Value::as_str).unwrap_or(""),
+                        data,
+                    )?,
+                    Some(Value::Array(data)) => data.iter().map(|x| x.as_u64().unwrap_or(0) as u32).collect(),
+                    _ => Vec::new(),
+                };
+                map.add_layer(tiles)?;
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+// The tiles of a TMX layer, from the tags of the `layer` element
+fn tmx_tiles(tags: &[Tag]) -> Result<Vec<u32>, String> {
+    let data = match tags.iter().position(|x| x.name == "data" && !x.closing) {
+        Some(x) => x,
+        None => return Ok(Vec::new()),
+    };
+    match tags[data].attribute("encoding") {
+        Some(encoding) => decode_tiles(encoding, tags[data].attribute("compression").unwrap_or(""), tags[data].text),
+        None => Ok(tags[data..]
+            .iter()
+            .take_while(|x| x.name != "data" || !x.closing)
+            .filter(|x| x.name == "tile")
+            .map(|x| x.attribute("gid").and_then(|x| x.parse().ok()).unwrap_or(0))
+            .collect()),
+    }
+}
+
+// Decodes tile data stored as CSV or base64
+fn decode_tiles(encoding: &str, compression: &str, data: &str) -> Result<Vec<u32>, String> {
+    if !compression.is_empty() {
+        return Err(format!("Tile layers compressed with {} aren't supported, save them as CSV or uncompressed base64", compression));
+    }
+    match encoding {
+        "csv" => data
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse().map_err(|_| format!("Invalid tile {}", x)))
+            .collect(),
+        "base64" => Ok(decode_base64(data)?
+            .chunks_exact(4)
+            .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect()),
+        _ => Err(format!("Unknown tile encoding {}", encoding)),
+    }
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).and_then(Value::as_array).map(|x| &x[..]).unwrap_or(&[])
+}
+
+// A JSON value as a string, as the TMX attributes it mirrors are
+fn json_string(value: &Value) -> String {
+    match value {
+        Value::String(x) => x.clone(),
+        x => x.to_string(),
+    }
+}
+
+// A tag of an XML document, enough of XML for Tiled's files
+struct Tag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, String)>,
+    // `</name>`
+    closing: bool,
+    // `<name/>`
+    empty: bool,
+    // The text after the tag, up to the next one
+    text: &'a str,
+}
+
+impl<'a> Tag<'a> {
+    fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.iter().find(|x| x.0 == key).map(|x| x.1.as_str())
+    }
+
+    fn number(&self, key: &str) -> Option<f64> {
+        self.attribute(key).and_then(|x| x.parse().ok())
+    }
+}
+
+// The number of tags from an opening tag starting `tags` up to and including its closing one
+fn skip_element(tags: &[Tag]) -> usize {
+    if tags[0].empty {
+        return 1;
+    }
+    let mut depth = 0;
+    for (i, tag) in tags.iter().enumerate() {
+        if tag.name == tags[0].name && !tag.empty {
+            depth += if tag.closing { -1 } else { 1 };
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+    }
+    tags.len()
+}
+
+// Splits `text` into its tags, skipping the declaration, comments and doctypes
+fn xml_tags(text: &str) -> Result<Vec<Tag>, String> {
+    let mut tags = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if rest.starts_with("!--") {
+            let end = rest.find("-->").ok_or("Unclosed XML comment")?;
+            rest = &rest[end + 3..];
+            continue;
+        }
+        let end = rest.find('>').ok_or("Unclosed XML tag")?;
+        let inner = &rest[..end];
+        rest = &rest[end + 1..];
+        if inner.starts_with('?') || inner.starts_with('!') {
+            continue;
+        }
+        let closing = inner.starts_with('/');
+        let empty = inner.ends_with('/');
+        let inner = inner.trim_start_matches('/').trim_end_matches('/');
+        let name_end = inner.find(char::is_whitespace).unwrap_or_else(|| inner.len());
+        let mut attributes = Vec::new();
+        let mut remaining = inner[name_end..].trim_start();
+        while let Some(equals) = remaining.find('=') {
+            let key = remaining[..equals].trim();
+            let value = remaining[equals + 1..].trim_start();
+            let quote = value.chars().next().filter(|&x| x == '"' || x == '\'').ok_or("Unquoted XML attribute")?;
+            let length = value[1..].find(quote).ok_or("Unclosed XML attribute")?;
+            attributes.push((key, unescape(&value[1..length + 1])));
+            remaining = value[length + 2..].trim_start();
+        }
+        tags.push(Tag {
+            name: &inner[..name_end],
+            attributes,
+            closing,
+            empty,
+            text: &rest[..rest.find('<').unwrap_or_else(|| rest.len())],
+        });
+    }
+    Ok(tags)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
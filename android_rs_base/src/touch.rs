@@ -0,0 +1,232 @@
+use piston::input::{Input, Motion, Touch, TouchArgs};
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// The raw and predicted position of a pointer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PredictedTouch {
+    pub id: i64,
+    /// The last position received
+    pub raw: [f64; 2],
+    /// Where the pointer is expected to be `TouchPredictor::lookahead` milliseconds after `raw`
+    pub predicted: [f64; 2],
+}
+
+/// Extrapolates where touches are headed from their recent history, so strokes drawn
+/// with a finger or stylus can hide some of the display's latency.
+///
+/// The velocity of each touch is fitted to the samples of the last `window` milliseconds.
+pub struct TouchPredictor {
+    /// How far ahead to predict, in milliseconds
+    pub lookahead: f64,
+    /// How much history to fit the velocity to, in milliseconds
+    pub window: f64,
+    start: Instant,
+    history: HashMap<i64, VecDeque<(f64, [f64; 2])>>,
+}
+
+impl TouchPredictor {
+    /// Creates a predictor looking `lookahead` milliseconds ahead, using a 40ms window
+    pub fn new(lookahead: f64) -> Self {
+        Self {
+            lookahead,
+            window: 40.0,
+            start: Instant::now(),
+            history: HashMap::new(),
+        }
+    }
+
+    /// Records a touch event, pass every event from `AppImpl::input` here
+    pub fn input(&mut self, input: &Input) {
+        if let Input::Move(Motion::Touch(args)) = input {
+            let now = self.start.elapsed().as_secs_f64() * 1000.0;
+            self.touch_at(args, now);
+        }
+    }
+
+    /// Records a touch which happened `time` milliseconds after this was created, for replaying recorded input
+    pub fn touch_at(&mut self, args: &TouchArgs, time: f64) {
+        match args.touch {
+            Touch::End | Touch::Cancel => {
+                self.history.remove(&args.id);
+            },
+            Touch::Start | Touch::Move => {
+                let window = self.window;
+                let samples = self.history.entry(args.id).or_insert_with(VecDeque::new);
+                samples.push_back((time, args.position()));
+                while samples.front().map(|&(t, _)| time - t > window).unwrap_or(false) {
+                    samples.pop_front();
+                }
+            },
+        }
+    }
+
+    /// Gets the predicted position of touch `id`, or `None` if it isn't down
+    pub fn predict(&self, id: i64) -> Option<PredictedTouch> {
+        let samples = self.history.get(&id)?;
+        let &(_, raw) = samples.back()?;
+        let [vx, vy] = velocity(samples);
+        Some(PredictedTouch {
+            id,
+            raw,
+            predicted: [raw[0] + vx * self.lookahead, raw[1] + vy * self.lookahead],
+        })
+    }
+
+    /// Gets the predictions of every touch which is down
+    pub fn predictions(&self) -> Vec<PredictedTouch> {
+        self.history.keys().filter_map(|&id| self.predict(id)).collect()
+    }
+}
+
+/// Least squares fit of the velocity (per millisecond) of the samples
+fn velocity(samples: &VecDeque<(f64, [f64; 2])>) -> [f64; 2] {
+    let n = samples.len() as f64;
+    if n < 2.0 {
+        return [0.0, 0.0];
+    }
+    let mean_t = samples.iter().map(|x| x.0).sum::<f64>() / n;
+    let mean_x = samples.iter().map(|x| x.1[0]).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|x| x.1[1]).sum::<f64>() / n;
+    let (mut tt, mut tx, mut ty) = (0.0, 0.0, 0.0);
+    for &(t, [x, y]) in samples {
+        let dt = t - mean_t;
+        tt += dt * dt;
+        tx += dt * (x - mean_x);
+        ty += dt * (y - mean_y);
+    }
+    if tt <= std::f64::EPSILON {
+        return [0.0, 0.0];
+    }
+    [tx / tt, ty / tt]
+}
+
+/// Maps raw touch pressure in `[0, 1]` to the pressure used for drawing
+#[derive(Debug, Clone, PartialEq)]
+pub enum PressureCurve {
+    Linear,
+    /// `pressure.powf(gamma)`, values below `1` make light touches heavier and above `1` lighter
+    Gamma(f64),
+    /// Linearly interpolated `[input, output]` points, sorted by input
+    Points(Vec<[f64; 2]>),
+}
+
+impl PressureCurve {
+    /// A curve which makes light strokes register more easily
+    pub fn soft() -> Self {
+        PressureCurve::Gamma(0.6)
+    }
+
+    /// A curve which needs firmer strokes for full pressure
+    pub fn firm() -> Self {
+        PressureCurve::Gamma(1.6)
+    }
+
+    /// Applies the curve, clamping the result to `[0, 1]`
+    pub fn apply(&self, pressure: f64) -> f64 {
+        let pressure = pressure.max(0.0).min(1.0);
+        let mapped = match self {
+            PressureCurve::Linear => pressure,
+            PressureCurve::Gamma(gamma) => pressure.powf(*gamma),
+            PressureCurve::Points(points) => match points.iter().position(|x| x[0] > pressure) {
+                Some(0) => points[0][1],
+                Some(i) => {
+                    let ([x0, y0], [x1, y1]) = (points[i - 1], points[i]);
+                    y0 + (y1 - y0) * (pressure - x0) / (x1 - x0)
+                },
+                None => points.last().map(|x| x[1]).unwrap_or(pressure),
+            },
+        };
+        mapped.max(0.0).min(1.0)
+    }
+}
+
+/// A touch along with the contact details palm rejection relies on, where the platform provides them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchContact {
+    pub args: TouchArgs,
+    /// The diameter of the contact in pixels, if known
+    pub size: Option<f64>,
+    /// Whether the touch comes from a stylus
+    pub is_stylus: bool,
+}
+
+impl From<TouchArgs> for TouchContact {
+    fn from(args: TouchArgs) -> Self {
+        Self {
+            args,
+            size: None,
+            is_stylus: false,
+        }
+    }
+}
+
+/// Remaps touch pressure and rejects touches which are likely a resting palm, set with `AppConfig::touch_filter`.
+///
+/// Touches are rejected for their whole lifetime when they start with a contact larger than
+/// `max_contact_size`, or when `pen_priority` is set and a stylus is (or recently was) down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TouchFilter {
+    pub pressure_curve: PressureCurve,
+    /// Contacts larger than this (in pixels) are treated as palms
+    pub max_contact_size: f64,
+    /// Ignores fingers while a stylus is in use
+    pub pen_priority: bool,
+    /// How long after a stylus is lifted fingers are still ignored, in milliseconds
+    pub pen_timeout: f64,
+    start: Instant,
+    last_pen: Option<f64>,
+    pens_down: usize,
+    rejected: Vec<i64>,
+}
+
+impl TouchFilter {
+    /// Creates a filter with a linear pressure curve, rejecting contacts over 120 pixels across and
+    /// ignoring fingers for 500ms after the stylus is used
+    pub fn new() -> Self {
+        Self {
+            pressure_curve: PressureCurve::Linear,
+            max_contact_size: 120.0,
+            pen_priority: true,
+            pen_timeout: 500.0,
+            start: Instant::now(),
+            last_pen: None,
+            pens_down: 0,
+            rejected: Vec::new(),
+        }
+    }
+
+    pub fn pressure_curve(mut self, curve: PressureCurve) -> Self {
+        self.pressure_curve = curve;
+        self
+    }
+
+    /// Filters a touch, returning it with its pressure remapped or `None` if it was rejected
+    pub fn filter(&mut self, contact: TouchContact) -> Option<TouchArgs> {
+        let now = self.start.elapsed().as_secs_f64() * 1000.0;
+        let args = contact.args;
+        let ending = args.touch == Touch::End || args.touch == Touch::Cancel;
+        if contact.is_stylus {
+            self.last_pen = Some(now);
+            match args.touch {
+                Touch::Start => self.pens_down += 1,
+                Touch::End | Touch::Cancel => self.pens_down = self.pens_down.saturating_sub(1),
+                Touch::Move => {},
+            }
+        } else if args.touch == Touch::Start {
+            let pen_active = self.pens_down > 0 || self.last_pen.map(|t| now - t < self.pen_timeout).unwrap_or(false);
+            let too_large = contact.size.map(|x| x > self.max_contact_size).unwrap_or(false);
+            if too_large || (self.pen_priority && pen_active) {
+                self.rejected.push(args.id);
+            }
+        }
+        if let Some(index) = self.rejected.iter().position(|&x| x == args.id) {
+            if ending {
+                self.rejected.swap_remove(index);
+            }
+            return None;
+        }
+        let pressure = self.pressure_curve.apply(args.pressure());
+        Some(TouchArgs::new(args.device, args.id, args.position(), pressure, args.touch))
+    }
+}
@@ -0,0 +1,274 @@
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::params::ParamValue;
+
+/// The values expressions and curves are evaluated with each frame
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TrackInputs {
+    /// Seconds of game time, usually `AppConfig::scaled_time`
+    pub time: f32,
+    /// The distance from the camera to whatever is being drawn
+    pub camera_distance: f32,
+}
+
+/// A function which can be called in an `Expr`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Abs,
+    Fract,
+    Min,
+    Max,
+    Clamp,
+    Mix,
+    Smoothstep,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<(Self, usize)> {
+        Some(match name {
+            "sin" => (Function::Sin, 1),
+            "cos" => (Function::Cos, 1),
+            "abs" => (Function::Abs, 1),
+            "fract" => (Function::Fract, 1),
+            "min" => (Function::Min, 2),
+            "max" => (Function::Max, 2),
+            "clamp" => (Function::Clamp, 3),
+            "mix" => (Function::Mix, 3),
+            "smoothstep" => (Function::Smoothstep, 3),
+            _ => return None,
+        })
+    }
+
+    fn call(self, args: &[f32]) -> f32 {
+        match self {
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Abs => args[0].abs(),
+            Function::Fract => args[0] - args[0].floor(),
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+            Function::Clamp => args[0].max(args[1]).min(args[2]),
+            Function::Mix => args[0] + (args[1] - args[0]) * args[2],
+            Function::Smoothstep => {
+                let t = ((args[2] - args[0]) / (args[1] - args[0])).max(0.0).min(1.0);
+                t * t * (3.0 - 2.0 * t)
+            },
+        }
+    }
+}
+
+/// A parsed arithmetic expression such as `0.5 + 0.5 * sin(time * 2)`.
+///
+/// Supports `+ - * / ^`, brackets, the variables `time`, `camera_distance` and `pi`,
+/// and the functions `sin cos abs fract min max clamp mix smoothstep`, named as in GLSL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    Time,
+    CameraDistance,
+    Negate(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Subtract(Box<Expr>, Box<Expr>),
+    Multiply(Box<Expr>, Box<Expr>),
+    Divide(Box<Expr>, Box<Expr>),
+    Power(Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    /// Parses an expression
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser { chars: source.chars().peekable() };
+        let expr = parser.expr()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(expr),
+            Some(c) => Err(format!("Unexpected '{}' in expression \"{}\"", c, source)),
+        }
+    }
+
+    /// Evaluates the expression
+    pub fn eval(&self, inputs: &TrackInputs) -> f32 {
+        match self {
+            Expr::Number(x) => *x,
+            Expr::Time => inputs.time,
+            Expr::CameraDistance => inputs.camera_distance,
+            Expr::Negate(x) => -x.eval(inputs),
+            Expr::Add(a, b) => a.eval(inputs) + b.eval(inputs),
+            Expr::Subtract(a, b) => a.eval(inputs) - b.eval(inputs),
+            Expr::Multiply(a, b) => a.eval(inputs) * b.eval(inputs),
+            Expr::Divide(a, b) => a.eval(inputs) / b.eval(inputs),
+            Expr::Power(a, b) => a.eval(inputs).powf(b.eval(inputs)),
+            Expr::Call(function, args) => {
+                let args = args.iter().map(|x| x.eval(inputs)).collect::<Vec<_>>();
+                function.call(&args)
+            },
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+            self.chars.next();
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&expected) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        loop {
+            if self.eat('+') {
+                lhs = Expr::Add(Box::new(lhs), Box::new(self.term()?));
+            } else if self.eat('-') {
+                lhs = Expr::Subtract(Box::new(lhs), Box::new(self.term()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            if self.eat('*') {
+                lhs = Expr::Multiply(Box::new(lhs), Box::new(self.unary()?));
+            } else if self.eat('/') {
+                lhs = Expr::Divide(Box::new(lhs), Box::new(self.unary()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(Expr::Power(Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        if self.eat('(') {
+            let inner = self.expr()?;
+            return if self.eat(')') { Ok(inner) } else { Err("Expected ')'".to_string()) };
+        }
+        let mut token = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_alphanumeric() || c == '_' || c == '.' {
+                token.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        if token.is_empty() {
+            return Err(format!("Expected a value, found {:?}", self.chars.peek()));
+        }
+        if let Ok(x) = token.parse::<f32>() {
+            return Ok(Expr::Number(x));
+        }
+        match token.as_str() {
+            "time" => Ok(Expr::Time),
+            "camera_distance" => Ok(Expr::CameraDistance),
+            "pi" => Ok(Expr::Number(std::f32::consts::PI)),
+            name => {
+                let (function, arity) = Function::from_name(name).ok_or_else(|| format!("Unknown name '{}'", name))?;
+                if !self.eat('(') {
+                    return Err(format!("Expected '(' after {}", name));
+                }
+                let mut args = vec![self.expr()?];
+                while self.eat(',') {
+                    args.push(self.expr()?);
+                }
+                if !self.eat(')') {
+                    return Err(format!("Expected ')' after the arguments of {}", name));
+                }
+                if args.len() != arity {
+                    return Err(format!("{} takes {} arguments, not {}", name, arity, args.len()));
+                }
+                Ok(Expr::Call(function, args))
+            },
+        }
+    }
+}
+
+/// A curve through `(time, value)` keys, blended linearly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    pub keys: Vec<[f32; 2]>,
+    /// Repeats the curve after its last key
+    pub looped: bool,
+}
+
+impl Curve {
+    /// Gets the value of the curve at `time`
+    pub fn sample(&self, time: f32) -> f32 {
+        let (first, last) = match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0,
+        };
+        let length = last[0] - first[0];
+        let time = if self.looped && length > 0.0 {
+            first[0] + (time - first[0]).rem_euclid(length)
+        } else {
+            time
+        };
+        match self.keys.iter().position(|x| x[0] > time) {
+            Some(0) => first[1],
+            Some(i) => {
+                let ([t0, v0], [t1, v1]) = (self.keys[i - 1], self.keys[i]);
+                v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+            },
+            None => last[1],
+        }
+    }
+}
+
+/// How a uniform gets its value each frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniformTrack {
+    Constant(ParamValue),
+    /// One expression per component, up to 4
+    Expressions(Vec<Expr>),
+    Curve(Curve),
+}
+
+impl UniformTrack {
+    /// Evaluates the track
+    pub fn eval(&self, inputs: &TrackInputs) -> ParamValue {
+        match self {
+            UniformTrack::Constant(x) => *x,
+            UniformTrack::Curve(curve) => ParamValue::Float(curve.sample(inputs.time)),
+            UniformTrack::Expressions(exprs) => {
+                let v = exprs.iter().map(|x| x.eval(inputs)).collect::<Vec<_>>();
+                match v.len() {
+                    2 => ParamValue::Vec2([v[0], v[1]]),
+                    3 => ParamValue::Vec3([v[0], v[1], v[2]]),
+                    4 => ParamValue::Vec4([v[0], v[1], v[2], v[3]]),
+                    _ => ParamValue::Float(v.first().copied().unwrap_or(0.0)),
+                }
+            },
+        }
+    }
+}
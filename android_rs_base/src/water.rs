@@ -0,0 +1,260 @@
+use opengl_graphics::{GLSL, GlGraphics, RenderTarget, Texture, gl};
+use opengl_graphics::gl::types::GLuint;
+use opengl_graphics::shader_utils::{Shader, Program, DynamicAttribute};
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUInt, SUMat4x4, SUFloat, SUVec3, SUVec4};
+use graphics::{Context, DrawState};
+use cgmath::{Matrix4, SquareMatrix, Point3, EuclideanSpace, Transform as Transformation};
+use crate::storage::{Drawable, ShaderContext, Transforms, ViewProj, glsl_header};
+use crate::post::fit_target;
+
+const VERTEX_SHADER: &str = "uniform mat4 view_proj;
+uniform mat4 model;
+
+in vec3 pos;
+
+out vec3 v_World;
+out vec4 v_Clip;
+
+void main() {
+    vec4 world = model * vec4(pos, 1.0);
+    v_World = world.xyz;
+    v_Clip = view_proj * world;
+    gl_Position = v_Clip;
+}";
+
+const FRAGMENT_SHADER: &str = "precision mediump float;
+uniform sampler2D s_texture;
+uniform sampler2D s_reflection;
+uniform float time;
+uniform vec4 scroll;
+uniform float tiling;
+uniform vec3 eye;
+uniform vec4 water_color;
+uniform float fresnel_base;
+uniform float reflection_distortion;
+
+in vec3 v_World;
+in vec4 v_Clip;
+
+out vec4 outColor;
+
+void main() {
+    vec2 uv = v_World.xz * tiling;
+    vec3 n1 = texture(s_texture, uv + scroll.xy * time).rgb * 2.0 - 1.0;
+    vec3 n2 = texture(s_texture, uv * 1.7 + scroll.zw * time).rgb * 2.0 - 1.0;
+    vec3 tangent_normal = normalize(n1 + n2);
+    // The surface faces up, so the normal map's z becomes y
+    vec3 normal = normalize(vec3(tangent_normal.x, tangent_normal.z, tangent_normal.y));
+
+    vec2 screen_uv = v_Clip.xy / v_Clip.w * 0.5 + 0.5;
+    vec3 reflection = texture(s_reflection, screen_uv + normal.xz * reflection_distortion).rgb;
+
+    vec3 to_eye = normalize(eye - v_World);
+    float fresnel = fresnel_base + (1.0 - fresnel_base) * pow(1.0 - max(dot(to_eye, normal), 0.0), 5.0);
+    outColor = vec4(mix(water_color.rgb, reflection, fresnel), mix(water_color.a, 1.0, fresnel));
+}";
+
+/// The shader `Water` is drawn with
+pub struct WaterShader {
+    program: Program,
+    vao: GLuint,
+    pos: DynamicAttribute<[f32; 3]>,
+    view_proj: ShaderUniform<SUMat4x4>,
+    model: ShaderUniform<SUMat4x4>,
+    time: ShaderUniform<SUFloat>,
+    scroll: ShaderUniform<SUVec4>,
+    tiling: ShaderUniform<SUFloat>,
+    eye: ShaderUniform<SUVec3>,
+    water_color: ShaderUniform<SUVec4>,
+    fresnel_base: ShaderUniform<SUFloat>,
+    reflection_distortion: ShaderUniform<SUFloat>,
+    pos_buffer: Vec<[f32; 3]>,
+    offset: usize,
+    normal_map_id: GLuint,
+    reflection_id: GLuint,
+}
+
+impl Drop for WaterShader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl Shader for WaterShader {
+    type Vertex = [f32; 3];
+    fn new(_glsl: GLSL, graphics: Option<&mut GlGraphics>) -> Self {
+        let graphics = graphics.expect("WaterShader requires a GlGraphics to find its uniforms");
+        let header = glsl_header(graphics.profile()).unwrap();
+        let program = Program::from_vs_fs(
+            &[header, VERTEX_SHADER].concat(),
+            &[header, FRAGMENT_SHADER].concat(),
+        ).unwrap();
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        graphics.use_program(program.id());
+        graphics.get_uniform::<SUInt>("s_reflection").unwrap().set(graphics, 1);
+        let shader = Self {
+            pos: DynamicAttribute::xyz(program.id(), "pos").unwrap(),
+            view_proj: graphics.get_uniform("view_proj").unwrap(),
+            model: graphics.get_uniform("model").unwrap(),
+            time: graphics.get_uniform("time").unwrap(),
+            scroll: graphics.get_uniform("scroll").unwrap(),
+            tiling: graphics.get_uniform("tiling").unwrap(),
+            eye: graphics.get_uniform("eye").unwrap(),
+            water_color: graphics.get_uniform("water_color").unwrap(),
+            fresnel_base: graphics.get_uniform("fresnel_base").unwrap(),
+            reflection_distortion: graphics.get_uniform("reflection_distortion").unwrap(),
+            program,
+            vao,
+            pos_buffer: vec![[0.0; 3]; 6],
+            offset: 0,
+            normal_map_id: 0,
+            reflection_id: 0,
+        };
+        graphics.clear_program();
+        shader
+    }
+
+    fn flush(&mut self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, self.reflection_id);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.normal_map_id);
+            self.pos.bind_vao(self.vao);
+            self.pos.set(&self.pos_buffer[..self.offset]);
+            gl::DrawArrays(gl::TRIANGLES, 0, self.offset as i32);
+            gl::BindVertexArray(0);
+        }
+        self.offset = 0;
+    }
+
+    fn program(&self) -> GLuint {
+        self.program.id()
+    }
+    fn offset(&mut self) -> &mut usize {
+        &mut self.offset
+    }
+    fn pos_buffer(&mut self) -> &mut Vec<[f32; 3]> {
+        &mut self.pos_buffer
+    }
+    fn model_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> {
+        Some(self.model)
+    }
+}
+
+/// A square of water at `height` with scrolling normal maps, reflecting the scene above it.
+///
+/// Call `render_reflection` each frame, drawing everything which should be reflected, then draw
+/// this with `ShaderContext::draw`. Objects below the water are not clipped from the reflection.
+pub struct Water {
+    /// The height (y coordinate) of the surface
+    pub height: f32,
+    /// Half the width of the square of water, centred on the origin of the current transform
+    pub half_size: f32,
+    /// The colour of the water when looking straight down, the alpha makes it see-through
+    pub color: [f32; 4],
+    /// The two directions the normal map scrolls in, in uvs per second
+    pub scroll: [[f32; 2]; 2],
+    /// How many times the normal map repeats per world unit
+    pub tiling: f32,
+    /// How reflective the water is when looking straight down, in `[0, 1]`
+    pub fresnel_base: f32,
+    /// How much the waves warp the reflection, in screen uvs
+    pub reflection_distortion: f32,
+    time: f32,
+    normal_map: Texture,
+    reflection: Option<RenderTarget>,
+}
+
+impl Water {
+    /// Creates water with the given (tiling) normal map
+    pub fn new(normal_map: Texture, height: f32, half_size: f32) -> Self {
+        Self {
+            height,
+            half_size,
+            color: [0.05, 0.2, 0.3, 0.9],
+            scroll: [[0.02, 0.01], [-0.015, 0.02]],
+            tiling: 0.1,
+            fresnel_base: 0.02,
+            reflection_distortion: 0.02,
+            time: 0.0,
+            normal_map,
+            reflection: None,
+        }
+    }
+
+    /// Advances the scrolling of the normal maps
+    pub fn update(&mut self, dt: f64) {
+        self.time += dt as f32;
+    }
+
+    /// Draws the reflection of everything drawn in `f`, as seen from the camera mirrored in the surface
+    pub fn render_reflection<F: FnOnce(&mut ShaderContext)>(&mut self, ctx: &mut ShaderContext, f: F) {
+        let viewport = match ctx.gl.get_current_viewport() {
+            Some(x) => x,
+            None => return,
+        };
+        let target = fit_target(&mut self.reflection, viewport);
+        target.bind(ctx.gl);
+        ctx.gl.clear_buffers(Some([0.0, 0.0, 0.0, 1.0]), None, None);
+        let mut mirrored = ctx.shaders.cache.reflected(self.height);
+        ctx.with_camera(&mut mirrored, None, f);
+        target.unbind(ctx.gl, viewport);
+    }
+}
+
+impl Drawable for Water {
+    type Shader = WaterShader;
+
+    fn draw_with(
+        &mut self,
+        data: &mut WaterShader,
+        graphics: &mut GlGraphics,
+        _context: &Context,
+        cache: &mut ViewProj,
+        _transforms: &mut Transforms
+    ) {
+        let reflection = match &self.reflection {
+            Some(x) => x,
+            None => return,
+        };
+        data.reflection_id = reflection.texture().get_id();
+        data.normal_map_id = self.normal_map.get_id();
+        let view_proj = cache.projection * cache.view;
+        let eye = cache.view
+            .invert()
+            .unwrap_or_else(Matrix4::identity)
+            .transform_point(Point3::origin());
+        let (s, y) = (self.half_size, self.height);
+        let quad = [
+            [-s, y, -s], [s, y, -s], [s, y, s],
+            [-s, y, -s], [s, y, s], [-s, y, s],
+        ];
+        let scroll = [self.scroll[0][0], self.scroll[0][1], self.scroll[1][0], self.scroll[1][1]];
+        graphics.shader_draw(
+            data,
+            &DrawState::new_alpha(),
+            &quad,
+            None,
+            None,
+            None,
+            None,
+            |shader, gl| {
+                shader.view_proj.set(gl, view_proj.as_ref());
+                shader.time.set(gl, self.time);
+                shader.scroll.set(gl, &scroll);
+                shader.tiling.set(gl, self.tiling);
+                shader.eye.set(gl, eye.as_ref());
+                shader.water_color.set(gl, &self.color);
+                shader.fresnel_base.set(gl, self.fresnel_base);
+                shader.reflection_distortion.set(gl, self.reflection_distortion);
+            }
+        );
+    }
+}
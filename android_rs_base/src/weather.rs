@@ -0,0 +1,281 @@
+use opengl_graphics::GlGraphics;
+use opengl_graphics::shader_uniforms::{ShaderUniform, SUInt, SUVec2, SUVec3};
+use graphics::{Context, Ellipse, Line, Transformed};
+
+/// GLSL declaring the fog uniforms and `apply_fog`, for shaders which support fog.
+///
+/// Paste this into a fragment shader after its `precision` line and finish with
+/// `outColor.rgb = apply_fog(outColor.rgb, distance_to_camera);`. Set the uniforms with `FogUniforms`.
+pub const FOG_GLSL: &str = "
+uniform int fog_mode;
+uniform vec2 fog_params;
+uniform vec3 fog_color;
+
+vec3 apply_fog(vec3 color, float dist) {
+    float amount = 0.0;
+    if (fog_mode == 1) {
+        amount = clamp((dist - fog_params.x) / (fog_params.y - fog_params.x), 0.0, 1.0);
+    } else if (fog_mode == 2) {
+        amount = 1.0 - exp(-fog_params.x * dist);
+    }
+    return mix(color, fog_color, amount);
+}
+";
+
+/// How fog thickens with distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FogMode {
+    None,
+    /// Fog starts at `start` and is opaque from `end` onwards
+    Linear { start: f32, end: f32 },
+    /// Fog gets thicker exponentially with distance
+    Exponential { density: f32 },
+}
+
+/// Fog settings, stored in `ShaderStorage::fog`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: [f32; 3],
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::None,
+            color: [0.7, 0.75, 0.8],
+        }
+    }
+}
+
+/// The uniforms declared by `FOG_GLSL` in a shader
+#[derive(Clone, Copy)]
+pub struct FogUniforms {
+    mode: ShaderUniform<SUInt>,
+    params: ShaderUniform<SUVec2>,
+    color: ShaderUniform<SUVec3>,
+}
+
+impl FogUniforms {
+    /// Finds the fog uniforms of the program currently in use, or `None` if it doesn't use fog
+    pub fn find(gl: &GlGraphics) -> Option<Self> {
+        Some(Self {
+            mode: gl.get_uniform("fog_mode")?,
+            params: gl.get_uniform("fog_params")?,
+            color: gl.get_uniform("fog_color")?,
+        })
+    }
+
+    /// Sets the uniforms to `fog`, the shader needs to be in use
+    pub fn set(&self, gl: &GlGraphics, fog: &Fog) {
+        let (mode, params) = match fog.mode {
+            FogMode::None => (0, [0.0, 0.0]),
+            FogMode::Linear { start, end } => (1, [start, end]),
+            FogMode::Exponential { density } => (2, [density, 0.0]),
+        };
+        self.mode.set(gl, mode);
+        self.params.set(gl, &params);
+        self.color.set(gl, &fog.color);
+    }
+}
+
+/// A colour which changes over a range, such as the sky over the course of a day
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorGradient {
+    stops: Vec<(f32, [f32; 4])>,
+}
+
+impl ColorGradient {
+    /// Creates a gradient from `(position, colour)` stops, which are sorted by position
+    pub fn new(mut stops: Vec<(f32, [f32; 4])>) -> Self {
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Self { stops }
+    }
+
+    /// Gets the colour at `t`, blending linearly between the stops around it
+    pub fn sample(&self, t: f32) -> [f32; 4] {
+        let after = match self.stops.iter().position(|&(x, _)| x > t) {
+            Some(0) => return self.stops[0].1,
+            Some(x) => x,
+            None => return self.stops.last().map(|x| x.1).unwrap_or([0.0; 4]),
+        };
+        let (t0, c0) = self.stops[after - 1];
+        let (t1, c1) = self.stops[after];
+        let f = (t - t0) / (t1 - t0);
+        [
+            c0[0] + (c1[0] - c0[0]) * f,
+            c0[1] + (c1[1] - c0[1]) * f,
+            c0[2] + (c1[2] - c0[2]) * f,
+            c0[3] + (c1[3] - c0[3]) * f,
+        ]
+    }
+}
+
+/// Tracks the time of day from game time, giving sky colours and the sun's direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct DayNightCycle {
+    /// How long a day lasts in seconds of game time
+    pub day_length: f32,
+    /// The sky colour over the day, sampled from `0` (midnight) through `0.5` (noon) to `1`
+    pub sky: ColorGradient,
+    time_of_day: f32,
+}
+
+impl DayNightCycle {
+    /// Creates a cycle starting at dawn, with a default sky gradient
+    pub fn new(day_length: f32) -> Self {
+        Self {
+            day_length,
+            sky: ColorGradient::new(vec![
+                (0.0, [0.02, 0.02, 0.08, 1.0]),
+                (0.22, [0.05, 0.05, 0.15, 1.0]),
+                (0.27, [0.95, 0.55, 0.35, 1.0]),
+                (0.35, [0.55, 0.75, 0.95, 1.0]),
+                (0.65, [0.55, 0.75, 0.95, 1.0]),
+                (0.73, [0.95, 0.45, 0.3, 1.0]),
+                (0.78, [0.05, 0.05, 0.15, 1.0]),
+                (1.0, [0.02, 0.02, 0.08, 1.0]),
+            ]),
+            time_of_day: 0.25,
+        }
+    }
+
+    /// Advances the time of day, pass the (scaled) `dt` from `update`
+    pub fn update(&mut self, dt: f64) {
+        self.time_of_day = (self.time_of_day + dt as f32 / self.day_length).fract();
+    }
+
+    /// Gets the time of day, from `0` at midnight to `1`
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    pub fn set_time_of_day(&mut self, time: f32) {
+        self.time_of_day = time.rem_euclid(1.0);
+    }
+
+    /// Gets the sky colour for the current time
+    pub fn sky_color(&self) -> [f32; 4] {
+        self.sky.sample(self.time_of_day)
+    }
+
+    /// Gets the direction sunlight travels in, rising in +x and setting in -x
+    pub fn sun_direction(&self) -> [f32; 3] {
+        let angle = (self.time_of_day - 0.25) * std::f32::consts::PI * 2.0;
+        [-angle.cos(), -angle.sin(), 0.0]
+    }
+}
+
+/// The kind of weather drawn by `WeatherParticles`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WeatherKind {
+    Rain,
+    Snow,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    position: [f64; 2],
+    speed: f64,
+    phase: f64,
+}
+
+/// Rain or snow falling across the screen, meant to be drawn in `draw_overlay_2d`
+pub struct WeatherParticles {
+    pub kind: WeatherKind,
+    /// Horizontal speed in pixels per second
+    pub wind: f64,
+    pub color: [f32; 4],
+    drops: Vec<Particle>,
+    size: [f64; 2],
+    seed: u32,
+}
+
+impl WeatherParticles {
+    /// Creates a rain preset with `count` drops
+    pub fn rain(count: usize) -> Self {
+        Self::new(WeatherKind::Rain, count, [0.7, 0.75, 0.85, 0.5])
+    }
+
+    /// Creates a snow preset with `count` flakes
+    pub fn snow(count: usize) -> Self {
+        Self::new(WeatherKind::Snow, count, [1.0, 1.0, 1.0, 0.85])
+    }
+
+    fn new(kind: WeatherKind, count: usize, color: [f32; 4]) -> Self {
+        Self {
+            kind,
+            wind: if kind == WeatherKind::Rain { 60.0 } else { 15.0 },
+            color,
+            drops: vec![Particle { position: [0.0; 2], speed: 0.0, phase: 0.0 }; count],
+            size: [0.0, 0.0],
+            seed: 0x9E37_79B9,
+        }
+    }
+
+    // Xorshift, good enough for scattering particles
+    fn random(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f64 / std::u32::MAX as f64
+    }
+
+    fn respawn(&mut self, index: usize, anywhere: bool) {
+        let x = self.random() * self.size[0];
+        let y = if anywhere { self.random() * self.size[1] } else { -self.random() * 20.0 };
+        let speed = match self.kind {
+            WeatherKind::Rain => 700.0 + self.random() * 300.0,
+            WeatherKind::Snow => 40.0 + self.random() * 40.0,
+        };
+        let phase = self.random() * std::f64::consts::PI * 2.0;
+        self.drops[index] = Particle { position: [x, y], speed, phase };
+    }
+
+    /// Moves the particles, `size` is the size of the window, call this from `update`
+    pub fn update(&mut self, dt: f64, size: [f64; 2]) {
+        if self.size != size {
+            self.size = size;
+            for i in 0..self.drops.len() {
+                self.respawn(i, true);
+            }
+        }
+        for i in 0..self.drops.len() {
+            let drop = &mut self.drops[i];
+            drop.phase += dt;
+            let sway = match self.kind {
+                WeatherKind::Rain => 0.0,
+                WeatherKind::Snow => drop.phase.sin() * 20.0,
+            };
+            drop.position[0] += (self.wind + sway) * dt;
+            drop.position[1] += drop.speed * dt;
+            let [x, y] = drop.position;
+            if y > self.size[1] || x < -20.0 || x > self.size[0] + 20.0 {
+                self.respawn(i, false);
+            }
+        }
+    }
+
+    /// Draws the particles in window coordinates
+    pub fn draw(&self, c: &Context, gl: &mut GlGraphics) {
+        match self.kind {
+            WeatherKind::Rain => {
+                let line = Line::new(self.color, 0.75);
+                for drop in &self.drops {
+                    // Streak along the direction of motion
+                    let length = 0.02;
+                    let [x, y] = drop.position;
+                    line.draw([x, y, x - self.wind * length, y - drop.speed * length], &c.draw_state, c.transform, gl);
+                }
+            }
+            WeatherKind::Snow => {
+                let flake = Ellipse::new(self.color);
+                for drop in &self.drops {
+                    let radius = 1.5 + drop.speed / 40.0;
+                    let [x, y] = drop.position;
+                    flake.draw([0.0, 0.0, radius * 2.0, radius * 2.0], &c.draw_state, c.transform.trans(x - radius, y - radius), gl);
+                }
+            }
+        }
+    }
+}
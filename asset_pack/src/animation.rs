@@ -0,0 +1,684 @@
+use crate::Reader;
+use std::ops::Range;
+
+// Each component of the three smallest of a unit quaternion's components is within this of zero
+const SMALLEST_THREE_RANGE: f32 = std::f32::consts::FRAC_1_SQRT_2;
+const ROTATION_STEPS: f32 = 32767.0;
+const VALUE_STEPS: f32 = 65535.0;
+// The size of a quantized key's value
+const VALUE_SIZE: usize = 6;
+
+///
+/// What a channel of an `AnimationClip` moves.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelProperty {
+    /// `[x, y, z, 0]` relative to the joint's parent.
+    Translation,
+    /// A quaternion as `[x, y, z, w]`.
+    Rotation,
+    /// `[x, y, z, 0]`.
+    Scale,
+}
+
+impl ChannelProperty {
+    fn to_byte(self) -> u8 {
+        match self {
+            ChannelProperty::Translation => 0,
+            ChannelProperty::Rotation => 1,
+            ChannelProperty::Scale => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, String> {
+        match byte {
+            0 => Ok(ChannelProperty::Translation),
+            1 => Ok(ChannelProperty::Rotation),
+            2 => Ok(ChannelProperty::Scale),
+            x => Err(format!("Unknown animation channel property {}", x)),
+        }
+    }
+
+    // How far apart two values are, in radians for rotations
+    fn error(self, a: [f32; 4], b: [f32; 4]) -> f32 {
+        match self {
+            ChannelProperty::Rotation => {
+                let dot = a.iter().zip(&b).map(|(a, b)| a * b).sum::<f32>().abs();
+                2.0 * dot.min(1.0).acos()
+            },
+            _ => a.iter().zip(&b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt(),
+        }
+    }
+
+    // Linearly interpolates, or normalized linearly for rotations
+    fn interpolate(self, a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+        let mut out = [0.0; 4];
+        // Rotations take the shorter way around
+        let sign = if self == ChannelProperty::Rotation && a.iter().zip(&b).map(|(a, b)| a * b).sum::<f32>() < 0.0 { -1.0 } else { 1.0 };
+        for i in 0..4 {
+            out[i] = a[i] + (b[i] * sign - a[i]) * t;
+        }
+        if self == ChannelProperty::Rotation {
+            normalize(&mut out);
+        }
+        out
+    }
+}
+
+fn normalize(q: &mut [f32; 4]) {
+    let length = q.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if length > 0.0 {
+        q.iter_mut().for_each(|x| *x /= length);
+    } else {
+        *q = [0.0, 0.0, 0.0, 1.0];
+    }
+}
+
+///
+/// The local transform of a joint, relative to its parent.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointPose {
+    pub translation: [f32; 3],
+    /// A quaternion as `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for JointPose {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl JointPose {
+    fn set(&mut self, property: ChannelProperty, value: [f32; 4]) {
+        match property {
+            ChannelProperty::Translation => self.translation = [value[0], value[1], value[2]],
+            ChannelProperty::Rotation => self.rotation = value,
+            ChannelProperty::Scale => self.scale = [value[0], value[1], value[2]],
+        }
+    }
+}
+
+///
+/// Keyframes of one property of one joint, linearly interpolated
+/// between, or normalized linearly for rotations.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationChannel {
+    pub joint: u16,
+    pub property: ChannelProperty,
+    /// In seconds, in increasing order.
+    pub times: Vec<f32>,
+    pub values: Vec<[f32; 4]>,
+}
+
+impl AnimationChannel {
+    ///
+    /// Gets the value at `time`, holding the first and last keyframes
+    /// before and after them.
+    ///
+    pub fn sample(&self, time: f32) -> [f32; 4] {
+        let count = self.times.len().min(self.values.len());
+        if count == 0 {
+            return match self.property {
+                ChannelProperty::Translation => [0.0; 4],
+                ChannelProperty::Rotation => [0.0, 0.0, 0.0, 1.0],
+                ChannelProperty::Scale => [1.0, 1.0, 1.0, 0.0],
+            };
+        }
+        let next = self.times[..count].partition_point(|&x| x <= time);
+        if next == 0 {
+            return self.values[0];
+        }
+        if next == count {
+            return self.values[count - 1];
+        }
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let t = if end > start { (time - start) / (end - start) } else { 0.0 };
+        self.property.interpolate(self.values[next - 1], self.values[next], t)
+    }
+}
+
+///
+/// An animation of a skeleton's joints, as exported by an editor.
+///
+/// Joints without a channel for a property keep whatever that property
+/// was when sampling.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnimationClip {
+    /// In seconds.
+    pub duration: f32,
+    pub joint_count: u16,
+    pub channels: Vec<AnimationChannel>,
+}
+
+impl AnimationClip {
+    ///
+    /// Sets the properties of `poses` (one per joint) which are animated
+    /// to their values at `time`.
+    ///
+    pub fn sample(&self, time: f32, poses: &mut [JointPose]) {
+        for channel in &self.channels {
+            if let Some(pose) = poses.get_mut(channel.joint as usize) {
+                pose.set(channel.property, channel.sample(time));
+            }
+        }
+    }
+
+    ///
+    /// Compresses the clip into the format `CompressedClip` reads.
+    ///
+    /// Every channel is resampled at `settings.sample_rate`, then the
+    /// keys which can be interpolated from their neighbours to within
+    /// the tolerances are dropped, and channels which don't move are
+    /// stored once. Rotations are quantized to 48 bits by their three
+    /// smallest components, and translations and scales to 16 bits per
+    /// component within their range.
+    ///
+    /// The keys are split into segments of `settings.segment_duration`,
+    /// each of which can be decoded on its own, so long clips can be
+    /// played while holding only the part being played.
+    ///
+    pub fn compress(&self, settings: &CompressionSettings) -> Result<Vec<u8>, String> {
+        if settings.sample_rate.is_nan() || settings.sample_rate <= 0.0 || self.duration.is_nan() || self.duration < 0.0 {
+            return Err("Animations need a positive sample rate and duration".to_string());
+        }
+        if self.channels.len() > u16::MAX as usize {
+            return Err("Animation has too many channels".to_string());
+        }
+        let frame_count = (self.duration * settings.sample_rate).ceil() as usize + 1;
+        let segment_frames = ((settings.segment_duration * settings.sample_rate).round() as usize).clamp(1, u16::MAX as usize);
+        let segment_count = frame_count.saturating_sub(2) / segment_frames + 1;
+        if segment_count > u16::MAX as usize || frame_count > u32::MAX as usize {
+            return Err("Animation is too long".to_string());
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&self.duration.to_le_bytes());
+        header.extend_from_slice(&settings.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(frame_count as u32).to_le_bytes());
+        header.extend_from_slice(&self.joint_count.to_le_bytes());
+        header.extend_from_slice(&(self.channels.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(segment_frames as u16).to_le_bytes());
+        header.extend_from_slice(&(segment_count as u16).to_le_bytes());
+
+        // The resampled frames and range of each channel which moves
+        let mut animated = Vec::new();
+        for channel in &self.channels {
+            let mut frames = (0..frame_count)
+                .map(|x| channel.sample((x as f32 / settings.sample_rate).min(self.duration)))
+                .collect::<Vec<_>>();
+            if channel.property == ChannelProperty::Rotation {
+                // Neighbouring keys in the same hemisphere, so they interpolate the shorter way around
+                for i in 0..frames.len() {
+                    normalize(&mut frames[i]);
+                    if i > 0 && frames[i].iter().zip(&frames[i - 1]).map(|(a, b)| a * b).sum::<f32>() < 0.0 {
+                        frames[i].iter_mut().for_each(|x| *x = -*x);
+                    }
+                }
+            }
+            let tolerance = settings.tolerance(channel.property);
+            let constant = frames.iter().all(|&x| channel.property.error(x, frames[0]) <= tolerance);
+            header.extend_from_slice(&channel.joint.to_le_bytes());
+            header.push(channel.property.to_byte());
+            header.push(constant as u8);
+            if constant {
+                for x in &frames[0] {
+                    header.extend_from_slice(&x.to_le_bytes());
+                }
+                continue;
+            }
+            let range = if channel.property == ChannelProperty::Rotation {
+                None
+            } else {
+                let mut min = [f32::INFINITY; 3];
+                let mut max = [f32::NEG_INFINITY; 3];
+                for frame in &frames {
+                    for i in 0..3 {
+                        min[i] = min[i].min(frame[i]);
+                        max[i] = max[i].max(frame[i]);
+                    }
+                }
+                let extent = [max[0] - min[0], max[1] - min[1], max[2] - min[2]];
+                for x in min.iter().chain(&extent) {
+                    header.extend_from_slice(&x.to_le_bytes());
+                }
+                Some((min, extent))
+            };
+            animated.push((channel.property, tolerance, range, frames));
+        }
+
+        let mut segments = Vec::new();
+        let mut offsets = Vec::with_capacity(segment_count + 1);
+        for segment in 0..segment_count {
+            offsets.push(segments.len());
+            let start = segment * segment_frames;
+            let end = (start + segment_frames).min(frame_count - 1);
+            for (property, tolerance, range, frames) in &animated {
+                let keys = reduce_keys(*property, *tolerance, &frames[start..=end]);
+                segments.extend_from_slice(&(keys.len() as u16).to_le_bytes());
+                for &key in &keys {
+                    segments.extend_from_slice(&(key as u16).to_le_bytes());
+                }
+                for &key in &keys {
+                    let value = frames[start + key];
+                    match range {
+                        None => segments.extend_from_slice(&quantize_rotation(value)),
+                        Some((min, extent)) => {
+                            for i in 0..3 {
+                                let x = if extent[i] > 0.0 { (value[i] - min[i]) / extent[i] } else { 0.0 };
+                                segments.extend_from_slice(&((x.clamp(0.0, 1.0) * VALUE_STEPS).round() as u16).to_le_bytes());
+                            }
+                        },
+                    }
+                }
+            }
+        }
+        offsets.push(segments.len());
+
+        let table_start = header.len();
+        let data_start = table_start + offsets.len() * 4;
+        let mut out = header;
+        for offset in offsets {
+            out.extend_from_slice(&((data_start + offset) as u32).to_le_bytes());
+        }
+        out.extend_from_slice(&segments);
+        Ok(out)
+    }
+}
+
+///
+/// How much an `AnimationClip` is compressed.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionSettings {
+    /// The frames per second the clip is resampled at.
+    pub sample_rate: f32,
+    /// The seconds of animation in each separately decoded segment.
+    pub segment_duration: f32,
+    /// The most a rotation may be off by, in radians.
+    pub rotation_tolerance: f32,
+    /// The most a translation may be off by, in the clip's units.
+    pub translation_tolerance: f32,
+    /// The most a scale may be off by.
+    pub scale_tolerance: f32,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 30.0,
+            segment_duration: 1.0,
+            rotation_tolerance: 0.002,
+            translation_tolerance: 0.001,
+            scale_tolerance: 0.001,
+        }
+    }
+}
+
+impl CompressionSettings {
+    fn tolerance(&self, property: ChannelProperty) -> f32 {
+        match property {
+            ChannelProperty::Translation => self.translation_tolerance,
+            ChannelProperty::Rotation => self.rotation_tolerance,
+            ChannelProperty::Scale => self.scale_tolerance,
+        }
+    }
+}
+
+// The frames to keep so every other frame is interpolated from the kept
+// ones to within `tolerance`, always keeping the first and last. Each key
+// is stretched as far as it can go before the next is placed.
+fn reduce_keys(property: ChannelProperty, tolerance: f32, frames: &[[f32; 4]]) -> Vec<usize> {
+    let mut keys = vec![0];
+    let mut start = 0;
+    while start + 1 < frames.len() {
+        let mut end = start + 1;
+        while end + 1 < frames.len() {
+            let candidate = end + 1;
+            let fits = (start + 1..candidate).all(|x| {
+                let t = (x - start) as f32 / (candidate - start) as f32;
+                property.error(property.interpolate(frames[start], frames[candidate], t), frames[x]) <= tolerance
+            });
+            if !fits {
+                break;
+            }
+            end = candidate;
+        }
+        keys.push(end);
+        start = end;
+    }
+    keys
+}
+
+// The three smallest components in 15 bits each, and which is largest in 2 bits
+fn quantize_rotation(mut q: [f32; 4]) -> [u8; VALUE_SIZE] {
+    normalize(&mut q);
+    let largest = (0..4).fold(0, |largest, i| if q[i].abs() > q[largest].abs() { i } else { largest });
+    // `q` and `-q` are the same rotation, so the largest is made positive and left out
+    let sign = if q[largest] < 0.0 { -1.0 } else { 1.0 };
+    let mut bits = largest as u64;
+    for i in (0..4).filter(|&x| x != largest) {
+        let x = (q[i] * sign / SMALLEST_THREE_RANGE).clamp(-1.0, 1.0) * 0.5 + 0.5;
+        bits = bits << 15 | (x * ROTATION_STEPS).round() as u64;
+    }
+    let bytes = bits.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]]
+}
+
+fn dequantize_rotation(bytes: &[u8]) -> [f32; 4] {
+    let bits = u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], 0, 0]);
+    let largest = (bits >> 45) as usize & 3;
+    let mut q = [0.0; 4];
+    let mut shift = 30;
+    for i in (0..4).filter(|&x| x != largest) {
+        let x = ((bits >> shift) & 0x7fff) as f32 / ROTATION_STEPS;
+        q[i] = (x * 2.0 - 1.0) * SMALLEST_THREE_RANGE;
+        shift -= 15;
+    }
+    q[largest] = (1.0 - q.iter().map(|x| x * x).sum::<f32>()).max(0.0).sqrt();
+    q
+}
+
+#[derive(Debug, Clone)]
+enum ChannelData {
+    Constant([f32; 4]),
+    // The minimum and extent of translations and scales, rotations have no range
+    Animated(Option<([f32; 3], [f32; 3])>),
+}
+
+#[derive(Debug, Clone)]
+struct ChannelHeader {
+    joint: u16,
+    property: ChannelProperty,
+    data: ChannelData,
+}
+
+///
+/// An `AnimationClip` compressed by `AnimationClip::compress`, read
+/// from `D`, which can be a `Vec<u8>` or anything else holding its
+/// bytes, like `AssetPack`.
+///
+/// Only the header is read up front. The keys are split into segments
+/// of time, decoded with `decode_segment` when they're needed, so a
+/// long clip in a memory mapped pack only takes up memory for the part
+/// being played.
+///
+pub struct CompressedClip<D: AsRef<[u8]>> {
+    data: D,
+    duration: f32,
+    sample_rate: f32,
+    frame_count: usize,
+    joint_count: u16,
+    segment_frames: usize,
+    channels: Vec<ChannelHeader>,
+    segments: Vec<Range<usize>>,
+}
+
+///
+/// The keys of a segment of a `CompressedClip`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipSegment {
+    index: usize,
+    // The frames (relative to the segment's first) and values of the keys of each animated channel
+    channels: Vec<(Vec<u16>, Vec<[f32; 4]>)>,
+}
+
+impl ClipSegment {
+    ///
+    /// Which segment of its clip this is.
+    ///
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    ///
+    /// Roughly how many bytes the decoded keys take up.
+    ///
+    pub fn memory_size(&self) -> usize {
+        self.channels.iter().map(|x| x.0.len() * (2 + 16)).sum()
+    }
+}
+
+impl<D: AsRef<[u8]>> CompressedClip<D> {
+    ///
+    /// Reads the header of a compressed clip.
+    ///
+    pub fn parse(data: D) -> Result<Self, String> {
+        let (duration, sample_rate, frame_count, joint_count, segment_frames, channels, segments) = {
+            let bytes = data.as_ref();
+            let mut reader = Reader::new(bytes);
+            let duration = reader.f32()?;
+            let sample_rate = reader.f32()?;
+            let frame_count = reader.u32()? as usize;
+            let joint_count = reader.u16()?;
+            let channel_count = reader.u16()? as usize;
+            let segment_frames = reader.u16()? as usize;
+            let segment_count = reader.u16()? as usize;
+            if sample_rate.is_nan() || sample_rate <= 0.0 || frame_count == 0 || segment_frames == 0 || segment_count == 0 {
+                return Err("Invalid animation header".to_string());
+            }
+            let mut channels = Vec::with_capacity(channel_count);
+            for _ in 0..channel_count {
+                let joint = reader.u16()?;
+                let property = ChannelProperty::from_byte(reader.u8()?)?;
+                let data = if reader.u8()? != 0 {
+                    ChannelData::Constant([reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?])
+                } else if property == ChannelProperty::Rotation {
+                    ChannelData::Animated(None)
+                } else {
+                    let min = [reader.f32()?, reader.f32()?, reader.f32()?];
+                    let extent = [reader.f32()?, reader.f32()?, reader.f32()?];
+                    ChannelData::Animated(Some((min, extent)))
+                };
+                channels.push(ChannelHeader { joint, property, data });
+            }
+            let offsets = (0..=segment_count).map(|_| reader.u32().map(|x| x as usize)).collect::<Result<Vec<_>, _>>()?;
+            let segments = offsets.windows(2).map(|x| x[0]..x[1]).collect::<Vec<_>>();
+            if segments.iter().any(|x| x.start > x.end || x.end > bytes.len()) {
+                return Err("Animation segment is outside of the data".to_string());
+            }
+            (duration, sample_rate, frame_count, joint_count, segment_frames, channels, segments)
+        };
+        Ok(Self { data, duration, sample_rate, frame_count, joint_count, segment_frames, channels, segments })
+    }
+
+    ///
+    /// The length of the clip in seconds.
+    ///
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    pub fn joint_count(&self) -> u16 {
+        self.joint_count
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    ///
+    /// The size of the compressed clip in bytes.
+    ///
+    pub fn compressed_size(&self) -> usize {
+        self.data.as_ref().len()
+    }
+
+    // `time` in frames, clamped to the clip
+    fn frame(&self, time: f32) -> f32 {
+        (time.clamp(0.0, self.duration) * self.sample_rate).min((self.frame_count - 1) as f32)
+    }
+
+    ///
+    /// The segment holding the keys for `time`, which is clamped to the clip.
+    ///
+    pub fn segment_at(&self, time: f32) -> usize {
+        ((self.frame(time) as usize) / self.segment_frames).min(self.segments.len() - 1)
+    }
+
+    ///
+    /// Decodes the keys of the segment `index`.
+    ///
+    pub fn decode_segment(&self, index: usize) -> Result<ClipSegment, String> {
+        let range = self.segments.get(index).ok_or_else(|| format!("Animation has no segment {}", index))?;
+        let mut reader = Reader::new(&self.data.as_ref()[range.clone()]);
+        let mut channels = Vec::new();
+        for channel in &self.channels {
+            let range = match channel.data {
+                ChannelData::Constant(_) => continue,
+                ChannelData::Animated(range) => range,
+            };
+            let count = reader.u16()? as usize;
+            let frames = (0..count).map(|_| reader.u16()).collect::<Result<Vec<_>, _>>()?;
+            let values = reader.bytes(count * VALUE_SIZE)?
+                .chunks(VALUE_SIZE)
+                .map(|x| match range {
+                    None => dequantize_rotation(x),
+                    Some((min, extent)) => {
+                        let mut value = [0.0; 4];
+                        for i in 0..3 {
+                            let step = u16::from_le_bytes([x[i * 2], x[i * 2 + 1]]) as f32 / VALUE_STEPS;
+                            value[i] = min[i] + step * extent[i];
+                        }
+                        value
+                    },
+                })
+                .collect();
+            channels.push((frames, values));
+        }
+        Ok(ClipSegment { index, channels })
+    }
+
+    ///
+    /// Sets the animated properties of `poses` (one per joint) to their
+    /// values at `time`, which has to be within `segment`, as given by
+    /// `segment_at`. Otherwise the nearest end of the segment is used.
+    ///
+    pub fn sample_segment(&self, segment: &ClipSegment, time: f32, poses: &mut [JointPose]) {
+        let frame = self.frame(time) - (segment.index * self.segment_frames) as f32;
+        let mut animated = segment.channels.iter();
+        for channel in &self.channels {
+            let value = match channel.data {
+                ChannelData::Constant(x) => x,
+                ChannelData::Animated(_) => match animated.next() {
+                    Some((frames, values)) if !frames.is_empty() => {
+                        let next = frames.partition_point(|&x| x as f32 <= frame);
+                        if next == 0 {
+                            values[0]
+                        } else if next == frames.len() {
+                            values[frames.len() - 1]
+                        } else {
+                            let (start, end) = (frames[next - 1] as f32, frames[next] as f32);
+                            channel.property.interpolate(values[next - 1], values[next], (frame - start) / (end - start))
+                        }
+                    },
+                    _ => continue,
+                },
+            };
+            if let Some(pose) = poses.get_mut(channel.joint as usize) {
+                pose.set(channel.property, value);
+            }
+        }
+    }
+
+    ///
+    /// Decodes the segment for `time` and samples it, see `sample_segment`.
+    /// Keep the segment around with `decode_segment` when sampling often.
+    ///
+    pub fn sample(&self, time: f32, poses: &mut [JointPose]) -> Result<(), String> {
+        let segment = self.decode_segment(self.segment_at(time))?;
+        self.sample_segment(&segment, time, poses);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Joint 0 spins around z for three seconds while rising, joint 1 stays put
+    fn spinning_clip() -> AnimationClip {
+        let times = (0..=90).map(|x| x as f32 / 30.0).collect::<Vec<_>>();
+        AnimationClip {
+            duration: 3.0,
+            joint_count: 2,
+            channels: vec![
+                AnimationChannel {
+                    joint: 0,
+                    property: ChannelProperty::Rotation,
+                    values: times.iter().map(|&t| {
+                        let half = t * std::f32::consts::PI * 0.5;
+                        [0.0, 0.0, half.sin(), half.cos()]
+                    }).collect(),
+                    times: times.clone(),
+                },
+                AnimationChannel {
+                    joint: 0,
+                    property: ChannelProperty::Translation,
+                    values: times.iter().map(|&t| [0.0, t * t, 0.0, 0.0]).collect(),
+                    times: times.clone(),
+                },
+                AnimationChannel {
+                    joint: 1,
+                    property: ChannelProperty::Scale,
+                    values: vec![[2.0, 2.0, 2.0, 0.0]; times.len()],
+                    times,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn rotation_quantization() {
+        let mut q = [0.3, -0.5, 0.1, -0.8];
+        normalize(&mut q);
+        let out = dequantize_rotation(&quantize_rotation(q));
+        assert!(ChannelProperty::Rotation.error(q, out) < 1e-3);
+    }
+
+    #[test]
+    fn compressed_within_tolerance() {
+        let clip = spinning_clip();
+        let settings = CompressionSettings::default();
+        let data = clip.compress(&settings).unwrap();
+        let compressed = CompressedClip::parse(&data[..]).unwrap();
+        assert_eq!(compressed.segment_count(), 3);
+        assert!(compressed.compressed_size() < clip.channels.len() * 91 * 20 / 2);
+        for step in 0..=120 {
+            let time = step as f32 / 40.0;
+            let (mut expected, mut actual) = ([JointPose::default(); 2], [JointPose::default(); 2]);
+            clip.sample(time, &mut expected);
+            compressed.sample(time, &mut actual).unwrap();
+            let rotation = ChannelProperty::Rotation.error(expected[0].rotation, actual[0].rotation);
+            assert!(rotation < settings.rotation_tolerance * 2.0, "{} off by {}", time, rotation);
+            assert!((expected[0].translation[1] - actual[0].translation[1]).abs() < settings.translation_tolerance * 2.0);
+            assert_eq!(actual[1].scale, [2.0; 3]);
+        }
+    }
+
+    #[test]
+    fn segments_decode_alone() {
+        let clip = spinning_clip();
+        let data = clip.compress(&CompressionSettings::default()).unwrap();
+        let compressed = CompressedClip::parse(data).unwrap();
+        // The constant scale channel has no keys in any segment
+        let segment = compressed.decode_segment(1).unwrap();
+        assert_eq!(segment.channels.len(), 2);
+        assert_eq!(compressed.segment_at(1.5), 1);
+        // Both segments either side of a boundary agree on it
+        let (mut before, mut after) = ([JointPose::default(); 2], [JointPose::default(); 2]);
+        compressed.sample_segment(&compressed.decode_segment(0).unwrap(), 1.0, &mut before);
+        compressed.sample_segment(&segment, 1.0, &mut after);
+        assert_eq!(before, after);
+        assert!(compressed.decode_segment(3).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::Reader;
+
+const HAS_NORMALS: u32 = 1;
+const HAS_UVS: u32 = 2;
+const HAS_TANGENTS: u32 = 4;
+
+///
+/// Triangles with a position, and optionally a normal, texture
+/// coordinates and a tangent, for each vertex.
+///
+/// Stored as a 16 byte header (vertex count, index count, flags and
+/// a reserved word) followed by each attribute as its own array, so
+/// each can be uploaded to a vertex buffer as it is.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CookedMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// The direction the texture's `u` increases along, with the
+    /// handedness of the bitangent in `w`, see `generate_tangents`.
+    pub tangents: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl CookedMesh {
+    pub fn encode(&self) -> Vec<u8> {
+        let count = self.positions.len();
+        assert!(self.normals.is_empty() || self.normals.len() == count, "Mesh has a different number of normals and positions");
+        assert!(self.uvs.is_empty() || self.uvs.len() == count, "Mesh has a different number of uvs and positions");
+        assert!(self.tangents.is_empty() || self.tangents.len() == count, "Mesh has a different number of tangents and positions");
+        let mut flags = 0;
+        if !self.normals.is_empty() {
+            flags |= HAS_NORMALS;
+        }
+        if !self.uvs.is_empty() {
+            flags |= HAS_UVS;
+        }
+        if !self.tangents.is_empty() {
+            flags |= HAS_TANGENTS;
+        }
+        let mut out = Vec::with_capacity(16 + count * 48 + self.indices.len() * 4);
+        out.extend_from_slice(&(count as u32).to_le_bytes());
+        out.extend_from_slice(&(self.indices.len() as u32).to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        let floats = self.positions.iter().flat_map(|x| x.iter())
+            .chain(self.normals.iter().flat_map(|x| x.iter()))
+            .chain(self.uvs.iter().flat_map(|x| x.iter()))
+            .chain(self.tangents.iter().flat_map(|x| x.iter()));
+        for x in floats {
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in &self.indices {
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(data);
+        let count = reader.u32()? as usize;
+        let index_count = reader.u32()? as usize;
+        let flags = reader.u32()?;
+        reader.u32()?;
+
+        fn floats<'a>(reader: &mut Reader<'a>, len: usize) -> Result<impl Iterator<Item = f32> + 'a, String> {
+            let bytes = reader.bytes(len.checked_mul(4).ok_or("Mesh is too large")?)?;
+            Ok(bytes.chunks(4).map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]])))
+        }
+        fn vec3s(reader: &mut Reader, count: usize) -> Result<Vec<[f32; 3]>, String> {
+            let mut floats = floats(reader, count * 3)?;
+            Ok((0..count).map(|_| [floats.next().unwrap(), floats.next().unwrap(), floats.next().unwrap()]).collect())
+        }
+
+        let positions = vec3s(&mut reader, count)?;
+        let normals = if flags & HAS_NORMALS != 0 { vec3s(&mut reader, count)? } else { Vec::new() };
+        let uvs = if flags & HAS_UVS != 0 {
+            let mut floats = floats(&mut reader, count * 2)?;
+            (0..count).map(|_| [floats.next().unwrap(), floats.next().unwrap()]).collect()
+        } else {
+            Vec::new()
+        };
+        let tangents = if flags & HAS_TANGENTS != 0 {
+            let mut floats = floats(&mut reader, count * 4)?;
+            (0..count).map(|_| [floats.next().unwrap(), floats.next().unwrap(), floats.next().unwrap(), floats.next().unwrap()]).collect()
+        } else {
+            Vec::new()
+        };
+        let indices = (0..index_count).map(|_| reader.u32()).collect::<Result<Vec<_>, _>>()?;
+        if let Some(&x) = indices.iter().find(|&&x| x as usize >= count) {
+            return Err(format!("Mesh index {} is out of bounds of its {} vertices", x, count));
+        }
+        Ok(Self { positions, normals, uvs, tangents, indices })
+    }
+}
+
+///
+/// How the pixels of a `CookedImage` are stored.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Four bytes per pixel, rows from the top down.
+    Rgba8,
+}
+
+impl ImageFormat {
+    fn to_u32(self) -> u32 {
+        match self {
+            ImageFormat::Rgba8 => 0,
+        }
+    }
+
+    fn from_u32(x: u32) -> Result<Self, String> {
+        match x {
+            0 => Ok(ImageFormat::Rgba8),
+            x => Err(format!("Unknown image format {}", x)),
+        }
+    }
+
+    ///
+    /// The number of bytes an image of this size takes.
+    ///
+    pub fn size(self, width: u32, height: u32) -> usize {
+        match self {
+            ImageFormat::Rgba8 => width as usize * height as usize * 4,
+        }
+    }
+}
+
+///
+/// An image which is ready to upload to a texture, stored as a 16 byte
+/// header (width, height, format and a reserved word) and its pixels.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CookedImage<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub format: ImageFormat,
+    pub pixels: &'a [u8],
+}
+
+impl<'a> CookedImage<'a> {
+    pub fn new(width: u32, height: u32, format: ImageFormat, pixels: &'a [u8]) -> Result<Self, String> {
+        if pixels.len() != format.size(width, height) {
+            return Err(format!("{} bytes of pixels don't make a {}x{} image", pixels.len(), width, height));
+        }
+        Ok(Self { width, height, format, pixels })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + self.pixels.len());
+        out.extend_from_slice(&self.width.to_le_bytes());
+        out.extend_from_slice(&self.height.to_le_bytes());
+        out.extend_from_slice(&self.format.to_u32().to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(self.pixels);
+        out
+    }
+
+    pub fn decode(data: &'a [u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(data);
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let format = ImageFormat::from_u32(reader.u32()?)?;
+        reader.u32()?;
+        let pixels = reader.bytes(format.size(width, height))?;
+        Ok(Self { width, height, format, pixels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AssetPack, PackWriter};
+
+    #[test]
+    fn mesh_round_trip() {
+        let mesh = CookedMesh {
+            positions: vec![[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]],
+            normals: vec![[0., 0., 1.]; 3],
+            uvs: Vec::new(),
+            tangents: Vec::new(),
+            indices: vec![0, 1, 2],
+        };
+        let mut writer = PackWriter::new();
+        writer.add_mesh("tri", &mesh);
+        let pack = AssetPack::parse(writer.finish()).unwrap();
+        assert_eq!(pack.mesh("tri").unwrap(), mesh);
+        assert!(pack.image("tri").is_err());
+
+        let mut tangents = mesh.clone();
+        tangents.uvs = vec![[0., 0.], [1., 0.], [0., 1.]];
+        tangents.tangents = vec![[1., 0., 0., -1.]; 3];
+        assert_eq!(CookedMesh::decode(&tangents.encode()).unwrap(), tangents);
+
+        let mut bad = mesh.clone();
+        bad.indices.push(3);
+        assert!(CookedMesh::decode(&bad.encode()).is_err());
+    }
+
+    #[test]
+    fn image_round_trip() {
+        let pixels = (0..2 * 3 * 4).map(|x| x as u8).collect::<Vec<_>>();
+        let image = CookedImage::new(2, 3, ImageFormat::Rgba8, &pixels).unwrap();
+        let encoded = image.encode();
+        assert_eq!(CookedImage::decode(&encoded).unwrap(), image);
+        assert!(CookedImage::new(2, 2, ImageFormat::Rgba8, &pixels).is_err());
+    }
+}
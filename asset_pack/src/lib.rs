@@ -0,0 +1,375 @@
+//!
+//! A container for assets which have been cooked ahead of time into
+//! formats that load without any parsing or decoding.
+//!
+//! A pack starts with a header and an index of named entries, followed
+//! by each entry's data, aligned to `ALIGNMENT` bytes so it can be read
+//! in place and uploaded to the GPU directly. Everything is little endian.
+//!
+//! Packs can be memory mapped with `AssetPack::open`, in which case
+//! entries are read straight out of the mapping without being copied.
+//!
+
+mod animation;
+mod cooked;
+mod mapped;
+mod obj;
+mod optimize;
+mod quantize;
+mod tangents;
+
+pub use animation::{AnimationChannel, AnimationClip, ChannelProperty, ClipSegment, CompressedClip, CompressionSettings, JointPose};
+pub use cooked::{CookedImage, CookedMesh, ImageFormat};
+pub use mapped::PackData;
+pub use obj::{parse_mtl, parse_obj, parse_obj_groups, ObjGroup, ObjMaterial, ObjModel};
+pub use quantize::{f16_to_f32, f32_to_f16, f32_to_snorm8, QuantizedMesh};
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The bytes every pack starts with.
+pub const MAGIC: [u8; 4] = *b"APAK";
+/// The version of the format written by `PackWriter`.
+pub const VERSION: u32 = 1;
+/// The alignment of each entry's data from the start of the pack.
+pub const ALIGNMENT: usize = 16;
+
+const HEADER_SIZE: usize = 16;
+
+///
+/// What an entry holds, and so how its data should be read.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// Bytes copied from the source file as they were.
+    Raw,
+    /// A `CookedMesh`.
+    Mesh,
+    /// A `CookedImage`.
+    Image,
+    /// An `AnimationClip` compressed for `CompressedClip`.
+    Animation,
+}
+
+impl EntryKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            EntryKind::Raw => 0,
+            EntryKind::Mesh => 1,
+            EntryKind::Image => 2,
+            EntryKind::Animation => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, String> {
+        match byte {
+            0 => Ok(EntryKind::Raw),
+            1 => Ok(EntryKind::Mesh),
+            2 => Ok(EntryKind::Image),
+            3 => Ok(EntryKind::Animation),
+            x => Err(format!("Unknown entry kind {}", x)),
+        }
+    }
+}
+
+///
+/// An entry of a pack.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry<'a> {
+    pub kind: EntryKind,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    kind: EntryKind,
+    offset: usize,
+    len: usize,
+}
+
+///
+/// Builds a pack from named entries.
+///
+pub struct PackWriter {
+    entries: Vec<(String, EntryKind, Vec<u8>)>,
+}
+
+impl PackWriter {
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    ///
+    /// Adds an entry, replacing any entry with the same name.
+    ///
+    pub fn add(&mut self, name: &str, kind: EntryKind, data: Vec<u8>) {
+        self.entries.retain(|x| x.0 != name);
+        self.entries.push((name.to_string(), kind, data));
+    }
+
+    pub fn add_mesh(&mut self, name: &str, mesh: &CookedMesh) {
+        self.add(name, EntryKind::Mesh, mesh.encode());
+    }
+
+    pub fn add_image(&mut self, name: &str, image: &CookedImage) {
+        self.add(name, EntryKind::Image, image.encode());
+    }
+
+    ///
+    /// Compresses `clip` with `settings` and adds it.
+    ///
+    pub fn add_animation(&mut self, name: &str, clip: &AnimationClip, settings: &CompressionSettings) -> Result<(), String> {
+        let data = clip.compress(settings)?;
+        self.add(name, EntryKind::Animation, data);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    ///
+    /// Writes the pack out.
+    ///
+    pub fn finish(&self) -> Vec<u8> {
+        let index_size = self.entries
+            .iter()
+            .map(|(name, ..)| 1 + 2 + name.len() + 8 + 8)
+            .sum::<usize>();
+        let mut offset = align(HEADER_SIZE + index_size);
+
+        let mut out = Vec::with_capacity(offset);
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(index_size as u32).to_le_bytes());
+        for (name, kind, data) in &self.entries {
+            out.push(kind.to_byte());
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            offset = align(offset + data.len());
+        }
+        for (_, _, data) in &self.entries {
+            out.resize(align(out.len()), 0);
+            out.extend_from_slice(data);
+        }
+        out
+    }
+}
+
+impl Default for PackWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn align(x: usize) -> usize {
+    x.div_ceil(ALIGNMENT) * ALIGNMENT
+}
+
+///
+/// Reads little endian integers from the front of a slice.
+///
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("Unexpected end of data".to_string());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, String> {
+        let mut x = [0; 2];
+        x.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(x))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, String> {
+        let mut x = [0; 4];
+        x.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(x))
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        let mut x = [0; 8];
+        x.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(x))
+    }
+}
+
+///
+/// A pack read from `D`, which can be a `Vec<u8>` or anything else
+/// holding the whole pack's bytes. Entries are borrowed from it
+/// without being copied.
+///
+pub struct AssetPack<D: AsRef<[u8]>> {
+    data: D,
+    index: HashMap<String, IndexEntry>,
+}
+
+impl<D: AsRef<[u8]>> AssetPack<D> {
+    ///
+    /// Validates the header and index of a pack.
+    ///
+    pub fn parse(data: D) -> Result<Self, String> {
+        let index = {
+            let bytes = data.as_ref();
+            let mut reader = Reader::new(bytes);
+            if reader.bytes(4).map_err(|_| "Not an asset pack".to_string())? != MAGIC {
+                return Err("Not an asset pack".to_string());
+            }
+            let version = reader.u32()?;
+            if version != VERSION {
+                return Err(format!("Unsupported asset pack version {}", version));
+            }
+            let count = reader.u32()? as usize;
+            let _index_size = reader.u32()?;
+            let mut index = HashMap::with_capacity(count);
+            for _ in 0..count {
+                let kind = EntryKind::from_byte(reader.u8()?)?;
+                let name_len = reader.u16()? as usize;
+                let name = std::str::from_utf8(reader.bytes(name_len)?)
+                    .map_err(|_| "Entry name isn't valid UTF-8".to_string())?
+                    .to_string();
+                let offset = reader.u64()? as usize;
+                let len = reader.u64()? as usize;
+                if !offset.is_multiple_of(ALIGNMENT) || offset.checked_add(len).is_none_or(|end| end > bytes.len()) {
+                    return Err(format!("Entry {} is outside of the pack", name));
+                }
+                index.insert(name, IndexEntry { kind, offset, len });
+            }
+            index
+        };
+        Ok(Self { data, index })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Entry<'_>> {
+        let entry = self.index.get(name)?;
+        Some(Entry {
+            kind: entry.kind,
+            data: &self.data.as_ref()[entry.offset..entry.offset + entry.len],
+        })
+    }
+
+    ///
+    /// Gets where the data of `name` is within `data()`, for holding
+    /// on to an entry without borrowing the pack.
+    ///
+    pub fn range(&self, name: &str) -> Option<Range<usize>> {
+        self.index.get(name).map(|x| x.offset..x.offset + x.len)
+    }
+
+    ///
+    /// Gets the bytes of the whole pack.
+    ///
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(|x| x.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    ///
+    /// Decodes the mesh called `name`.
+    ///
+    pub fn mesh(&self, name: &str) -> Result<CookedMesh, String> {
+        match self.get(name) {
+            Some(Entry { kind: EntryKind::Mesh, data }) => CookedMesh::decode(data),
+            Some(_) => Err(format!("{} isn't a mesh", name)),
+            None => Err(format!("{} isn't in the pack", name)),
+        }
+    }
+
+    ///
+    /// Reads the image called `name`, borrowing its pixels.
+    ///
+    pub fn image(&self, name: &str) -> Result<CookedImage<'_>, String> {
+        match self.get(name) {
+            Some(Entry { kind: EntryKind::Image, data }) => CookedImage::decode(data),
+            Some(_) => Err(format!("{} isn't an image", name)),
+            None => Err(format!("{} isn't in the pack", name)),
+        }
+    }
+
+    ///
+    /// Reads the header of the animation called `name`, borrowing its
+    /// keys to be decoded as they're needed.
+    ///
+    pub fn animation(&self, name: &str) -> Result<CompressedClip<&[u8]>, String> {
+        match self.get(name) {
+            Some(Entry { kind: EntryKind::Animation, data }) => CompressedClip::parse(data),
+            Some(_) => Err(format!("{} isn't an animation", name)),
+            None => Err(format!("{} isn't in the pack", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = PackWriter::new();
+        writer.add("a.txt", EntryKind::Raw, b"hello".to_vec());
+        writer.add("b.bin", EntryKind::Raw, vec![1; 37]);
+        writer.add("a.txt", EntryKind::Raw, b"replaced".to_vec());
+        let pack = AssetPack::parse(writer.finish()).unwrap();
+        assert_eq!(pack.len(), 2);
+        assert_eq!(pack.get("a.txt").unwrap().data, b"replaced");
+        assert_eq!(pack.get("b.bin").unwrap().data, &[1; 37][..]);
+        assert!(pack.get("c").is_none());
+        let base = pack.data.as_ptr() as usize;
+        for name in pack.names() {
+            assert_eq!((pack.get(name).unwrap().data.as_ptr() as usize - base) % ALIGNMENT, 0);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(AssetPack::parse(b"nope".to_vec()).is_err());
+        let mut writer = PackWriter::new();
+        writer.add("a", EntryKind::Raw, vec![0; 100]);
+        let mut bytes = writer.finish();
+        bytes.truncate(bytes.len() - 1);
+        assert!(AssetPack::parse(bytes).is_err());
+    }
+}
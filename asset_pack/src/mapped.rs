@@ -0,0 +1,69 @@
+use crate::AssetPack;
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+///
+/// The bytes of a pack, either mapped from a file or read into memory.
+///
+/// Mapped packs are paged in by the OS as entries are used, so opening
+/// one costs next to nothing however large it is, and the memory can
+/// be reclaimed under pressure.
+///
+#[derive(Debug)]
+pub enum PackData {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl AsRef<[u8]> for PackData {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            PackData::Mapped(x) => x,
+            PackData::Owned(x) => x,
+        }
+    }
+}
+
+impl From<Vec<u8>> for PackData {
+    fn from(x: Vec<u8>) -> Self {
+        PackData::Owned(x)
+    }
+}
+
+impl AssetPack<PackData> {
+    ///
+    /// Maps the pack at `path` into memory and validates it.
+    ///
+    /// Mappings are page aligned, so each entry's data stays aligned
+    /// to `ALIGNMENT`. The file mustn't be changed while it's mapped.
+    ///
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        // Safety: packs are only written by the cook tool, never while the app has them open
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| format!("Could not map {}: {}", path.display(), e))?;
+        Self::parse(PackData::Mapped(map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AssetPack, EntryKind, PackWriter, ALIGNMENT};
+
+    #[test]
+    fn open_mapped() {
+        let mut writer = PackWriter::new();
+        writer.add("a", EntryKind::Raw, vec![7; 33]);
+        writer.add("b", EntryKind::Raw, vec![9; 5]);
+        let path = std::env::temp_dir().join(format!("asset_pack_test_{}.pak", std::process::id()));
+        std::fs::write(&path, writer.finish()).unwrap();
+        let pack = AssetPack::open(&path).unwrap();
+        let b = pack.get("b").unwrap().data;
+        assert_eq!(b, &[9; 5]);
+        assert_eq!(b.as_ptr() as usize % ALIGNMENT, 0);
+        assert_eq!(pack.range("a").unwrap().len(), 33);
+        drop(pack);
+        std::fs::remove_file(path).unwrap();
+        assert!(AssetPack::open(std::path::Path::new("/nonexistent.pak")).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::CookedMesh;
+use std::collections::HashMap;
+
+fn parse_floats(parts: &mut std::str::SplitWhitespace, count: usize, line: usize) -> Result<Vec<f32>, String> {
+    (0..count)
+        .map(|_| parts.next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| format!("Line {}: Expected {} numbers", line, count)))
+        .collect()
+}
+
+// Resolves a 1-based (or negative, relative to the end) OBJ index
+fn resolve(index: &str, len: usize, line: usize) -> Result<usize, String> {
+    let index: i64 = index.parse().map_err(|_| format!("Line {}: Invalid index {}", line, index))?;
+    let resolved = if index < 0 { len as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved as usize >= len {
+        return Err(format!("Line {}: Index {} is out of bounds", line, index));
+    }
+    Ok(resolved as usize)
+}
+
+// The vertex attributes of an OBJ file, and the corners of its triangles
+// as (position, uv, normal) with the material each triangle uses
+#[derive(Default)]
+struct Corners {
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    corners: Vec<(usize, Option<usize>, Option<usize>)>,
+    materials: Vec<usize>,
+    material_names: Vec<Option<String>>,
+    libraries: Vec<String>,
+}
+
+fn read_corners(source: &str) -> Result<Corners, String> {
+    let mut obj = Corners {
+        material_names: vec![None],
+        ..Corners::default()
+    };
+    let mut material = 0;
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("v") => {
+                let x = parse_floats(&mut parts, 3, number)?;
+                obj.positions.push([x[0], x[1], x[2]]);
+            },
+            Some("vt") => {
+                let x = parse_floats(&mut parts, 2, number)?;
+                // OBJ's v goes up, textures' go down
+                obj.uvs.push([x[0], 1.0 - x[1]]);
+            },
+            Some("vn") => {
+                let x = parse_floats(&mut parts, 3, number)?;
+                obj.normals.push([x[0], x[1], x[2]]);
+            },
+            Some("f") => {
+                let mut face = Vec::new();
+                for vertex in parts {
+                    let mut indices = vertex.split('/');
+                    let position = resolve(indices.next().unwrap_or(""), obj.positions.len(), number)?;
+                    let uv = match indices.next() {
+                        Some(x) if !x.is_empty() => Some(resolve(x, obj.uvs.len(), number)?),
+                        _ => None,
+                    };
+                    let normal = match indices.next() {
+                        Some(x) if !x.is_empty() => Some(resolve(x, obj.normals.len(), number)?),
+                        _ => None,
+                    };
+                    face.push((position, uv, normal));
+                }
+                if face.len() < 3 {
+                    return Err(format!("Line {}: Faces need at least 3 vertices", number));
+                }
+                for i in 1..face.len() - 1 {
+                    obj.corners.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                    obj.materials.push(material);
+                }
+            },
+            Some("usemtl") => {
+                let name = parts.collect::<Vec<_>>().join(" ");
+                material = match obj.material_names.iter().position(|x| x.as_deref() == Some(&name[..])) {
+                    Some(x) => x,
+                    None => {
+                        obj.material_names.push(Some(name));
+                        obj.material_names.len() - 1
+                    },
+                };
+            },
+            Some("mtllib") => obj.libraries.extend(parts.map(|x| x.to_string())),
+            _ => {},
+        }
+    }
+    Ok(obj)
+}
+
+impl Corners {
+    // Merges the corners of the triangles `triangles` picks into a mesh
+    fn build(&self, triangles: impl Fn(usize) -> bool) -> CookedMesh {
+        let corners = self.corners
+            .chunks(3)
+            .enumerate()
+            .filter(|&(i, _)| triangles(i))
+            .flat_map(|(_, x)| x.iter().cloned())
+            .collect::<Vec<_>>();
+        let keep_uvs = corners.iter().all(|x| x.1.is_some());
+        let keep_normals = corners.iter().all(|x| x.2.is_some());
+        let mut mesh = CookedMesh::default();
+        let mut merged = HashMap::new();
+        for &(position, uv, normal) in &corners {
+            let key = (position, uv.filter(|_| keep_uvs), normal.filter(|_| keep_normals));
+            let index = *merged.entry(key).or_insert_with(|| {
+                mesh.positions.push(self.positions[position]);
+                if let Some(uv) = key.1 {
+                    mesh.uvs.push(self.uvs[uv]);
+                }
+                if let Some(normal) = key.2 {
+                    mesh.normals.push(self.normals[normal]);
+                }
+                mesh.positions.len() as u32 - 1
+            });
+            mesh.indices.push(index);
+        }
+        mesh
+    }
+}
+
+///
+/// Reads the triangles of a Wavefront OBJ file into a mesh.
+///
+/// Polygons are split into fans of triangles, and vertices which share
+/// a position, texture coordinate and normal are merged. Texture
+/// coordinates and normals are only kept if every vertex has them.
+/// Groups, objects and materials are ignored, see `parse_obj_groups`
+/// to keep the materials apart.
+///
+pub fn parse_obj(source: &str) -> Result<CookedMesh, String> {
+    Ok(read_corners(source)?.build(|_| true))
+}
+
+///
+/// The triangles of an OBJ file which use one material.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjGroup {
+    /// The name given to `usemtl`, `None` for faces before any.
+    pub material: Option<String>,
+    pub mesh: CookedMesh,
+}
+
+///
+/// An OBJ file split by material, as read by `parse_obj_groups`.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjModel {
+    /// The paths given to `mtllib`, relative to the OBJ file.
+    pub material_libraries: Vec<String>,
+    /// A group for each material, in the order they're first used.
+    pub groups: Vec<ObjGroup>,
+}
+
+///
+/// Reads an OBJ file like `parse_obj`, with a mesh for each material
+/// so they can be drawn with different textures. Whether texture
+/// coordinates and normals are kept is decided for each mesh.
+///
+pub fn parse_obj_groups(source: &str) -> Result<ObjModel, String> {
+    let obj = read_corners(source)?;
+    let mut used = Vec::new();
+    for &material in &obj.materials {
+        if !used.contains(&material) {
+            used.push(material);
+        }
+    }
+    let groups = used
+        .into_iter()
+        .map(|material| ObjGroup {
+            material: obj.material_names[material].clone(),
+            mesh: obj.build(|i| obj.materials[i] == material),
+        })
+        .collect();
+    Ok(ObjModel {
+        material_libraries: obj.libraries,
+        groups,
+    })
+}
+
+///
+/// A material of an MTL file. Colours are as they're written, which
+/// is usually sRGB, and texture paths are relative to the MTL file.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    /// `Kd`, white when not given.
+    pub diffuse: [f32; 3],
+    /// `d`, or one minus `Tr`.
+    pub opacity: f32,
+    /// `Ke`.
+    pub emissive: [f32; 3],
+    /// `Ns`, the exponent of Phong highlights.
+    pub shininess: Option<f32>,
+    /// `Pr` from the PBR extension.
+    pub roughness: Option<f32>,
+    /// `Pm` from the PBR extension.
+    pub metallic: Option<f32>,
+    /// `map_Kd`.
+    pub diffuse_texture: Option<String>,
+    /// `norm`, or `map_Bump` and `bump` which exporters often use for normal maps.
+    pub normal_texture: Option<String>,
+    /// `map_Ke`.
+    pub emissive_texture: Option<String>,
+}
+
+impl ObjMaterial {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: [1.0; 3],
+            opacity: 1.0,
+            emissive: [0.0; 3],
+            shininess: None,
+            roughness: None,
+            metallic: None,
+            diffuse_texture: None,
+            normal_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+///
+/// Reads the materials of a Wavefront MTL file. Texture options such
+/// as `-bm` are skipped, leaving the path, and unknown statements are
+/// ignored.
+///
+pub fn parse_mtl(source: &str) -> Result<Vec<ObjMaterial>, String> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut parts = line.split_whitespace();
+        let statement = match parts.next() {
+            Some(x) => x,
+            None => continue,
+        };
+        if statement == "newmtl" {
+            materials.push(ObjMaterial::new(&parts.collect::<Vec<_>>().join(" ")));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(x) => x,
+            None if statement.starts_with('#') => continue,
+            None => return Err(format!("Line {}: Expected newmtl before {}", number, statement)),
+        };
+        let texture = |parts: std::str::SplitWhitespace| {
+            // Options each take one or more values, but the path is always last
+            parts.last().map(|x| x.replace('\\', "/")).ok_or_else(|| format!("Line {}: Expected a texture path", number))
+        };
+        match statement {
+            "Kd" => {
+                let x = parse_floats(&mut parts, 3, number)?;
+                material.diffuse = [x[0], x[1], x[2]];
+            },
+            "Ke" => {
+                let x = parse_floats(&mut parts, 3, number)?;
+                material.emissive = [x[0], x[1], x[2]];
+            },
+            "d" => material.opacity = parse_floats(&mut parts, 1, number)?[0],
+            "Tr" => material.opacity = 1.0 - parse_floats(&mut parts, 1, number)?[0],
+            "Ns" => material.shininess = Some(parse_floats(&mut parts, 1, number)?[0]),
+            "Pr" => material.roughness = Some(parse_floats(&mut parts, 1, number)?[0]),
+            "Pm" => material.metallic = Some(parse_floats(&mut parts, 1, number)?[0]),
+            "map_Kd" => material.diffuse_texture = Some(texture(parts)?),
+            "norm" | "map_Bump" | "map_bump" | "bump" => material.normal_texture = Some(texture(parts)?),
+            "map_Ke" => material.emissive_texture = Some(texture(parts)?),
+            _ => {},
+        }
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quad() {
+        let source = "
+            # A quad
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 1
+            vn 0 0 1
+            f 1/1/1 2/1/1 3/2/1 -1/2/1
+        ";
+        let mesh = parse_obj(source).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.normals, vec![[0., 0., 1.]; 4]);
+        assert_eq!(mesh.uvs[2], [1., 0.]);
+    }
+
+    #[test]
+    fn partial_attributes() {
+        let mesh = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2 3").unwrap();
+        assert!(mesh.normals.is_empty());
+        assert!(mesh.uvs.is_empty());
+        assert!(parse_obj("v 0 0 0\nf 1 2 3").is_err());
+    }
+
+    #[test]
+    fn material_groups() {
+        let source = "
+            mtllib scene.mtl
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            vt 0 0
+            f 1 2 3
+            usemtl red
+            f 1/1 2/1 3/1
+            usemtl blue
+            f 3 2 1
+            usemtl red
+            f 2/1 3/1 1/1
+        ";
+        let model = parse_obj_groups(source).unwrap();
+        assert_eq!(model.material_libraries, vec!["scene.mtl"]);
+        let names = model.groups.iter().map(|x| x.material.as_deref()).collect::<Vec<_>>();
+        assert_eq!(names, vec![None, Some("red"), Some("blue")]);
+        assert_eq!(model.groups[1].mesh.indices, vec![0, 1, 2, 1, 2, 0]);
+        assert_eq!(model.groups[1].mesh.uvs.len(), 3);
+        assert!(model.groups[0].mesh.uvs.is_empty());
+        assert_eq!(parse_obj(source).unwrap().indices.len(), 12);
+    }
+
+    #[test]
+    fn materials() {
+        let source = "
+            # Exported
+            newmtl Painted Metal
+            Kd 0.5 0.25 1
+            Tr 0.25
+            Pm 1
+            map_Kd textures\\paint.png
+            map_Bump -bm 0.5 normal.png
+            newmtl plain
+        ";
+        let materials = parse_mtl(source).unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "Painted Metal");
+        assert_eq!(materials[0].diffuse, [0.5, 0.25, 1.0]);
+        assert_eq!(materials[0].opacity, 0.75);
+        assert_eq!(materials[0].metallic, Some(1.0));
+        assert_eq!(materials[0].diffuse_texture.as_deref(), Some("textures/paint.png"));
+        assert_eq!(materials[0].normal_texture.as_deref(), Some("normal.png"));
+        assert_eq!(materials[1], ObjMaterial::new("plain"));
+        assert!(parse_mtl("Kd 1 1 1").is_err());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::CookedMesh;
+
+// The post-transform cache size triangles are ordered for, most mobile GPUs have at least this many entries
+const CACHE_SIZE: usize = 32;
+// The cache size used to find where the cache-ordered triangles lose locality, for splitting them into clusters
+const CLUSTER_CACHE_SIZE: usize = 16;
+const MIN_CLUSTER_TRIANGLES: usize = 16;
+
+// Tom Forsyth's "Linear-Speed Vertex Cache Optimisation" scoring
+fn vertex_score(cache_position: Option<usize>, remaining: u32) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cached = match cache_position {
+        Some(x) if x < 3 => 0.75,
+        Some(x) => (1.0 - (x - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0,
+    };
+    cached + 2.0 * (remaining as f32).powf(-0.5)
+}
+
+pub(crate) fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+impl CookedMesh {
+    ///
+    /// Runs every optimisation in order: `optimize_vertex_cache`, then
+    /// `optimize_overdraw` and lastly `optimize_vertex_fetch`. Meshes
+    /// which aren't indexed are left as they are.
+    ///
+    pub fn optimize(&mut self) {
+        self.optimize_vertex_cache();
+        self.optimize_overdraw();
+        self.optimize_vertex_fetch();
+    }
+
+    ///
+    /// Reorders the triangles so vertices are reused while they're still
+    /// in the GPU's post-transform cache, rather than being shaded again.
+    ///
+    pub fn optimize_vertex_cache(&mut self) {
+        let triangle_count = self.indices.len() / 3;
+        if triangle_count == 0 {
+            return;
+        }
+        let vertex_count = self.positions.len();
+        let mut remaining = vec![0u32; vertex_count];
+        for &x in &self.indices {
+            remaining[x as usize] += 1;
+        }
+        // The triangles using each vertex, those already emitted are removed as we go
+        let mut offsets = Vec::with_capacity(vertex_count + 1);
+        offsets.push(0);
+        for &x in &remaining {
+            offsets.push(offsets.last().unwrap() + x as usize);
+        }
+        let mut adjacency = vec![0; self.indices.len()];
+        let mut filled = offsets[..vertex_count].to_vec();
+        for (triangle, corners) in self.indices.chunks(3).enumerate() {
+            for &x in corners {
+                adjacency[filled[x as usize]] = triangle;
+                filled[x as usize] += 1;
+            }
+        }
+
+        let mut cache_position = vec![None; vertex_count];
+        let mut vertex_scores = remaining.iter().map(|&x| vertex_score(None, x)).collect::<Vec<_>>();
+        let mut triangle_scores = self.indices
+            .chunks(3)
+            .map(|x| x.iter().map(|&x| vertex_scores[x as usize]).sum::<f32>())
+            .collect::<Vec<_>>();
+        let mut emitted = vec![false; triangle_count];
+        let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+        let mut order = Vec::with_capacity(self.indices.len());
+        let mut best = None;
+        let mut scan_from = 0;
+
+        for _ in 0..triangle_count {
+            let triangle = match best {
+                Some(x) => x,
+                None => {
+                    // Nothing in the cache has triangles left, so start again from the best anywhere
+                    while emitted[scan_from] {
+                        scan_from += 1;
+                    }
+                    (scan_from..triangle_count)
+                        .filter(|&x| !emitted[x])
+                        .max_by(|&a, &b| triangle_scores[a].partial_cmp(&triangle_scores[b]).unwrap())
+                        .unwrap()
+                }
+            };
+            emitted[triangle] = true;
+            let corners = [self.indices[triangle * 3], self.indices[triangle * 3 + 1], self.indices[triangle * 3 + 2]];
+            order.extend_from_slice(&corners);
+
+            for &vertex in &corners {
+                let vertex = vertex as usize;
+                remaining[vertex] -= 1;
+                let triangles = &mut adjacency[offsets[vertex]..offsets[vertex + 1]];
+                let position = triangles[..remaining[vertex] as usize + 1].iter().position(|&x| x == triangle).unwrap();
+                triangles.swap(position, remaining[vertex] as usize);
+            }
+            cache.retain(|x| !corners.contains(x));
+            for &vertex in corners.iter().rev() {
+                cache.insert(0, vertex);
+            }
+            let evicted = if cache.len() > CACHE_SIZE { cache.split_off(CACHE_SIZE) } else { Vec::new() };
+            for &vertex in &evicted {
+                cache_position[vertex as usize] = None;
+            }
+            for (position, &vertex) in cache.iter().enumerate() {
+                cache_position[vertex as usize] = Some(position);
+            }
+
+            best = None;
+            let mut best_score = -1.0;
+            for &vertex in cache.iter().chain(&evicted) {
+                let vertex = vertex as usize;
+                let score = vertex_score(cache_position[vertex], remaining[vertex]);
+                let change = score - vertex_scores[vertex];
+                vertex_scores[vertex] = score;
+                for &triangle in &adjacency[offsets[vertex]..offsets[vertex] + remaining[vertex] as usize] {
+                    triangle_scores[triangle] += change;
+                    if cache_position[vertex].is_some() && triangle_scores[triangle] > best_score {
+                        best_score = triangle_scores[triangle];
+                        best = Some(triangle);
+                    }
+                }
+            }
+        }
+        order.extend_from_slice(&self.indices[triangle_count * 3..]);
+        self.indices = order;
+    }
+
+    ///
+    /// Splits the (cache ordered) triangles into clusters where they lose
+    /// locality, then sorts the clusters so those facing outwards from the
+    /// middle of the mesh are drawn first and hide the ones behind them.
+    /// Keeps most of the cache efficiency, while fewer pixels are shaded twice.
+    ///
+    pub fn optimize_overdraw(&mut self) {
+        let triangle_count = self.indices.len() / 3;
+        if triangle_count == 0 {
+            return;
+        }
+        let mut starts = vec![0];
+        let mut cache: Vec<u32> = Vec::with_capacity(CLUSTER_CACHE_SIZE);
+        for (triangle, corners) in self.indices[..triangle_count * 3].chunks(3).enumerate() {
+            let mut misses = 0;
+            for &vertex in corners {
+                if !cache.contains(&vertex) {
+                    misses += 1;
+                    cache.insert(0, vertex);
+                    cache.truncate(CLUSTER_CACHE_SIZE);
+                }
+            }
+            if misses == 3 && triangle - starts.last().unwrap() >= MIN_CLUSTER_TRIANGLES {
+                starts.push(triangle);
+            }
+        }
+        starts.push(triangle_count);
+
+        let centre = {
+            let sum = self.positions.iter().fold([0.0; 3], |acc, x| [acc[0] + x[0], acc[1] + x[1], acc[2] + x[2]]);
+            let count = self.positions.len().max(1) as f32;
+            [sum[0] / count, sum[1] / count, sum[2] / count]
+        };
+        let mut clusters = starts
+            .windows(2)
+            .map(|range| {
+                let mut area_normal = [0.0; 3];
+                let mut centroid = [0.0; 3];
+                let mut area = 0.0;
+                for corners in self.indices[range[0] * 3..range[1] * 3].chunks(3) {
+                    let [a, b, c] = [0, 1, 2].map(|x| self.positions[corners[x] as usize]);
+                    let normal = cross(sub(b, a), sub(c, a));
+                    let weight = dot(normal, normal).sqrt();
+                    for axis in 0..3 {
+                        area_normal[axis] += normal[axis];
+                        centroid[axis] += (a[axis] + b[axis] + c[axis]) / 3.0 * weight;
+                    }
+                    area += weight;
+                }
+                let centroid = centroid.map(|x| x / area.max(f32::EPSILON));
+                let length = dot(area_normal, area_normal).sqrt().max(f32::EPSILON);
+                let facing = dot(sub(centroid, centre), area_normal.map(|x| x / length));
+                (facing, range[0], range[1])
+            })
+            .collect::<Vec<_>>();
+        clusters.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut order = Vec::with_capacity(self.indices.len());
+        for (_, start, end) in clusters {
+            order.extend_from_slice(&self.indices[start * 3..end * 3]);
+        }
+        order.extend_from_slice(&self.indices[triangle_count * 3..]);
+        self.indices = order;
+    }
+
+    ///
+    /// Reorders the vertices in the order the indices first use them, so
+    /// vertex data is read from memory in order, and drops those which
+    /// aren't used at all.
+    ///
+    pub fn optimize_vertex_fetch(&mut self) {
+        if self.indices.is_empty() {
+            return;
+        }
+        let mut remap = vec![u32::MAX; self.positions.len()];
+        let mut order = Vec::with_capacity(self.positions.len());
+        for index in &mut self.indices {
+            let new = &mut remap[*index as usize];
+            if *new == u32::MAX {
+                *new = order.len() as u32;
+                order.push(*index as usize);
+            }
+            *index = *new;
+        }
+        self.positions = order.iter().map(|&x| self.positions[x]).collect();
+        if !self.normals.is_empty() {
+            self.normals = order.iter().map(|&x| self.normals[x]).collect();
+        }
+        if !self.uvs.is_empty() {
+            self.uvs = order.iter().map(|&x| self.uvs[x]).collect();
+        }
+        if !self.tangents.is_empty() {
+            self.tangents = order.iter().map(|&x| self.tangents[x]).collect();
+        }
+    }
+
+    ///
+    /// The average number of vertices shaded per triangle with a FIFO
+    /// post-transform cache of `cache_size` entries, between 0.5 for a
+    /// large regular grid and 3 when no vertex is ever reused.
+    ///
+    pub fn average_cache_miss_ratio(&self, cache_size: usize) -> f32 {
+        let triangle_count = self.indices.len() / 3;
+        if triangle_count == 0 {
+            return 0.0;
+        }
+        let mut cache = std::collections::VecDeque::with_capacity(cache_size);
+        let mut misses = 0;
+        for &vertex in &self.indices[..triangle_count * 3] {
+            if !cache.contains(&vertex) {
+                misses += 1;
+                cache.push_back(vertex);
+                if cache.len() > cache_size {
+                    cache.pop_front();
+                }
+            }
+        }
+        misses as f32 / triangle_count as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A grid of `size` by `size` quads, with its triangles in a scrambled order
+    fn scrambled_grid(size: u32) -> CookedMesh {
+        let positions = (0..=size)
+            .flat_map(|y| (0..=size).map(move |x| [x as f32, y as f32, 0.0]))
+            .collect::<Vec<_>>();
+        let mut triangles = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let corner = y * (size + 1) + x;
+                triangles.push([corner, corner + 1, corner + size + 1]);
+                triangles.push([corner + 1, corner + size + 2, corner + size + 1]);
+            }
+        }
+        let count = triangles.len();
+        let mut scrambled = Vec::with_capacity(count);
+        for i in 0..count {
+            scrambled.extend_from_slice(&triangles[i * 7919 % count]);
+        }
+        CookedMesh {
+            normals: vec![[0.0, 0.0, 1.0]; positions.len()],
+            uvs: positions.iter().map(|x| [x[0], x[1]]).collect(),
+            tangents: Vec::new(),
+            positions,
+            indices: scrambled,
+        }
+    }
+
+    fn triangles(mesh: &CookedMesh) -> Vec<[[u32; 2]; 3]> {
+        let mut triangles = mesh.indices
+            .chunks(3)
+            .map(|x| {
+                let mut corners = [0, 1, 2].map(|i| {
+                    let [x, y, _] = mesh.positions[x[i] as usize];
+                    [x as u32, y as u32]
+                });
+                // Rotate so the smallest corner is first, keeping the winding
+                let smallest = (0..3).min_by_key(|&i| corners[i]).unwrap();
+                corners.rotate_left(smallest);
+                corners
+            })
+            .collect::<Vec<_>>();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn optimizing_keeps_triangles() {
+        let original = scrambled_grid(20);
+        let mut mesh = original.clone();
+        mesh.optimize();
+        assert_eq!(triangles(&mesh), triangles(&original));
+        assert_eq!(mesh.positions.len(), original.positions.len());
+        for (i, position) in mesh.positions.iter().enumerate() {
+            assert_eq!(mesh.uvs[i], [position[0], position[1]]);
+        }
+        let first_uses = mesh.indices.iter().fold(Vec::new(), |mut acc, &x| {
+            if !acc.contains(&x) {
+                acc.push(x);
+            }
+            acc
+        });
+        assert!(first_uses.iter().enumerate().all(|(i, &x)| x == i as u32));
+    }
+
+    #[test]
+    fn vertex_cache_order_reuses_vertices() {
+        let mut mesh = scrambled_grid(20);
+        let before = mesh.average_cache_miss_ratio(CACHE_SIZE);
+        mesh.optimize_vertex_cache();
+        let after = mesh.average_cache_miss_ratio(CACHE_SIZE);
+        assert!(after < 1.0 && after < before, "{} -> {}", before, after);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::CookedMesh;
+use crate::optimize::{cross, dot, sub};
+
+fn scale(a: [f32; 3], x: f32) -> [f32; 3] {
+    [a[0] * x, a[1] * x, a[2] * x]
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn normalize(a: [f32; 3]) -> Option<[f32; 3]> {
+    let length = dot(a, a).sqrt();
+    if length > 1e-12 { Some(scale(a, 1.0 / length)) } else { None }
+}
+
+// The part of `a` perpendicular to the unit vector `n`
+fn reject(a: [f32; 3], n: [f32; 3]) -> [f32; 3] {
+    sub(a, scale(n, dot(a, n)))
+}
+
+// Any unit vector perpendicular to the unit vector `n`
+fn perpendicular(n: [f32; 3]) -> [f32; 3] {
+    let axis = if n[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    normalize(reject(axis, n)).unwrap_or([1.0, 0.0, 0.0])
+}
+
+impl CookedMesh {
+    ///
+    /// Fills in `tangents` from the normals and texture coordinates, as
+    /// normal mapping needs. Each tangent points along the direction `u`
+    /// increases in, with `w` being `1` or `-1` so the bitangent is
+    /// `cross(normal, tangent.xyz) * tangent.w`, as in glTF.
+    ///
+    /// Like MikkTSpace, each triangle's tangent is weighted by the angle
+    /// of its corner at the vertex, and vertices shared by triangles whose
+    /// texture is mirrored are split in two, so both sides are correct.
+    /// This can add vertices, so it's best done before `optimize`.
+    ///
+    pub fn generate_tangents(&mut self) -> Result<(), String> {
+        let count = self.positions.len();
+        if self.normals.len() != count || self.uvs.len() != count {
+            return Err("Generating tangents needs a normal and texture coordinates for every vertex".to_string());
+        }
+        let indexed = !self.indices.is_empty();
+        let mut corners = if indexed {
+            self.indices.iter().map(|&x| x as usize).collect::<Vec<_>>()
+        } else {
+            (0..count).collect()
+        };
+        let normals = self.normals.iter().map(|&x| normalize(x).unwrap_or([0.0, 0.0, 1.0])).collect::<Vec<_>>();
+
+        // The vertex used by the corners of each handedness, the original is used by whichever comes first
+        let mut split = vec![[None; 2]; count];
+        let mut sums = vec![[0.0f32; 3]; count];
+        let mut signs = vec![1.0f32; count];
+        for triangle in 0..corners.len() / 3 {
+            let [a, b, c] = [corners[triangle * 3], corners[triangle * 3 + 1], corners[triangle * 3 + 2]];
+            let (edge_1, edge_2) = (sub(self.positions[b], self.positions[a]), sub(self.positions[c], self.positions[a]));
+            let (du_1, dv_1) = (self.uvs[b][0] - self.uvs[a][0], self.uvs[b][1] - self.uvs[a][1]);
+            let (du_2, dv_2) = (self.uvs[c][0] - self.uvs[a][0], self.uvs[c][1] - self.uvs[a][1]);
+            // Only the direction matters, so the determinant is left out beyond its sign
+            let flip = if du_1 * dv_2 - du_2 * dv_1 < 0.0 { -1.0 } else { 1.0 };
+            let tangent = scale(sub(scale(edge_1, dv_2), scale(edge_2, dv_1)), flip);
+            let bitangent = scale(sub(scale(edge_2, du_1), scale(edge_1, du_2)), flip);
+
+            for corner in 0..3 {
+                let vertex = corners[triangle * 3 + corner];
+                let (previous, next) = (corners[triangle * 3 + (corner + 2) % 3], corners[triangle * 3 + (corner + 1) % 3]);
+                let n = normals[vertex];
+                let angle = match (normalize(sub(self.positions[next], self.positions[vertex])),
+                                   normalize(sub(self.positions[previous], self.positions[vertex]))) {
+                    (Some(x), Some(y)) => dot(x, y).clamp(-1.0, 1.0).acos(),
+                    _ => 0.0,
+                };
+                let tangent = normalize(reject(tangent, n));
+                let sign = if dot(cross(n, tangent.unwrap_or([0.0; 3])), bitangent) < 0.0 { -1.0 } else { 1.0 };
+                let slot = (sign < 0.0) as usize;
+                let target = match split[vertex] {
+                    [Some(x), _] if slot == 0 => x,
+                    [_, Some(x)] if slot == 1 => x,
+                    [None, None] => {
+                        split[vertex][slot] = Some(vertex);
+                        vertex
+                    },
+                    _ => {
+                        let new = self.positions.len();
+                        self.positions.push(self.positions[vertex]);
+                        self.normals.push(self.normals[vertex]);
+                        self.uvs.push(self.uvs[vertex]);
+                        sums.push([0.0; 3]);
+                        signs.push(1.0);
+                        split[vertex][slot] = Some(new);
+                        new
+                    },
+                };
+                signs[target] = sign;
+                if let Some(tangent) = tangent {
+                    sums[target] = add(sums[target], scale(tangent, angle));
+                }
+                corners[triangle * 3 + corner] = target;
+            }
+        }
+
+        self.tangents = sums.iter()
+            .zip(&signs)
+            .enumerate()
+            .map(|(vertex, (&sum, &sign))| {
+                let n = normalize(self.normals[vertex]).unwrap_or([0.0, 0.0, 1.0]);
+                let [x, y, z] = normalize(reject(sum, n)).unwrap_or_else(|| perpendicular(n));
+                [x, y, z, sign]
+            })
+            .collect();
+        if indexed {
+            self.indices = corners.iter().map(|&x| x as u32).collect();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two quads side by side in the xy plane, with the texture mirrored on the right one
+    fn mirrored_quads() -> CookedMesh {
+        let positions = (0..2)
+            .flat_map(|y| (0..3).map(move |x| [x as f32, y as f32, 0.0]))
+            .collect::<Vec<_>>();
+        CookedMesh {
+            normals: vec![[0.0, 0.0, 1.0]; positions.len()],
+            uvs: positions.iter().map(|x| [1.0 - (x[0] - 1.0).abs(), x[1]]).collect(),
+            tangents: Vec::new(),
+            positions,
+            indices: vec![0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4],
+        }
+    }
+
+    #[test]
+    fn flat_quad() {
+        let mut mesh = mirrored_quads();
+        mesh.indices.truncate(6);
+        mesh.generate_tangents().unwrap();
+        assert_eq!(mesh.positions.len(), 6);
+        for &vertex in &mesh.indices {
+            assert_eq!(mesh.tangents[vertex as usize], [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn mirrored_vertices_are_split() {
+        let original = mirrored_quads();
+        let mut mesh = original.clone();
+        mesh.generate_tangents().unwrap();
+        // The two vertices on the mirror line
+        assert_eq!(mesh.positions.len(), 8);
+        for (i, triangle) in mesh.indices.chunks(3).enumerate() {
+            let expected = if i < 2 { [1.0, 0.0, 0.0, 1.0] } else { [-1.0, 0.0, 0.0, -1.0] };
+            for &vertex in triangle {
+                assert_eq!(mesh.tangents[vertex as usize], expected);
+            }
+        }
+        for (&new, &old) in mesh.indices.iter().zip(&original.indices) {
+            assert_eq!(mesh.positions[new as usize], original.positions[old as usize]);
+            assert_eq!(mesh.uvs[new as usize], original.uvs[old as usize]);
+        }
+    }
+
+    #[test]
+    fn needs_uvs() {
+        let mut mesh = mirrored_quads();
+        mesh.uvs.clear();
+        assert!(mesh.generate_tangents().is_err());
+    }
+}
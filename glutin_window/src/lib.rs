@@ -82,20 +82,25 @@ fn window_builder_from_settings(settings: &WindowSettings) -> glutin::WindowBuil
 }
 
 fn context_builder_from_settings(
-    settings: &WindowSettings
+    settings: &WindowSettings,
+    depth_bits: Option<u8>,
 ) -> Result<glutin::ContextBuilder<glutin::NotCurrent>, Box<dyn Error>> {
     let api = settings.get_maybe_graphics_api().unwrap_or(Api::opengl(3, 2));
-    if api.api != "OpenGL" {
-        return Err(UnsupportedGraphicsApiError {
+    let version = (api.major as u8, api.minor as u8);
+    let request = match &*api.api {
+        "OpenGL" => GlRequest::GlThenGles {
+            opengl_version: version,
+            opengles_version: version,
+        },
+        // Only ever OpenGL ES, for the shaders and drivers on mobile
+        "OpenGL ES" => GlRequest::Specific(glutin::Api::OpenGlEs, version),
+        _ => return Err(UnsupportedGraphicsApiError {
             found: api.api,
-            expected: vec!["OpenGL".into()]
-        }.into());
+            expected: vec!["OpenGL".into(), "OpenGL ES".into()]
+        }.into()),
     };
     let mut builder = glutin::ContextBuilder::new()
-        .with_gl(GlRequest::GlThenGles {
-            opengl_version: (api.major as u8, api.minor as u8),
-            opengles_version: (api.major as u8, api.minor as u8),
-        })
+        .with_gl(request)
         .with_srgb(settings.get_srgb());
     let samples = settings.get_samples();
     if settings.get_vsync() {
@@ -104,6 +109,9 @@ fn context_builder_from_settings(
     if samples != 0 {
         builder = builder.with_multisampling(samples as u16);
     }
+    if let Some(bits) = depth_bits {
+        builder = builder.with_depth_buffer(bits);
+    }
     Ok(builder)
 }
 
@@ -111,18 +119,23 @@ impl GlutinWindow {
 
     /// Creates a new game window for Glutin.
     pub fn new(settings: &WindowSettings) -> Result<Self, Box<dyn Error>> {
+        GlutinWindow::with_depth_buffer(settings, None)
+    }
+
+    /// Creates a new game window for Glutin, requesting a depth buffer of at least `depth_bits` bits.
+    pub fn with_depth_buffer(settings: &WindowSettings, depth_bits: Option<u8>) -> Result<Self, Box<dyn Error>> {
         let events_loop = glutin::EventsLoop::new();
         let title = settings.get_title();
         let exit_on_esc = settings.get_exit_on_esc();
         let window_builder = window_builder_from_settings(&settings);
-        let context_builder = context_builder_from_settings(&settings)?;
+        let context_builder = context_builder_from_settings(&settings, depth_bits)?;
         let ctx = context_builder.build_windowed(window_builder, &events_loop);
         let ctx = match ctx {
                 Ok(ctx) => ctx,
                 Err(_) => {
                     let settings = settings.clone().samples(0);
                     let window_builder = window_builder_from_settings(&settings);
-                    let context_builder = context_builder_from_settings(&settings)?;
+                    let context_builder = context_builder_from_settings(&settings, depth_bits)?;
                     let ctx = context_builder.build_windowed(window_builder, &events_loop)?;
                     ctx
                 }
@@ -513,6 +526,41 @@ impl OpenGLWindow for GlutinWindow {
     }
 }
 
+/// An OpenGL context without a window, for drawing offscreen such as in tests.
+pub struct HeadlessContext {
+    /// The context.
+    pub ctx: glutin::Context<glutin::PossiblyCurrent>,
+    // The context may need the event loop's connection to the display.
+    _events_loop: glutin::EventsLoop,
+}
+
+impl HeadlessContext {
+    /// Creates a context with a default framebuffer of the size in `settings`,
+    /// requesting its graphics API, samples and sRGB like `GlutinWindow` does.
+    /// The title and the window's own settings are ignored.
+    pub fn new(settings: &WindowSettings, depth_bits: Option<u8>) -> Result<Self, Box<dyn Error>> {
+        let events_loop = glutin::EventsLoop::new();
+        let size = settings.get_size();
+        let size = glutin::dpi::PhysicalSize::new(size.width, size.height);
+        let context_builder = context_builder_from_settings(settings, depth_bits)?;
+        let ctx = context_builder.build_headless(&events_loop, size)?;
+        let ctx = unsafe { ctx.make_current().map_err(|(_, err)| err)? };
+
+        // Load the OpenGL function pointers.
+        gl::load_with(|s| ctx.get_proc_address(s) as *const _);
+
+        Ok(HeadlessContext {
+            ctx,
+            _events_loop: events_loop,
+        })
+    }
+
+    /// Gets the address of an OpenGL function, to load function pointers with.
+    pub fn get_proc_address(&self, proc_name: &str) -> ProcAddress {
+        self.ctx.get_proc_address(proc_name) as *const _
+    }
+}
+
 /// Maps Glutin's key to Piston's key.
 pub fn map_key(keycode: glutin::VirtualKeyCode) -> keyboard::Key {
     use input::keyboard::Key;
@@ -0,0 +1,124 @@
+//!
+//! Transforms applied to many matrices, points or normals at once, such
+//! as the joints of a skinned mesh or the vertices of a batched one.
+//!
+//! The loops work on four lanes of `f32` at a time with no branches,
+//! which is what lets them compile to NEON (or SSE) instructions.
+//!
+
+use cgmath::Matrix4;
+
+#[inline(always)]
+fn mul_lanes(a: &Matrix4<f32>, b: [f32; 4]) -> [f32; 4] {
+    let mut out = [0.0; 4];
+    for (lane, out) in out.iter_mut().enumerate() {
+        *out = a.x[lane] * b[0] + a.y[lane] * b[1] + a.z[lane] * b[2] + a.w[lane] * b[3];
+    }
+    out
+}
+
+#[inline(always)]
+fn mul_matrix(a: &Matrix4<f32>, b: &Matrix4<f32>) -> Matrix4<f32> {
+    let columns = [
+        mul_lanes(a, b.x.into()),
+        mul_lanes(a, b.y.into()),
+        mul_lanes(a, b.z.into()),
+        mul_lanes(a, b.w.into()),
+    ];
+    Matrix4::from(columns)
+}
+
+///
+/// Multiplies pairs of matrices, writing `a[i] * b[i]` into `out[i]`.
+///
+/// Panics if the slices aren't all the same length.
+///
+pub fn multiply_pairs(a: &[Matrix4<f32>], b: &[Matrix4<f32>], out: &mut [Matrix4<f32>]) {
+    assert!(a.len() == b.len() && a.len() == out.len(), "Slices of matrices have different lengths");
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = mul_matrix(a, b);
+    }
+}
+
+///
+/// Transforms each of `points` by `matrix`, treating it as an affine
+/// transform (the result isn't divided by `w`).
+///
+/// Panics if `out` is shorter than `points`.
+///
+pub fn transform_points(matrix: &Matrix4<f32>, points: &[[f32; 3]], out: &mut [[f32; 3]]) {
+    assert!(out.len() >= points.len(), "Output has room for {} of {} points", out.len(), points.len());
+    for (out, &[x, y, z]) in out.iter_mut().zip(points) {
+        let [x, y, z, _] = mul_lanes(matrix, [x, y, z, 1.0]);
+        *out = [x, y, z];
+    }
+}
+
+///
+/// Transforms each of `vectors` by `matrix`, ignoring its translation.
+///
+/// For normals `matrix` should be the inverse transpose of the model
+/// matrix, unless it only rotates and uniformly scales. The results
+/// aren't normalised.
+///
+/// Panics if `out` is shorter than `vectors`.
+///
+pub fn transform_normals(matrix: &Matrix4<f32>, vectors: &[[f32; 3]], out: &mut [[f32; 3]]) {
+    assert!(out.len() >= vectors.len(), "Output has room for {} of {} vectors", out.len(), vectors.len());
+    for (out, &[x, y, z]) in out.iter_mut().zip(vectors) {
+        let [x, y, z, _] = mul_lanes(matrix, [x, y, z, 0.0]);
+        *out = [x, y, z];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Point3, Transform, Rotation3, Quaternion, InnerSpace, Vector3};
+
+    fn sample(i: usize) -> Matrix4<f32> {
+        let i = i as f32;
+        Matrix4::from_translation(Vector3::new(i, -2. * i, 0.5))
+            * Matrix4::from(Quaternion::from_angle_y(Deg(15. * i)))
+            * Matrix4::from_nonuniform_scale(1. + i, 2., 0.5)
+    }
+
+    fn close(a: &Matrix4<f32>, b: &Matrix4<f32>) -> bool {
+        let (a, b): (&[f32; 16], &[f32; 16]) = (a.as_ref(), b.as_ref());
+        a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-4)
+    }
+
+    #[test]
+    fn matrices() {
+        let bs = (0..9).map(sample).collect::<Vec<_>>();
+        let mut out = vec![Matrix4::from_scale(0.); 9];
+        multiply_pairs(&bs, &bs, &mut out);
+        for (out, b) in out.iter().zip(&bs) {
+            assert!(close(out, &(b * b)));
+        }
+    }
+
+    #[test]
+    fn points() {
+        let m = sample(2);
+        let points = (0..7).map(|i| [i as f32, 1., -(i as f32)]).collect::<Vec<_>>();
+        let mut out = vec![[0.; 3]; 7];
+        transform_points(&m, &points, &mut out);
+        for (out, &p) in out.iter().zip(&points) {
+            let expected = m.transform_point(Point3::from(p));
+            assert!((Point3::from(*out) - expected).magnitude() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn normals() {
+        let m = sample(1);
+        let vectors = [[0., 1., 0.], [1., 0., 0.]];
+        let mut out = [[0.; 3]; 2];
+        transform_normals(&m, &vectors, &mut out);
+        for (out, &v) in out.iter().zip(&vectors) {
+            let expected = m.transform_vector(Vector3::from(v));
+            assert!((Vector3::from(*out) - expected).magnitude() < 1e-4);
+        }
+    }
+}
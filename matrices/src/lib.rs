@@ -1,5 +1,12 @@
+pub mod batch;
+pub mod scene;
+pub mod nav;
+mod quaternion;
+
+pub use quaternion::TransformQ;
+
 use std::ops::{Mul, Deref, DerefMut};
-use cgmath::{Matrix4, One, Point3, Vector3, InnerSpace, Rad};
+use cgmath::{Matrix, Matrix3, Matrix4, One, Point3, SquareMatrix, Vector3, InnerSpace, Rad};
 
 ///
 /// A transform that can be pushed onto a transformation
@@ -155,6 +162,77 @@ impl Transform<Matrix4<f32>> {
     pub fn scale_xyz(&mut self, x: f32, y: f32, z: f32) {
         self.scale = self.scale * Matrix4::from_nonuniform_scale(x, y, z);
     }
+
+    ///
+    /// Combines the three parts into one matrix, multiplied in the
+    /// same way as the function given to `TransformHierarchy::new`.
+    /// `ShaderContext` and `SceneGraph` use `|s, r, t| s * r * t`.
+    ///
+    #[inline]
+    pub fn to_matrix<F: Fn(Matrix4<f32>, Matrix4<f32>, Matrix4<f32>) -> Matrix4<f32>>(&self, order: F) -> Matrix4<f32> {
+        order(self.scale, self.rotate, self.translate)
+    }
+
+    ///
+    /// Splits a matrix into a scale, rotation and translation which
+    /// give it back when multiplied as `scale * rotate * translate`.
+    ///
+    /// Returns `None` when the matrix isn't made only of those, such
+    /// as when it's a projection, is sheared or scales an axis to 0.
+    /// Mirroring is kept as a negative scale along x.
+    ///
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Option<Self> {
+        const EPSILON: f32 = 1e-4;
+        if matrix.x.w.abs() > EPSILON || matrix.y.w.abs() > EPSILON || matrix.z.w.abs() > EPSILON || (matrix.w.w - 1.).abs() > EPSILON {
+            return None;
+        }
+        // The upper 3x3 is `scale * rotate`, so each row is a row of the rotation scaled by the scale along that axis
+        let upper = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+        let mut rows = [upper.row(0), upper.row(1), upper.row(2)];
+        let mut scale = [rows[0].magnitude(), rows[1].magnitude(), rows[2].magnitude()];
+        if scale.iter().any(|&x| x < EPSILON) {
+            return None;
+        }
+        if upper.determinant() < 0. {
+            scale[0] = -scale[0];
+        }
+        for (row, scale) in rows.iter_mut().zip(&scale) {
+            *row /= *scale;
+        }
+        // The transpose of the rotation, which is its inverse
+        let inverse_rotate = Matrix3::from_cols(rows[0], rows[1], rows[2]);
+        let rotate = inverse_rotate.transpose();
+        let product: [[f32; 3]; 3] = (rotate * inverse_rotate).into();
+        for (i, column) in product.iter().enumerate() {
+            for (j, x) in column.iter().enumerate() {
+                if (x - if i == j { 1. } else { 0. }).abs() > EPSILON {
+                    return None;
+                }
+            }
+        }
+        let unscaled = Vector3::new(matrix.w.x / scale[0], matrix.w.y / scale[1], matrix.w.z / scale[2]);
+        Some(Self {
+            scale: Matrix4::from_nonuniform_scale(scale[0], scale[1], scale[2]),
+            rotate: rotate.into(),
+            translate: Matrix4::from_translation(inverse_rotate * unscaled),
+        })
+    }
+
+    ///
+    /// Inverts each of the three parts, or returns `None` if one of
+    /// them can't be inverted.
+    ///
+    /// Undoing a transform means applying the inverted parts in the
+    /// opposite order, so `t.to_matrix(|s, r, t| s * r * t)` is undone
+    /// by `t.inverse()?.to_matrix(|s, r, t| t * r * s)`.
+    ///
+    pub fn inverse(&self) -> Option<Self> {
+        Some(Self {
+            scale: self.scale.invert()?,
+            rotate: self.rotate.invert()?,
+            translate: self.translate.invert()?,
+        })
+    }
 }
 
 ///
@@ -176,7 +254,7 @@ pub enum TransformLock<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> {
 impl<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> Drop for TransformLock<'a, T, F> {
     fn drop(&mut self) {
         if let TransformLock::With {lock, ..} = self {
-            lock.pop_one();
+            lock.pop_discard();
         }
     }
 }
@@ -189,7 +267,7 @@ impl<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> TransformLock<'a, T, F>
         match self {
             TransformLock::With {lock, index} |
             TransformLock::Without {lock, index} => {
-                &lock.matrices[*index]
+                &lock.matrices.get(*index).unwrap().0
             }
         }
     }
@@ -197,6 +275,7 @@ impl<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> TransformLock<'a, T, F>
 
 impl<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> Deref for TransformLock<'a, T, F> {
     type Target = TransformHierarchy<T, F>;
+    #[allow(clippy::explicit_auto_deref)]
     fn deref(&self) -> &Self::Target {
         match self {
             TransformLock::With {lock, ..} |
@@ -208,6 +287,7 @@ impl<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> Deref for TransformLock<
 }
 
 impl<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> DerefMut for TransformLock<'a, T, F> {
+    #[allow(clippy::explicit_auto_deref)]
     fn deref_mut(&mut self) -> &mut Self::Target {
         match self {
             TransformLock::With { lock, .. } |
@@ -218,6 +298,84 @@ impl<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> DerefMut for TransformLo
     }
 }
 
+///
+/// How many transforms a `TransformHierarchy` holds before it allocates.
+///
+const INLINE_DEPTH: usize = 8;
+
+///
+/// A stack which keeps its first `INLINE_DEPTH` elements inline,
+/// only allocating for deeper hierarchies.
+///
+#[derive(Debug, Clone)]
+struct InlineStack<T> {
+    inline: [Option<T>; INLINE_DEPTH],
+    len: usize,
+    spilled: Vec<T>,
+}
+
+impl<T> InlineStack<T> {
+    fn new() -> Self {
+        Self {
+            inline: [None, None, None, None, None, None, None, None],
+            len: 0,
+            spilled: Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push(&mut self, value: T) {
+        if self.len < INLINE_DEPTH {
+            self.inline[self.len] = Some(value);
+        } else {
+            self.spilled.push(value);
+        }
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        if self.len < INLINE_DEPTH {
+            self.inline[self.len].take()
+        } else {
+            self.spilled.pop()
+        }
+    }
+
+    #[inline]
+    fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            None
+        } else if index < INLINE_DEPTH {
+            self.inline[index].as_ref()
+        } else {
+            self.spilled.get(index - INLINE_DEPTH)
+        }
+    }
+
+    #[inline]
+    fn last(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|x| self.get(x))
+    }
+
+    #[inline]
+    fn last_mut(&mut self) -> Option<&mut T> {
+        let index = self.len.checked_sub(1)?;
+        if index < INLINE_DEPTH {
+            self.inline[index].as_mut()
+        } else {
+            self.spilled.get_mut(index - INLINE_DEPTH)
+        }
+    }
+}
+
 ///
 /// A Binary-tree traversal method of storing transformations.
 ///
@@ -229,35 +387,75 @@ impl<'a, T: Clone + Mul<Output=T>, F: Fn(T, T, T) -> T> DerefMut for TransformLo
 /// This assumes the point operations will happen like so:
 /// `new_point = projection * view * world * point`.
 ///
+/// The first few levels of the hierarchy are stored inline, so
+/// shallow hierarchies never allocate.
+///
 #[derive(Debug, Clone)]
 pub struct TransformHierarchy<T: Clone + Mul<Output = T>, F: Fn(T, T, T) -> T> {
-    matrices: Vec<T>,
+    // Each matrix with the number of identity transforms pushed on top of it
+    matrices: InlineStack<(T, usize)>,
     order_func: F,
 }
 
 impl<T: Clone + Mul<Output = T>, F: Fn(T, T, T) -> T> TransformHierarchy<T, F> {
     pub fn new(identity: T, func: F) -> Self {
+        let mut matrices = InlineStack::new();
+        matrices.push((identity, 0));
         Self {
             order_func: func,
-            matrices: vec![identity]
+            matrices,
         }
     }
 
     pub fn push(&'_ mut self, push_scale: T, push_rotate: T, push_translate: T) -> TransformLock<'_, T, F> {
-        let previous = self.matrices.last()
-            .cloned()
-            .unwrap();
+        let previous = self.current().clone();
         let new_transform = previous * (self.order_func)(
             push_scale, push_rotate, push_translate
         );
         let len = self.matrices.len();
-        self.matrices.push(new_transform.clone());
+        self.matrices.push((new_transform, 0));
         TransformLock::With {
             lock: self,
             index: len
         }
     }
 
+    ///
+    /// Pushes an identity transform, which leaves the current
+    /// transform as it is without copying or multiplying it.
+    ///
+    /// Popped when the lock is dropped, like with `push`.
+    ///
+    pub fn push_identity(&'_ mut self) -> TransformLock<'_, T, F> {
+        self.matrices.last_mut().unwrap().1 += 1;
+        let len = self.matrices.len() - 1;
+        TransformLock::With {
+            lock: self,
+            index: len
+        }
+    }
+
+    ///
+    /// Gets the transform at the top of the stack, which is the
+    /// combination of every transform currently pushed.
+    ///
+    #[inline]
+    pub fn current(&self) -> &T {
+        &self.matrices.last().unwrap().0
+    }
+
+    ///
+    /// Gets the number of transforms currently pushed, not
+    /// counting the first one: The identity.
+    ///
+    pub fn depth(&self) -> usize {
+        let mut depth = self.matrices.len() - 1;
+        for index in 0..self.matrices.len() {
+            depth += self.matrices.get(index).unwrap().1;
+        }
+        depth
+    }
+
     #[inline]
     pub fn push_transform(&'_ mut self, Transform {scale, rotate, translate}: Transform<T>) -> TransformLock<'_, T, F> {
         self.push(scale, rotate, translate)
@@ -271,28 +469,85 @@ impl<T: Clone + Mul<Output = T>, F: Fn(T, T, T) -> T> TransformHierarchy<T, F> {
         }
     }
 
+    #[cfg(test)]
     pub(crate) fn pop_one(&mut self) -> T {
-        if self.matrices.len() > 1 {
-            let val = self.matrices.pop();
+        let top = self.matrices.last_mut().unwrap();
+        if top.1 > 0 {
+            top.1 -= 1;
+            top.0.clone()
+        } else if self.matrices.len() > 1 {
+            self.matrices.pop().unwrap().0
+        } else {
+            panic!("Cannot pop past the first element: The identity.");
+        }
+    }
 
-            val.unwrap()
+    // Pops like `pop_one` without copying the transform out
+    fn pop_discard(&mut self) {
+        let top = self.matrices.last_mut().unwrap();
+        if top.1 > 0 {
+            top.1 -= 1;
+        } else if self.matrices.len() > 1 {
+            self.matrices.pop();
         } else {
             panic!("Cannot pop past the first element: The identity.");
         }
     }
 }
 
+impl<T: Clone + Mul<Output = T> + PartialEq + One, F: Fn(T, T, T) -> T> TransformHierarchy<T, F> {
+    ///
+    /// Pushes a transform like `push`, but uses `push_identity`
+    /// when all three parts are the identity.
+    ///
+    pub fn push_checked(&'_ mut self, push_scale: T, push_rotate: T, push_translate: T) -> TransformLock<'_, T, F> {
+        if push_scale.is_one() && push_rotate.is_one() && push_translate.is_one() {
+            self.push_identity()
+        } else {
+            self.push(push_scale, push_rotate, push_translate)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::TransformHierarchy;
+    use crate::{Transform, TransformHierarchy};
+    use cgmath::{InnerSpace, Matrix4, One, Rad, Vector3};
+
+    fn assert_close(a: Matrix4<f32>, b: Matrix4<f32>) {
+        let a: &[f32; 16] = a.as_ref();
+        let b: &[f32; 16] = b.as_ref();
+        assert!(a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-4), "{:?} != {:?}", a, b);
+    }
 
     #[test]
+    fn decompose() {
+        let mut transform = Transform::identity();
+        transform.scale_xyz(-2., 3., 0.5);
+        transform.rotate_axis(Vector3::new(1., 2., 3.).normalize(), Rad(0.7));
+        transform.translate_by(Vector3::new(4., -5., 6.));
+        let matrix = transform.to_matrix(|s, r, t| s * r * t);
+        let decomposed = Transform::from_matrix(matrix).unwrap();
+        assert_close(decomposed.to_matrix(|s, r, t| s * r * t), matrix);
+        assert_close(decomposed.translate, transform.translate);
+
+        let inverse = transform.inverse().unwrap().to_matrix(|s, r, t| t * r * s);
+        assert_close(matrix * inverse, Matrix4::one());
+
+        let sheared = Matrix4::from_nonuniform_scale(1., 2., 1.) * Matrix4::from_angle_z(Rad(0.5)) * Matrix4::from_nonuniform_scale(3., 1., 1.);
+        assert!(Transform::from_matrix(sheared).is_none());
+        assert!(Transform::from_matrix(cgmath::perspective(Rad(1.), 1., 0.1, 10.)).is_none());
+    }
+
+    #[test]
+    #[allow(unused_variables)]
     fn identity() {
         let mut transform = TransformHierarchy::<f32, _>::new(1f32, |x, y, z| x * y * z);
         let lock = transform.push(1., 1., 1.);
     }
 
     #[test]
+    #[allow(clippy::excessive_precision)]
     fn push() {
         let mut transform = TransformHierarchy::<f32, _>::new(1f32, |x, y, z| x * y * z);
         let mut first = transform.push(100., -3., std::f32::consts::SQRT_2);
@@ -316,4 +571,48 @@ mod tests {
         std::mem::forget(x);
         assert_eq!(transform.pop_one(), 2. * 3. * 4.);
     }
+
+    #[test]
+    fn current() {
+        let mut transform = TransformHierarchy::<f32, _>::new(1f32, |x, y, z| x * y * z);
+        assert_eq!(*transform.current(), 1.);
+        {
+            let lock = transform.push(2., 3., 4.);
+            assert_eq!(*lock.current(), 24.);
+            assert_eq!(*TransformHierarchy::current(&lock), 24.);
+        }
+        assert_eq!(*transform.current(), 1.);
+    }
+
+    #[test]
+    fn deep() {
+        let mut transform = TransformHierarchy::<f32, _>::new(1f32, |x, y, z| x * y * z);
+        let locks = (0..20).map(|_| {
+            let lock = transform.push(2., 1., 1.);
+            let value = *lock.current();
+            std::mem::forget(lock);
+            value
+        }).collect::<Vec<_>>();
+        assert_eq!(locks[19], 2f32.powi(20));
+        assert_eq!(transform.depth(), 20);
+        for i in (0..20).rev() {
+            assert_eq!(transform.pop_one(), 2f32.powi(i + 1));
+        }
+        assert_eq!(*transform.current(), 1.);
+    }
+
+    #[test]
+    fn identity_push() {
+        let mut transform = TransformHierarchy::<f32, _>::new(1f32, |x, y, z| x * y * z);
+        let mut first = transform.push(2., 3., 4.);
+        {
+            let mut second = first.push_checked(1., 1., 1.);
+            assert_eq!(second.depth(), 2);
+            let third = second.push(2., 1., 1.);
+            assert_eq!(*third.current(), 48.);
+            assert_eq!(third.depth(), 3);
+        }
+        assert_eq!(first.depth(), 1);
+        assert_eq!(*first.current(), 24.);
+    }
 }
@@ -0,0 +1,744 @@
+//!
+//! Navigation for AI agents: a `NavMesh` baked from the walkable
+//! triangles of level geometry, or a `NavGrid` of walkable cells for
+//! levels without geometry to bake from, such as tile maps.
+//!
+//! Both find paths with A* and then smooth them, into the shortest
+//! path through the triangles or cells found. Queries only need `&self`
+//! and make a few small allocations, so they can be run from `update`.
+//!
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, Point3};
+
+// How far outside a triangle or cell a point can be and still be in it
+const EPSILON: f32 = 1e-5;
+
+///
+/// How `NavMesh::bake` picks and joins walkable triangles.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavSettings {
+    ///
+    /// The steepest slope agents can walk, in radians from flat.
+    /// Defaults to 45 degrees.
+    ///
+    pub max_slope: f32,
+    ///
+    /// How close vertices have to be to get merged, so triangles
+    /// which only share an edge by position are joined. Defaults
+    /// to `0.01`.
+    ///
+    pub weld_distance: f32,
+}
+
+impl Default for NavSettings {
+    fn default() -> Self {
+        Self {
+            max_slope: std::f32::consts::FRAC_PI_4,
+            weld_distance: 0.01,
+        }
+    }
+}
+
+///
+/// The walkable surfaces of a level as a mesh of triangles, which
+/// agents find paths across.
+///
+/// Positions have `y` up. Only triangles flat enough to walk on are
+/// kept, so walls and steep slopes split the floor, but obstacles
+/// standing on a floor modelled as a single surface don't cut holes
+/// in it; leave the floor under them out, or use a `NavGrid`.
+///
+/// Finding the triangle under a point looks at every triangle, which
+/// suits the few thousand triangles of a mobile level.
+///
+#[derive(Debug, Clone)]
+pub struct NavMesh {
+    vertices: Vec<Point3<f32>>,
+    triangles: Vec<[usize; 3]>,
+    // The triangle across each edge, edge `i` running from vertex `i` to vertex `i + 1`
+    neighbours: Vec<[Option<usize>; 3]>,
+    centers: Vec<Point3<f32>>,
+}
+
+impl NavMesh {
+    ///
+    /// Bakes the triangles of `indices` (three to a triangle) into a
+    /// navigation mesh, keeping those agents can walk on and joining
+    /// the ones sharing an edge.
+    ///
+    /// Indices out of bounds of `positions` are skipped along with
+    /// their triangles.
+    ///
+    pub fn bake(positions: &[[f32; 3]], indices: &[u32], settings: &NavSettings) -> Self {
+        let min_up = settings.max_slope.cos();
+        let mut welder = Welder::new(settings.weld_distance);
+        let mut triangles = Vec::new();
+        for triangle in indices.chunks_exact(3) {
+            let corners = match (positions.get(triangle[0] as usize), positions.get(triangle[1] as usize), positions.get(triangle[2] as usize)) {
+                (Some(&a), Some(&b), Some(&c)) => [Point3::from(a), Point3::from(b), Point3::from(c)],
+                _ => continue,
+            };
+            let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            let length = normal.magnitude();
+            if length <= EPSILON || normal.y.abs() / length < min_up {
+                continue;
+            }
+            let welded = [welder.add(corners[0]), welder.add(corners[1]), welder.add(corners[2])];
+            if welded[0] != welded[1] && welded[1] != welded[2] && welded[2] != welded[0] {
+                triangles.push(welded);
+            }
+        }
+
+        let mut edges = HashMap::<(usize, usize), Vec<(usize, usize)>>::new();
+        for (index, triangle) in triangles.iter().enumerate() {
+            for edge in 0..3 {
+                let (a, b) = (triangle[edge], triangle[(edge + 1) % 3]);
+                edges.entry((a.min(b), a.max(b))).or_default().push((index, edge));
+            }
+        }
+        let mut neighbours = vec![[None; 3]; triangles.len()];
+        // Edges shared by more than two triangles aren't walked across
+        for sides in edges.values().filter(|x| x.len() == 2) {
+            let ((a, a_edge), (b, b_edge)) = (sides[0], sides[1]);
+            neighbours[a][a_edge] = Some(b);
+            neighbours[b][b_edge] = Some(a);
+        }
+
+        let vertices = welder.vertices;
+        let centers = triangles
+            .iter()
+            .map(|x| Point3::centroid(&[vertices[x[0]], vertices[x[1]], vertices[x[2]]]))
+            .collect();
+        Self {
+            vertices,
+            triangles,
+            neighbours,
+            centers,
+        }
+    }
+
+    ///
+    /// The vertices of the mesh, after welding.
+    ///
+    pub fn vertices(&self) -> &[Point3<f32>] {
+        &self.vertices
+    }
+
+    ///
+    /// The walkable triangles, as indices into `vertices`.
+    ///
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    ///
+    /// The triangles sharing an edge with `triangle`.
+    ///
+    pub fn neighbours(&self, triangle: usize) -> impl Iterator<Item = usize> + '_ {
+        self.neighbours[triangle].iter().filter_map(|x| *x)
+    }
+
+    fn corners(&self, triangle: usize) -> [Point3<f32>; 3] {
+        let [a, b, c] = self.triangles[triangle];
+        [self.vertices[a], self.vertices[b], self.vertices[c]]
+    }
+
+    ///
+    /// Finds the triangle straight above or below `point`, the
+    /// closest one vertically if there are a few, along with the
+    /// point on it.
+    ///
+    pub fn locate(&self, point: Point3<f32>) -> Option<(usize, Point3<f32>)> {
+        let mut closest: Option<(usize, Point3<f32>)> = None;
+        for triangle in 0..self.triangles.len() {
+            let [a, b, c] = self.corners(triangle);
+            let area = area2(a, b, c);
+            if area.abs() <= EPSILON {
+                continue;
+            }
+            let (u, v) = (area2(b, c, point) / area, area2(c, a, point) / area);
+            let w = 1. - u - v;
+            if u < -EPSILON || v < -EPSILON || w < -EPSILON {
+                continue;
+            }
+            let on = Point3::new(point.x, a.y * u + b.y * v + c.y * w, point.z);
+            if closest.is_none_or(|(_, x)| (x.y - point.y).abs() > (on.y - point.y).abs()) {
+                closest = Some((triangle, on));
+            }
+        }
+        closest
+    }
+
+    ///
+    /// Finds the point on the mesh closest to `point`, and the
+    /// triangle it's on.
+    ///
+    pub fn nearest(&self, point: Point3<f32>) -> Option<(usize, Point3<f32>)> {
+        (0..self.triangles.len())
+            .map(|triangle| {
+                let [a, b, c] = self.corners(triangle);
+                (triangle, closest_on_triangle(point, a, b, c))
+            })
+            .min_by(|a, b| point.distance2(a.1).partial_cmp(&point.distance2(b.1)).unwrap_or(Ordering::Equal))
+    }
+
+    // The triangle under `point`, or the closest one when it's off the mesh
+    fn snap(&self, point: Point3<f32>) -> Option<(usize, Point3<f32>)> {
+        self.locate(point).or_else(|| self.nearest(point))
+    }
+
+    ///
+    /// Finds the triangles a path from `start` to `end` passes
+    /// through with A*, from the one under `start` to the one under
+    /// `end`. Points off the mesh start from the closest point on it.
+    ///
+    /// Returns `None` when the two aren't connected.
+    ///
+    pub fn find_corridor(&self, start: Point3<f32>, end: Point3<f32>) -> Option<Vec<usize>> {
+        let ((from, _), (to, end)) = (self.snap(start)?, self.snap(end)?);
+        self.corridor(from, to, end)
+    }
+
+    fn corridor(&self, from: usize, to: usize, end: Point3<f32>) -> Option<Vec<usize>> {
+        astar(
+            self.triangles.len(),
+            from,
+            to,
+            |triangle, adjacent| {
+                let center = self.centers[triangle];
+                adjacent.extend(self.neighbours(triangle).map(|x| (x, center.distance(self.centers[x]))));
+            },
+            |triangle| self.centers[triangle].distance(end),
+        )
+    }
+
+    ///
+    /// Finds the shortest path from `start` to `end` through the
+    /// corridor `find_corridor` finds, pulled tight around its corners.
+    ///
+    /// The path begins and ends on the mesh, at the points under (or
+    /// closest to) `start` and `end`, and turns at the mesh's vertices.
+    ///
+    pub fn find_path(&self, start: Point3<f32>, end: Point3<f32>) -> Option<Vec<Point3<f32>>> {
+        let ((from, start), (to, end)) = (self.snap(start)?, self.snap(end)?);
+        let corridor = self.corridor(from, to, end)?;
+        let mut portals = Vec::with_capacity(corridor.len() + 1);
+        portals.push((start, start));
+        for pair in corridor.windows(2) {
+            let triangle = self.triangles[pair[0]];
+            let edge = (0..3).find(|&x| self.neighbours[pair[0]][x] == Some(pair[1])).unwrap();
+            let (a, b) = (self.vertices[triangle[edge]], self.vertices[triangle[(edge + 1) % 3]]);
+            // Leaving a counter-clockwise triangle, its edges run from right to left
+            let [p, q, r] = self.corners(pair[0]);
+            portals.push(if area2(p, q, r) > 0. { (b, a) } else { (a, b) });
+        }
+        portals.push((end, end));
+        Some(funnel(&portals))
+    }
+}
+
+///
+/// A grid of walkable and blocked cells agents find paths across,
+/// for levels built from tiles, or without geometry to bake.
+///
+/// Points are in the grid's own plane, cell `[x, y]` covering from
+/// `origin + [x, y] * cell_size` to one `cell_size` further along. Agents move between neighbouring cells, diagonally only
+/// when both cells beside the diagonal are walkable.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavGrid {
+    width: usize,
+    height: usize,
+    cell_size: [f32; 2],
+    origin: [f32; 2],
+    walkable: Vec<bool>,
+}
+
+impl NavGrid {
+    ///
+    /// Creates a grid of `width` by `height` walkable cells at the
+    /// origin.
+    ///
+    pub fn new(width: usize, height: usize, cell_size: [f32; 2]) -> Self {
+        Self {
+            width,
+            height,
+            cell_size,
+            origin: [0., 0.],
+            walkable: vec![true; width * height],
+        }
+    }
+
+    ///
+    /// Moves the grid's first cell to `origin`.
+    ///
+    pub fn with_origin(mut self, origin: [f32; 2]) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn cell_size(&self) -> [f32; 2] {
+        self.cell_size
+    }
+
+    ///
+    /// Whether cell `[x, y]` can be walked on, which cells outside the
+    /// grid can't.
+    ///
+    pub fn is_walkable(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.walkable[y * self.width + x]
+    }
+
+    ///
+    /// Sets whether cell `[x, y]` can be walked on. Cells outside the
+    /// grid are ignored.
+    ///
+    pub fn set_walkable(&mut self, x: usize, y: usize, walkable: bool) {
+        if x < self.width && y < self.height {
+            self.walkable[y * self.width + x] = walkable;
+        }
+    }
+
+    fn walkable_at(&self, x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && self.is_walkable(x as usize, y as usize)
+    }
+
+    // In cells from the origin
+    fn local(&self, point: [f32; 2]) -> [f32; 2] {
+        [(point[0] - self.origin[0]) / self.cell_size[0], (point[1] - self.origin[1]) / self.cell_size[1]]
+    }
+
+    ///
+    /// Gets the cell `point` is in, if it's in the grid.
+    ///
+    pub fn cell_at(&self, point: [f32; 2]) -> Option<[usize; 2]> {
+        let [x, y] = self.local(point);
+        if x < 0. || y < 0. || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some([x as usize, y as usize])
+    }
+
+    ///
+    /// Gets the middle of cell `[x, y]`.
+    ///
+    pub fn cell_center(&self, cell: [usize; 2]) -> [f32; 2] {
+        [
+            self.origin[0] + (cell[0] as f32 + 0.5) * self.cell_size[0],
+            self.origin[1] + (cell[1] as f32 + 0.5) * self.cell_size[1],
+        ]
+    }
+
+    ///
+    /// Whether the straight line from `a` to `b` only crosses walkable
+    /// cells, passing between two cells at a corner only when both
+    /// are walkable.
+    ///
+    pub fn line_of_sight(&self, a: [f32; 2], b: [f32; 2]) -> bool {
+        let ([ax, ay], [bx, by]) = (self.local(a), self.local(b));
+        let mut cell = [ax.floor() as isize, ay.floor() as isize];
+        let end = [bx.floor() as isize, by.floor() as isize];
+        if !self.walkable_at(cell[0], cell[1]) {
+            return false;
+        }
+        let (dx, dy) = (bx - ax, by - ay);
+        let step = [if dx > 0. { 1 } else { -1 }, if dy > 0. { 1 } else { -1 }];
+        // How far along the line each step across a column or row is, and where the next one is
+        let delta = [1. / dx.abs(), 1. / dy.abs()];
+        let mut next = [
+            if dx > 0. { (cell[0] as f32 + 1. - ax) * delta[0] } else { (ax - cell[0] as f32) * delta[0] },
+            if dy > 0. { (cell[1] as f32 + 1. - ay) * delta[1] } else { (ay - cell[1] as f32) * delta[1] },
+        ];
+        while cell != end {
+            if next[0].min(next[1]) > 1. {
+                break;
+            }
+            if (next[0] - next[1]).abs() <= EPSILON {
+                if !self.walkable_at(cell[0] + step[0], cell[1]) || !self.walkable_at(cell[0], cell[1] + step[1]) {
+                    return false;
+                }
+                cell = [cell[0] + step[0], cell[1] + step[1]];
+                next = [next[0] + delta[0], next[1] + delta[1]];
+            } else if next[0] < next[1] {
+                cell[0] += step[0];
+                next[0] += delta[0];
+            } else {
+                cell[1] += step[1];
+                next[1] += delta[1];
+            }
+            if !self.walkable_at(cell[0], cell[1]) {
+                return false;
+            }
+        }
+        true
+    }
+
+    ///
+    /// Finds the cells of the shortest path from the cell of `start`
+    /// to the cell of `end` with A*.
+    ///
+    /// Returns `None` when either is outside the grid or blocked, or
+    /// when they aren't connected.
+    ///
+    pub fn find_cells(&self, start: [f32; 2], end: [f32; 2]) -> Option<Vec<[usize; 2]>> {
+        let (from, to) = (self.cell_at(start)?, self.cell_at(end)?);
+        if !self.is_walkable(from[0], from[1]) || !self.is_walkable(to[0], to[1]) {
+            return None;
+        }
+        let width = self.width;
+        let [cell_width, cell_height] = self.cell_size;
+        let diagonal = cell_width.hypot(cell_height);
+        let path = astar(
+            self.walkable.len(),
+            from[1] * width + from[0],
+            to[1] * width + to[0],
+            |cell, adjacent| {
+                let (x, y) = ((cell % width) as isize, (cell / width) as isize);
+                for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if !self.walkable_at(nx, ny) {
+                        continue;
+                    }
+                    let step = match (dx, dy) {
+                        (0, _) => cell_height,
+                        (_, 0) => cell_width,
+                        _ if self.walkable_at(x + dx, y) && self.walkable_at(x, y + dy) => diagonal,
+                        _ => continue,
+                    };
+                    adjacent.push((ny as usize * width + nx as usize, step));
+                }
+            },
+            |cell| {
+                let dx = ((cell % width) as f32 - to[0] as f32) * cell_width;
+                let dy = ((cell / width) as f32 - to[1] as f32) * cell_height;
+                dx.hypot(dy)
+            },
+        )?;
+        Some(path.into_iter().map(|x| [x % width, x / width]).collect())
+    }
+
+    ///
+    /// Finds a path from `start` to `end` through the cells
+    /// `find_cells` finds, smoothed by skipping ahead to the furthest
+    /// point in sight at each turn.
+    ///
+    pub fn find_path(&self, start: [f32; 2], end: [f32; 2]) -> Option<Vec<[f32; 2]>> {
+        let cells = self.find_cells(start, end)?;
+        let mut points = Vec::with_capacity(cells.len());
+        points.push(start);
+        if cells.len() > 2 {
+            points.extend(cells[1..cells.len() - 1].iter().map(|&x| self.cell_center(x)));
+        }
+        points.push(end);
+
+        let mut path = vec![start];
+        let mut current = 0;
+        while current < points.len() - 1 {
+            let mut furthest = current + 1;
+            while furthest + 1 < points.len() && self.line_of_sight(points[current], points[furthest + 1]) {
+                furthest += 1;
+            }
+            path.push(points[furthest]);
+            current = furthest;
+        }
+        Some(path)
+    }
+}
+
+// Twice the signed area of a triangle seen from above, positive when counter-clockwise in `x` and `z`
+fn area2(a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> f32 {
+    (b.x - a.x) * (c.z - a.z) - (b.z - a.z) * (c.x - a.x)
+}
+
+// Pulls a path tight through a series of `(left, right)` portals, seen from above
+fn funnel(portals: &[(Point3<f32>, Point3<f32>)]) -> Vec<Point3<f32>> {
+    let (mut apex, mut left, mut right) = (portals[0].0, portals[0].0, portals[0].1);
+    let (mut left_index, mut right_index) = (0, 0);
+    let mut path = vec![apex];
+    let mut index = 1;
+    while index < portals.len() {
+        let (next_left, next_right) = portals[index];
+        if area2(apex, right, next_right) >= 0. {
+            if apex == right || area2(apex, left, next_right) < 0. {
+                right = next_right;
+                right_index = index;
+            } else {
+                // The right side crossed over the left, which makes the left a corner
+                apex = left;
+                // Corners shared by the next portals can be reached again
+                if path.last() != Some(&apex) {
+                    path.push(apex);
+                }
+                right = apex;
+                right_index = left_index;
+                index = left_index + 1;
+                continue;
+            }
+        }
+        if area2(apex, left, next_left) <= 0. {
+            if apex == left || area2(apex, right, next_left) > 0. {
+                left = next_left;
+                left_index = index;
+            } else {
+                apex = right;
+                if path.last() != Some(&apex) {
+                    path.push(apex);
+                }
+                left = apex;
+                left_index = right_index;
+                index = right_index + 1;
+                continue;
+            }
+        }
+        index += 1;
+    }
+    let end = portals[portals.len() - 1].0;
+    if path.last() != Some(&end) {
+        path.push(end);
+    }
+    path
+}
+
+// The closest point to `p` on the triangle `abc`, from Real-Time Collision Detection
+fn closest_on_triangle(p: Point3<f32>, a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> Point3<f32> {
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0. && d2 <= 0. {
+        return a;
+    }
+    let bp = p - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0. && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0. && d1 >= 0. && d3 <= 0. {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0. && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0. && d2 >= 0. && d6 <= 0. {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0. && d4 - d3 >= 0. && d5 - d6 >= 0. {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = 1. / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+// Merges vertices closer than a distance, looking them up by the cell of a grid they're in
+struct Welder {
+    distance: f32,
+    vertices: Vec<Point3<f32>>,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+}
+
+impl Welder {
+    fn new(distance: f32) -> Self {
+        Self {
+            distance: distance.max(EPSILON),
+            vertices: Vec::new(),
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, point: Point3<f32>) -> [i64; 3] {
+        [
+            (point.x / self.distance).floor() as i64,
+            (point.y / self.distance).floor() as i64,
+            (point.z / self.distance).floor() as i64,
+        ]
+    }
+
+    fn add(&mut self, point: Point3<f32>) -> usize {
+        let [x, y, z] = self.cell(point);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(close) = self.cells
+                        .get(&[x + dx, y + dy, z + dz])
+                        .and_then(|x| x.iter().find(|&&x| self.vertices[x].distance(point) <= self.distance))
+                    {
+                        return *close;
+                    }
+                }
+            }
+        }
+        self.vertices.push(point);
+        self.cells.entry([x, y, z]).or_default().push(self.vertices.len() - 1);
+        self.vertices.len() - 1
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Open {
+    estimate: f32,
+    cost: f32,
+    node: usize,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    // Reversed, so the heap gives the lowest estimate first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.partial_cmp(&self.estimate).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Finds the cheapest path from `start` to `goal` through `nodes` nodes, where `neighbours`
+// adds each node next to one along with the cost of moving to it
+fn astar(
+    nodes: usize,
+    start: usize,
+    goal: usize,
+    mut neighbours: impl FnMut(usize, &mut Vec<(usize, f32)>),
+    heuristic: impl Fn(usize) -> f32,
+) -> Option<Vec<usize>> {
+    let mut costs = vec![f32::INFINITY; nodes];
+    let mut came_from = vec![None; nodes];
+    let mut open = BinaryHeap::new();
+    let mut adjacent = Vec::new();
+    costs[start] = 0.;
+    open.push(Open { estimate: heuristic(start), cost: 0., node: start });
+    while let Some(Open { cost, node, .. }) = open.pop() {
+        if node == goal {
+            let mut path = vec![goal];
+            while let Some(previous) = came_from[*path.last().unwrap()] {
+                path.push(previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        // Already reached more cheaply since being added
+        if cost > costs[node] {
+            continue;
+        }
+        adjacent.clear();
+        neighbours(node, &mut adjacent);
+        for &(next, step) in &adjacent {
+            let cost = cost + step;
+            if cost < costs[next] {
+                costs[next] = cost;
+                came_from[next] = Some(node);
+                open.push(Open { estimate: cost + heuristic(next), cost, node: next });
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A floor of unit squares, each with its own vertices, every other one wound clockwise
+    fn floor(squares: &[(f32, f32)]) -> (Vec<[f32; 3]>, Vec<u32>) {
+        let (mut positions, mut indices) = (Vec::new(), Vec::new());
+        for (i, &(x, z)) in squares.iter().enumerate() {
+            let first = positions.len() as u32;
+            positions.extend_from_slice(&[[x, 0., z], [x + 1., 0., z], [x + 1., 0., z + 1.], [x, 0., z + 1.]]);
+            if i % 2 == 0 {
+                indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+            } else {
+                indices.extend_from_slice(&[first, first + 2, first + 1, first, first + 3, first + 2]);
+            }
+        }
+        (positions, indices)
+    }
+
+    #[test]
+    fn bakes_walkable_triangles() {
+        let (mut positions, mut indices) = floor(&[(0., 0.), (1., 0.)]);
+        // A wall along one side
+        let first = positions.len() as u32;
+        positions.extend_from_slice(&[[0., 0., 0.], [2., 0., 0.], [2., 2., 0.]]);
+        indices.extend_from_slice(&[first, first + 1, first + 2]);
+        let mesh = NavMesh::bake(&positions, &indices, &NavSettings::default());
+        assert_eq!(mesh.triangles().len(), 4);
+        assert_eq!(mesh.vertices().len(), 6);
+        let connected = (0..4).map(|x| mesh.neighbours(x).count()).sum::<usize>();
+        // Three edges inside the floor, each counted from both sides
+        assert_eq!(connected, 6);
+
+        let (triangle, on) = mesh.locate(Point3::new(1.5, 3., 0.5)).unwrap();
+        assert!(triangle < 4);
+        assert_eq!(on, Point3::new(1.5, 0., 0.5));
+        assert!(mesh.locate(Point3::new(3., 0., 0.5)).is_none());
+        let (_, near) = mesh.nearest(Point3::new(3., 0., 0.5)).unwrap();
+        assert!(near.distance(Point3::new(2., 0., 0.5)) < 1e-5);
+    }
+
+    #[test]
+    fn mesh_paths_turn_at_corners() {
+        let (positions, indices) = floor(&[(0., 0.), (1., 0.), (2., 0.), (2., 1.), (2., 2.)]);
+        let mesh = NavMesh::bake(&positions, &indices, &NavSettings::default());
+        let (start, end) = (Point3::new(0.5, 0., 0.5), Point3::new(2.5, 0., 2.5));
+        let path = mesh.find_path(start, end).unwrap();
+        assert_eq!(path.len(), 3);
+        assert_eq!(path[0], start);
+        assert!(path[1].distance(Point3::new(2., 0., 1.)) < 1e-5);
+        assert_eq!(path[2], end);
+
+        let (positions, indices) = floor(&[(0., 0.), (1., 0.), (2., 0.), (2., -1.), (2., -2.)]);
+        let mirrored = NavMesh::bake(&positions, &indices, &NavSettings::default());
+        let path = mirrored.find_path(start, Point3::new(2.5, 0., -1.5)).unwrap();
+        assert_eq!(path.len(), 3);
+        assert!(path[1].distance(Point3::new(2., 0., 0.)) < 1e-5);
+
+        let straight = mesh.find_path(start, Point3::new(2.5, 0., 0.5)).unwrap();
+        assert_eq!(straight.len(), 2);
+
+        let (positions, indices) = floor(&[(0., 0.), (5., 0.)]);
+        let apart = NavMesh::bake(&positions, &indices, &NavSettings::default());
+        assert!(apart.find_path(start, Point3::new(5.5, 0., 0.5)).is_none());
+    }
+
+    #[test]
+    fn grid_paths() {
+        // A wall down the middle with a gap in the last row
+        let mut grid = NavGrid::new(5, 5, [2., 2.]).with_origin([10., 0.]);
+        for y in 0..4 {
+            grid.set_walkable(2, y, false);
+        }
+        let (start, end) = ([11., 1.], [19., 1.]);
+        let cells = grid.find_cells(start, end).unwrap();
+        assert_eq!(cells.first(), Some(&[0, 0]));
+        assert_eq!(cells.last(), Some(&[4, 0]));
+        assert!(cells.iter().all(|x| grid.is_walkable(x[0], x[1])));
+
+        let path = grid.find_path(start, end).unwrap();
+        assert!(path.len() < cells.len());
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&end));
+        assert!(path.windows(2).all(|x| grid.line_of_sight(x[0], x[1])));
+        assert!(!grid.line_of_sight(start, end));
+
+        grid.set_walkable(2, 4, false);
+        assert!(grid.find_path(start, end).is_none());
+        assert!(grid.find_path([0., 0.], end).is_none());
+    }
+}
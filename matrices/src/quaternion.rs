@@ -0,0 +1,197 @@
+use crate::{Transform, TransformHierarchy, TransformLock};
+use cgmath::{InnerSpace, Matrix3, Matrix4, One, Quaternion, Rad, Rotation3, Vector3, VectorSpace, Zero};
+
+///
+/// A `Transform` which stores its rotation as a quaternion, and its
+/// scale and translation as vectors.
+///
+/// Multiplying rotation matrices together over and over lets them
+/// drift away from being rotations, while a quaternion only needs to
+/// be normalized. Quaternions can also be interpolated, so this is
+/// the form to keep animated transforms in, converting to a
+/// `Transform` (or pushing with `TransformHierarchy::push_q`) to
+/// draw them.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformQ {
+    pub scale: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub translation: Vector3<f32>,
+}
+
+impl Default for TransformQ {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl TransformQ {
+    ///
+    /// An identity transform. Does nothing when applied
+    ///
+    #[inline]
+    pub fn identity() -> Self {
+        Self {
+            scale: Vector3::new(1., 1., 1.),
+            rotation: Quaternion::one(),
+            translation: Vector3::zero(),
+        }
+    }
+
+    ///
+    /// Converts a `Transform`, or returns `None` if its parts aren't
+    /// a scale, rotation and translation (see `Transform::from_matrix`).
+    ///
+    pub fn from_transform(transform: &Transform<Matrix4<f32>>) -> Option<Self> {
+        let parts = Transform::from_matrix(transform.to_matrix(|s, r, t| s * r * t))?;
+        let rotate = Matrix3::from_cols(parts.rotate.x.truncate(), parts.rotate.y.truncate(), parts.rotate.z.truncate());
+        Some(Self {
+            scale: Vector3::new(parts.scale.x.x, parts.scale.y.y, parts.scale.z.z),
+            rotation: Quaternion::from(rotate).normalize(),
+            translation: parts.translate.w.truncate(),
+        })
+    }
+
+    ///
+    /// Converts into a `Transform` of matrices.
+    ///
+    pub fn to_transform(&self) -> Transform<Matrix4<f32>> {
+        Transform {
+            scale: Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z),
+            rotate: self.rotation.into(),
+            translate: Matrix4::from_translation(self.translation),
+        }
+    }
+
+    ///
+    /// Translates this transform by `delta_pos` relative
+    /// to its local origin (Before rotations).
+    ///
+    #[inline]
+    pub fn translate_by(&mut self, delta_pos: Vector3<f32>) {
+        self.translation += delta_pos;
+    }
+
+    ///
+    /// Rotates this transform along an axis by `angle` radians.
+    ///
+    #[inline]
+    pub fn rotate_axis(&mut self, axis: Vector3<f32>, angle: Rad<f32>) {
+        self.rotation = self.rotation * Quaternion::from_axis_angle(axis, angle);
+    }
+
+    ///
+    /// Scales this transform's x, y, and z.
+    ///
+    #[inline]
+    pub fn scale_xyz(&mut self, x: f32, y: f32, z: f32) {
+        self.scale = Vector3::new(self.scale.x * x, self.scale.y * y, self.scale.z * z);
+    }
+
+    ///
+    /// Brings the rotation back to unit length, undoing any drift
+    /// from rotating it many times.
+    ///
+    #[inline]
+    pub fn normalize(&mut self) {
+        self.rotation = self.rotation.normalize();
+    }
+
+    // The rotation of `other`, flipped if needed to take the shorter way around from this one
+    fn nearest_rotation(&self, other: &Self) -> Quaternion<f32> {
+        if self.rotation.dot(other.rotation) < 0. {
+            -other.rotation
+        } else {
+            other.rotation
+        }
+    }
+
+    ///
+    /// Interpolates towards `other` by `amount` (from 0 to 1),
+    /// normalizing the linearly interpolated rotation.
+    ///
+    /// Cheaper than `slerp`, but doesn't rotate at a constant speed,
+    /// which is only noticeable between rotations that are far apart.
+    ///
+    pub fn nlerp(&self, other: &Self, amount: f32) -> Self {
+        Self {
+            scale: self.scale.lerp(other.scale, amount),
+            rotation: self.rotation.nlerp(self.nearest_rotation(other), amount),
+            translation: self.translation.lerp(other.translation, amount),
+        }
+    }
+
+    ///
+    /// Interpolates towards `other` by `amount` (from 0 to 1),
+    /// rotating the shorter way around at a constant speed.
+    ///
+    pub fn slerp(&self, other: &Self, amount: f32) -> Self {
+        Self {
+            scale: self.scale.lerp(other.scale, amount),
+            rotation: self.rotation.normalize().slerp(self.nearest_rotation(other).normalize(), amount),
+            translation: self.translation.lerp(other.translation, amount),
+        }
+    }
+}
+
+impl From<TransformQ> for Transform<Matrix4<f32>> {
+    fn from(transform: TransformQ) -> Self {
+        transform.to_transform()
+    }
+}
+
+impl<F: Fn(Matrix4<f32>, Matrix4<f32>, Matrix4<f32>) -> Matrix4<f32>> TransformHierarchy<Matrix4<f32>, F> {
+    ///
+    /// Pushes a `TransformQ`, like `push_transform`.
+    ///
+    #[inline]
+    pub fn push_q(&'_ mut self, transform: &TransformQ) -> TransformLock<'_, Matrix4<f32>, F> {
+        self.push_transform(transform.to_transform())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-4
+    }
+
+    #[test]
+    fn convert() {
+        let mut transform = TransformQ::identity();
+        transform.scale_xyz(2., 1., 3.);
+        transform.rotate_axis(Vector3::unit_y(), Rad(1.2));
+        transform.translate_by(Vector3::new(1., 2., 3.));
+        let back = TransformQ::from_transform(&transform.into()).unwrap();
+        assert!(close(back.scale, transform.scale));
+        assert!(close(back.translation, transform.translation));
+        assert!((back.rotation.dot(transform.rotation).abs() - 1.).abs() < 1e-4);
+
+        let mut transforms = TransformHierarchy::new(Matrix4::one(), |s, r, t| s * r * t);
+        let lock = transforms.push_q(&transform);
+        assert_eq!(*lock.current(), transform.to_transform().to_matrix(|s, r, t| s * r * t));
+    }
+
+    #[test]
+    fn interpolate() {
+        let a = TransformQ::identity();
+        let mut b = TransformQ::identity();
+        b.rotate_axis(Vector3::unit_z(), Rad(std::f32::consts::FRAC_PI_2));
+        b.translate_by(Vector3::new(2., 0., 0.));
+        // The same rotation as `b`, the long way around
+        let mut c = b;
+        c.rotation = -c.rotation;
+
+        let half = a.slerp(&b, 0.5);
+        let expected = Quaternion::from_axis_angle(Vector3::unit_z(), Rad(std::f32::consts::FRAC_PI_4));
+        assert!((half.rotation.dot(expected) - 1.).abs() < 1e-4);
+        assert!(close(half.translation, Vector3::new(1., 0., 0.)));
+        assert!((a.slerp(&c, 0.5).rotation.dot(expected) - 1.).abs() < 1e-4);
+        assert!((a.nlerp(&c, 0.5).rotation.dot(expected) - 1.).abs() < 1e-4);
+        let start = a.slerp(&b, 0.);
+        assert!((start.rotation.dot(a.rotation) - 1.).abs() < 1e-4);
+        assert!(close(start.translation, a.translation));
+    }
+}
@@ -0,0 +1,351 @@
+//!
+//! A tree of nodes, each with its own `Transform` relative to its
+//! parent, which keeps every node's world matrix cached.
+//!
+//! Where a `TransformHierarchy` is built up and torn down each frame,
+//! a `SceneGraph` persists: nodes are added once, moved by changing
+//! their transforms, and only the world matrices of nodes which moved
+//! (or whose ancestors moved) are multiplied again.
+//!
+
+use crate::Transform;
+use cgmath::{Matrix4, One};
+
+///
+/// A handle to a node of a `SceneGraph`.
+///
+/// Handles to removed nodes never refer to a node added later, even
+/// if it reuses the same slot.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Node<D> {
+    data: D,
+    transform: Transform<Matrix4<f32>>,
+    world: Matrix4<f32>,
+    dirty: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone)]
+struct Slot<D> {
+    generation: u32,
+    node: Option<Node<D>>,
+}
+
+///
+/// A tree of nodes holding `D`, each placed relative to its parent.
+///
+/// A node's world matrix is `parent_world * scale * rotate * translate`,
+/// the same order `ShaderContext` pushes transforms in, so drawing a
+/// child inside its parent's transform and drawing it with its world
+/// matrix give the same result.
+///
+/// World matrices are brought up to date by `update`, which the
+/// traversal functions call first.
+///
+#[derive(Debug, Clone)]
+pub struct SceneGraph<D> {
+    slots: Vec<Slot<D>>,
+    free: Vec<usize>,
+    roots: Vec<NodeId>,
+}
+
+impl<D> SceneGraph<D> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            roots: Vec::new(),
+        }
+    }
+
+    fn node(&self, id: NodeId) -> Option<&Node<D>> {
+        self.slots
+            .get(id.index)
+            .filter(|x| x.generation == id.generation)
+            .and_then(|x| x.node.as_ref())
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node<D>> {
+        self.slots
+            .get_mut(id.index)
+            .filter(|x| x.generation == id.generation)
+            .and_then(|x| x.node.as_mut())
+    }
+
+    fn insert(&mut self, node: Node<D>) -> NodeId {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.node = Some(node);
+                NodeId { index, generation: slot.generation }
+            },
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node) });
+                NodeId { index: self.slots.len() - 1, generation: 0 }
+            }
+        }
+    }
+
+    ///
+    /// Adds a node at the top of the tree.
+    ///
+    pub fn add(&mut self, data: D, transform: Transform<Matrix4<f32>>) -> NodeId {
+        let id = self.insert(Node {
+            data,
+            transform,
+            world: Matrix4::one(),
+            dirty: true,
+            parent: None,
+            children: Vec::new(),
+        });
+        self.roots.push(id);
+        id
+    }
+
+    ///
+    /// Adds a node as the last child of `parent`, or returns `None`
+    /// if `parent` has been removed.
+    ///
+    pub fn add_child(&mut self, parent: NodeId, data: D, transform: Transform<Matrix4<f32>>) -> Option<NodeId> {
+        self.node(parent)?;
+        let id = self.insert(Node {
+            data,
+            transform,
+            world: Matrix4::one(),
+            dirty: true,
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        self.node_mut(parent).unwrap().children.push(id);
+        Some(id)
+    }
+
+    ///
+    /// Removes a node along with all of its descendants, returning
+    /// its data.
+    ///
+    pub fn remove(&mut self, id: NodeId) -> Option<D> {
+        let parent = self.node(id)?.parent;
+        self.detach(id, parent);
+        let mut removed = None;
+        let mut stack = vec![id];
+        while let Some(next) = stack.pop() {
+            let slot = &mut self.slots[next.index];
+            let node = slot.node.take().unwrap();
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(next.index);
+            stack.extend_from_slice(&node.children);
+            if next == id {
+                removed = Some(node.data);
+            }
+        }
+        removed
+    }
+
+    // Takes `id` out of its parent's (or the roots') list of children
+    fn detach(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let siblings = match parent {
+            Some(parent) => &mut self.node_mut(parent).unwrap().children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&x| x != id);
+    }
+
+    ///
+    /// Moves `id` (and its descendants) to be the last child of
+    /// `parent`, or to the top of the tree when `parent` is `None`.
+    /// The node keeps its local transform, so it moves with its new
+    /// parent.
+    ///
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), String> {
+        let old = self.node(id).ok_or("Node has been removed")?.parent;
+        if let Some(parent) = parent {
+            self.node(parent).ok_or("Parent has been removed")?;
+            if self.ancestors(parent).any(|x| x == id) || parent == id {
+                return Err("A node can't be moved under itself".to_string());
+            }
+        }
+        self.detach(id, old);
+        match parent {
+            Some(parent) => self.node_mut(parent).unwrap().children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = self.node_mut(id).unwrap();
+        node.parent = parent;
+        node.dirty = true;
+        Ok(())
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.node(id).is_some()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&D> {
+        self.node(id).map(|x| &x.data)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut D> {
+        self.node_mut(id).map(|x| &mut x.data)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id)?.parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.node(id).map(|x| &x.children[..]).unwrap_or(&[])
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    ///
+    /// Iterates over the parent of `id`, then its parent and so on
+    /// up to the top of the tree.
+    ///
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), move |&x| self.parent(x))
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn transform(&self, id: NodeId) -> Option<&Transform<Matrix4<f32>>> {
+        self.node(id).map(|x| &x.transform)
+    }
+
+    ///
+    /// Gets the transform of `id` to change, marking its world matrix
+    /// (and those of its descendants) as needing to be updated.
+    ///
+    pub fn transform_mut(&mut self, id: NodeId) -> Option<&mut Transform<Matrix4<f32>>> {
+        let node = self.node_mut(id)?;
+        node.dirty = true;
+        Some(&mut node.transform)
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform<Matrix4<f32>>) {
+        if let Some(x) = self.transform_mut(id) {
+            *x = transform;
+        }
+    }
+
+    ///
+    /// Gets the world matrix of `id` as of the last `update`.
+    ///
+    pub fn world(&self, id: NodeId) -> Option<&Matrix4<f32>> {
+        self.node(id).map(|x| &x.world)
+    }
+
+    ///
+    /// Multiplies out the world matrices of every node which has moved
+    /// since the last update, along with their descendants.
+    ///
+    pub fn update(&mut self) {
+        let mut stack = self.roots
+            .iter()
+            .rev()
+            .map(|&x| (x, Matrix4::one(), false))
+            .collect::<Vec<_>>();
+        while let Some((id, parent_world, parent_moved)) = stack.pop() {
+            let node = self.node_mut(id).unwrap();
+            let moved = parent_moved || node.dirty;
+            if moved {
+                node.world = parent_world * node.transform.to_matrix(|s, r, t| s * r * t);
+                node.dirty = false;
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|&x| (x, world, moved)));
+        }
+    }
+
+    ///
+    /// Updates the world matrices, then calls `f` with every node's
+    /// world matrix and data, visiting parents before their children
+    /// and children in the order they were added.
+    ///
+    pub fn for_each_world<F: FnMut(NodeId, &Matrix4<f32>, &mut D)>(&mut self, mut f: F) {
+        self.update();
+        let mut stack = self.roots.iter().rev().copied().collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            let node = self.node_mut(id).unwrap();
+            f(id, &node.world, &mut node.data);
+            stack.extend(node.children.iter().rev());
+        }
+    }
+}
+
+impl<D> Default for SceneGraph<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector3;
+
+    fn moved(x: f32) -> Transform<Matrix4<f32>> {
+        let mut transform = Transform::identity();
+        transform.translate_by(Vector3::new(x, 0., 0.));
+        transform
+    }
+
+    #[test]
+    fn world_matrices() {
+        let mut scene = SceneGraph::new();
+        let root = scene.add("root", moved(1.));
+        let child = scene.add_child(root, "child", moved(2.)).unwrap();
+        let grandchild = scene.add_child(child, "grandchild", moved(3.)).unwrap();
+        scene.update();
+        assert_eq!(scene.world(grandchild).unwrap().w.x, 6.);
+
+        scene.transform_mut(root).unwrap().translate_by(Vector3::new(10., 0., 0.));
+        scene.update();
+        assert_eq!(scene.world(child).unwrap().w.x, 13.);
+        assert_eq!(scene.world(grandchild).unwrap().w.x, 16.);
+
+        let mut visited = Vec::new();
+        scene.for_each_world(|_, world, data| visited.push((*data, world.w.x)));
+        assert_eq!(visited, vec![("root", 11.), ("child", 13.), ("grandchild", 16.)]);
+    }
+
+    #[test]
+    fn reparent_and_remove() {
+        let mut scene = SceneGraph::new();
+        let a = scene.add(0, moved(1.));
+        let b = scene.add(1, moved(5.));
+        let c = scene.add_child(a, 2, moved(1.)).unwrap();
+        assert!(scene.set_parent(a, Some(c)).is_err());
+
+        scene.set_parent(c, Some(b)).unwrap();
+        scene.update();
+        assert_eq!(scene.world(c).unwrap().w.x, 6.);
+        assert_eq!(scene.children(b), &[c]);
+        assert!(scene.children(a).is_empty());
+
+        assert_eq!(scene.remove(b), Some(1));
+        assert!(!scene.contains(c));
+        assert_eq!(scene.len(), 1);
+        let d = scene.add(3, moved(0.));
+        assert_ne!(d, b);
+        assert_ne!(d, c);
+        assert!(scene.get(b).is_none());
+        assert_eq!(scene.roots(), &[a, d]);
+    }
+}
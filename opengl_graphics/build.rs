@@ -9,7 +9,7 @@ fn main() {
     let dest = env::var("CARGO_MANIFEST_DIR").unwrap();
     let mut file = File::create(&Path::new(&dest).join("./src/gl.rs")).unwrap();
 
-    Registry::new(Api::Gles2, (3, 2), Profile::Compatibility, Fallbacks::All, [])
+    Registry::new(Api::Gles2, (3, 2), Profile::Compatibility, Fallbacks::All, ["GL_EXT_multi_draw_arrays"])
         .write_bindings(GlobalGenerator, &mut file)
         .unwrap();
 }
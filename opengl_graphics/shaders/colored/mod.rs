@@ -4,4 +4,16 @@
 pub const VERTEX_GLSL_120: &'static [u8] = include_bytes!("120.glslv");
 
 /// Fragment shader for GLSL 1.20
-pub const FRAGMENT_GLSL_120: &'static [u8] = include_bytes!("120.glslf");
\ No newline at end of file
+pub const FRAGMENT_GLSL_120: &'static [u8] = include_bytes!("120.glslf");
+
+/// Vertex shader for GLSL ES 1.00, used with OpenGL ES 2.0
+pub const VERTEX_GLSL_ES_100: &'static [u8] = include_bytes!("es100.glslv");
+
+/// Fragment shader for GLSL ES 1.00, used with OpenGL ES 2.0
+pub const FRAGMENT_GLSL_ES_100: &'static [u8] = include_bytes!("es100.glslf");
+
+/// Vertex shader for GLSL ES 3.00, used with OpenGL ES 3.0 and later
+pub const VERTEX_GLSL_ES_300: &'static [u8] = include_bytes!("es300.glslv");
+
+/// Fragment shader for GLSL ES 3.00, used with OpenGL ES 3.0 and later
+pub const FRAGMENT_GLSL_ES_300: &'static [u8] = include_bytes!("es300.glslf");
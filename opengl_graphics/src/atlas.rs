@@ -0,0 +1,181 @@
+//! Packing images into one texture and drawing sprites from it
+
+// External crates.
+use graphics::math::Matrix2d;
+use graphics::{DrawState, Graphics, BACK_END_MAX_VERTEX_COUNT};
+use image::{imageops, RgbaImage};
+use std::collections::HashMap;
+
+// Local crate.
+use back_end::GlGraphics;
+use texture::Texture;
+use TextureSettings;
+
+/// Where an image was packed into a `TextureAtlas`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRegion {
+    /// The position and size of the image in the atlas, in pixels
+    pub rect: [u32; 4],
+    /// The texture coordinates of the image's corners, `[left, top, right, bottom]`
+    pub uv: [f32; 4],
+}
+
+/// Many images packed into a single texture, so sprites using any of them can be drawn
+/// without flushing for a texture change.
+pub struct TextureAtlas {
+    texture: Texture,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    /// Packs `images` into rows of an atlas at most `max_size` pixels wide and tall, with
+    /// `padding` pixels between them to stop filtering bleeding neighbours into each other.
+    ///
+    /// Fails if the images don't fit.
+    pub fn pack(images: &[(&str, &RgbaImage)],
+                max_size: u32,
+                padding: u32,
+                settings: &TextureSettings)
+                -> Result<Self, String> {
+        // Packing the tallest first wastes less space in each row
+        let mut order = (0..images.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| ::std::cmp::Reverse(images[i].1.height()));
+
+        let mut placed = Vec::with_capacity(images.len());
+        let (mut x, mut y, mut row_height, mut width) = (0, 0, 0, 0);
+        for i in order {
+            let (name, image) = images[i];
+            let (w, h) = image.dimensions();
+            if w > max_size {
+                return Err(format!("{} is wider than the atlas", name));
+            }
+            if x + w > max_size {
+                x = 0;
+                y += row_height + padding;
+                row_height = 0;
+            }
+            if y + h > max_size {
+                return Err(format!("The images don't fit in a {}x{} atlas", max_size, max_size));
+            }
+            placed.push((i, [x, y, w, h]));
+            x += w + padding;
+            row_height = row_height.max(h);
+            width = width.max(x - padding);
+        }
+        let height = y + row_height;
+
+        let mut atlas = RgbaImage::new(width.max(1), height.max(1));
+        let mut regions = HashMap::with_capacity(images.len());
+        for (i, rect) in placed {
+            let (name, image) = images[i];
+            imageops::replace(&mut atlas, image, rect[0], rect[1]);
+            let (aw, ah) = (atlas.width() as f32, atlas.height() as f32);
+            regions.insert(name.to_string(), AtlasRegion {
+                rect: rect,
+                uv: [rect[0] as f32 / aw,
+                     rect[1] as f32 / ah,
+                     (rect[0] + rect[2]) as f32 / aw,
+                     (rect[1] + rect[3]) as f32 / ah],
+            });
+        }
+        Ok(TextureAtlas {
+            texture: Texture::from_image(&atlas, settings),
+            regions: regions,
+        })
+    }
+
+    /// The texture the images were packed into
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Where the image called `name` is
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).cloned()
+    }
+
+    /// The names of every image in the atlas
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.regions.keys().map(|x| x.as_str())
+    }
+}
+
+/// Collects sprites from a `TextureAtlas` and draws all of them in one draw call.
+///
+/// All the sprites in a batch share a colour, as the `Textured` shader tints with a uniform.
+pub struct SpriteBatch {
+    vertices: Vec<[f32; 2]>,
+    uvs: Vec<[f32; 2]>,
+}
+
+impl SpriteBatch {
+    /// Creates an empty batch
+    pub fn new() -> Self {
+        SpriteBatch {
+            vertices: Vec::new(),
+            uvs: Vec::new(),
+        }
+    }
+
+    /// The number of sprites waiting to be drawn
+    pub fn len(&self) -> usize {
+        self.vertices.len() / 6
+    }
+
+    /// Whether there are no sprites waiting to be drawn
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Removes every sprite without drawing them
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.uvs.clear();
+    }
+
+    /// Adds `region` drawn over `rect` (`[x, y, width, height]`), moved by `transform`
+    pub fn add(&mut self, region: AtlasRegion, rect: [f64; 4], transform: Matrix2d) {
+        let [x, y, w, h] = rect;
+        let [u0, v0, u1, v1] = region.uv;
+        let corners = [([x, y], [u0, v0]),
+                       ([x + w, y], [u1, v0]),
+                       ([x, y + h], [u0, v1]),
+                       ([x + w, y + h], [u1, v1])];
+        for &i in &[0, 1, 2, 1, 3, 2] {
+            let (pos, uv) = corners[i];
+            let m = transform;
+            self.vertices.push([(m[0][0] * pos[0] + m[0][1] * pos[1] + m[0][2]) as f32,
+                                (m[1][0] * pos[0] + m[1][1] * pos[1] + m[1][2]) as f32]);
+            self.uvs.push(uv);
+        }
+    }
+
+    /// Adds the image called `name` from `atlas`, returning whether the atlas has it
+    pub fn add_named(&mut self, atlas: &TextureAtlas, name: &str, rect: [f64; 4], transform: Matrix2d) -> bool {
+        match atlas.region(name) {
+            Some(region) => {
+                self.add(region, rect, transform);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Draws every sprite added since the last draw with `atlas`'s texture, then empties the batch
+    pub fn draw(&mut self, atlas: &TextureAtlas, color: [f32; 4], draw_state: &DrawState, gl: &mut GlGraphics) {
+        if self.is_empty() {
+            return;
+        }
+        {
+            let (vertices, uvs) = (&self.vertices, &self.uvs);
+            // Whole sprites in each chunk, so no triangle is split across flushes
+            let chunk = BACK_END_MAX_VERTEX_COUNT / 6 * 6;
+            gl.tri_list_uv(draw_state, &color, atlas.texture(), |f| {
+                for (vertices, uvs) in vertices.chunks(chunk).zip(uvs.chunks(chunk)) {
+                    f(vertices, uvs);
+                }
+            });
+        }
+        self.clear();
+    }
+}
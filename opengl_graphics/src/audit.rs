@@ -0,0 +1,84 @@
+//! Counting redundant state changes
+
+// External crates.
+use gl::types::GLuint;
+use graphics::DrawState;
+use std::fmt;
+
+/// How many times the back-end bound programs, textures and draw states
+/// since the audit was last taken, and how many of those changed nothing
+/// because the same one was already bound.
+///
+/// Redundant binds come from flushing between draws which share a shader
+/// or texture, so sorting draws by program then texture lowers them.
+///
+/// Only binds made through `GlGraphics` (including `Mesh::draw` and custom
+/// shader draws) are seen, so state changed directly with `gl` calls can
+/// make a bind look redundant when it isn't.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StateAudit {
+    /// Calls to `glUseProgram`.
+    pub program_binds: u32,
+    /// Programs bound while already in use.
+    pub redundant_program_binds: u32,
+    /// Textures bound for drawing.
+    pub texture_binds: u32,
+    /// Textures bound while already bound.
+    pub redundant_texture_binds: u32,
+    /// Draw states (blending, stencil and scissor) applied.
+    pub draw_state_binds: u32,
+    /// Draw states applied while identical to the current one.
+    pub redundant_draw_state_binds: u32,
+}
+
+impl StateAudit {
+    /// The total number of redundant binds.
+    pub fn redundant(&self) -> u32 {
+        self.redundant_program_binds + self.redundant_texture_binds + self.redundant_draw_state_binds
+    }
+}
+
+impl fmt::Display for StateAudit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "programs: {} ({} redundant), textures: {} ({} redundant), draw states: {} ({} redundant)",
+               self.program_binds, self.redundant_program_binds,
+               self.texture_binds, self.redundant_texture_binds,
+               self.draw_state_binds, self.redundant_draw_state_binds)
+    }
+}
+
+// Remembers what was last bound, which outlives each audit as GL state does
+#[derive(Clone, Copy, Default)]
+pub(crate) struct StateAuditor {
+    pub(crate) counts: StateAudit,
+    program: Option<GLuint>,
+    texture: Option<GLuint>,
+    draw_state: Option<DrawState>,
+}
+
+impl StateAuditor {
+    pub(crate) fn program(&mut self, program: GLuint) {
+        self.counts.program_binds += 1;
+        if self.program == Some(program) {
+            self.counts.redundant_program_binds += 1;
+        }
+        self.program = Some(program);
+    }
+
+    pub(crate) fn texture(&mut self, texture: GLuint) {
+        self.counts.texture_binds += 1;
+        if self.texture == Some(texture) {
+            self.counts.redundant_texture_binds += 1;
+        }
+        self.texture = Some(texture);
+    }
+
+    pub(crate) fn draw_state(&mut self, draw_state: &DrawState) {
+        self.counts.draw_state_binds += 1;
+        if self.draw_state.as_ref() == Some(draw_state) {
+            self.counts.redundant_draw_state_binds += 1;
+        }
+        self.draw_state = Some(*draw_state);
+    }
+}
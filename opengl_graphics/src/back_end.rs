@@ -1,7 +1,9 @@
 //! OpenGL back-end for Piston-Graphics.
 
 // External crates.
+use std::cell::RefCell;
 use std::ffi::CString;
+use image::RgbaImage;
 use shader_version::{OpenGL, Shaders};
 use shader_version::glsl::GLSL;
 use graphics::{Context, DrawState, Graphics, Viewport};
@@ -13,7 +15,13 @@ use gl::types::{GLint, GLsizei, GLuint};
 // Local crate.
 use draw_state;
 use Texture;
-use shader_utils::{compile_shader, DynamicAttribute, Shader};
+use shader_utils::{compile_shader, DynamicAttribute, ElementBuffer, Shader};
+use profile::{Profile, has_extension};
+use shader_uniforms::UniformCache;
+use audit::{StateAudit, StateAuditor};
+use timer::{GpuTimer, GpuTiming, time_flush};
+use error::{Attribute, DrawError};
+use shader_batch::ShaderBatch;
 
 // The number of chunks to fill up before rendering.
 // Amount of memory used: `BUFFER_SIZE * CHUNKS * 4 * (2 + 4)`
@@ -30,6 +38,8 @@ pub struct Colored {
     color: DynamicAttribute<[f32; 4]>,
     pos_buffer: Vec<[f32; 2]>,
     color_buffer: Vec<[f32; 4]>,
+    indices: Vec<u16>,
+    ebo: ElementBuffer,
     offset: usize,
 }
 
@@ -75,10 +85,11 @@ impl Shader for Colored {
             self.color.set(&self.color_buffer[..self.offset]);
             self.pos.bind_vao(self.vao);
             self.pos.set(&self.pos_buffer[..self.offset]);
-            gl::DrawArrays(gl::TRIANGLES, 0, self.offset as i32);
+            self.ebo.draw(&self.indices, self.offset);
             gl::BindVertexArray(0);
         }
 
+        self.indices.clear();
         self.offset = 0;
     }
 
@@ -95,11 +106,36 @@ impl Shader for Colored {
         Some(&mut self.color_buffer)
     }
     fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> { None }
-    fn index_buffer(&mut self) -> Option<&mut Vec<u16>> { None }
+    fn index_buffer(&mut self) -> Option<&mut Vec<u16>> {
+        Some(&mut self.indices)
+    }
     fn normal_buffer(&mut self) -> Option<&mut Vec<[f32; 3]>> { None }
 }
 
 impl Colored {
+    /// Generate using the pass-through shaders written for `profile`.
+    ///
+    /// # Panics
+    /// If the shaders fail to compile
+    pub fn for_profile(profile: Profile) -> Self {
+        use shaders::colored;
+        let (vertex, fragment) = match profile {
+            Profile::Desktop(glsl) => return Colored::new(glsl, None),
+            Profile::Gles2 => (colored::VERTEX_GLSL_ES_100, colored::FRAGMENT_GLSL_ES_100),
+            Profile::Gles3 => (colored::VERTEX_GLSL_ES_300, colored::FRAGMENT_GLSL_ES_300),
+        };
+        let src = |bytes| unsafe { ::std::str::from_utf8_unchecked(bytes) };
+
+        // `Shaders` is keyed by desktop GLSL versions, so the key is only a placeholder
+        let mut vertex_shaders = Shaders::new();
+        vertex_shaders.set(GLSL::V1_50, src(vertex));
+
+        let mut fragment_shaders = Shaders::new();
+        fragment_shaders.set(GLSL::V1_50, src(fragment));
+
+        Colored::from_vs_fs(GLSL::V1_50, vertex_shaders, fragment_shaders).unwrap()
+    }
+
     /// Generate using custom vertex and fragment shaders.
     pub fn from_vs_fs(glsl: GLSL, vertex_shaders   : Shaders<GLSL, str>,
                                   fragment_shaders : Shaders<GLSL, str>)
@@ -140,6 +176,8 @@ impl Colored {
             color: color,
             pos_buffer: vec![[0.0; 2]; CHUNKS * BUFFER_SIZE],
             color_buffer: vec![[0.0; 4]; CHUNKS * BUFFER_SIZE],
+            indices: Vec::new(),
+            ebo: ElementBuffer::new(),
             offset: 0,
         })
 
@@ -157,6 +195,8 @@ pub struct Textured {
     uv: DynamicAttribute<[f32; 2]>,
     pos_buffer: Vec<[f32; 2]>,
     uv_buffer: Vec<[f32; 2]>,
+    indices: Vec<u16>,
+    ebo: ElementBuffer,
     offset: usize,
     last_texture_id: GLuint,
     last_color: [f32; 4],
@@ -204,10 +244,11 @@ impl Shader for Textured {
             gl::Disable(gl::CULL_FACE);
             self.pos.set(&self.pos_buffer[..self.offset]);
             self.uv.set(&self.uv_buffer[..self.offset]);
-            gl::DrawArrays(gl::TRIANGLES, 0, self.offset as i32);
+            self.ebo.draw(&self.indices, self.offset);
             gl::BindVertexArray(0);
         }
 
+        self.indices.clear();
         self.offset = 0;
     }
 
@@ -224,11 +265,36 @@ impl Shader for Textured {
     fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> {
         Some(&mut self.uv_buffer)
     }
-    fn index_buffer(&mut self) -> Option<&mut Vec<u16>> { None }
+    fn index_buffer(&mut self) -> Option<&mut Vec<u16>> {
+        Some(&mut self.indices)
+    }
     fn normal_buffer(&mut self) -> Option<&mut Vec<[f32; 3]>> { None }
 }
 
 impl Textured {
+    /// Generate using the pass-through shaders written for `profile`.
+    ///
+    /// # Panics
+    /// If the shaders fail to compile
+    pub fn for_profile(profile: Profile) -> Self {
+        use shaders::textured;
+        let (vertex, fragment) = match profile {
+            Profile::Desktop(glsl) => return Textured::new(glsl, None),
+            Profile::Gles2 => (textured::VERTEX_GLSL_ES_100, textured::FRAGMENT_GLSL_ES_100),
+            Profile::Gles3 => (textured::VERTEX_GLSL_ES_300, textured::FRAGMENT_GLSL_ES_300),
+        };
+        let src = |bytes| unsafe { ::std::str::from_utf8_unchecked(bytes) };
+
+        // `Shaders` is keyed by desktop GLSL versions, so the key is only a placeholder
+        let mut vertex_shaders = Shaders::new();
+        vertex_shaders.set(GLSL::V1_50, src(vertex));
+
+        let mut fragment_shaders = Shaders::new();
+        fragment_shaders.set(GLSL::V1_50, src(fragment));
+
+        Textured::from_vs_fs(GLSL::V1_50, vertex_shaders, fragment_shaders).unwrap()
+    }
+
     /// Generate using custom vertex and fragment shaders.
     pub fn from_vs_fs(glsl: GLSL, vertex_shaders   : Shaders<GLSL, str>,
                                   fragment_shaders : Shaders<GLSL, str>)
@@ -277,6 +343,8 @@ impl Textured {
             uv: uv,
             pos_buffer: vec![[0.0; 2]; CHUNKS * BUFFER_SIZE],
             uv_buffer: vec![[0.0; 2]; CHUNKS * BUFFER_SIZE],
+            indices: Vec::new(),
+            ebo: ElementBuffer::new(),
             offset: 0,
             last_texture_id: 0,
             last_color: [0.0; 4],
@@ -301,25 +369,47 @@ pub struct GlGraphics {
     current_draw_state: Option<DrawState>,
     // Keeps track of the current viewport
     current_viewport: Option<Viewport>,
+    // Whether custom shader draws test against and write to the depth buffer
+    depth_test: bool,
+    depth_write: bool,
+    profile: Profile,
+    pub(crate) uniform_cache: RefCell<UniformCache>,
+    // Counts binds while auditing state changes
+    audit: Option<StateAuditor>,
+    // Times flushes while timing the GPU
+    gpu_timer: Option<GpuTimer>,
+    multi_draw: bool,
+    max_vertex_uniform_vectors: usize,
 }
 
 impl<'a> GlGraphics {
     /// Creates a new OpenGL back-end.
     ///
+    /// Its shaders are picked to suit the current context (see `Profile::detect`),
+    /// `opengl` decides the GLSL version used with desktop OpenGL contexts.
+    ///
     /// # Panics
     /// If the OpenGL function pointers have not been loaded yet.
     /// See https://github.com/PistonDevelopers/opengl_graphics/issues/103 for more info.
     pub fn new(opengl: OpenGL) -> Self {
         assert!(gl::Enable::is_loaded(), GL_FUNC_NOT_LOADED);
 
-        let glsl = opengl.to_glsl();
+        let profile = Profile::detect(opengl);
         // Load the vertices, color and texture coord buffers.
         GlGraphics {
-            colored: Colored::new(glsl, None),
-            textured: Textured::new(glsl, None),
+            colored: Colored::for_profile(profile),
+            textured: Textured::for_profile(profile),
             current_program: None,
             current_draw_state: None,
             current_viewport: None,
+            depth_test: false,
+            depth_write: true,
+            profile: profile,
+            uniform_cache: RefCell::new(UniformCache::default()),
+            audit: None,
+            gpu_timer: None,
+            multi_draw: supports_multi_draw(profile),
+            max_vertex_uniform_vectors: max_vertex_uniform_vectors(profile),
         }
     }
 
@@ -332,6 +422,7 @@ impl<'a> GlGraphics {
     pub fn from_colored_textured(colored : Colored, textured : Textured) -> Self {
         assert!(gl::Enable::is_loaded(), GL_FUNC_NOT_LOADED);
 
+        let profile = Profile::detect(OpenGL::V3_2);
         // Load the vertices, color and texture coord buffers.
         GlGraphics {
             colored: colored,
@@ -339,9 +430,34 @@ impl<'a> GlGraphics {
             current_program: None,
             current_draw_state: None,
             current_viewport: None,
+            depth_test: false,
+            depth_write: true,
+            profile: profile,
+            uniform_cache: RefCell::new(UniformCache::default()),
+            audit: None,
+            gpu_timer: None,
+            multi_draw: supports_multi_draw(profile),
+            max_vertex_uniform_vectors: max_vertex_uniform_vectors(profile),
         }
     }
 
+    /// Gets the kind of context this back-end is drawing with.
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// Whether many ranges of a mesh can be drawn in one call (see `Mesh::draw_ranges`),
+    /// which desktop OpenGL always can and OpenGL ES can with `GL_EXT_multi_draw_arrays`.
+    pub fn multi_draw(&self) -> bool {
+        self.multi_draw
+    }
+
+    /// How many `vec4`s of uniforms a vertex shader can declare, which bounds the size of
+    /// uniform arrays like bone palettes. OpenGL ES guarantees at least 128 (256 since 3.0).
+    pub fn max_vertex_uniform_vectors(&self) -> usize {
+        self.max_vertex_uniform_vectors
+    }
+
     /// Sets viewport with normalized coordinates and center as origin.
     fn viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
         unsafe {
@@ -349,6 +465,21 @@ impl<'a> GlGraphics {
         }
     }
 
+    /// Changes the viewport in the middle of a frame, flushing anything drawn with the previous one.
+    ///
+    /// The viewport's rectangle is in pixels with the origin at the bottom left.
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.draw_end();
+        let rect = viewport.rect;
+        self.viewport(rect[0], rect[1], rect[2], rect[3]);
+        self.current_viewport = Some(viewport);
+    }
+
+    /// Returns the current viewport
+    pub fn get_current_viewport(&self) -> Option<Viewport> {
+        self.current_viewport
+    }
+
     /// Returns the current program
     pub fn get_current_program(&self) -> Option<GLuint> {
         self.current_program
@@ -369,6 +500,61 @@ impl<'a> GlGraphics {
             gl::UseProgram(program);
         }
         self.current_program = Some(program);
+        if let Some(ref mut audit) = self.audit {
+            audit.program(program);
+        }
+    }
+
+    /// Starts or stops counting the programs, textures and draw states bound,
+    /// to find the ones bound again while already in use.
+    ///
+    /// Auditing is off by default, as it costs a little on every bind.
+    pub fn set_state_audit(&mut self, enabled: bool) {
+        if enabled != self.audit.is_some() {
+            self.audit = if enabled { Some(StateAuditor::default()) } else { None };
+        }
+    }
+
+    /// Gets the binds counted since the audit was last taken, and starts counting
+    /// again from zero. Taken after every frame, this gives per frame counts.
+    ///
+    /// Returns `None` when auditing is off, see `set_state_audit`.
+    pub fn take_state_audit(&mut self) -> Option<StateAudit> {
+        self.audit.as_mut().map(|audit| ::std::mem::replace(&mut audit.counts, StateAudit::default()))
+    }
+
+    /// Starts or stops timing how long the GPU spends on each flush, with
+    /// timer queries. Needs `GL_EXT_disjoint_timer_query` on OpenGL ES, or
+    /// `GL_ARB_timer_query` on desktop OpenGL, and stays off without them.
+    ///
+    /// Timing is off by default, as it adds a query to every flush.
+    pub fn set_gpu_timing(&mut self, enabled: bool) {
+        if enabled != self.gpu_timer.is_some() {
+            self.gpu_timer = if enabled { GpuTimer::new() } else { None };
+        }
+    }
+
+    /// Whether the GPU is being timed, which is only ever true when the
+    /// context supports it, see `set_gpu_timing`.
+    pub fn gpu_timing(&self) -> bool {
+        self.gpu_timer.is_some()
+    }
+
+    /// Ends the frame being timed, to be called once after every frame, and
+    /// gets the GPU time of the newest frame whose results came in since.
+    /// Results lag a frame or two behind, as they aren't waited for.
+    ///
+    /// Returns `None` when no new results came in, when they were disturbed
+    /// (such as by the GPU changing clock speed), or when timing is off.
+    pub fn take_gpu_timing(&mut self) -> Option<GpuTiming> {
+        self.gpu_timer.as_mut().and_then(|timer| timer.end_frame())
+    }
+
+    /// Counts a texture bound for drawing when auditing state changes.
+    pub(crate) fn audit_texture(&mut self, texture: GLuint) {
+        if let Some(ref mut audit) = self.audit {
+            audit.texture(texture);
+        }
     }
 
     /// Unset the current program.
@@ -378,6 +564,72 @@ impl<'a> GlGraphics {
         self.current_program = None
     }
 
+    /// Enables or disables depth testing for custom shader draws (`shader_draw` and `Mesh::draw`),
+    /// so 3D geometry hides whatever is behind it. Needs a depth buffer, which must be cleared every frame.
+    ///
+    /// 2D drawing through `Graphics` is never depth tested, so it can still be drawn over 3D content.
+    pub fn set_depth(&mut self, test: bool) {
+        self.depth_test = test;
+    }
+
+    /// Whether custom shader draws are depth tested, see `set_depth`
+    pub fn depth(&self) -> bool {
+        self.depth_test
+    }
+
+    /// Sets whether depth tested draws write their depth, turn it off for transparent geometry
+    /// which should be hidden by opaque geometry without hiding what's behind it
+    pub fn set_depth_write(&mut self, write: bool) {
+        self.depth_write = write;
+    }
+
+    /// Applies the depth settings around a custom shader draw, and times it.
+    pub(crate) fn with_depth<F: FnOnce()>(&mut self, draw: F) {
+        if !self.depth_test {
+            time_flush(&mut self.gpu_timer, draw);
+            return;
+        }
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::DepthFunc(gl::LEQUAL);
+            gl::DepthMask(if self.depth_write { gl::TRUE } else { gl::FALSE });
+        }
+        time_flush(&mut self.gpu_timer, draw);
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            // The depth buffer can't be cleared while writing to it is disabled
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+
+    /// Draws the coloured and textured triangles batched so far, before drawing with another shader.
+    pub(crate) fn flush_batches(&mut self) {
+        if self.textured.offset > 0 {
+            self.flush_textured();
+        }
+        if self.colored.offset > 0 {
+            self.flush_colored();
+        }
+    }
+
+    // Draws the coloured triangles batched so far
+    fn flush_colored(&mut self) {
+        let program = self.colored.program;
+        self.use_program(program);
+        let colored = &mut self.colored;
+        time_flush(&mut self.gpu_timer, || colored.flush());
+    }
+
+    // Draws the textured triangles batched so far
+    fn flush_textured(&mut self) {
+        let program = self.textured.program;
+        self.use_program(program);
+        let texture = self.textured.last_texture_id;
+        self.audit_texture(texture);
+        let textured = &mut self.textured;
+        time_flush(&mut self.gpu_timer, || textured.flush());
+    }
+
     /// Sets the current draw state, by detecting changes.
     pub fn use_draw_state(&mut self, draw_state: &DrawState) {
         match self.current_draw_state {
@@ -391,6 +643,9 @@ impl<'a> GlGraphics {
             }
         }
         self.current_draw_state = Some(*draw_state);
+        if let Some(ref mut audit) = self.audit {
+            audit.draw_state(draw_state);
+        }
     }
 
     /// Unsets the current draw state.
@@ -413,14 +668,10 @@ impl<'a> GlGraphics {
     /// Finalize the frame's draw calls.
     pub fn draw_end(&mut self) {
         if self.colored.offset > 0 {
-            let program = self.colored.program;
-            self.use_program(program);
-            self.colored.flush();
+            self.flush_colored();
         }
         if self.textured.offset > 0 {
-            let program = self.textured.program;
-            self.use_program(program);
-            self.textured.flush();
+            self.flush_textured();
         }
     }
 
@@ -437,12 +688,66 @@ impl<'a> GlGraphics {
         res
     }
 
+    /// Clears the buffers of the current framebuffer which are given a value.
+    ///
+    /// Buffers given `None` keep their contents.
+    pub fn clear_buffers(&mut self, color: Option<[f32; 4]>, depth: Option<f32>, stencil: Option<u8>) {
+        let mut mask = 0;
+        unsafe {
+            if let Some(color) = color {
+                let color = gamma_srgb_to_linear(color);
+                gl::ClearColor(color[0], color[1], color[2], color[3]);
+                mask |= gl::COLOR_BUFFER_BIT;
+            }
+            if let Some(depth) = depth {
+                gl::ClearDepthf(depth);
+                mask |= gl::DEPTH_BUFFER_BIT;
+            }
+            if let Some(stencil) = stencil {
+                gl::ClearStencil(stencil as GLint);
+                mask |= gl::STENCIL_BUFFER_BIT;
+            }
+            if mask != 0 {
+                gl::Clear(mask);
+            }
+        }
+    }
+
+    /// Reads the pixels in `rect` (in pixels, with the origin at the bottom left)
+    /// of the framebuffer being drawn to, flushing anything drawn before.
+    ///
+    /// The rows are flipped so the first row of the image is the top of `rect`.
+    pub fn read_pixels(&mut self, rect: [i32; 4]) -> RgbaImage {
+        self.draw_end();
+        let (width, height) = (rect[2].max(0) as u32, rect[3].max(0) as u32);
+        let row = width as usize * 4;
+        let mut pixels = vec![0u8; row * height as usize];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(rect[0],
+                           rect[1],
+                           width as GLsizei,
+                           height as GLsizei,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           pixels.as_mut_ptr() as *mut _);
+        }
+        let mut flipped = Vec::with_capacity(pixels.len());
+        for line in pixels.chunks(row.max(1)).rev() {
+            flipped.extend_from_slice(line);
+        }
+        RgbaImage::from_raw(width, height, flipped).expect("Pixel buffer has the wrong size")
+    }
+
     /// Assume all textures has alpha channel for now.
     pub fn has_texture_alpha(&self, _texture: &Texture) -> bool {
         true
     }
 
     /// Draws using a custom shader
+    ///
+    /// # Panics
+    /// If the inputs don't suit the shader, see `try_shader_draw`
     pub fn shader_draw<S: Shader>(
         &mut self, 
         shader: &mut S, 
@@ -453,17 +758,86 @@ impl<'a> GlGraphics {
         colour: Option<&[[f32; 4]]>,
         normals: Option<&[[f32; 3]]>,
         uniforms: impl FnOnce(&mut S, &mut Self)) {
-        
-        if self.textured.offset > 0 {
-            let program = self.textured.program;
-            self.use_program(program);
-            self.textured.flush();
+
+        if let Err(e) = self.try_shader_draw(shader, draw_state, vertices, indices, texture, colour, normals, uniforms) {
+            panic!("{}", e);
         }
-        if self.colored.offset > 0 {
-            let program = self.colored.program;
-            self.use_program(program);
-            self.colored.flush();
+    }
+
+    /// Draws using a custom shader, or returns why the colours, uvs and texture,
+    /// normals or indices given don't suit the shader without drawing anything,
+    /// so a bad mesh can be skipped instead of taking down the app.
+    pub fn try_shader_draw<S: Shader>(
+        &mut self,
+        shader: &mut S,
+        draw_state: &DrawState,
+        vertices: &[S::Vertex],
+        indices: Option<&[u16]>,
+        texture: Option<(&Texture, &[[f32; 2]])>,
+        colour: Option<&[[f32; 4]]>,
+        normals: Option<&[[f32; 3]]>,
+        uniforms: impl FnOnce(&mut S, &mut Self)) -> Result<(), DrawError> {
+
+        self.buffer_shader_draw(shader, draw_state, vertices, indices, texture, colour, normals, uniforms)?;
+        self.flush_shader(shader);
+        self.clear_program();
+        Ok(())
+    }
+
+    /// Draws `instances` copies of the vertices using a custom shader which supports
+    /// instancing (see `Shader::flush_instanced`) in a single draw call.
+    ///
+    /// Per-instance data such as offsets goes in the shader's `InstancedAttribute`s,
+    /// which can be set in `uniforms` or before calling this.
+    ///
+    /// # Panics
+    /// If the inputs don't suit the shader, see `try_shader_draw`
+    pub fn shader_draw_instanced<S: Shader>(
+        &mut self,
+        shader: &mut S,
+        draw_state: &DrawState,
+        vertices: &[S::Vertex],
+        indices: Option<&[u16]>,
+        texture: Option<(&Texture, &[[f32; 2]])>,
+        colour: Option<&[[f32; 4]]>,
+        normals: Option<&[[f32; 3]]>,
+        instances: usize,
+        uniforms: impl FnOnce(&mut S, &mut Self)) {
+
+        if let Err(e) = self.buffer_shader_draw(shader, draw_state, vertices, indices, texture, colour, normals, uniforms) {
+            panic!("{}", e);
+        }
+        if let Some(&mut texture) = shader.texture_id() {
+            self.audit_texture(texture);
+        }
+        self.with_depth(|| shader.flush_instanced(instances));
+        if let Some(indices) = shader.index_buffer() {
+            indices.clear();
         }
+        self.clear_program();
+    }
+
+    // Uses the shader and fills its buffers, leaving it to be flushed.
+    // Nothing is touched if the inputs don't suit the shader.
+    fn buffer_shader_draw<S: Shader>(
+        &mut self,
+        shader: &mut S,
+        draw_state: &DrawState,
+        vertices: &[S::Vertex],
+        indices: Option<&[u16]>,
+        texture: Option<(&Texture, &[[f32; 2]])>,
+        colour: Option<&[[f32; 4]]>,
+        normals: Option<&[[f32; 3]]>,
+        uniforms: impl FnOnce(&mut S, &mut Self)) -> Result<(), DrawError> {
+
+        check_shader_inputs(shader,
+                            vertices.len(),
+                            indices,
+                            texture.map(|x| x.1.len()),
+                            colour.map(|x| x.len()),
+                            normals.map(|x| x.len()))?;
+
+        self.flush_batches();
 
         let program = shader.program();
         self.use_program(program);
@@ -474,76 +848,81 @@ impl<'a> GlGraphics {
             self.use_draw_state(draw_state);
         }
 
-        let items = vertices.len();
-        let offset = *shader.offset();
+        self.append_shader_inputs(shader, vertices, indices, texture, colour, normals);
+        Ok(())
+    }
 
+    // Copies inputs which passed `check_shader_inputs` into the shader's buffers,
+    // drawing what's already buffered first if they don't fit
+    pub(crate) fn append_shader_inputs<S: Shader>(
+        &mut self,
+        shader: &mut S,
+        vertices: &[S::Vertex],
+        indices: Option<&[u16]>,
+        texture: Option<(&Texture, &[[f32; 2]])>,
+        colour: Option<&[[f32; 4]]>,
+        normals: Option<&[[f32; 3]]>) {
 
-        if offset + items > shader.pos_buffer().len() {
-            shader.flush();
-            assert!(offset + items > *shader.offset() + items, 
-                "Either the shader comes preloaded with too many items \
-                or there were too many items being drawn at once.");
+        let items = vertices.len();
+        let offset = *shader.offset();
+        let indexed = shader.index_buffer().map_or(false, |x| !x.is_empty());
+        // Indices are moved past the vertices already buffered, so those have to stay addressable,
+        // and indexed draws can't share a flush with draws of plain triangles
+        if offset > 0 && (offset + items > shader.pos_buffer().len() ||
+                          indices.is_some() && offset + items > 1 << 16 ||
+                          indices.is_some() != indexed) {
+            self.flush_shader(shader);
         }
 
         let offset = *shader.offset();
-        match (shader.colour_buffer(), colour) {
-            (None, Some(_)) => panic!("Colour was given but not expected!"),
-            (Some(buf), Some(src)) => {
-                assert!(src.len() == items, 
-                    "The number of vertices ({}) is not equal to the number
-                    of Colours ({})!", items, src.len());
-                for (lhs, rhs) in buf[offset..offset + items].iter_mut().zip(src[..items].iter()) {
-                    *lhs = gamma_srgb_to_linear(*rhs);
-                }
-            },
-            (Some(_), None) => panic!("Colour was expected but not given!"),
-            (None, None) => {}
-        }
-        let text = shader.has_texture();
-        match (shader.uv_buffer(), text, texture) {
-            (Some(_), false, _) | (None, true, _) => panic!("Shader expects a mismatch of UVs and Texture!"),
-            (None, false, Some(_)) => panic!("UVs and Texture were given but not expected!"),
-            (Some(_), true, None) => panic!("UVs and Texture were expected but not given!"),
-            (Some(buf), true, Some((_, src))) => {
-                assert!(src.len() == items, 
-                    "The number of vertices ({}) is not equal to the number
-                    of UV positions ({})!", items, src.len());
-                buf[offset..offset + items]
-                    .copy_from_slice(src);
-            },
-            (None, false, None) => {}
-        }
-        match (shader.texture_id(), texture) {
-            (None, None) => {},
-            (Some(src), Some((text, _))) => *src = text.get_id(),
-            _ => unreachable!(),
+        if let (Some(buf), Some(src)) = (shader.colour_buffer(), colour) {
+            for (lhs, rhs) in buf[offset..offset + items].iter_mut().zip(src.iter()) {
+                *lhs = gamma_srgb_to_linear(*rhs);
+            }
         }
-        
-        match (shader.normal_buffer(), normals) {
-            (None, Some(_)) => panic!("Normals were given but not expected!"),
-            (Some(buf), Some(src)) => {
-                assert!(src.len() == items, 
-                    "The number of vertices ({}) is not equal to the number
-                    of normals positions ({})!", items, src.len());
-                buf[offset..offset + items]
-                    .copy_from_slice(src);
-            },
-            (Some(_), None) => panic!("Normals were expected but not given!"),
-            (None, None) => {}
-        }
-        match (shader.index_buffer(), indices) {
-            (None, Some(_)) => panic!("Indices was given but not expected!"),
-            (Some(buf), Some(src)) => {
-                buf.extend(src.iter());
-            },
-            _ => {}
+        if let (Some(buf), Some((_, src))) = (shader.uv_buffer(), texture) {
+            buf[offset..offset + items].copy_from_slice(src);
+        }
+        if let (Some(src), Some((text, _))) = (shader.texture_id(), texture) {
+            *src = text.get_id();
+        }
+        if let (Some(buf), Some(src)) = (shader.normal_buffer(), normals) {
+            buf[offset..offset + items].copy_from_slice(src);
+        }
+        if let (Some(buf), Some(src)) = (shader.index_buffer(), indices) {
+            buf.extend(src.iter().map(|&x| x + offset as u16));
         }
         shader.pos_buffer()[offset..offset + items]
             .copy_from_slice(vertices);
         *shader.offset() += items;
+    }
+
+    /// Draws what's buffered in `shader`, which must be the program in use.
+    pub(crate) fn flush_shader<S: Shader>(&mut self, shader: &mut S) {
+        if let Some(&mut texture) = shader.texture_id() {
+            self.audit_texture(texture);
+        }
+        self.with_depth(|| shader.flush());
+        if let Some(indices) = shader.index_buffer() {
+            indices.clear();
+        }
+    }
 
-        shader.flush();
+    /// Draws with `shader` many times in `f` while only flushing it when needed, instead of
+    /// after every draw like `shader_draw`. The draws made through the `ShaderBatch` are
+    /// buffered together until the draw state or texture changes, `ShaderBatch::set_uniforms`
+    /// is called, the shader's buffers fill up, or `f` returns.
+    ///
+    /// Anything drawn with `Graphics` in between has to wait until after `f`.
+    pub fn shader_batch<S: Shader, R>(&mut self, shader: &mut S, f: impl FnOnce(&mut ShaderBatch<S>) -> R) -> R {
+        let result = {
+            let mut batch = ShaderBatch::new(self, shader);
+            let result = f(&mut batch);
+            batch.flush();
+            result
+        };
         self.clear_program();
+        result
     }
 }
 
@@ -572,9 +951,7 @@ impl Graphics for GlGraphics {
         let color = gamma_srgb_to_linear(*color);
 
         if self.textured.offset > 0 {
-            let program = self.textured.program;
-            self.use_program(program);
-            self.textured.flush();
+            self.flush_textured();
         }
 
         // Flush when draw state changes.
@@ -586,7 +963,7 @@ impl Graphics for GlGraphics {
                 self.use_draw_state(&Default::default());
             }
             if self.colored.offset > 0 {
-                self.colored.flush();
+                self.flush_colored();
             }
             self.use_draw_state(draw_state);
         }
@@ -596,9 +973,7 @@ impl Graphics for GlGraphics {
 
             // Render if there is not enough room.
             if self.colored.offset + items > BUFFER_SIZE * CHUNKS {
-                let program = self.colored.program;
-                self.use_program(program);
-                self.colored.flush();
+                self.flush_colored();
             }
 
             let ref mut shader = self.colored;
@@ -621,9 +996,7 @@ impl Graphics for GlGraphics {
         let color = gamma_srgb_to_linear(*color);
 
         if self.colored.offset > 0 {
-            let program = self.colored.program;
-            self.use_program(program);
-            self.colored.flush();
+            self.flush_colored();
         }
 
         // Flush when draw state changes.
@@ -632,13 +1005,11 @@ impl Graphics for GlGraphics {
            self.textured.last_texture_id != texture.get_id() ||
            self.textured.last_color != color
         {
-            let program = self.textured.program;
             if self.current_draw_state.is_none() {
                 self.use_draw_state(&Default::default());
             }
             if self.textured.offset > 0 {
-                self.use_program(program);
-                self.textured.flush();
+                self.flush_textured();
             }
             self.use_draw_state(draw_state);
         }
@@ -650,9 +1021,7 @@ impl Graphics for GlGraphics {
 
             // Render if there is not enough room.
             if self.textured.offset + items > BUFFER_SIZE * CHUNKS {
-                let shader_program = self.textured.program;
-                self.use_program(shader_program);
-                self.textured.flush();
+                self.flush_textured();
             }
 
             let ref mut shader = self.textured;
@@ -665,6 +1034,68 @@ impl Graphics for GlGraphics {
     }
 }
 
+// Checks the inputs to a custom shader draw against what the shader takes,
+// given how many values of each attribute there are
+pub(crate) fn check_shader_inputs<S: Shader>(shader: &mut S,
+                                  items: usize,
+                                  indices: Option<&[u16]>,
+                                  uvs: Option<usize>,
+                                  colours: Option<usize>,
+                                  normals: Option<usize>) -> Result<(), DrawError> {
+    let check = |expected: bool, given: Option<usize>, attribute: Attribute| match (expected, given) {
+        (false, Some(_)) => Err(DrawError::Unexpected(attribute)),
+        (true, None) => Err(DrawError::Missing(attribute)),
+        (true, Some(found)) if found != items => Err(DrawError::WrongLength {
+            attribute: attribute,
+            vertices: items,
+            found: found,
+        }),
+        _ => Ok(()),
+    };
+    let capacity = shader.pos_buffer().len();
+    if items > capacity {
+        return Err(DrawError::TooManyVertices { vertices: items, capacity: capacity });
+    }
+    if shader.uv_buffer().is_some() != shader.has_texture() {
+        return Err(DrawError::ShaderMismatch);
+    }
+    check(shader.colour_buffer().is_some(), colours, Attribute::Colour)?;
+    check(shader.uv_buffer().is_some(), uvs, Attribute::Uv)?;
+    check(shader.normal_buffer().is_some(), normals, Attribute::Normal)?;
+    if let Some(indices) = indices {
+        if shader.index_buffer().is_none() {
+            return Err(DrawError::Unexpected(Attribute::Index));
+        }
+        if let Some(&index) = indices.iter().find(|&&x| x as usize >= items) {
+            return Err(DrawError::IndexOutOfRange { index: index, vertices: items });
+        }
+    }
+    Ok(())
+}
+
+// The bindings are generated for OpenGL ES, so only have the `EXT` entry points,
+// which desktop drivers give out too. OpenGL ES drivers may give them out without
+// supporting the extension.
+fn supports_multi_draw(profile: Profile) -> bool {
+    gl::MultiDrawArraysEXT::is_loaded() && gl::MultiDrawElementsEXT::is_loaded() &&
+        (!profile.is_gles() || has_extension("GL_EXT_multi_draw_arrays"))
+}
+
+// Desktop OpenGL counts uniform components rather than vectors
+fn max_vertex_uniform_vectors(profile: Profile) -> usize {
+    let mut value = 0;
+    unsafe {
+        if profile.is_gles() {
+            gl::GetIntegerv(gl::MAX_VERTEX_UNIFORM_VECTORS, &mut value);
+        } else {
+            gl::GetIntegerv(gl::MAX_VERTEX_UNIFORM_COMPONENTS, &mut value);
+            value /= 4;
+        }
+    }
+    // The minimum OpenGL ES 2.0 allows, if the query failed
+    if value > 0 { value as usize } else { 128 }
+}
+
 // Might not fail if previous tests loaded functions.
 #[test]
 #[should_panic]
@@ -0,0 +1,225 @@
+//! Basis Universal textures, transcoded at runtime to a format the GPU supports
+
+// External crates.
+use gl;
+use gl::types::GLenum;
+
+// Local crate.
+use compressed::{compressed_formats, read_u32, read_u64, CompressedImage, KTX2_IDENTIFIER};
+use texture::{apply_settings, Texture};
+use TextureSettings;
+
+// Data format descriptor colour models
+const MODEL_ETC1S: u8 = 163;
+const MODEL_UASTC: u8 = 166;
+// Data format descriptor channels which hold alpha
+const ETC1S_CHANNEL_AAA: u8 = 15;
+const UASTC_CHANNEL_RGBA: u8 = 3;
+const UASTC_CHANNEL_RRRG: u8 = 5;
+const TRANSFER_SRGB: u8 = 2;
+
+/// How a Basis Universal texture was encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BasisEncoding {
+    /// Small files of lower quality, supercompressed with BasisLZ.
+    Etc1s,
+    /// Higher quality, optionally supercompressed with Zstandard.
+    Uastc,
+}
+
+/// The format a Basis Universal texture is transcoded to, from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TranscodeTarget {
+    /// ASTC with 4x4 blocks.
+    Astc4x4,
+    /// ETC2 with EAC alpha.
+    Etc2Rgba,
+    /// ETC2 without alpha.
+    Etc2Rgb,
+    /// Uncompressed 8 bit RGBA pixels, row by row, when no compressed format suits.
+    Rgba8,
+}
+
+impl TranscodeTarget {
+    /// Picks the best target the current context can upload, ASTC if it can,
+    /// then ETC2, then uncompressed.
+    pub fn detect(has_alpha: bool, srgb: bool) -> Self {
+        let formats = compressed_formats();
+        let candidates = [
+            TranscodeTarget::Astc4x4,
+            if has_alpha { TranscodeTarget::Etc2Rgba } else { TranscodeTarget::Etc2Rgb },
+        ];
+        candidates
+            .iter()
+            .cloned()
+            .find(|x| x.gl_format(srgb).map_or(false, |format| formats.contains(&format)))
+            .unwrap_or(TranscodeTarget::Rgba8)
+    }
+
+    /// Gets the GL internal format of the target, `None` for `Rgba8`.
+    pub fn gl_format(&self, srgb: bool) -> Option<GLenum> {
+        Some(match (*self, srgb) {
+            (TranscodeTarget::Astc4x4, false) => gl::COMPRESSED_RGBA_ASTC_4x4,
+            (TranscodeTarget::Astc4x4, true) => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_4x4,
+            (TranscodeTarget::Etc2Rgba, false) => gl::COMPRESSED_RGBA8_ETC2_EAC,
+            (TranscodeTarget::Etc2Rgba, true) => gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
+            (TranscodeTarget::Etc2Rgb, false) => gl::COMPRESSED_RGB8_ETC2,
+            (TranscodeTarget::Etc2Rgb, true) => gl::COMPRESSED_SRGB8_ETC2,
+            (TranscodeTarget::Rgba8, _) => return None,
+        })
+    }
+}
+
+/// Transcodes Basis Universal data, usually by binding the reference transcoder.
+/// The back-end reads the container and picks the target, leaving only the
+/// decoding of the payload to the transcoder.
+pub trait BasisTranscoder {
+    /// Transcodes mip `level` of `texture` into `target`, giving the blocks (or pixels,
+    /// for `TranscodeTarget::Rgba8`) row by row, as OpenGL takes them.
+    fn transcode(&mut self, texture: &BasisTexture, level: usize, target: TranscodeTarget) -> Result<Vec<u8>, String>;
+}
+
+/// A Basis Universal texture read from a KTX2 file, not transcoded yet.
+///
+/// One file works on every GPU: it's transcoded when loading to the best
+/// format the device supports (see `TranscodeTarget::detect`).
+#[derive(Clone)]
+pub struct BasisTexture {
+    /// How the texture was encoded.
+    pub encoding: BasisEncoding,
+    /// The KTX2 supercompression scheme of the levels, `1` for BasisLZ
+    /// and `2` for Zstandard (or `0` for none).
+    pub supercompression: u32,
+    /// The width of the largest level.
+    pub width: u32,
+    /// The height of the largest level.
+    pub height: u32,
+    /// Whether the texture has an alpha channel.
+    pub has_alpha: bool,
+    /// Whether the colours are sRGB.
+    pub srgb: bool,
+    /// The supercompression global data, holding the BasisLZ codebooks.
+    pub global_data: Vec<u8>,
+    /// The payload of each mip level, largest first.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl BasisTexture {
+    /// Reads a KTX2 file holding ETC1S or UASTC data.
+    pub fn from_ktx2(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 80 || data[..12] != KTX2_IDENTIFIER {
+            return Err("Not a KTX2 file".to_string());
+        }
+        let field = |index: usize| read_u32(data, 12 + index * 4, false);
+        if field(0)? != 0 {
+            return Err("Not a Basis Universal texture, load it with `CompressedImage::from_ktx`".to_string());
+        }
+        let (width, height) = (field(2)?, field(3)?);
+        if field(4)? > 1 || field(5)? > 1 || field(6)? != 1 {
+            return Err("Only 2D KTX2 textures are supported".to_string());
+        }
+        let supercompression = field(8)?;
+
+        // The colour model, transfer function and channels are in the first descriptor block
+        let dfd = field(9)? as usize + 4;
+        let byte = |offset: usize| data.get(dfd + offset).cloned().ok_or_else(|| "Unexpected end of KTX2 file".to_string());
+        let block_size = (read_u32(data, dfd + 4, false)? >> 16) as usize;
+        let channels = (0..block_size.saturating_sub(24) / 16)
+            .map(|sample| byte(24 + sample * 16 + 3).map(|x| x & 0xF))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (encoding, has_alpha) = match byte(8)? {
+            MODEL_ETC1S => (BasisEncoding::Etc1s, channels.contains(&ETC1S_CHANNEL_AAA)),
+            MODEL_UASTC => (BasisEncoding::Uastc,
+                            channels.first().map_or(false, |&x| x == UASTC_CHANNEL_RGBA || x == UASTC_CHANNEL_RRRG)),
+            model => return Err(format!("Unsupported KTX2 colour model {}", model)),
+        };
+        let srgb = byte(10)? == TRANSFER_SRGB;
+
+        let (global_start, global_length) = (read_u64(data, 64)? as usize, read_u64(data, 72)? as usize);
+        let global_data = match data.get(global_start..global_start + global_length) {
+            Some(x) => x.to_vec(),
+            None => return Err("Unexpected end of KTX2 file".to_string()),
+        };
+        let level_count = field(7)?.max(1) as usize;
+        let mut levels = Vec::with_capacity(level_count);
+        for index in 0..level_count {
+            let entry = 80 + index * 24;
+            let (start, length) = (read_u64(data, entry)? as usize, read_u64(data, entry + 8)? as usize);
+            match data.get(start..start + length) {
+                Some(x) => levels.push(x.to_vec()),
+                None => return Err("Unexpected end of KTX2 file".to_string()),
+            }
+        }
+        Ok(BasisTexture {
+            encoding: encoding,
+            supercompression: supercompression,
+            width: width,
+            height: height,
+            has_alpha: has_alpha,
+            srgb: srgb,
+            global_data: global_data,
+            levels: levels,
+        })
+    }
+
+    /// Gets the width and height of mip `level`.
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+}
+
+impl Texture {
+    /// Transcodes a Basis Universal texture to the best format the context supports
+    /// and creates a texture from it, see `Texture::from_compressed`.
+    ///
+    /// sRGB textures are transcoded to sRGB formats, so they're converted to linear when
+    /// sampled. Unless every level is stored, uncompressed textures generate mipmaps
+    /// when `settings` asks for them, while compressed ones aren't mipmapped.
+    pub fn from_basis(texture: &BasisTexture,
+                      transcoder: &mut dyn BasisTranscoder,
+                      settings: &TextureSettings)
+                      -> Result<Self, String> {
+        let target = TranscodeTarget::detect(texture.has_alpha, texture.srgb);
+        let levels = (0..texture.levels.len())
+            .map(|level| transcoder.transcode(texture, level, target))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(format) = target.gl_format(texture.srgb) {
+            let image = CompressedImage::from_levels(format, texture.width, texture.height, levels)?;
+            return Texture::from_compressed(&image, settings);
+        }
+
+        for (level, data) in levels.iter().enumerate() {
+            let (width, height) = texture.level_size(level);
+            if data.len() < (width * height * 4) as usize {
+                return Err(format!("Transcoded mip level {} is truncated", level));
+            }
+        }
+        let mipmapped = levels.len() > 1 || settings.get_generate_mipmap();
+        let mut settings = *settings;
+        settings.set_generate_mipmap(mipmapped);
+        let mut id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            apply_settings(&settings);
+            for (level, data) in levels.iter().enumerate() {
+                let (width, height) = texture.level_size(level);
+                gl::TexImage2D(gl::TEXTURE_2D,
+                               level as i32,
+                               gl::RGBA as i32,
+                               width as i32,
+                               height as i32,
+                               0,
+                               gl::RGBA,
+                               gl::UNSIGNED_BYTE,
+                               data.as_ptr() as *const _);
+            }
+            if levels.len() == 1 && mipmapped {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            } else {
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, levels.len() as i32 - 1);
+            }
+        }
+        Ok(Texture::new(id, texture.width, texture.height))
+    }
+}
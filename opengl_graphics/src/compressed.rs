@@ -0,0 +1,487 @@
+//! Textures compressed ahead of time, loaded from KTX and KTX2 files
+
+// External crates.
+use gl;
+use gl::types::{GLenum, GLint};
+use image::RgbaImage;
+
+use std::path::Path;
+
+// Local crate.
+use texture::{apply_settings, Texture};
+use TextureSettings;
+
+const KTX1_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+pub(crate) const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Etc2Rgb,
+    Etc2Rgb1,
+    Etc2Eac,
+    Astc,
+}
+
+// (GL internal format, Vulkan format used by KTX2, block width, block height, kind)
+const FORMATS: [(GLenum, u32, u32, u32, Kind); 34] = [
+    (gl::COMPRESSED_RGB8_ETC2, 147, 4, 4, Kind::Etc2Rgb),
+    (gl::COMPRESSED_SRGB8_ETC2, 148, 4, 4, Kind::Etc2Rgb),
+    (gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2, 149, 4, 4, Kind::Etc2Rgb1),
+    (gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2, 150, 4, 4, Kind::Etc2Rgb1),
+    (gl::COMPRESSED_RGBA8_ETC2_EAC, 151, 4, 4, Kind::Etc2Eac),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC, 152, 4, 4, Kind::Etc2Eac),
+    (gl::COMPRESSED_RGBA_ASTC_4x4, 157, 4, 4, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_4x4, 158, 4, 4, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_5x4, 159, 5, 4, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_5x4, 160, 5, 4, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_5x5, 161, 5, 5, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_5x5, 162, 5, 5, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_6x5, 163, 6, 5, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_6x5, 164, 6, 5, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_6x6, 165, 6, 6, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_6x6, 166, 6, 6, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_8x5, 167, 8, 5, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_8x5, 168, 8, 5, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_8x6, 169, 8, 6, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_8x6, 170, 8, 6, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_8x8, 171, 8, 8, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_8x8, 172, 8, 8, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_10x5, 173, 10, 5, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_10x5, 174, 10, 5, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_10x6, 175, 10, 6, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_10x6, 176, 10, 6, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_10x8, 177, 10, 8, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_10x8, 178, 10, 8, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_10x10, 179, 10, 10, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_10x10, 180, 10, 10, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_12x10, 181, 12, 10, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_12x10, 182, 12, 10, Kind::Astc),
+    (gl::COMPRESSED_RGBA_ASTC_12x12, 183, 12, 12, Kind::Astc),
+    (gl::COMPRESSED_SRGB8_ALPHA8_ASTC_12x12, 184, 12, 12, Kind::Astc),
+];
+
+fn format_info(format: GLenum) -> Option<(u32, u32, Kind)> {
+    FORMATS.iter().find(|x| x.0 == format).map(|x| (x.2, x.3, x.4))
+}
+
+/// Gets the compressed formats the current context can upload.
+pub fn compressed_formats() -> Vec<GLenum> {
+    unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_COMPRESSED_TEXTURE_FORMATS, &mut count);
+        let mut formats = vec![0 as GLint; count.max(0) as usize];
+        if !formats.is_empty() {
+            gl::GetIntegerv(gl::COMPRESSED_TEXTURE_FORMATS, formats.as_mut_ptr());
+        }
+        formats.into_iter().map(|x| x as GLenum).collect()
+    }
+}
+
+/// An image compressed ahead of time into a block format the GPU samples
+/// directly, read from a KTX or KTX2 file.
+///
+/// ETC2 (with or without EAC alpha) and ASTC payloads are supported, as a
+/// single 2D image with any number of mip levels.
+#[derive(Clone)]
+pub struct CompressedImage {
+    format: GLenum,
+    width: u32,
+    height: u32,
+    levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    /// Reads a KTX or KTX2 file, telling them apart by their identifier.
+    pub fn from_ktx(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 12 {
+            return Err("File too short to be a KTX file".to_string());
+        }
+        let image = if data[..12] == KTX1_IDENTIFIER {
+            read_ktx1(data)?
+        } else if data[..12] == KTX2_IDENTIFIER {
+            read_ktx2(data)?
+        } else {
+            return Err("Not a KTX or KTX2 file".to_string());
+        };
+        CompressedImage::from_levels(image.format, image.width, image.height, image.levels)
+    }
+
+    /// Checks every level holds enough blocks for its size, such as after transcoding.
+    pub(crate) fn from_levels(format: GLenum, width: u32, height: u32, levels: Vec<Vec<u8>>) -> Result<Self, String> {
+        let image = CompressedImage {
+            format: format,
+            width: width,
+            height: height,
+            levels: levels,
+        };
+        let (block_width, block_height, kind) = match format_info(format) {
+            Some(x) => x,
+            None => return Err(format!("Unsupported compressed format 0x{:X}", format)),
+        };
+        for (index, level) in image.levels.iter().enumerate() {
+            let (width, height) = image.level_size(index);
+            let blocks = ((width + block_width - 1) / block_width) * ((height + block_height - 1) / block_height);
+            if level.len() < blocks as usize * block_bytes(kind) {
+                return Err(format!("Mip level {} is truncated", index));
+            }
+        }
+        Ok(image)
+    }
+
+    /// Gets the GL internal format of the compressed data.
+    pub fn get_format(&self) -> GLenum {
+        self.format
+    }
+
+    /// Gets the width and height of the largest level.
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Gets the number of mip levels stored.
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Gets whether the current context can upload this image as it is.
+    pub fn is_supported(&self) -> bool {
+        compressed_formats().contains(&self.format)
+    }
+
+    /// Decodes a mip level, for contexts which can't sample the format.
+    ///
+    /// Only the ETC2 formats can be decoded so far, ASTC images give an error.
+    pub fn decompress(&self, level: usize) -> Result<RgbaImage, String> {
+        let data = match self.levels.get(level) {
+            Some(x) => x,
+            None => return Err(format!("No mip level {}", level)),
+        };
+        let (width, height) = self.level_size(level);
+        let kind = format_info(self.format).unwrap().2;
+        if kind == Kind::Astc {
+            return Err(format!("Can't decode format 0x{:X} without GPU support", self.format));
+        }
+        let mut image = RgbaImage::new(width, height);
+        let blocks_wide = (width + 3) / 4;
+        let size = block_bytes(kind);
+        for (index, block) in data.chunks(size).take((blocks_wide * ((height + 3) / 4)) as usize).enumerate() {
+            let mut pixels = [[0u8; 4]; 16];
+            match kind {
+                Kind::Etc2Rgb => decode_etc2(read_u64_be(block), false, &mut pixels),
+                Kind::Etc2Rgb1 => decode_etc2(read_u64_be(block), true, &mut pixels),
+                _ => {
+                    decode_etc2(read_u64_be(&block[8..]), false, &mut pixels);
+                    decode_eac(read_u64_be(block), &mut pixels);
+                }
+            }
+            let (block_x, block_y) = (index as u32 % blocks_wide * 4, index as u32 / blocks_wide * 4);
+            for (pixel, colour) in pixels.iter().enumerate() {
+                // Pixels are stored a column at a time
+                let (x, y) = (block_x + pixel as u32 / 4, block_y + pixel as u32 % 4);
+                if x < width && y < height {
+                    image.put_pixel(x, y, ::image::Rgba(*colour));
+                }
+            }
+        }
+        Ok(image)
+    }
+
+    fn level_size(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+}
+
+fn block_bytes(kind: Kind) -> usize {
+    match kind {
+        Kind::Etc2Rgb | Kind::Etc2Rgb1 => 8,
+        Kind::Etc2Eac | Kind::Astc => 16,
+    }
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Result<u32, String> {
+    let bytes = match data.get(offset..offset + 4) {
+        Some(x) => [x[0], x[1], x[2], x[3]],
+        None => return Err("Unexpected end of KTX file".to_string()),
+    };
+    Ok(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> Result<u64, String> {
+    Ok(read_u32(data, offset, false)? as u64 | (read_u32(data, offset + 4, false)? as u64) << 32)
+}
+
+fn read_u64_be(block: &[u8]) -> u64 {
+    block[..8].iter().fold(0, |acc, &x| acc << 8 | x as u64)
+}
+
+fn read_ktx1(data: &[u8]) -> Result<CompressedImage, String> {
+    let big_endian = match read_u32(data, 12, false)? {
+        0x04030201 => false,
+        0x01020304 => true,
+        _ => return Err("Invalid KTX endianness".to_string()),
+    };
+    let field = |index: usize| read_u32(data, 16 + index * 4, big_endian);
+    let format = field(3)?;
+    if format_info(format).is_none() {
+        return Err(format!("Unsupported KTX format 0x{:X}", format));
+    }
+    let (width, height) = (field(5)?, field(6)?);
+    if field(7)? > 1 || field(8)? > 0 || field(9)? != 1 {
+        return Err("Only 2D KTX textures are supported".to_string());
+    }
+    let level_count = field(10)?.max(1);
+    let mut offset = 64 + field(11)? as usize;
+    let mut levels = Vec::new();
+    for _ in 0..level_count {
+        let size = read_u32(data, offset, big_endian)? as usize;
+        offset += 4;
+        match data.get(offset..offset + size) {
+            Some(x) => levels.push(x.to_vec()),
+            None => return Err("Unexpected end of KTX file".to_string()),
+        }
+        offset += (size + 3) / 4 * 4;
+    }
+    Ok(CompressedImage {
+        format: format,
+        width: width,
+        height: height,
+        levels: levels,
+    })
+}
+
+fn read_ktx2(data: &[u8]) -> Result<CompressedImage, String> {
+    let field = |index: usize| read_u32(data, 12 + index * 4, false);
+    let vk_format = field(0)?;
+    if vk_format == 0 {
+        return Err("Basis Universal KTX2 files load with `BasisTexture`".to_string());
+    }
+    let format = match FORMATS.iter().find(|x| x.1 == vk_format && x.1 != 0) {
+        Some(x) => x.0,
+        None => return Err(format!("Unsupported KTX2 format {}", vk_format)),
+    };
+    let (width, height) = (field(2)?, field(3)?);
+    if field(4)? > 1 || field(5)? > 1 || field(6)? != 1 {
+        return Err("Only 2D KTX2 textures are supported".to_string());
+    }
+    if field(8)? != 0 {
+        return Err("Supercompressed KTX2 files aren't supported".to_string());
+    }
+    let level_count = field(7)?.max(1) as usize;
+    let mut levels = Vec::with_capacity(level_count);
+    for index in 0..level_count {
+        let entry = 80 + index * 24;
+        let (start, length) = (read_u64(data, entry)? as usize, read_u64(data, entry + 8)? as usize);
+        match data.get(start..start + length) {
+            Some(x) => levels.push(x.to_vec()),
+            None => return Err("Unexpected end of KTX2 file".to_string()),
+        }
+    }
+    Ok(CompressedImage {
+        format: format,
+        width: width,
+        height: height,
+        levels: levels,
+    })
+}
+
+const ETC_MODIFIERS: [[i32; 2]; 8] = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]];
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn bits(block: u64, high: u32, count: u32) -> i32 {
+    ((block >> (high + 1 - count)) & ((1 << count) - 1)) as i32
+}
+
+fn extend(value: i32, count: u32) -> i32 {
+    (value << (8 - count)) | (value >> (2 * count - 8))
+}
+
+fn clamp(value: i32) -> u8 {
+    value.max(0).min(255) as u8
+}
+
+fn offset(colour: [i32; 3], by: i32) -> [u8; 4] {
+    [clamp(colour[0] + by), clamp(colour[1] + by), clamp(colour[2] + by), 255]
+}
+
+// Decodes an ETC2 colour block into pixels in column order. With `punchthrough`,
+// the differential bit says whether the block is opaque instead.
+fn decode_etc2(block: u64, punchthrough: bool, pixels: &mut [[u8; 4]; 16]) {
+    let differential = punchthrough || bits(block, 33, 1) == 1;
+    let opaque = !punchthrough || bits(block, 33, 1) == 1;
+    let index = |pixel: usize| (bits(block, 16 + pixel as u32, 1) << 1 | bits(block, pixel as u32, 1)) as usize;
+
+    if differential {
+        let base = [bits(block, 63, 5), bits(block, 55, 5), bits(block, 47, 5)];
+        let delta = [bits(block, 58, 3), bits(block, 50, 3), bits(block, 42, 3)];
+        let second = [0, 1, 2].map(|x| base[x] + (delta[x] << 29 >> 29));
+        if second[0] < 0 || second[0] > 31 {
+            // T mode
+            let first = [bits(block, 60, 2) << 2 | bits(block, 57, 2), bits(block, 55, 4), bits(block, 51, 4)].map(|x| extend(x, 4));
+            let second = [bits(block, 47, 4), bits(block, 43, 4), bits(block, 39, 4)].map(|x| extend(x, 4));
+            let distance = ETC_DISTANCES[(bits(block, 35, 2) << 1 | bits(block, 32, 1)) as usize];
+            let paint = [offset(first, 0), offset(second, distance), offset(second, 0), offset(second, -distance)];
+            decode_paint(&paint, opaque, index, pixels);
+        } else if second[1] < 0 || second[1] > 31 {
+            // H mode
+            let first = [bits(block, 62, 4), bits(block, 58, 3) << 1 | bits(block, 52, 1), bits(block, 51, 1) << 3 | bits(block, 49, 3)];
+            let second = [bits(block, 46, 4), bits(block, 42, 4), bits(block, 38, 4)];
+            let ordered = (first[0] << 8 | first[1] << 4 | first[2]) >= (second[0] << 8 | second[1] << 4 | second[2]);
+            let distance = ETC_DISTANCES[(bits(block, 34, 1) << 2 | bits(block, 32, 1) << 1 | ordered as i32) as usize];
+            let (first, second) = (first.map(|x| extend(x, 4)), second.map(|x| extend(x, 4)));
+            let paint = [offset(first, distance), offset(first, -distance), offset(second, distance), offset(second, -distance)];
+            decode_paint(&paint, opaque, index, pixels);
+        } else if second[2] < 0 || second[2] > 31 {
+            decode_planar(block, pixels);
+        } else {
+            decode_subblocks(block, [base.map(|x| extend(x, 5)), second.map(|x| extend(x, 5))], opaque, index, pixels);
+        }
+    } else {
+        let first = [bits(block, 63, 4), bits(block, 55, 4), bits(block, 47, 4)].map(|x| extend(x, 4));
+        let second = [bits(block, 59, 4), bits(block, 51, 4), bits(block, 43, 4)].map(|x| extend(x, 4));
+        decode_subblocks(block, [first, second], true, index, pixels);
+    }
+}
+
+// The individual and differential modes, which split the block into two halves
+fn decode_subblocks<F: Fn(usize) -> usize>(block: u64, colours: [[i32; 3]; 2], opaque: bool, index: F, pixels: &mut [[u8; 4]; 16]) {
+    let tables = [bits(block, 39, 3) as usize, bits(block, 36, 3) as usize];
+    let flip = bits(block, 32, 1) == 1;
+    for (pixel, out) in pixels.iter_mut().enumerate() {
+        let (x, y) = (pixel / 4, pixel % 4);
+        let half = if flip { (y >= 2) as usize } else { (x >= 2) as usize };
+        let modifiers = ETC_MODIFIERS[tables[half]];
+        *out = match index(pixel) {
+            // Without the opaque bit, the smaller modifiers are dropped and index 2 is transparent
+            0 if !opaque => offset(colours[half], 0),
+            2 if !opaque => [0; 4],
+            0 => offset(colours[half], modifiers[0]),
+            1 => offset(colours[half], modifiers[1]),
+            2 => offset(colours[half], -modifiers[0]),
+            _ => offset(colours[half], -modifiers[1]),
+        };
+    }
+}
+
+fn decode_paint<F: Fn(usize) -> usize>(paint: &[[u8; 4]; 4], opaque: bool, index: F, pixels: &mut [[u8; 4]; 16]) {
+    for (pixel, out) in pixels.iter_mut().enumerate() {
+        let index = index(pixel);
+        *out = if !opaque && index == 2 { [0; 4] } else { paint[index] };
+    }
+}
+
+fn decode_planar(block: u64, pixels: &mut [[u8; 4]; 16]) {
+    let origin = [
+        extend(bits(block, 62, 6), 6),
+        extend(bits(block, 56, 1) << 6 | bits(block, 54, 6), 7),
+        extend(bits(block, 48, 1) << 5 | bits(block, 44, 2) << 3 | bits(block, 41, 3), 6),
+    ];
+    let horizontal = [
+        extend(bits(block, 38, 5) << 1 | bits(block, 32, 1), 6),
+        extend(bits(block, 31, 7), 7),
+        extend(bits(block, 24, 6), 6),
+    ];
+    let vertical = [extend(bits(block, 18, 6), 6), extend(bits(block, 12, 7), 7), extend(bits(block, 5, 6), 6)];
+    for (pixel, out) in pixels.iter_mut().enumerate() {
+        let (x, y) = ((pixel / 4) as i32, (pixel % 4) as i32);
+        let channel = |c: usize| clamp((x * (horizontal[c] - origin[c]) + y * (vertical[c] - origin[c]) + 4 * origin[c] + 2) >> 2);
+        *out = [channel(0), channel(1), channel(2), 255];
+    }
+}
+
+// Decodes an EAC alpha block into the alpha of pixels in column order
+fn decode_eac(block: u64, pixels: &mut [[u8; 4]; 16]) {
+    let base = bits(block, 63, 8);
+    let multiplier = bits(block, 55, 4);
+    let modifiers = EAC_MODIFIERS[bits(block, 51, 4) as usize];
+    for (pixel, out) in pixels.iter_mut().enumerate() {
+        let index = bits(block, 47 - pixel as u32 * 3, 3) as usize;
+        out[3] = clamp(base + modifiers[index] * multiplier);
+    }
+}
+
+impl Texture {
+    /// Creates a texture from a compressed image, uploading it as it is when
+    /// the context supports its format, and decompressing it otherwise.
+    ///
+    /// The stored mip levels are used in place of generating them, compressed
+    /// textures with a single level aren't mipmapped.
+    pub fn from_compressed(image: &CompressedImage, settings: &TextureSettings) -> Result<Self, String> {
+        let supported = image.is_supported();
+        let decompressed = if supported {
+            Vec::new()
+        } else {
+            (0..image.level_count()).map(|x| image.decompress(x)).collect::<Result<Vec<_>, _>>()?
+        };
+        let mipmapped = image.level_count() > 1 || (!supported && settings.get_generate_mipmap());
+        let mut settings = *settings;
+        settings.set_generate_mipmap(mipmapped);
+        let mut id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            apply_settings(&settings);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, image.level_count() as i32 - 1);
+            if supported {
+                for (level, data) in image.levels.iter().enumerate() {
+                    let (width, height) = image.level_size(level);
+                    gl::CompressedTexImage2D(gl::TEXTURE_2D,
+                                             level as i32,
+                                             image.format,
+                                             width as i32,
+                                             height as i32,
+                                             0,
+                                             data.len() as i32,
+                                             data.as_ptr() as *const _);
+                }
+            } else {
+                for (level, data) in decompressed.iter().enumerate() {
+                    gl::TexImage2D(gl::TEXTURE_2D,
+                                   level as i32,
+                                   gl::RGBA as i32,
+                                   data.width() as i32,
+                                   data.height() as i32,
+                                   0,
+                                   gl::RGBA,
+                                   gl::UNSIGNED_BYTE,
+                                   data.as_ptr() as *const _);
+                }
+                if image.level_count() == 1 && mipmapped {
+                    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 1000);
+                    gl::GenerateMipmap(gl::TEXTURE_2D);
+                }
+            }
+        }
+        Ok(Texture::new(id, image.width, image.height))
+    }
+
+    /// Loads a KTX or KTX2 file, see `from_compressed`.
+    pub fn from_ktx_path<P>(path: P, settings: &TextureSettings) -> Result<Self, String>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        let data = match ::std::fs::read(path) {
+            Ok(x) => x,
+            Err(e) => return Err(format!("Could not load '{:?}': {:?}", path.file_name().unwrap(), e)),
+        };
+        let image = CompressedImage::from_ktx(&data)?;
+        Texture::from_compressed(&image, settings)
+    }
+}
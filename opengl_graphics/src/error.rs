@@ -20,3 +20,69 @@ impl From<::std::io::Error> for Error {
         Error::IoError(err)
     }
 }
+
+/// A vertex attribute given to (or expected by) a custom shader draw.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attribute {
+    /// Vertex colours.
+    Colour,
+    /// Texture coordinates, which come with a texture.
+    Uv,
+    /// Vertex normals.
+    Normal,
+    /// Indices.
+    Index,
+}
+
+/// Why the inputs of `GlGraphics::try_shader_draw` don't suit its shader.
+/// Nothing is drawn when one of these is returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawError {
+    /// The shader has a uv buffer but no texture, or a texture but no uv buffer.
+    ShaderMismatch,
+    /// An attribute was given which the shader doesn't use.
+    Unexpected(Attribute),
+    /// The shader uses an attribute which wasn't given.
+    Missing(Attribute),
+    /// An attribute wasn't given for every vertex.
+    WrongLength {
+        /// The attribute with the wrong length.
+        attribute: Attribute,
+        /// The number of vertices.
+        vertices: usize,
+        /// The number of values given.
+        found: usize,
+    },
+    /// More vertices were given than fit in the shader's buffers at once.
+    TooManyVertices {
+        /// The number of vertices given.
+        vertices: usize,
+        /// The number of vertices the shader's buffers hold.
+        capacity: usize,
+    },
+    /// An index refers past the end of the vertices given with it.
+    IndexOutOfRange {
+        /// The first index out of range.
+        index: u16,
+        /// The number of vertices.
+        vertices: usize,
+    },
+}
+
+impl fmt::Display for DrawError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DrawError::ShaderMismatch => write!(f, "Shader expects a mismatch of UVs and Texture"),
+            DrawError::Unexpected(attribute) => write!(f, "{:?} was given but not expected", attribute),
+            DrawError::Missing(attribute) => write!(f, "{:?} was expected but not given", attribute),
+            DrawError::WrongLength { attribute, vertices, found } =>
+                write!(f, "The number of vertices ({}) is not equal to the number of {:?} values ({})", vertices, attribute, found),
+            DrawError::TooManyVertices { vertices, capacity } =>
+                write!(f, "Too many vertices ({}) to fit in the shader's buffers ({})", vertices, capacity),
+            DrawError::IndexOutOfRange { index, vertices } =>
+                write!(f, "The index {} is out of range of the vertices ({})", index, vertices),
+        }
+    }
+}
+
+impl ::std::error::Error for DrawError {}
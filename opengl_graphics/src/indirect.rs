@@ -0,0 +1,204 @@
+//! Culling instances on the GPU for indirect draws
+
+// External crates.
+use gl;
+use gl::types::{GLchar, GLint, GLsizeiptr, GLuint};
+use std::ffi::CString;
+use std::{mem, ptr};
+
+// Local crate.
+use back_end::GlGraphics;
+use profile::Profile;
+use shader_utils::compile_shader;
+
+// Instances are tested in groups of this many
+const GROUP_SIZE: usize = 64;
+
+// Culls each bounding sphere against the frustum's planes, appending those
+// inside to `visible` and counting them into the indirect command.
+const CULL_SHADER: &'static str = "
+layout(local_size_x = 64) in;
+
+layout(std430, binding = 0) readonly buffer Instances {
+    vec4 instances[];
+};
+layout(std430, binding = 1) writeonly buffer Visible {
+    vec4 visible[];
+};
+// Where the instance count sits in both arrays and elements commands
+layout(std430, binding = 2) buffer Command {
+    uint count;
+    uint instance_count;
+};
+
+uniform vec4 planes[6];
+
+void main() {
+    uint i = gl_GlobalInvocationID.x;
+    if (i >= uint(instances.length())) {
+        return;
+    }
+    vec4 sphere = instances[i];
+    for (int p = 0; p < 6; p++) {
+        if (dot(planes[p].xyz, sphere.xyz) + planes[p].w < -sphere.w) {
+            return;
+        }
+    }
+    visible[atomicAdd(instance_count, 1u)] = sphere;
+}
+";
+
+/// Instances of a mesh culled against the view frustum by a compute shader, which writes
+/// the indirect draw command itself so the instances never come back to the CPU.
+/// This suits very large instance counts like grass or debris.
+///
+/// Each instance is a bounding sphere, `[x, y, z, radius]`. Those inside the frustum are
+/// passed to the shader drawing them in its per-instance `vec4` attribute named `instance`
+/// (see `Mesh::draw_indirect`), which can also use the radius as a scale.
+///
+/// Needs compute shaders and indirect draws, so OpenGL ES 3.1 or OpenGL 4.3.
+/// The buffers and program get deleted when running out of scope.
+pub struct GpuCuller {
+    program: GLuint,
+    shader: GLuint,
+    planes: GLint,
+    instances: GLuint,
+    pub(crate) visible: GLuint,
+    pub(crate) command: GLuint,
+    len: usize,
+}
+
+impl Drop for GpuCuller {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+            gl::DeleteShader(self.shader);
+            let buffers = [self.instances, self.visible, self.command];
+            gl::DeleteBuffers(buffers.len() as i32, buffers.as_ptr());
+        }
+    }
+}
+
+impl GpuCuller {
+    /// Uploads `instances` and compiles the culling shader, failing where compute
+    /// shaders aren't supported so the caller can fall back to `shader_draw_instanced`.
+    pub fn new(gl: &GlGraphics, instances: &[[f32; 4]]) -> Result<Self, String> {
+        let version = match gl.profile() {
+            Profile::Gles3 => "#version 310 es\n",
+            Profile::Desktop(_) => "#version 430\n",
+            Profile::Gles2 => return Err("Culling on the GPU needs OpenGL ES 3.1".to_string()),
+        };
+        if !gl::DispatchCompute::is_loaded() || !gl::DrawArraysIndirect::is_loaded() {
+            return Err("Compute shaders or indirect draws are not supported".to_string());
+        }
+        let shader = compile_shader(gl::COMPUTE_SHADER, &format!("{}{}", version, CULL_SHADER))
+            .map_err(|s| format!("Error compiling culling shader: {}", s))?;
+        let mut culler = GpuCuller {
+            program: unsafe { gl::CreateProgram() },
+            shader: shader,
+            planes: -1,
+            instances: 0,
+            visible: 0,
+            command: 0,
+            len: 0,
+        };
+        unsafe {
+            gl::AttachShader(culler.program, shader);
+            gl::LinkProgram(culler.program);
+            let mut status = gl::FALSE as GLint;
+            gl::GetProgramiv(culler.program, gl::LINK_STATUS, &mut status);
+            if status != (gl::TRUE as GLint) {
+                let mut len = 0;
+                gl::GetProgramiv(culler.program, gl::INFO_LOG_LENGTH, &mut len);
+                let mut buf = vec![0; (len as usize).max(1) - 1];
+                gl::GetProgramInfoLog(culler.program, len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
+                return Err(format!("Error linking culling shader: {}", String::from_utf8_lossy(&buf)));
+            }
+            let name = CString::new("planes").unwrap();
+            culler.planes = gl::GetUniformLocation(culler.program, name.as_ptr());
+            let mut buffers = [0; 3];
+            gl::GenBuffers(buffers.len() as i32, buffers.as_mut_ptr());
+            culler.instances = buffers[0];
+            culler.visible = buffers[1];
+            culler.command = buffers[2];
+            // Large enough for either kind of command, the instance count is the second value of both
+            let command = [0u32; 5];
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, culler.command);
+            gl::BufferData(gl::DRAW_INDIRECT_BUFFER,
+                           mem::size_of_val(&command) as GLsizeiptr,
+                           command.as_ptr() as *const _,
+                           gl::DYNAMIC_DRAW);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+        culler.set_instances(instances);
+        Ok(culler)
+    }
+
+    /// Replaces the instances, which can change how many there are
+    pub fn set_instances(&mut self, instances: &[[f32; 4]]) {
+        self.len = instances.len();
+        let size = (instances.len() * mem::size_of::<[f32; 4]>()) as GLsizeiptr;
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.instances);
+            gl::BufferData(gl::SHADER_STORAGE_BUFFER, size, instances.as_ptr() as *const _, gl::STATIC_DRAW);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.visible);
+            gl::BufferData(gl::SHADER_STORAGE_BUFFER, size, ptr::null(), gl::DYNAMIC_COPY);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+    }
+
+    /// The number of instances, visible or not
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no instances
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Culls the instances against the frustum of `view_proj` (column major, as
+    /// `cgmath::Matrix4::as_ref` gives it), for the next `Mesh::draw_indirect`.
+    pub fn cull(&mut self, graphics: &mut GlGraphics, view_proj: &[[f32; 4]; 4]) {
+        let planes = frustum_planes(view_proj);
+        unsafe {
+            let zero = 0u32;
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.command);
+            gl::BufferSubData(gl::DRAW_INDIRECT_BUFFER,
+                              mem::size_of::<u32>() as GLsizeiptr,
+                              mem::size_of::<u32>() as GLsizeiptr,
+                              &zero as *const u32 as *const _);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+        if self.len == 0 {
+            return;
+        }
+        graphics.flush_batches();
+        unsafe {
+            gl::UseProgram(self.program);
+            gl::Uniform4fv(self.planes, 6, planes.as_ptr() as *const f32);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, self.instances);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 1, self.visible);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 2, self.command);
+            gl::DispatchCompute(((self.len + GROUP_SIZE - 1) / GROUP_SIZE) as GLuint, 1, 1);
+            gl::MemoryBarrier(gl::COMMAND_BARRIER_BIT | gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+        }
+        // The culling program replaced whichever one `graphics` had in use
+        graphics.clear_program();
+    }
+}
+
+// The left, right, bottom, top, near and far planes of a view-projection matrix,
+// facing inwards and normalized so distances to them are in world units
+fn frustum_planes(m: &[[f32; 4]; 4]) -> [[f32; 4]; 6] {
+    let row = |i: usize| [m[0][i], m[1][i], m[2][i], m[3][i]];
+    let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+    let combine = |a: [f32; 4], b: [f32; 4], sign: f32| {
+        let plane = [a[0] + sign * b[0], a[1] + sign * b[1], a[2] + sign * b[2], a[3] + sign * b[3]];
+        let length = (plane[0] * plane[0] + plane[1] * plane[1] + plane[2] * plane[2]).sqrt().max(1e-6);
+        [plane[0] / length, plane[1] / length, plane[2] / length, plane[3] / length]
+    };
+    [combine(w, x, 1.0), combine(w, x, -1.0),
+     combine(w, y, 1.0), combine(w, y, -1.0),
+     combine(w, z, 1.0), combine(w, z, -1.0)]
+}
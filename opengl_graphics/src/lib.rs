@@ -13,7 +13,20 @@ extern crate viewport;
 pub use shader_version::{OpenGL, Shaders};
 pub use shader_version::glsl::GLSL;
 pub use back_end::{Colored, Textured, GlGraphics};
-pub use texture::Texture;
+pub use texture::{Texture, max_anisotropy};
+pub use render_target::RenderTarget;
+pub use mesh::{Instances, Mesh};
+pub use text::TextError;
+pub use error::DrawError;
+pub use atlas::{AtlasRegion, TextureAtlas, SpriteBatch};
+pub use readback::AsyncReadback;
+pub use profile::{Profile, has_extension};
+pub use audit::StateAudit;
+pub use timer::GpuTiming;
+pub use indirect::GpuCuller;
+pub use shader_batch::ShaderBatch;
+pub use compressed::{CompressedImage, compressed_formats};
+pub use basis::{BasisEncoding, BasisTexture, BasisTranscoder, TranscodeTarget};
 pub use texture_lib::*;
 
 pub mod shader_utils;
@@ -29,3 +42,15 @@ pub mod gl;
 mod back_end;
 mod texture;
 mod draw_state;
+mod render_target;
+mod mesh;
+mod text;
+mod atlas;
+mod readback;
+mod compressed;
+mod basis;
+mod profile;
+mod audit;
+mod timer;
+mod indirect;
+mod shader_batch;
@@ -0,0 +1,546 @@
+//! Geometry kept in GPU buffers
+
+// External crates.
+use gl;
+use gl::types::{GLenum, GLint, GLsizei, GLsizeiptr, GLuint};
+use graphics::DrawState;
+use graphics::color::gamma_srgb_to_linear;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::os::raw::c_void;
+use std::{mem, ptr};
+
+// Local crate.
+use back_end::GlGraphics;
+use shader_utils::{attribute_location, Shader, VertexAttribute};
+use texture::Texture;
+use indirect::GpuCuller;
+
+// A vertex buffer and the attribute it's bound to
+struct MeshBuffer {
+    vbo: GLuint,
+    name: &'static str,
+    size: i32,
+    ty: GLenum,
+    normalized: bool,
+    len: usize,
+    usage: GLenum,
+}
+
+impl MeshBuffer {
+    fn new<T: VertexAttribute>(name: &'static str, data: &[T], usage: GLenum) -> Self {
+        let mut vbo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut vbo);
+        }
+        let mut buffer = MeshBuffer {
+            vbo: vbo,
+            name: name,
+            size: T::SIZE,
+            ty: T::TY,
+            normalized: T::NORMALIZED,
+            len: 0,
+            usage: usage,
+        };
+        buffer.set(gl::ARRAY_BUFFER, data);
+        buffer
+    }
+
+    fn set<T>(&mut self, target: GLenum, data: &[T]) {
+        self.len = data.len();
+        unsafe {
+            gl::BindBuffer(target, self.vbo);
+            gl::BufferData(target,
+                           (data.len() * mem::size_of::<T>()) as GLsizeiptr,
+                           data.as_ptr() as *const _,
+                           self.usage);
+            gl::BindBuffer(target, 0);
+        }
+    }
+
+    fn update<T>(&mut self, target: GLenum, offset: usize, data: &[T]) {
+        assert!(offset + data.len() <= self.len,
+            "Updating {} items at {} is outside of the buffer's {} items", data.len(), offset, self.len);
+        unsafe {
+            gl::BindBuffer(target, self.vbo);
+            gl::BufferSubData(target,
+                              (offset * mem::size_of::<T>()) as GLsizeiptr,
+                              (data.len() * mem::size_of::<T>()) as GLsizeiptr,
+                              data.as_ptr() as *const _);
+            gl::BindBuffer(target, 0);
+        }
+    }
+}
+
+impl Drop for MeshBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
+
+/// Values given to each instance drawn by `Mesh::draw_instanced`, such as where each copy of the
+/// mesh goes. Each buffer is bound to the shader's attribute with the name it was set with, which
+/// advances once per instance rather than once per vertex.
+///
+/// The buffers get deleted when running out of scope.
+pub struct Instances {
+    buffers: Vec<MeshBuffer>,
+}
+
+impl Instances {
+    /// Creates a set of instances without any attributes
+    pub fn new() -> Self {
+        Instances { buffers: Vec::new() }
+    }
+
+    /// Replaces (or adds) the values of the attribute called `name`, one for each instance
+    pub fn set<T: VertexAttribute>(&mut self, name: &'static str, data: &[T]) {
+        match self.buffers.iter_mut().find(|x| x.name == name) {
+            Some(buffer) => buffer.set(gl::ARRAY_BUFFER, data),
+            None => self.buffers.push(MeshBuffer::new(name, data, gl::DYNAMIC_DRAW)),
+        }
+    }
+
+    /// The number of instances, the fewest values any of the attributes has
+    pub fn len(&self) -> usize {
+        self.buffers.iter().map(|x| x.len).min().unwrap_or(0)
+    }
+
+    /// Whether there are no instances to draw
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for Instances {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Vertices (and optionally normals, uvs, tangents, colours and indices) uploaded to the GPU once,
+/// so static geometry doesn't get copied on every draw like with `GlGraphics::shader_draw`.
+///
+/// The buffers are bound to the attributes named `pos`, `normal`, `uv`, `tangent` and `color` of
+/// whichever shader the mesh is drawn with, those it doesn't have are skipped.
+/// The buffers get deleted when running out of scope.
+pub struct Mesh<V: VertexAttribute> {
+    positions: MeshBuffer,
+    normals: Option<MeshBuffer>,
+    uvs: Option<MeshBuffer>,
+    tangents: Option<MeshBuffer>,
+    colours: Option<MeshBuffer>,
+    indices: Option<MeshBuffer>,
+    // Buffers bound to attributes by the names they were added with
+    attributes: Vec<MeshBuffer>,
+    usage: GLenum,
+    // A vertex array object for each program the mesh has been drawn with
+    vaos: Vec<(GLuint, GLuint)>,
+    phantom: PhantomData<V>,
+}
+
+impl<V: VertexAttribute> Drop for Mesh<V> {
+    fn drop(&mut self) {
+        self.clear_vaos();
+    }
+}
+
+impl<V: VertexAttribute> Mesh<V> {
+    /// Uploads the positions of a mesh
+    pub fn new(positions: &[V]) -> Self {
+        Self::with_usage(positions, gl::STATIC_DRAW)
+    }
+
+    /// Uploads the positions of a mesh whose vertices are rewritten often, such as every
+    /// frame, hinting the driver to keep all of its buffers where they're quick to update
+    pub fn new_dynamic(positions: &[V]) -> Self {
+        Self::with_usage(positions, gl::DYNAMIC_DRAW)
+    }
+
+    fn with_usage(positions: &[V], usage: GLenum) -> Self {
+        Mesh {
+            positions: MeshBuffer::new("pos", positions, usage),
+            normals: None,
+            uvs: None,
+            tangents: None,
+            colours: None,
+            indices: None,
+            attributes: Vec::new(),
+            usage: usage,
+            vaos: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Uploads a normal for each vertex
+    pub fn with_normals<N: VertexAttribute>(mut self, normals: &[N]) -> Self {
+        self.set_normals(normals);
+        self
+    }
+
+    /// Uploads texture coordinates for each vertex
+    pub fn with_uvs<U: VertexAttribute>(mut self, uvs: &[U]) -> Self {
+        self.set_uvs(uvs);
+        self
+    }
+
+    /// Uploads a tangent for each vertex, for normal mapping. The handedness of the
+    /// bitangent goes in `w`, as `CookedMesh::generate_tangents` gives them
+    pub fn with_tangents<T: VertexAttribute>(mut self, tangents: &[T]) -> Self {
+        self.set_tangents(tangents);
+        self
+    }
+
+    /// Uploads a colour for each vertex, converted to linear like the colours given to `shader_draw`
+    pub fn with_colours(mut self, colours: &[[f32; 4]]) -> Self {
+        self.set_colours(colours);
+        self
+    }
+
+    /// Uploads indices, the mesh is then drawn as indexed triangles
+    pub fn with_indices(mut self, indices: &[u16]) -> Self {
+        self.set_indices(indices);
+        self
+    }
+
+    /// Uploads a value for each vertex, bound to the shader's attribute called `name`,
+    /// for attributes beyond the standard ones such as bone weights
+    pub fn with_attribute<T: VertexAttribute>(mut self, name: &'static str, data: &[T]) -> Self {
+        self.set_attribute(name, data);
+        self
+    }
+
+    /// The number of vertices
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len
+    }
+
+    /// The number of indices, `0` if the mesh isn't indexed
+    pub fn index_count(&self) -> usize {
+        self.indices.as_ref().map(|x| x.len).unwrap_or(0)
+    }
+
+    /// Replaces the positions, which can change the number of vertices
+    pub fn set_positions(&mut self, positions: &[V]) {
+        self.positions.set(gl::ARRAY_BUFFER, positions);
+    }
+
+    /// Replaces (or adds) the normals, which can be packed into a smaller attribute type like `Snorm4`
+    pub fn set_normals<N: VertexAttribute>(&mut self, normals: &[N]) {
+        Self::set_buffer(&mut self.normals, &mut self.vaos, "normal", normals, self.usage);
+    }
+
+    /// Replaces (or adds) the texture coordinates, which can be packed into a smaller attribute type like `Half2`
+    pub fn set_uvs<U: VertexAttribute>(&mut self, uvs: &[U]) {
+        Self::set_buffer(&mut self.uvs, &mut self.vaos, "uv", uvs, self.usage);
+    }
+
+    /// Replaces (or adds) the tangents, which can be packed into a `Snorm4`
+    pub fn set_tangents<T: VertexAttribute>(&mut self, tangents: &[T]) {
+        Self::set_buffer(&mut self.tangents, &mut self.vaos, "tangent", tangents, self.usage);
+    }
+
+    /// Replaces (or adds) the colours
+    pub fn set_colours(&mut self, colours: &[[f32; 4]]) {
+        let colours = colours.iter().map(|&x| gamma_srgb_to_linear(x)).collect::<Vec<_>>();
+        Self::set_buffer(&mut self.colours, &mut self.vaos, "color", &colours, self.usage);
+    }
+
+    /// Replaces (or adds) the values of the attribute called `name`, see `with_attribute`
+    pub fn set_attribute<T: VertexAttribute>(&mut self, name: &'static str, data: &[T]) {
+        if let Some(buffer) = self.attributes.iter_mut().find(|x| x.name == name) {
+            buffer.set(gl::ARRAY_BUFFER, data);
+            return;
+        }
+        let mut buffer = None;
+        Self::set_buffer(&mut buffer, &mut self.vaos, name, data, self.usage);
+        self.attributes.extend(buffer);
+    }
+
+    /// Replaces (or adds) the indices
+    pub fn set_indices(&mut self, indices: &[u16]) {
+        match self.indices {
+            Some(ref mut buffer) => buffer.set(gl::ELEMENT_ARRAY_BUFFER, indices),
+            None => {
+                let mut buffer = MeshBuffer::new::<f32>("", &[], self.usage);
+                buffer.set(gl::ELEMENT_ARRAY_BUFFER, indices);
+                self.indices = Some(buffer);
+                self.clear_vaos();
+            }
+        }
+    }
+
+    /// Overwrites the positions starting at vertex `offset`, without re-uploading the rest
+    pub fn update_positions(&mut self, offset: usize, positions: &[V]) {
+        self.positions.update(gl::ARRAY_BUFFER, offset, positions);
+    }
+
+    /// Overwrites the normals starting at vertex `offset`
+    pub fn update_normals<N: VertexAttribute>(&mut self, offset: usize, normals: &[N]) {
+        self.normals.as_mut().expect("The mesh has no normals").update(gl::ARRAY_BUFFER, offset, normals);
+    }
+
+    /// Overwrites the texture coordinates starting at vertex `offset`
+    pub fn update_uvs<U: VertexAttribute>(&mut self, offset: usize, uvs: &[U]) {
+        self.uvs.as_mut().expect("The mesh has no uvs").update(gl::ARRAY_BUFFER, offset, uvs);
+    }
+
+    /// Overwrites the tangents starting at vertex `offset`
+    pub fn update_tangents<T: VertexAttribute>(&mut self, offset: usize, tangents: &[T]) {
+        self.tangents.as_mut().expect("The mesh has no tangents").update(gl::ARRAY_BUFFER, offset, tangents);
+    }
+
+    /// Overwrites the values of the attribute called `name` starting at vertex `offset`
+    pub fn update_attribute<T: VertexAttribute>(&mut self, name: &'static str, offset: usize, data: &[T]) {
+        self.attributes
+            .iter_mut()
+            .find(|x| x.name == name)
+            .unwrap_or_else(|| panic!("The mesh has no attribute called {}", name))
+            .update(gl::ARRAY_BUFFER, offset, data);
+    }
+
+    /// Overwrites the indices starting at `offset`
+    pub fn update_indices(&mut self, offset: usize, indices: &[u16]) {
+        self.indices.as_mut().expect("The mesh has no indices").update(gl::ELEMENT_ARRAY_BUFFER, offset, indices);
+    }
+
+    fn set_buffer<T: VertexAttribute>(buffer: &mut Option<MeshBuffer>,
+                                      vaos: &mut Vec<(GLuint, GLuint)>,
+                                      name: &'static str,
+                                      data: &[T],
+                                      usage: GLenum) {
+        match *buffer {
+            Some(ref mut buffer) => buffer.set(gl::ARRAY_BUFFER, data),
+            None => {
+                *buffer = Some(MeshBuffer::new(name, data, usage));
+                // Existing vertex array objects don't know about the new buffer
+                for &(_, vao) in vaos.iter() {
+                    unsafe {
+                        gl::DeleteVertexArrays(1, &vao);
+                    }
+                }
+                vaos.clear();
+            }
+        }
+    }
+
+    fn clear_vaos(&mut self) {
+        for &(_, vao) in &self.vaos {
+            unsafe {
+                gl::DeleteVertexArrays(1, &vao);
+            }
+        }
+        self.vaos.clear();
+    }
+
+    fn vao(&mut self, program: GLuint) -> GLuint {
+        if let Some(&(_, vao)) = self.vaos.iter().find(|x| x.0 == program) {
+            return vao;
+        }
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+            let attributes = Some(&self.positions).into_iter()
+                .chain(self.normals.as_ref())
+                .chain(self.uvs.as_ref())
+                .chain(self.tangents.as_ref())
+                .chain(self.colours.as_ref())
+                .chain(self.attributes.iter());
+            for buffer in attributes {
+                if let Ok(location) = attribute_location(program, buffer.name) {
+                    gl::BindBuffer(gl::ARRAY_BUFFER, buffer.vbo);
+                    let normalized = if buffer.normalized { gl::TRUE } else { gl::FALSE };
+                    gl::VertexAttribPointer(location, buffer.size, buffer.ty, normalized, 0, ptr::null());
+                    gl::EnableVertexAttribArray(location);
+                }
+            }
+            if let Some(ref indices) = self.indices {
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, indices.vbo);
+            }
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        self.vaos.push((program, vao));
+        vao
+    }
+
+    /// Draws the mesh with `shader`, setting its uniforms in `uniforms` first.
+    ///
+    /// `texture` is bound to texture unit 0. Anything buffered in `shader` is flushed first.
+    pub fn draw<S: Shader>(&mut self,
+                           graphics: &mut GlGraphics,
+                           shader: &mut S,
+                           draw_state: &DrawState,
+                           texture: Option<&Texture>,
+                           uniforms: impl FnOnce(&mut S, &mut GlGraphics)) {
+        let (vertices, indices) = (self.vertex_count() as i32, self.index_count() as i32);
+        let indexed = self.indices.is_some();
+        self.draw_with(graphics, shader, draw_state, texture, uniforms, || unsafe {
+            if indexed {
+                gl::DrawElements(gl::TRIANGLES, indices, gl::UNSIGNED_SHORT, ptr::null());
+            } else {
+                gl::DrawArrays(gl::TRIANGLES, 0, vertices);
+            }
+        });
+    }
+
+    /// Draws several ranges of the mesh with `shader`, like the parts of a model which share
+    /// a material. The ranges are of indices if the mesh is indexed, otherwise of vertices.
+    ///
+    /// They are submitted in a single call where `GlGraphics::multi_draw` is supported,
+    /// and one call each otherwise.
+    pub fn draw_ranges<S: Shader>(&mut self,
+                                  graphics: &mut GlGraphics,
+                                  shader: &mut S,
+                                  draw_state: &DrawState,
+                                  texture: Option<&Texture>,
+                                  ranges: &[Range<usize>],
+                                  uniforms: impl FnOnce(&mut S, &mut GlGraphics)) {
+        let indexed = self.indices.is_some();
+        let len = if indexed { self.index_count() } else { self.vertex_count() };
+        for range in ranges {
+            assert!(range.start <= range.end && range.end <= len,
+                "The range {:?} is outside of the mesh's {} {}", range, len, if indexed { "indices" } else { "vertices" });
+        }
+        let multi_draw = graphics.multi_draw();
+        let counts = ranges.iter().map(|x| (x.end - x.start) as GLsizei).collect::<Vec<_>>();
+        self.draw_with(graphics, shader, draw_state, texture, uniforms, || unsafe {
+            if indexed {
+                let offsets = ranges.iter()
+                    .map(|x| (x.start * mem::size_of::<u16>()) as *const c_void)
+                    .collect::<Vec<_>>();
+                if multi_draw {
+                    gl::MultiDrawElementsEXT(gl::TRIANGLES, counts.as_ptr(), gl::UNSIGNED_SHORT, offsets.as_ptr(), ranges.len() as GLsizei);
+                } else {
+                    for (&count, &offset) in counts.iter().zip(offsets.iter()) {
+                        gl::DrawElements(gl::TRIANGLES, count, gl::UNSIGNED_SHORT, offset);
+                    }
+                }
+            } else {
+                let firsts = ranges.iter().map(|x| x.start as GLint).collect::<Vec<_>>();
+                if multi_draw {
+                    gl::MultiDrawArraysEXT(gl::TRIANGLES, firsts.as_ptr(), counts.as_ptr(), ranges.len() as GLsizei);
+                } else {
+                    for (&first, &count) in firsts.iter().zip(counts.iter()) {
+                        gl::DrawArrays(gl::TRIANGLES, first, count);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Draws the instances of `culler` left by its last `GpuCuller::cull`, with one indirect draw
+    /// whose instance count never leaves the GPU. Each instance's bounding sphere goes to the
+    /// shader's per-instance `vec4` attribute named `instance`.
+    ///
+    /// # Panics
+    /// If `shader` has no `instance` attribute
+    pub fn draw_indirect<S: Shader>(&mut self,
+                                    graphics: &mut GlGraphics,
+                                    shader: &mut S,
+                                    draw_state: &DrawState,
+                                    texture: Option<&Texture>,
+                                    culler: &GpuCuller,
+                                    uniforms: impl FnOnce(&mut S, &mut GlGraphics)) {
+        let location = attribute_location(shader.program(), "instance").unwrap();
+        let indexed = self.indices.is_some();
+        let count = if indexed { self.index_count() } else { self.vertex_count() } as u32;
+        self.draw_with(graphics, shader, draw_state, texture, uniforms, || unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, culler.visible);
+            gl::EnableVertexAttribArray(location);
+            gl::VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, 0, ptr::null());
+            gl::VertexAttribDivisor(location, 1);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            // The culler only writes the instance count
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, culler.command);
+            gl::BufferSubData(gl::DRAW_INDIRECT_BUFFER, 0, mem::size_of::<u32>() as GLsizeiptr, &count as *const u32 as *const _);
+            if indexed {
+                gl::DrawElementsIndirect(gl::TRIANGLES, gl::UNSIGNED_SHORT, ptr::null());
+            } else {
+                gl::DrawArraysIndirect(gl::TRIANGLES, ptr::null());
+            }
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+            // The vertex array object is shared with the mesh's other draws
+            gl::VertexAttribDivisor(location, 0);
+            gl::DisableVertexAttribArray(location);
+        });
+    }
+
+    /// Draws a copy of the mesh for each of `instances` with `shader`, in a single draw call.
+    /// The attributes of `instances` the shader doesn't have are skipped.
+    pub fn draw_instanced<S: Shader>(&mut self,
+                                     graphics: &mut GlGraphics,
+                                     shader: &mut S,
+                                     draw_state: &DrawState,
+                                     texture: Option<&Texture>,
+                                     instances: &Instances,
+                                     uniforms: impl FnOnce(&mut S, &mut GlGraphics)) {
+        let count = instances.len() as GLsizei;
+        if count == 0 {
+            return;
+        }
+        let locations = instances.buffers
+            .iter()
+            .filter_map(|x| attribute_location(shader.program(), x.name).ok().map(|location| (location, x)))
+            .collect::<Vec<_>>();
+        let (vertices, indices) = (self.vertex_count() as i32, self.index_count() as i32);
+        let indexed = self.indices.is_some();
+        self.draw_with(graphics, shader, draw_state, texture, uniforms, || unsafe {
+            for &(location, buffer) in &locations {
+                gl::BindBuffer(gl::ARRAY_BUFFER, buffer.vbo);
+                let normalized = if buffer.normalized { gl::TRUE } else { gl::FALSE };
+                gl::VertexAttribPointer(location, buffer.size, buffer.ty, normalized, 0, ptr::null());
+                gl::EnableVertexAttribArray(location);
+                gl::VertexAttribDivisor(location, 1);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            if indexed {
+                gl::DrawElementsInstanced(gl::TRIANGLES, indices, gl::UNSIGNED_SHORT, ptr::null(), count);
+            } else {
+                gl::DrawArraysInstanced(gl::TRIANGLES, 0, vertices, count);
+            }
+            // The vertex array object is shared with the mesh's other draws
+            for &(location, _) in &locations {
+                gl::VertexAttribDivisor(location, 0);
+                gl::DisableVertexAttribArray(location);
+            }
+        });
+    }
+
+    // Binds everything needed to draw with `shader`, then makes the draw calls in `submit`
+    fn draw_with<S: Shader>(&mut self,
+                            graphics: &mut GlGraphics,
+                            shader: &mut S,
+                            draw_state: &DrawState,
+                            texture: Option<&Texture>,
+                            uniforms: impl FnOnce(&mut S, &mut GlGraphics),
+                            submit: impl FnOnce()) {
+        graphics.flush_batches();
+        if *shader.offset() > 0 {
+            graphics.use_program(shader.program());
+            shader.flush();
+        }
+        let program = shader.program();
+        graphics.use_program(program);
+        uniforms(shader, graphics);
+        graphics.use_draw_state(draw_state);
+        if let Some(texture) = texture {
+            graphics.audit_texture(texture.get_id());
+        }
+
+        let vao = self.vao(program);
+        graphics.with_depth(|| unsafe {
+            if let Some(texture) = texture {
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_2D, texture.get_id());
+            }
+            gl::BindVertexArray(vao);
+            submit();
+            gl::BindVertexArray(0);
+        });
+        graphics.clear_program();
+    }
+}
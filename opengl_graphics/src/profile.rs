@@ -0,0 +1,84 @@
+//! Telling desktop OpenGL and OpenGL ES contexts apart
+
+// External crates.
+use gl;
+use shader_version::OpenGL;
+use shader_version::glsl::GLSL;
+use std::ffi::CStr;
+
+/// The kind of context being drawn with, which decides the shaders the
+/// back-end compiles for itself.
+///
+/// OpenGL ES 2.0 contexts also need `OES_vertex_array_object`, and some
+/// features (like `AsyncReadback`) need OpenGL ES 3.0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// Desktop OpenGL, with shaders for this GLSL version.
+    Desktop(GLSL),
+    /// OpenGL ES 2.0, with GLSL ES 1.00 shaders.
+    Gles2,
+    /// OpenGL ES 3.0 or later, with GLSL ES 3.00 shaders.
+    Gles3,
+}
+
+impl Profile {
+    /// Gets the profile of the current context from its version string,
+    /// assuming `opengl` for desktop contexts.
+    pub fn detect(opengl: OpenGL) -> Self {
+        let version = unsafe {
+            let version = gl::GetString(gl::VERSION);
+            if version.is_null() {
+                return Profile::Desktop(opengl.to_glsl());
+            }
+            CStr::from_ptr(version as *const _).to_string_lossy().into_owned()
+        };
+        Profile::from_version(&version).unwrap_or(Profile::Desktop(opengl.to_glsl()))
+    }
+
+    /// Reads an OpenGL ES profile from a `GL_VERSION` string, which
+    /// starts with `OpenGL ES` and then the version for OpenGL ES contexts.
+    pub fn from_version(version: &str) -> Option<Self> {
+        if !version.starts_with("OpenGL ES ") {
+            return None;
+        }
+        let major = version["OpenGL ES ".len()..]
+            .split('.')
+            .next()
+            .and_then(|x| x.parse::<u32>().ok());
+        match major {
+            Some(x) if x >= 3 => Some(Profile::Gles3),
+            Some(2) => Some(Profile::Gles2),
+            _ => None,
+        }
+    }
+
+    /// Whether this is an OpenGL ES profile.
+    pub fn is_gles(&self) -> bool {
+        match *self {
+            Profile::Desktop(_) => false,
+            Profile::Gles2 | Profile::Gles3 => true,
+        }
+    }
+}
+
+/// Whether the current context supports the extension `name`, like `GL_EXT_multi_draw_arrays`.
+pub fn has_extension(name: &str) -> bool {
+    unsafe {
+        if gl::GetStringi::is_loaded() {
+            let mut count = 0;
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+            if count > 0 {
+                return (0..count as u32).any(|i| {
+                    let extension = gl::GetStringi(gl::EXTENSIONS, i);
+                    !extension.is_null() && CStr::from_ptr(extension as *const _).to_bytes() == name.as_bytes()
+                });
+            }
+        }
+        // OpenGL ES 2.0 only lists them all in one string
+        let extensions = gl::GetString(gl::EXTENSIONS);
+        !extensions.is_null() && CStr::from_ptr(extensions as *const _)
+            .to_string_lossy()
+            .split(' ')
+            .any(|x| x == name)
+    }
+}
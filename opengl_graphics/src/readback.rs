@@ -0,0 +1,160 @@
+//! Reading pixels back without waiting for the GPU
+
+// External crates.
+use gl;
+use gl::types::{GLsizeiptr, GLsync, GLuint};
+use image::RgbaImage;
+use std::ptr;
+
+// Local crate.
+use back_end::GlGraphics;
+
+struct PendingRead {
+    buffer: GLuint,
+    fence: GLsync,
+    width: u32,
+    height: u32,
+}
+
+/// Reads pixels into pixel buffer objects, so `glReadPixels` returns straight away
+/// instead of stalling until the GPU has finished drawing the frame.
+///
+/// Each read is started with `GlGraphics::read_pixels_async` and collected with
+/// `poll` once the GPU has caught up, usually a frame or two later. Up to
+/// `buffers` reads can be in flight at once, so capturing every frame doesn't
+/// wait on any of them.
+///
+/// The buffers and fences get deleted when running out of scope.
+pub struct AsyncReadback {
+    free: Vec<GLuint>,
+    pending: Vec<PendingRead>,
+    sizes: Vec<(GLuint, usize)>,
+}
+
+impl AsyncReadback {
+    /// Creates a readback with `buffers` pixel buffer objects, two being
+    /// enough to capture every frame.
+    pub fn new(buffers: usize) -> Self {
+        let mut free = vec![0; buffers.max(1)];
+        unsafe {
+            gl::GenBuffers(free.len() as i32, free.as_mut_ptr());
+        }
+        AsyncReadback {
+            sizes: free.iter().map(|&x| (x, 0)).collect(),
+            free: free,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Gets whether any reads haven't been collected by `poll` yet.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Gets whether there's a buffer free to start another read with.
+    pub fn can_read(&self) -> bool {
+        !self.free.is_empty()
+    }
+
+    /// Collects the oldest read if the GPU has finished it, without waiting.
+    ///
+    /// The rows are flipped so the first row of the image is the top of the
+    /// rectangle read, like `GlGraphics::read_pixels`.
+    pub fn poll(&mut self) -> Option<RgbaImage> {
+        let ready = match self.pending.first() {
+            Some(read) => unsafe {
+                let status = gl::ClientWaitSync(read.fence, 0, 0);
+                status == gl::ALREADY_SIGNALED || status == gl::CONDITION_SATISFIED
+            },
+            None => false,
+        };
+        if !ready {
+            return None;
+        }
+        let read = self.pending.remove(0);
+        let row = read.width as usize * 4;
+        let len = row * read.height as usize;
+        let mut flipped = Vec::with_capacity(len);
+        unsafe {
+            gl::DeleteSync(read.fence);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, read.buffer);
+            let mapped = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, len as GLsizeiptr, gl::MAP_READ_BIT);
+            if !mapped.is_null() {
+                let pixels = ::std::slice::from_raw_parts(mapped as *const u8, len);
+                for line in pixels.chunks(row.max(1)).rev() {
+                    flipped.extend_from_slice(line);
+                }
+                gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            }
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+        self.free.push(read.buffer);
+        if flipped.len() != len {
+            return None;
+        }
+        RgbaImage::from_raw(read.width, read.height, flipped)
+    }
+
+    // Copies the pixels in `rect` of the bound framebuffer into a free buffer
+    fn start(&mut self, rect: [i32; 4]) -> bool {
+        let buffer = match self.free.pop() {
+            Some(x) => x,
+            None => return false,
+        };
+        let (width, height) = (rect[2].max(0) as u32, rect[3].max(0) as u32);
+        let len = width as usize * height as usize * 4;
+        unsafe {
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, buffer);
+            let size = &mut self.sizes.iter_mut().find(|x| x.0 == buffer).unwrap().1;
+            if *size != len {
+                gl::BufferData(gl::PIXEL_PACK_BUFFER, len as GLsizeiptr, ptr::null(), gl::STREAM_READ);
+                *size = len;
+            }
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(rect[0],
+                           rect[1],
+                           width as i32,
+                           height as i32,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           ptr::null_mut());
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            let fence = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+            self.pending.push(PendingRead {
+                buffer: buffer,
+                fence: fence,
+                width: width,
+                height: height,
+            });
+        }
+        true
+    }
+}
+
+impl Drop for AsyncReadback {
+    fn drop(&mut self) {
+        unsafe {
+            for read in &self.pending {
+                gl::DeleteSync(read.fence);
+            }
+            let buffers = self.sizes.iter().map(|x| x.0).collect::<Vec<_>>();
+            gl::DeleteBuffers(buffers.len() as i32, buffers.as_ptr());
+        }
+    }
+}
+
+impl GlGraphics {
+    /// Starts reading the pixels in `rect` (in pixels, with the origin at the
+    /// bottom left) of the framebuffer being drawn to into `readback`,
+    /// flushing anything drawn before. Collect the image with `readback.poll()`.
+    ///
+    /// Returns `false` without reading anything when every buffer of
+    /// `readback` is still waiting to be collected.
+    pub fn read_pixels_async(&mut self, readback: &mut AsyncReadback, rect: [i32; 4]) -> bool {
+        if !readback.can_read() {
+            return false;
+        }
+        self.draw_end();
+        readback.start(rect)
+    }
+}
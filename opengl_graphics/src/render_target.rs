@@ -0,0 +1,198 @@
+//! Off-screen render targets
+
+// External crates.
+use gl;
+use gl::types::{GLint, GLuint};
+use graphics::{Context, Viewport};
+use image::RgbaImage;
+use std::ptr;
+
+// Local crate.
+use back_end::GlGraphics;
+use texture::Texture;
+use ImageSize;
+
+/// A framebuffer with a colour texture (and optionally a depth buffer) attached which can be
+/// drawn to instead of the screen, and whose texture can then be used like any other.
+///
+/// The framebuffer, texture and depth buffer get deleted when running out of scope.
+pub struct RenderTarget {
+    fbo: GLuint,
+    depth: Option<GLuint>,
+    texture: Texture,
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            if let Some(depth) = self.depth {
+                gl::DeleteRenderbuffers(1, &depth);
+            }
+        }
+    }
+}
+
+impl RenderTarget {
+    /// Creates a render target with an RGBA texture of the given size.
+    ///
+    /// Returns `Err` if the framebuffer is not complete.
+    pub fn new(width: u32, height: u32) -> Result<Self, String> {
+        Self::create(width, height, false)
+    }
+
+    /// Creates a render target with an RGBA texture and a 24 bit depth buffer of the given size,
+    /// for drawing 3D scenes which need depth testing off-screen.
+    ///
+    /// Returns `Err` if the framebuffer is not complete.
+    pub fn with_depth(width: u32, height: u32) -> Result<Self, String> {
+        Self::create(width, height, true)
+    }
+
+    fn create(width: u32, height: u32, depth: bool) -> Result<Self, String> {
+        let mut id = 0;
+        let mut fbo = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexImage2D(gl::TEXTURE_2D,
+                           0,
+                           gl::RGBA as i32,
+                           width as i32,
+                           height as i32,
+                           0,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           ptr::null());
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER,
+                                     gl::COLOR_ATTACHMENT0,
+                                     gl::TEXTURE_2D,
+                                     id,
+                                     0);
+        }
+        let depth = if depth {
+            let mut renderbuffer = 0;
+            unsafe {
+                gl::GenRenderbuffers(1, &mut renderbuffer);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+                gl::RenderbufferStorage(gl::RENDERBUFFER,
+                                        gl::DEPTH_COMPONENT24,
+                                        width as i32,
+                                        height as i32);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER,
+                                            gl::DEPTH_ATTACHMENT,
+                                            gl::RENDERBUFFER,
+                                            renderbuffer);
+            }
+            Some(renderbuffer)
+        } else {
+            None
+        };
+        let target = RenderTarget {
+            fbo: fbo,
+            depth: depth,
+            texture: Texture::new(id, width, height),
+        };
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Framebuffer is not complete: {:#x}", status));
+        }
+        Ok(target)
+    }
+
+    /// Gets the OpenGL id of the framebuffer.
+    pub fn get_id(&self) -> GLuint {
+        self.fbo
+    }
+
+    /// Whether this has a depth buffer.
+    pub fn has_depth(&self) -> bool {
+        self.depth.is_some()
+    }
+
+    /// Gets the texture this renders to.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Gets a viewport covering the whole render target.
+    pub fn viewport(&self) -> Viewport {
+        let (width, height) = self.texture.get_size();
+        Viewport {
+            rect: [0, 0, width as i32, height as i32],
+            draw_size: [width, height],
+            window_size: [width as f64, height as f64],
+        }
+    }
+
+    /// Makes this the framebuffer drawn to, flushing anything drawn before.
+    pub fn bind(&self, graphics: &mut GlGraphics) {
+        graphics.draw_end();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        }
+        graphics.set_viewport(self.viewport());
+    }
+
+    /// Goes back to drawing to the screen with `viewport`, flushing anything drawn to this.
+    pub fn unbind(&self, graphics: &mut GlGraphics, viewport: Viewport) {
+        graphics.draw_end();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        graphics.set_viewport(viewport);
+    }
+
+    /// Reads back the contents of the texture, flushing anything drawn before.
+    ///
+    /// Leaves the screen's framebuffer bound afterwards.
+    pub fn read_pixels(&self, graphics: &mut GlGraphics) -> RgbaImage {
+        graphics.draw_end();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        }
+        let (width, height) = self.texture.get_size();
+        let image = graphics.read_pixels([0, 0, width as i32, height as i32]);
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        image
+    }
+}
+
+impl GlGraphics {
+    /// Draws to `target` instead of the current framebuffer, like `draw` does to the screen.
+    ///
+    /// `viewport` is the part of the target drawn to, usually `target.viewport()`. Afterwards the
+    /// previously bound framebuffer and viewport are restored, so this can be nested or called
+    /// in the middle of drawing a frame, and `target.texture()` can be used by later passes.
+    pub fn draw_to<F, U>(&mut self, target: &RenderTarget, viewport: Viewport, f: F) -> U
+        where F: FnOnce(Context, &mut Self) -> U
+    {
+        self.draw_end();
+        let mut previous: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target.fbo);
+        }
+        let previous_viewport = self.get_current_viewport();
+        let res = self.draw(viewport, f);
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous as GLuint);
+        }
+        if let Some(viewport) = previous_viewport {
+            self.set_viewport(viewport);
+        }
+        res
+    }
+}
@@ -0,0 +1,76 @@
+//! Batching custom shader draws
+
+// External crates.
+use graphics::DrawState;
+use gl::types::GLuint;
+
+// Local crate.
+use back_end::{check_shader_inputs, GlGraphics};
+use error::DrawError;
+use shader_utils::Shader;
+use texture::Texture;
+
+/// Draws with one custom shader, buffering consecutive draws which share a draw state
+/// and texture into a single flush. Made by `GlGraphics::shader_batch`.
+///
+/// The indices of each draw are moved past the vertices buffered before it, so
+/// they stay relative to that draw's own vertices.
+pub struct ShaderBatch<'a, S: Shader + 'a> {
+    gl: &'a mut GlGraphics,
+    shader: &'a mut S,
+    // The draw state and texture of what's buffered, if anything is
+    state: Option<(DrawState, Option<GLuint>)>,
+}
+
+impl<'a, S: Shader> ShaderBatch<'a, S> {
+    pub(crate) fn new(gl: &'a mut GlGraphics, shader: &'a mut S) -> Self {
+        ShaderBatch {
+            gl: gl,
+            shader: shader,
+            state: None,
+        }
+    }
+
+    /// Buffers a draw like `GlGraphics::try_shader_draw`, flushing what was buffered
+    /// before first if it used another draw state or texture.
+    pub fn draw(&mut self,
+                draw_state: &DrawState,
+                vertices: &[S::Vertex],
+                indices: Option<&[u16]>,
+                texture: Option<(&Texture, &[[f32; 2]])>,
+                colour: Option<&[[f32; 4]]>,
+                normals: Option<&[[f32; 3]]>) -> Result<(), DrawError> {
+
+        check_shader_inputs(&mut *self.shader,
+                            vertices.len(),
+                            indices,
+                            texture.map(|x| x.1.len()),
+                            colour.map(|x| x.len()),
+                            normals.map(|x| x.len()))?;
+        let state = (*draw_state, texture.map(|x| x.0.get_id()));
+        if self.state != Some(state) {
+            self.flush();
+            self.gl.flush_batches();
+            self.gl.use_program(self.shader.program());
+            self.gl.use_draw_state(draw_state);
+            self.state = Some(state);
+        }
+        self.gl.append_shader_inputs(&mut *self.shader, vertices, indices, texture, colour, normals);
+        Ok(())
+    }
+
+    /// Sets the shader's uniforms, flushing what was buffered with the old ones first.
+    pub fn set_uniforms(&mut self, uniforms: impl FnOnce(&mut S, &mut GlGraphics)) {
+        self.flush();
+        self.gl.use_program(self.shader.program());
+        uniforms(&mut *self.shader, &mut *self.gl);
+    }
+
+    /// Draws everything buffered so far.
+    pub fn flush(&mut self) {
+        if self.state.take().is_some() && *self.shader.offset() > 0 {
+            self.gl.use_program(self.shader.program());
+            self.gl.flush_shader(&mut *self.shader);
+        }
+    }
+}
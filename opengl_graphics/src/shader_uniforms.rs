@@ -2,6 +2,7 @@
 
 
 // External crates.
+use std::collections::HashMap;
 use std::ffi::CString;
 use gl;
 use gl::types::{GLboolean, GLint, GLuint};
@@ -25,6 +26,44 @@ pub trait UniformType<'a> {
     type Value: 'a;
     /// Sets the uniform to a value
     fn set(Self::Value, location: GLint, program: GLuint);
+    /// Writes out the bits of a value, so setting a uniform to the value it already has can be skipped.
+    /// Returns `false` if the value can't be compared, in which case it's always set
+    fn bits(_value: &Self::Value, _out: &mut Vec<u32>) -> bool {
+        false
+    }
+}
+
+/// The last value set to each uniform of each program, as bits
+#[derive(Default)]
+pub(crate) struct UniformCache {
+    values: HashMap<(GLuint, GLint), Vec<u32>>,
+    scratch: Vec<u32>,
+}
+
+impl UniformCache {
+    // Whether `value` differs from the last value set to the uniform, remembering it if it does
+    fn changed<'a, T: UniformType<'a>>(&mut self, program: GLuint, location: GLint, value: &T::Value) -> bool {
+        self.scratch.clear();
+        if !T::bits(value, &mut self.scratch) {
+            return true;
+        }
+        match self.values.get_mut(&(program, location)) {
+            Some(ref last) if **last == self.scratch => false,
+            Some(last) => {
+                last.clear();
+                last.extend_from_slice(&self.scratch);
+                true
+            }
+            None => {
+                self.values.insert((program, location), self.scratch.clone());
+                true
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+    }
 }
 
 /// Shader uniform float
@@ -35,6 +74,10 @@ impl UniformType<'_> for SUFloat {
     fn set(value: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniform1f(p, location, value)}
     }
+    fn bits(value: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.push(value.to_bits());
+        true
+    }
 }
 
 /// Shader uniform integer
@@ -45,6 +88,10 @@ impl UniformType<'_> for SUInt {
     fn set(value: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniform1i(p, location, value)}
     }
+    fn bits(value: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.push(*value as u32);
+        true
+    }
 }
 
 /// Shader uniform integer
@@ -55,6 +102,10 @@ impl UniformType<'_> for SUUInt3 {
     fn set(value: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniform3ui(p, location, value[0], value[1], value[2])}
     }
+    fn bits(value: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend_from_slice(value);
+        true
+    }
 }
 
 /// Shader uniform vector of size 2
@@ -66,6 +117,10 @@ impl<'a> UniformType<'a> for SUVec2 {
     fn set(value: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniform2f(p, location, value[0], value[1])}
     }
+    fn bits(value: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(value.iter().map(|x| x.to_bits()));
+        true
+    }
 }
 
 /// Shader uniform vector of size 3
@@ -77,6 +132,10 @@ impl<'a> UniformType<'a> for SUVec3 {
     fn set(value: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniform3f(p, location, value[0], value[1], value[2])}
     }
+    fn bits(value: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(value.iter().map(|x| x.to_bits()));
+        true
+    }
 }
 
 /// Shader uniform vector of size 4
@@ -88,6 +147,10 @@ impl<'a> UniformType<'a> for SUVec4 {
     fn set(value: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniform4f(p, location, value[0], value[1], value[2], value[3])}
     }
+    fn bits(value: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(value.iter().map(|x| x.to_bits()));
+        true
+    }
 }
 
 /// Shader uniform 2x2 matrix
@@ -99,6 +162,10 @@ impl<'a> UniformType<'a> for SUMat2x2 {
     fn set(values: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniformMatrix2fv(p, location, 1 as GLint, false as GLboolean, values.as_ptr())}
     }
+    fn bits(values: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(values.iter().map(|x| x.to_bits()));
+        true
+    }
 }
 
 /// Shader uniform 3x3 matrix
@@ -110,6 +177,10 @@ impl<'a> UniformType<'a> for SUMat3x3 {
     fn set(values: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniformMatrix3fv(p, location, 1 as GLint, false as GLboolean, values.as_ptr())}
     }
+    fn bits(values: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(values.iter().map(|x| x.to_bits()));
+        true
+    }
 }
 
 /// Shader uniform 4x4 matrix
@@ -121,9 +192,93 @@ impl<'a> UniformType<'a> for SUMat4x4 {
     fn set(values: Self::Value, location: GLint, p: GLuint) {
         unsafe {gl::ProgramUniformMatrix4fv(p, location, 1 as GLint, false as GLboolean, values.as_ptr())}
     }
+    fn bits(values: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(values.iter().map(|x| x.to_bits()));
+        true
+    }
+}
+
+/// Shader uniform array of 4x4 matrices
+/// Matrix elements are floats
+#[derive(Clone, Copy)]
+pub struct SUMat4x4Array {}
+impl<'a> UniformType<'a> for SUMat4x4Array {
+    type Value = &'a [[f32; 16]];
+    fn set(values: Self::Value, location: GLint, p: GLuint) {
+        unsafe {gl::ProgramUniformMatrix4fv(p, location, values.len() as GLint, false as GLboolean, values.as_ptr() as *const f32)}
+    }
+    fn bits(values: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(values.iter().flat_map(|x| x.iter()).map(|x| x.to_bits()));
+        true
+    }
+}
+
+/// Shader uniform array of floats
+#[derive(Clone, Copy)]
+pub struct SUFloatArray {}
+impl<'a> UniformType<'a> for SUFloatArray {
+    type Value = &'a [f32];
+    fn set(values: Self::Value, location: GLint, p: GLuint) {
+        unsafe {gl::ProgramUniform1fv(p, location, values.len() as GLint, values.as_ptr())}
+    }
+    fn bits(values: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(values.iter().map(|x| x.to_bits()));
+        true
+    }
+}
+
+/// Shader uniform array of vectors of size 2
+/// Vector elements are floats
+#[derive(Clone, Copy)]
+pub struct SUVec2Array {}
+impl<'a> UniformType<'a> for SUVec2Array {
+    type Value = &'a [[f32; 2]];
+    fn set(values: Self::Value, location: GLint, p: GLuint) {
+        unsafe {gl::ProgramUniform2fv(p, location, values.len() as GLint, values.as_ptr() as *const f32)}
+    }
+    fn bits(values: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(values.iter().flat_map(|x| x.iter()).map(|x| x.to_bits()));
+        true
+    }
+}
+
+/// Shader uniform array of vectors of size 3
+/// Vector elements are floats
+#[derive(Clone, Copy)]
+pub struct SUVec3Array {}
+impl<'a> UniformType<'a> for SUVec3Array {
+    type Value = &'a [[f32; 3]];
+    fn set(values: Self::Value, location: GLint, p: GLuint) {
+        unsafe {gl::ProgramUniform3fv(p, location, values.len() as GLint, values.as_ptr() as *const f32)}
+    }
+    fn bits(values: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(values.iter().flat_map(|x| x.iter()).map(|x| x.to_bits()));
+        true
+    }
+}
+
+/// Shader uniform array of vectors of size 4
+/// Vector elements are floats
+#[derive(Clone, Copy)]
+pub struct SUVec4Array {}
+impl<'a> UniformType<'a> for SUVec4Array {
+    type Value = &'a [[f32; 4]];
+    fn set(values: Self::Value, location: GLint, p: GLuint) {
+        unsafe {gl::ProgramUniform4fv(p, location, values.len() as GLint, values.as_ptr() as *const f32)}
+    }
+    fn bits(values: &Self::Value, out: &mut Vec<u32>) -> bool {
+        out.extend(values.iter().flat_map(|x| x.iter()).map(|x| x.to_bits()));
+        true
+    }
 }
 
 impl GlGraphics {
+    /// Forgets the values uniforms were last set to, so they're all set again.
+    /// Needed after setting uniforms without `ShaderUniform::set`, or relinking a program
+    pub fn forget_uniforms(&self) {
+        self.uniform_cache.borrow_mut().clear();
+    }
+
     /// Try to get uniform from the current shader of a given name.
     pub fn get_uniform<T: ?Sized>(&self, name : &str) -> Option<ShaderUniform<T>> where for<'a> T: UniformType<'a> {
         self.get_current_program().and_then( |p| {
@@ -133,6 +288,8 @@ impl GlGraphics {
                     let uniform = match gl::GetUniformLocation(p, name.as_ptr()) {
                         -1 => None,
                         location => {
+                            // A new handle may be for a new program reusing an old one's id
+                            self.uniform_cache.borrow_mut().values.remove(&(p, location));
                             Some(ShaderUniform{
                                 location : location,
                                 phantom : PhantomData,
@@ -149,9 +306,14 @@ impl GlGraphics {
 
 impl<'a, T> ShaderUniform<T> where T: UniformType<'a> {
     /// Set the value of the float uniform.
+    ///
+    /// Does nothing when the uniform already has this value from the last
+    /// time it was set, see `GlGraphics::forget_uniforms`.
     pub fn set(&self, gl : &GlGraphics, value: T::Value) {
         gl.get_current_program().map(|p| {
-            T::set(value, self.location, p);
+            if gl.uniform_cache.borrow_mut().changed::<T>(p, self.location, &value) {
+                T::set(value, self.location, p);
+            }
         });
     }
 }
@@ -8,12 +8,17 @@ use std::ffi::CString;
 use std::{ptr, mem};
 use std::marker::PhantomData;
 
+// Local crate.
+use shader_uniforms::{ShaderUniform, SUMat4x4};
+
 /// Vertices attributes
 pub unsafe trait VertexAttribute: Copy {
     /// GL type.
     const TY: GLenum;
     /// Number of components
     const SIZE: i32;
+    /// Whether integer components are mapped to `0..1` (or `-1..1` when signed) in the shader.
+    const NORMALIZED: bool = false;
 }
 
 unsafe impl VertexAttribute for f32 {
@@ -36,6 +41,37 @@ unsafe impl VertexAttribute for [f32; 4] {
     const SIZE: i32 = 4;
 }
 
+/// Two half precision floats, stored as their bits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+pub struct Half2(pub [u16; 2]);
+
+unsafe impl VertexAttribute for Half2 {
+    const TY: GLenum = gl::HALF_FLOAT;
+    const SIZE: i32 = 2;
+}
+
+/// Four half precision floats, stored as their bits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+pub struct Half4(pub [u16; 4]);
+
+unsafe impl VertexAttribute for Half4 {
+    const TY: GLenum = gl::HALF_FLOAT;
+    const SIZE: i32 = 4;
+}
+
+/// Four signed bytes, read in the shader as floats from -1 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+pub struct Snorm4(pub [i8; 4]);
+
+unsafe impl VertexAttribute for Snorm4 {
+    const TY: GLenum = gl::BYTE;
+    const SIZE: i32 = 4;
+    const NORMALIZED: bool = true;
+}
+
 /// Describes a shader attribute.
 pub struct DynamicAttribute<T: VertexAttribute> {
     /// The vertex buffer object.
@@ -163,6 +199,14 @@ impl<T: VertexAttribute> InstancedAttribute<T> {
         }
     }
 
+    /// Creates an attribute which advances once per instance
+    pub fn per_instance(program: GLuint, name: &str) -> Result<Self, String> {
+        Ok(Self {
+            dynamic_attribute: DynamicAttribute::new(program, name, gl::FALSE)?,
+            divisor: 1,
+        })
+    }
+
     /// Sets the data in this instanced attribute
     pub unsafe fn set(&mut self, data: &[T]) {
         gl::EnableVertexAttribArray(self.dynamic_attribute.location);
@@ -195,6 +239,68 @@ impl<T: VertexAttribute> InstancedAttribute<T> {
     }
 }
 
+/// The element buffer object a shader draws its indices from.
+///
+/// The buffer is bound to whichever vertex array object is bound when drawing,
+/// which remembers it for later like it does the attributes.
+pub struct ElementBuffer {
+    ebo: GLuint,
+}
+
+impl Drop for ElementBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.ebo);
+        }
+    }
+}
+
+impl Default for ElementBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ElementBuffer {
+    /// Creates an empty element buffer.
+    pub fn new() -> Self {
+        let mut ebo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut ebo);
+        }
+        ElementBuffer { ebo: ebo }
+    }
+
+    /// Draws the first `vertices` vertices of the bound vertex array object as triangles,
+    /// through `indices` with `gl::DrawElements` unless there are none.
+    pub unsafe fn draw(&self, indices: &[u16], vertices: usize) {
+        if indices.is_empty() {
+            gl::DrawArrays(gl::TRIANGLES, 0, vertices as i32);
+        } else {
+            self.set(indices);
+            gl::DrawElements(gl::TRIANGLES, indices.len() as i32, gl::UNSIGNED_SHORT, ptr::null());
+        }
+    }
+
+    /// Like `draw`, but draws `instances` copies of the triangles.
+    pub unsafe fn draw_instanced(&self, indices: &[u16], vertices: usize, instances: usize) {
+        if indices.is_empty() {
+            gl::DrawArraysInstanced(gl::TRIANGLES, 0, vertices as i32, instances as i32);
+        } else {
+            self.set(indices);
+            gl::DrawElementsInstanced(gl::TRIANGLES, indices.len() as i32, gl::UNSIGNED_SHORT, ptr::null(), instances as i32);
+        }
+    }
+
+    unsafe fn set(&self, indices: &[u16]) {
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+        gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
+                       mem::size_of_val(indices) as GLsizeiptr,
+                       indices.as_ptr() as *const _,
+                       gl::DYNAMIC_DRAW);
+    }
+}
+
 /// Compiles a shader.
 ///
 /// Returns a shader or a message with the error.
@@ -237,6 +343,74 @@ pub fn compile_shader(shader_type: GLenum, source: &str) -> Result<GLuint, Strin
     }
 }
 
+/// A linked shader program.
+///
+/// The program and its shaders are deleted when running out of scope.
+pub struct Program {
+    program: GLuint,
+    vertex_shader: GLuint,
+    fragment_shader: GLuint,
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+            gl::DeleteShader(self.vertex_shader);
+            gl::DeleteShader(self.fragment_shader);
+        }
+    }
+}
+
+impl Program {
+    /// Compiles and links a program from vertex and fragment shader sources.
+    ///
+    /// Returns a program or a message with the error.
+    pub fn from_vs_fs(vertex_shader: &str, fragment_shader: &str) -> Result<Self, String> {
+        let vertex_shader = compile_shader(gl::VERTEX_SHADER, vertex_shader)
+            .map_err(|s| format!("Error compiling vertex shader: {}", s))?;
+        let fragment_shader = match compile_shader(gl::FRAGMENT_SHADER, fragment_shader) {
+            Ok(x) => x,
+            Err(s) => {
+                unsafe { gl::DeleteShader(vertex_shader); }
+                return Err(format!("Error compiling fragment shader: {}", s));
+            }
+        };
+        let program = Program {
+            program: unsafe { gl::CreateProgram() },
+            vertex_shader: vertex_shader,
+            fragment_shader: fragment_shader,
+        };
+        unsafe {
+            gl::AttachShader(program.program, vertex_shader);
+            gl::AttachShader(program.program, fragment_shader);
+            gl::LinkProgram(program.program);
+            let mut status = gl::FALSE as GLint;
+            gl::GetProgramiv(program.program, gl::LINK_STATUS, &mut status);
+            if status != (gl::TRUE as GLint) {
+                let mut len = 0;
+                gl::GetProgramiv(program.program, gl::INFO_LOG_LENGTH, &mut len);
+                if len == 0 {
+                    return Err("Linking failed with no log.".to_string());
+                }
+                // Subtract 1 to skip the trailing null character.
+                let mut buf = vec![0; len as usize - 1];
+                gl::GetProgramInfoLog(program.program,
+                                      len,
+                                      ptr::null_mut(),
+                                      buf.as_mut_ptr() as *mut GLchar);
+                return Err(format!("Error linking program: {}", String::from_utf8_lossy(&buf)));
+            }
+        }
+        Ok(program)
+    }
+
+    /// Gets the OpenGL id of the program.
+    pub fn id(&self) -> GLuint {
+        self.program
+    }
+}
+
 /// Finds attribute location from a program.
 ///
 /// Returns `Err` if there is no attribute with such name.
@@ -285,6 +459,13 @@ pub trait Shader {
     fn new(glsl: GLSL, gl: Option<&mut crate::back_end::GlGraphics>) -> Self where Self: Sized;
     /// Flushes values to the gpu and draws them
     fn flush(&mut self);
+    /// Flushes values to the gpu and draws `instances` copies of them, usually with
+    /// `gl::DrawArraysInstanced` or `gl::DrawElementsInstanced` after binding the
+    /// shader's `InstancedAttribute`s. Not supported unless implemented
+    fn flush_instanced(&mut self, instances: usize) {
+        let _ = instances;
+        panic!("This shader doesn't support instanced drawing.")
+    }
     /// Gets the program for this shader
     fn program(&self) -> GLuint;
     /// Gets the offset of the vertices currently buffered
@@ -295,7 +476,10 @@ pub trait Shader {
     fn colour_buffer(&mut self) -> Option<&mut Vec<[f32; 4]>> { None }
     /// Optionally gets a mutable reference to the uv buffer if supported
     fn uv_buffer(&mut self) -> Option<&mut Vec<[f32; 2]>> { None }
-    /// Optionally gets a mutable reference to the index buffer if supported
+    /// Optionally gets a mutable reference to the index buffer if supported.
+    ///
+    /// Indices are offset by the vertices already buffered, so `flush` should draw with
+    /// them when there are any (see `ElementBuffer::draw`). They're cleared after flushing.
     fn index_buffer(&mut self) -> Option<&mut Vec<u16>> { None }
     /// Optionally gets a mutable reference to the normal buffer if supported
     fn normal_buffer(&mut self) -> Option<&mut Vec<[f32; 3]>> { None }
@@ -303,6 +487,12 @@ pub trait Shader {
     fn texture_id(&mut self) -> Option<&mut GLuint> { None }
     /// Returns if it supports a texture
     fn has_texture(&self) -> bool { false }
+    /// Optionally gets the uniform which the world matrix of whatever is
+    /// being drawn is written to before drawing, if supported
+    fn model_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> { None }
+    /// Optionally gets the uniform which the camera's projection matrix
+    /// multiplied by its view matrix is written to before drawing, if supported
+    fn view_proj_uniform(&self) -> Option<ShaderUniform<SUMat4x4>> { None }
 }
 
 macro_rules! unit_unimplemented_panic {
@@ -0,0 +1,41 @@
+//! Drawing text with a glyph cache
+
+// External crates.
+use graphics::character::CharacterCache;
+use graphics::math::Matrix2d;
+use graphics::text::Text;
+use graphics::types::FontSize;
+use graphics::DrawState;
+
+// Local crate.
+use back_end::GlGraphics;
+use GlyphCache;
+
+/// The error given when a glyph can't be rendered.
+pub type TextError<'a> = <GlyphCache<'a> as CharacterCache>::Error;
+
+impl GlGraphics {
+    /// Draws `text` with its baseline starting at the origin of `transform`, at `size` pixels.
+    ///
+    /// Glyphs are rendered into `font` as they're first used, and drawn with the `Textured` shader,
+    /// so text batches with other textured 2D drawing.
+    pub fn draw_text<'a>(&mut self,
+                         text: &str,
+                         font: &mut GlyphCache<'a>,
+                         size: FontSize,
+                         color: [f32; 4],
+                         transform: Matrix2d,
+                         draw_state: &DrawState)
+                         -> Result<(), TextError<'a>> {
+        Text::new_color(color, size).draw(text, font, draw_state, transform, self)
+    }
+
+    /// Measures how wide `text` is when drawn at `size` pixels.
+    pub fn text_width<'a>(&mut self,
+                          text: &str,
+                          font: &mut GlyphCache<'a>,
+                          size: FontSize)
+                          -> Result<f64, TextError<'a>> {
+        font.width(size, text)
+    }
+}
@@ -59,7 +59,7 @@ impl GlSettings for TextureSettings {
             Wrap::Repeat => gl::REPEAT,
             Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
             Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
-            Wrap::ClampToBorder => unimplemented!(), //gl::CLAMP_TO_BORDER,
+            Wrap::ClampToBorder => gl::CLAMP_TO_BORDER,
         }
     }
 
@@ -68,12 +68,42 @@ impl GlSettings for TextureSettings {
             Wrap::Repeat => gl::REPEAT,
             Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
             Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
-            Wrap::ClampToBorder => unimplemented!(), //gl::CLAMP_TO_BORDER,
+            Wrap::ClampToBorder => gl::CLAMP_TO_BORDER,
         }
     }
 
 }
 
+// From EXT_texture_filter_anisotropic, which isn't part of GLES
+const TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FF;
+
+/// Sets the filtering and wrapping of the bound texture
+pub(crate) unsafe fn apply_settings(settings: &TextureSettings) {
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, settings.get_gl_min() as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, settings.get_gl_mag() as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, settings.get_gl_wrap_u() as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, settings.get_gl_wrap_v() as i32);
+    if settings.get_wrap_u() == Wrap::ClampToBorder ||
+        settings.get_wrap_v() == Wrap::ClampToBorder {
+        gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, settings.get_border_color().as_ptr());
+    }
+}
+
+/// Gets the most anisotropic filtering the context supports, `1.0` when it doesn't support any.
+pub fn max_anisotropy() -> f32 {
+    let mut max = 1.0;
+    unsafe {
+        // Clear any earlier error, so it isn't mistaken for the extension being missing
+        while gl::GetError() != gl::NO_ERROR {}
+        gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+        if gl::GetError() != gl::NO_ERROR {
+            return 1.0;
+        }
+    }
+    max.max(1.0)
+}
+
 /// Wraps OpenGL texture data.
 /// The texture gets deleted when running out of scope.
 ///
@@ -83,6 +113,7 @@ pub struct Texture {
     id: GLuint,
     width: u32,
     height: u32,
+    mipmaps: bool,
 }
 
 impl Texture {
@@ -93,6 +124,7 @@ impl Texture {
             id: id,
             width: width,
             height: height,
+            mipmaps: false,
         }
     }
 
@@ -155,6 +187,37 @@ impl Texture {
 
         UpdateTexture::update(self, &mut (), Format::Rgba8, img, [0, 0], [width, height]).unwrap();
     }
+
+    /// Changes the filtering and wrapping of the texture after it was created,
+    /// generating mipmaps if they're asked for and it doesn't have them yet.
+    ///
+    /// Gamma conversion can't be changed after creation, so is ignored.
+    pub fn update_settings(&mut self, settings: &TextureSettings) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            apply_settings(settings);
+            if settings.get_generate_mipmap() && !self.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+                self.mipmaps = true;
+            }
+        }
+    }
+
+    /// Sets how many samples anisotropic filtering takes, sharpening textures
+    /// seen at glancing angles. `1.0` turns it off, and amounts above
+    /// `max_anisotropy()` are clamped to it.
+    ///
+    /// Does nothing when the context doesn't support anisotropic filtering.
+    pub fn set_anisotropy(&mut self, amount: f32) {
+        let max = max_anisotropy();
+        if max <= 1.0 {
+            return;
+        }
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, amount.max(1.0).min(max));
+        }
+    }
 }
 
 impl Drop for Texture {
@@ -194,22 +257,7 @@ impl CreateTexture<()> for Texture {
         unsafe {
             gl::GenTextures(1, &mut id);
             gl::BindTexture(gl::TEXTURE_2D, id);
-            gl::TexParameteri(gl::TEXTURE_2D,
-                              gl::TEXTURE_MIN_FILTER,
-                              settings.get_gl_min() as i32);
-            gl::TexParameteri(gl::TEXTURE_2D,
-                              gl::TEXTURE_MAG_FILTER,
-                              settings.get_gl_mag() as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, settings.get_gl_wrap_u() as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, settings.get_gl_wrap_v() as i32);
-            if settings.get_wrap_u() == Wrap::ClampToBorder ||
-                settings.get_wrap_v() == Wrap::ClampToBorder {
-                // gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, settings.get_border_color().as_ptr());
-                unimplemented!()
-            }
-            if settings.get_generate_mipmap() {
-                gl::GenerateMipmap(gl::TEXTURE_2D);
-            }
+            apply_settings(settings);
             gl::TexImage2D(gl::TEXTURE_2D,
                            0,
                            internal_format as i32,
@@ -219,9 +267,15 @@ impl CreateTexture<()> for Texture {
                            gl::RGBA,
                            gl::UNSIGNED_BYTE,
                            memory.as_ptr() as *const _);
+            // Mipmaps are generated from the image, so only once it's there
+            if settings.get_generate_mipmap() {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
         }
 
-        Ok(Texture::new(id, size[0], size[1]))
+        let mut texture = Texture::new(id, size[0], size[1]);
+        texture.mipmaps = settings.get_generate_mipmap();
+        Ok(texture)
     }
 }
 
@@ -246,6 +300,9 @@ impl UpdateTexture<()> for Texture {
                               gl::RGBA,
                               gl::UNSIGNED_BYTE,
                               memory.as_ptr() as *const _);
+            if self.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
         }
 
         Ok(())
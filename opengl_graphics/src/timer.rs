@@ -0,0 +1,165 @@
+//! Timing the GPU with timer queries
+
+// External crates.
+use gl;
+use gl::types::{GLenum, GLint, GLuint};
+use std::collections::VecDeque;
+use std::mem;
+
+// Local crate.
+use profile::has_extension;
+
+// From `GL_EXT_disjoint_timer_query`, the same values as desktop OpenGL 3.3's
+const TIME_ELAPSED: GLenum = 0x88BF;
+const GPU_DISJOINT: GLenum = 0x8FBB;
+// Frames left waiting for their results before their queries are given up on
+const MAX_PENDING_FRAMES: usize = 4;
+
+/// How long the GPU spent on the flushes of a frame, measured with timer
+/// queries by `GlGraphics::set_gpu_timing`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GpuTiming {
+    /// The total time the GPU spent drawing the frame's flushes, in milliseconds.
+    pub milliseconds: f64,
+    /// How many flushes were timed.
+    pub flushes: u32,
+}
+
+// Wraps each flush in a timer query, collecting the results of a frame once
+// all of its queries are available, which is usually a frame or two later
+pub(crate) struct GpuTimer {
+    free: Vec<GLuint>,
+    frame: Vec<GLuint>,
+    // Oldest first
+    pending: VecDeque<Vec<GLuint>>,
+    running: bool,
+    // Only OpenGL ES reports when timings were disturbed, such as by a change of clock speed
+    check_disjoint: bool,
+}
+
+impl GpuTimer {
+    /// Creates a timer if the context supports timer queries.
+    pub(crate) fn new() -> Option<Self> {
+        if !gl::BeginQuery::is_loaded() {
+            return None;
+        }
+        let check_disjoint = has_extension("GL_EXT_disjoint_timer_query");
+        if !check_disjoint && !has_extension("GL_ARB_timer_query") {
+            return None;
+        }
+        Some(GpuTimer {
+            free: Vec::new(),
+            frame: Vec::new(),
+            pending: VecDeque::new(),
+            running: false,
+            check_disjoint: check_disjoint,
+        })
+    }
+
+    /// Starts timing a flush. Queries can't overlap, so this does nothing
+    /// while another flush is being timed.
+    pub(crate) fn begin(&mut self) {
+        if self.running {
+            return;
+        }
+        let query = match self.free.pop() {
+            Some(query) => query,
+            None => {
+                let mut query = 0;
+                unsafe {
+                    gl::GenQueries(1, &mut query);
+                }
+                query
+            }
+        };
+        unsafe {
+            gl::BeginQuery(TIME_ELAPSED, query);
+        }
+        self.frame.push(query);
+        self.running = true;
+    }
+
+    /// Stops timing the flush started by `begin`.
+    pub(crate) fn end(&mut self) {
+        if self.running {
+            unsafe {
+                gl::EndQuery(TIME_ELAPSED);
+            }
+            self.running = false;
+        }
+    }
+
+    /// Ends the frame being timed, and collects the newest frame whose
+    /// results have all become available since the last call.
+    pub(crate) fn end_frame(&mut self) -> Option<GpuTiming> {
+        self.end();
+        let frame = mem::replace(&mut self.frame, Vec::new());
+        self.pending.push_back(frame);
+        if self.pending.len() > MAX_PENDING_FRAMES {
+            let dropped = self.pending.pop_front().unwrap();
+            self.free.extend(dropped);
+        }
+        let mut latest = None;
+        while self.pending.front().map_or(false, |x| x.iter().all(|&x| is_available(x))) {
+            let queries = self.pending.pop_front().unwrap();
+            let nanoseconds = queries.iter().map(|&x| result(x) as u64).sum::<u64>();
+            latest = Some(GpuTiming {
+                milliseconds: nanoseconds as f64 / 1e6,
+                flushes: queries.len() as u32,
+            });
+            self.free.extend(queries);
+        }
+        if self.check_disjoint {
+            let mut disjoint: GLint = 0;
+            unsafe {
+                gl::GetIntegerv(GPU_DISJOINT, &mut disjoint);
+            }
+            if disjoint != 0 {
+                return None;
+            }
+        }
+        latest
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        self.end();
+        let queries = self.free
+            .drain(..)
+            .chain(self.frame.drain(..))
+            .chain(self.pending.drain(..).flat_map(|x| x))
+            .collect::<Vec<_>>();
+        unsafe {
+            gl::DeleteQueries(queries.len() as i32, queries.as_ptr());
+        }
+    }
+}
+
+/// Times `flush` with `timer`, if there is one.
+pub(crate) fn time_flush<F: FnOnce()>(timer: &mut Option<GpuTimer>, flush: F) {
+    if let Some(ref mut timer) = *timer {
+        timer.begin();
+    }
+    flush();
+    if let Some(ref mut timer) = *timer {
+        timer.end();
+    }
+}
+
+fn is_available(query: GLuint) -> bool {
+    let mut available = 0;
+    unsafe {
+        gl::GetQueryObjectuiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+    }
+    available != 0
+}
+
+// In nanoseconds
+fn result(query: GLuint) -> GLuint {
+    let mut time = 0;
+    unsafe {
+        gl::GetQueryObjectuiv(query, gl::QUERY_RESULT, &mut time);
+    }
+    time
+}
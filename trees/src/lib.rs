@@ -1,5 +1,5 @@
-use android_base::{AppImpl, UpdateArgs, enable_backtrace, AppContainer, AppConfig, ShaderStorage, ShaderContext};
-use graphics::{Context, clear};
+use android_base::{AppImpl, UpdateArgs, enable_backtrace, AppContainer, AppConfig, ShaderStorage, ShaderContext, StateStore};
+use graphics::Context;
 use opengl_graphics::{GlGraphics, GLSL};
 use piston::input::RenderArgs;
 
@@ -9,7 +9,7 @@ pub struct App {
 
 impl AppImpl for App {
     type InitializationData = ();
-    fn new(gl: &mut GlGraphics, _data: Self::InitializationData, _shaders: &mut ShaderStorage) -> Self {
+    fn new(gl: &mut GlGraphics, _data: Self::InitializationData, _shaders: &mut ShaderStorage, _state: &StateStore) -> Self {
         Self {
             time: 0.0,
         }
@@ -27,13 +27,11 @@ impl AppImpl for App {
         // For shader calls
     }
 
-    fn draw_2d(&mut self, _c: Context, gl: &mut GlGraphics, args: RenderArgs, _cfg: &mut AppConfig) {
+    fn draw_2d(&mut self, _c: Context, _gl: &mut GlGraphics, args: RenderArgs, _cfg: &mut AppConfig) {
         self.time += args.ext_dt;
-        clear([163.0 / 255.0, 250.0 / 255.0, 255.0 / 255.0, 1.], gl);
-        
     }
 
-    fn on_die(self) {
+    fn on_die(self, _state: &mut StateStore) {
         println!("Dieing!");
     }
     fn cancel_poll(&self) -> bool {
@@ -43,6 +41,10 @@ impl AppImpl for App {
 
 pub fn main() {
     enable_backtrace();
-    let mut container = AppContainer::<App>::init(AppConfig::new(), ());
+    let mut container = AppContainer::<App>::init(
+        AppConfig::new()
+            .clear_color(Some([163.0 / 255.0, 250.0 / 255.0, 255.0 / 255.0, 1.])),
+        ()
+    ).unwrap();
     container.run();
 }